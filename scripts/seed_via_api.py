@@ -2,57 +2,83 @@
 """
 Seed the forum database via API calls
 """
+import os
 import requests
 import json
 import time
 
 BASE_URL = "https://cyber-forum.onrender.com"
+# Session token of an admin or moderator account, needed to clear the forum
+ADMIN_TOKEN = os.environ.get("FORUM_ADMIN_TOKEN")
+# Password the seed accounts are registered with
+SEED_PASSWORD = "seed-password-123"
+# Session token of each seed account, which posts are made with
+TOKENS = {}
+
+def succeeded(response):
+    """Any 2xx; creating things answers 201"""
+    return 200 <= response.status_code < 300
 
 def clear_forum():
     """Clear all existing data"""
+    if not ADMIN_TOKEN:
+        print("FORUM_ADMIN_TOKEN not set, leaving existing forum data in place")
+        return
     print("Clearing existing forum data...")
+    headers = {"Authorization": f"Bearer {ADMIN_TOKEN}"}
     try:
         # Get all threads and delete them
         response = requests.get(f"{BASE_URL}/threads")
-        if response.status_code == 200:
+        if succeeded(response):
             threads = response.json()
             for thread in threads:
                 # Delete all comments in thread
                 comments_response = requests.get(f"{BASE_URL}/threads/{thread['id']}/comments")
-                if comments_response.status_code == 200:
+                if succeeded(comments_response):
                     comments = comments_response.json()
                     for comment in comments:
-                        requests.delete(f"{BASE_URL}/comments/{comment['id']}")
+                        requests.delete(f"{BASE_URL}/comments/{comment['id']}", headers=headers)
                 # Delete thread
-                requests.delete(f"{BASE_URL}/threads/{thread['id']}")
+                response = requests.delete(f"{BASE_URL}/threads/{thread['id']}", headers=headers)
+                if not succeeded(response):
+                    print(f"Could not delete thread {thread['id']}: {response.status_code} {response.text}")
         print("Forum cleared!")
     except Exception as e:
         print(f"Error clearing forum: {e}")
 
 def create_user(username):
-    """Create a user"""
+    """Create a user, or log in if it already exists"""
     try:
-        response = requests.post(f"{BASE_URL}/register", json={"username": username})
-        if response.status_code == 200:
+        credentials = {"username": username, "password": SEED_PASSWORD}
+        response = requests.post(f"{BASE_URL}/auth/register", json=credentials)
+        if succeeded(response):
             print(f"Created user: {username}")
-            return response.json()
         else:
             # User might already exist
             print(f"User {username} might already exist")
-            return {"id": "existing", "username": username}
+            response = requests.post(f"{BASE_URL}/auth/login", json=credentials)
+        if not succeeded(response):
+            print(f"Could not log in as {username}: {response.text}")
+            return None
+        user = response.json()
+        TOKENS[username] = user["token"]
+        return user
     except Exception as e:
         print(f"Error creating user {username}: {e}")
         return None
 
+def auth_headers(username):
+    """Posts are made as the logged-in user the token belongs to"""
+    return {"Authorization": f"Bearer {TOKENS.get(username, '')}"}
+
 def create_thread(title, content, username):
     """Create a thread"""
     try:
         response = requests.post(f"{BASE_URL}/threads", json={
             "title": title,
-            "content": content,
-            "author": username
-        })
-        if response.status_code == 200:
+            "content": content
+        }, headers=auth_headers(username))
+        if succeeded(response):
             thread = response.json()
             print(f"Created thread: {title}")
             return thread
@@ -68,10 +94,9 @@ def create_comment(thread_id, content, username):
     try:
         response = requests.post(f"{BASE_URL}/comments", json={
             "thread_id": thread_id,
-            "content": content,
-            "author": username
-        })
-        if response.status_code == 200:
+            "content": content
+        }, headers=auth_headers(username))
+        if succeeded(response):
             print(f"Added comment to thread {thread_id}")
         else:
             print(f"Error adding comment: {response.text}")
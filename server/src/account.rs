@@ -0,0 +1,251 @@
+// account.rs
+use actix_web::{delete, get, http::header, put, web, HttpRequest, HttpResponse, Responder};
+use chrono::Utc;
+use serde::Deserialize;
+use serde_json::Value;
+use sqlx::{PgConnection, PgPool};
+
+use crate::audit;
+use crate::auth::{self, AuthUser};
+use crate::reactions;
+use crate::validation::{self, Validate};
+
+// The account itself, exported as a single object
+const EXPORT_ACCOUNT: &str = r#"SELECT id, username, display_name, bio, signature, location, avatar_hash as avatar, role,
+    totp_enabled as two_factor_enabled, created_at FROM users WHERE id = $1"#;
+
+// The rest of the export: one list per section, each the rows of a query for the user ($1)
+const EXPORT_SECTIONS: [(&str, &str); 11] = [
+    ("threads", r#"SELECT t.id, t.title, t.content, t.image_url, c.name as category,
+                          ARRAY(SELECT g.tag FROM thread_tags g WHERE g.thread_id = t.id ORDER BY g.tag) as tags,
+                          t.created_at, t.deleted_at
+                   FROM threads t LEFT JOIN categories c ON c.id = t.category_id
+                   WHERE t.user_id = $1 ORDER BY t.created_at"#),
+    ("comments", "SELECT id, thread_id, content, image_url, created_at, deleted_at FROM comments WHERE user_id = $1 ORDER BY created_at"),
+    ("reactions", "SELECT target_type, target_id, emoji, created_at FROM reactions WHERE user_id = $1 ORDER BY created_at"),
+    ("messages", r#"SELECT m.conversation_id, s.username as sender, m.content, m.created_at
+                    FROM dm_messages m JOIN users s ON s.id = m.sender_id
+                    WHERE m.conversation_id IN (SELECT conversation_id FROM dm_participants WHERE user_id = $1)
+                    ORDER BY m.created_at"#),
+    ("notifications", r#"SELECT n.kind, a.username as actor, n.thread_id, n.comment_id, n.created_at, n.read_at
+                         FROM notifications n JOIN users a ON a.id = n.actor_id
+                         WHERE n.user_id = $1 ORDER BY n.created_at"#),
+    ("subscriptions", "SELECT thread_id, created_at FROM thread_subscriptions WHERE user_id = $1 ORDER BY created_at"),
+    ("blocks", r#"SELECT u.username, b.created_at FROM user_blocks b JOIN users u ON u.id = b.blocked_id
+                  WHERE b.blocker_id = $1 ORDER BY b.created_at"#),
+    ("reports", "SELECT target_type, target_id, reason, status, resolution, created_at FROM reports WHERE reporter_id = $1 ORDER BY created_at"),
+    ("sanctions", "SELECT action, reason, until, created_at FROM user_sanctions WHERE user_id = $1 ORDER BY created_at"),
+    ("sessions", "SELECT created_at FROM sessions WHERE user_id = $1 ORDER BY created_at"),
+    ("ssh_keys", "SELECT name, algorithm, fingerprint, created_at, last_used_at FROM ssh_keys WHERE user_id = $1 ORDER BY created_at"),
+];
+
+// Personal data removed with the account; the users row itself stays, since
+// posts, messages, reports and sanctions refer to it
+const DELETE_PERSONAL: [&str; 9] = [
+    "DELETE FROM sessions WHERE user_id = $1",
+    "DELETE FROM reactions WHERE user_id = $1",
+    "DELETE FROM thread_subscriptions WHERE user_id = $1",
+    "DELETE FROM thread_reads WHERE user_id = $1",
+    "DELETE FROM notifications WHERE user_id = $1 OR actor_id = $1",
+    "DELETE FROM user_blocks WHERE blocker_id = $1 OR blocked_id = $1",
+    "DELETE FROM dm_participants WHERE user_id = $1",
+    "DELETE FROM recovery_codes WHERE user_id = $1",
+    "DELETE FROM ssh_keys WHERE user_id = $1",
+];
+
+pub async fn ensure_tables(pool: &PgPool) {
+    // Set when the owner deletes the account
+    let _ = sqlx::query("ALTER TABLE users ADD COLUMN IF NOT EXISTS deleted_at TIMESTAMPTZ")
+        .execute(pool)
+        .await;
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PasswordChange {
+    #[serde(default)]
+    current_password: String,
+    #[serde(default)]
+    new_password: String,
+}
+
+impl Validate for PasswordChange {
+    fn validate(&mut self) -> Result<(), validation::Errors> {
+        let mut errors = validation::Errors::default();
+        self.new_password = errors.check(&validation::NEW_PASSWORD, &self.new_password);
+        errors.finish()
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AccountDeletion {
+    #[serde(default)]
+    password: String,
+    // "anonymize" keeps the posts under a placeholder name, "delete" removes them
+    #[serde(default)]
+    posts: String,
+}
+
+pub async fn password_matches(db: &PgPool, user_id: &str, password: &str) -> bool {
+    let stored: Option<String> = sqlx::query_scalar("SELECT password_hash FROM users WHERE id = $1")
+        .bind(user_id)
+        .fetch_optional(db)
+        .await
+        .unwrap_or(None);
+    stored.is_some_and(|hash| hash == auth::hash_password(password))
+}
+
+fn field_error(field: &'static str, message: &str) -> HttpResponse {
+    let mut errors = validation::Errors::default();
+    errors.add(field, message.to_string());
+    errors.response()
+}
+
+#[put("/users/me/password")]
+pub async fn change_password(req: HttpRequest, db: web::Data<PgPool>, user: AuthUser, payload: web::Json<PasswordChange>) -> impl Responder {
+    let mut payload = payload.into_inner();
+    if let Err(errors) = payload.validate() {
+        return errors.response();
+    }
+    if !password_matches(&db, &user.id, &payload.current_password).await {
+        return field_error("current_password", "is incorrect");
+    }
+
+    let result = sqlx::query("UPDATE users SET password_hash = $2 WHERE id = $1")
+        .bind(&user.id)
+        .bind(auth::hash_password(&payload.new_password))
+        .execute(&**db)
+        .await;
+    if result.is_err() {
+        return HttpResponse::InternalServerError().finish();
+    }
+    // Whoever knew the old password may be holding a session, so end every one but this
+    let token = auth::bearer_token(&req).unwrap_or_default();
+    let revoked = sqlx::query("DELETE FROM sessions WHERE user_id = $1 AND token <> $2")
+        .bind(&user.id)
+        .bind(&token)
+        .execute(&**db)
+        .await
+        .map(|r| r.rows_affected())
+        .unwrap_or(0);
+
+    audit::record(&db, Some(&user), "account.password_change", "user", Some(&user.id), None, Some(serde_json::json!({
+        "sessions_revoked": revoked
+    }))).await;
+    HttpResponse::Ok().json(serde_json::json!({ "sessions_revoked": revoked }))
+}
+
+/// Permanently remove the user's comments, and their threads with every reply in them.
+async fn remove_posts(conn: &mut PgConnection, user_id: &str) -> Result<(), sqlx::Error> {
+    let comment_ids: Vec<String> = sqlx::query_scalar("SELECT id FROM comments WHERE user_id = $1")
+        .bind(user_id)
+        .fetch_all(&mut *conn)
+        .await?;
+    reactions::remove_for(conn, "comment", &comment_ids).await?;
+    sqlx::query("DELETE FROM comments WHERE user_id = $1")
+        .bind(user_id)
+        .execute(&mut *conn)
+        .await?;
+
+    let thread_ids: Vec<String> = sqlx::query_scalar("SELECT id FROM threads WHERE user_id = $1")
+        .bind(user_id)
+        .fetch_all(&mut *conn)
+        .await?;
+    for thread_id in &thread_ids {
+        crate::remove_thread(conn, thread_id).await?;
+    }
+    Ok(())
+}
+
+#[delete("/users/me")]
+pub async fn delete_account(db: web::Data<PgPool>, user: AuthUser, payload: web::Json<AccountDeletion>) -> impl Responder {
+    if !matches!(payload.posts.as_str(), "anonymize" | "delete") {
+        return field_error("posts", "must be 'anonymize' or 'delete'");
+    }
+    if !password_matches(&db, &user.id, &payload.password).await {
+        return field_error("password", "is incorrect");
+    }
+
+    // The full id, as a shortened one could be shared with another deleted account
+    let placeholder = format!("{}{}", validation::DELETED_USERNAME_PREFIX, user.id);
+    if let Err(e) = erase_account(&db, &user, &payload.posts, &placeholder).await {
+        log::error!("deleting account {} failed: {}", user.id, e);
+        return HttpResponse::InternalServerError().finish();
+    }
+    HttpResponse::Ok().finish()
+}
+
+/// Everything `delete_account` changes, in one transaction so a failure
+/// part way leaves the account as it was.
+async fn erase_account(db: &PgPool, user: &AuthUser, posts: &str, placeholder: &str) -> Result<(), sqlx::Error> {
+    let mut tx = db.begin().await?;
+    if posts == "delete" {
+        remove_posts(&mut tx, &user.id).await?;
+    }
+    for sql in DELETE_PERSONAL {
+        sqlx::query(sql).bind(&user.id).execute(&mut *tx).await?;
+    }
+    // An empty hash matches no password, so nobody can log in as the placeholder
+    sqlx::query(
+        r#"UPDATE users SET username = $2, password_hash = '', role = 'user', deleted_at = $3,
+                  display_name = NULL, bio = NULL, signature = NULL, location = NULL, avatar = NULL, avatar_hash = NULL,
+                  totp_enabled = FALSE, totp_secret = NULL, totp_last_step = NULL
+           WHERE id = $1"#
+    )
+    .bind(&user.id)
+    .bind(placeholder)
+    .bind(Utc::now())
+    .execute(&mut *tx)
+    .await?;
+
+    audit::record_in(
+        &mut tx,
+        Some(user),
+        "account.delete",
+        "user",
+        Some(&user.id),
+        Some(serde_json::json!({ "username": user.username })),
+        Some(serde_json::json!({ "username": placeholder, "posts": posts })),
+    )
+    .await?;
+    tx.commit().await
+}
+
+/// Rows of `sql` for `user_id` as a JSON array.
+async fn export_rows(db: &PgPool, sql: &str, user_id: &str) -> Result<Value, sqlx::Error> {
+    sqlx::query_scalar(&format!("SELECT COALESCE(json_agg(r), '[]'::json) FROM ({}) r", sql))
+        .bind(user_id)
+        .fetch_one(db)
+        .await
+}
+
+/// Everything stored about the logged-in user, as a JSON download.
+#[get("/users/me/export")]
+pub async fn export_account(db: web::Data<PgPool>, user: AuthUser) -> impl Responder {
+    let mut archive = serde_json::Map::new();
+    archive.insert("exported_at".to_string(), serde_json::json!(Utc::now()));
+    let account = export_rows(&db, EXPORT_ACCOUNT, &user.id).await;
+    match account {
+        Ok(Value::Array(mut rows)) if !rows.is_empty() => {
+            archive.insert("account".to_string(), rows.swap_remove(0));
+        }
+        _ => return HttpResponse::InternalServerError().finish(),
+    }
+    for (section, sql) in EXPORT_SECTIONS {
+        match export_rows(&db, sql, &user.id).await {
+            Ok(rows) => {
+                archive.insert(section.to_string(), rows);
+            }
+            Err(e) => {
+                log::error!("exporting {} for {} failed: {}", section, user.id, e);
+                return HttpResponse::InternalServerError().finish();
+            }
+        }
+    }
+
+    HttpResponse::Ok()
+        .insert_header((
+            header::CONTENT_DISPOSITION,
+            format!("attachment; filename=\"{}-export.json\"", user.username),
+        ))
+        .json(archive)
+}
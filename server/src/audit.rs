@@ -0,0 +1,170 @@
+// audit.rs
+use actix_web::{get, web, HttpResponse, Responder};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sqlx::postgres::PgArguments;
+use sqlx::query::Query;
+use sqlx::{PgConnection, PgPool, Postgres};
+use uuid::Uuid;
+
+use crate::auth::{Admin, AuthUser};
+
+// Most entries a single GET /admin/audit returns
+const MAX_ENTRIES: i64 = 500;
+
+pub async fn ensure_tables(pool: &PgPool) {
+    // No foreign keys: entries must outlive the users and content they mention
+    let _ = sqlx::query(
+        r#"CREATE TABLE IF NOT EXISTS audit_log (
+                id TEXT PRIMARY KEY,
+                actor_id TEXT,
+                actor TEXT NOT NULL,
+                action TEXT NOT NULL,
+                target_type TEXT NOT NULL,
+                target_id TEXT,
+                before JSONB,
+                after JSONB,
+                created_at TIMESTAMPTZ NOT NULL
+            );"#
+    )
+    .execute(pool)
+    .await;
+    let _ = sqlx::query("CREATE INDEX IF NOT EXISTS idx_audit_log_created ON audit_log(created_at)")
+        .execute(pool)
+        .await;
+    // Reject edits and deletions at the database level
+    let _ = sqlx::query(
+        r#"CREATE OR REPLACE FUNCTION audit_log_append_only() RETURNS trigger AS $$
+           BEGIN
+               RAISE EXCEPTION 'audit_log is append-only';
+           END;
+           $$ LANGUAGE plpgsql;"#
+    )
+    .execute(pool)
+    .await;
+    let _ = sqlx::query(
+        r#"DO $$ BEGIN
+               IF NOT EXISTS (SELECT 1 FROM pg_trigger WHERE tgname = 'audit_log_append_only') THEN
+                   CREATE TRIGGER audit_log_append_only BEFORE UPDATE OR DELETE ON audit_log
+                       FOR EACH ROW EXECUTE FUNCTION audit_log_append_only();
+               END IF;
+           END $$;"#
+    )
+    .execute(pool)
+    .await;
+}
+
+/// Record an administrative or moderation action. `actor` is `None` for
+/// actions run from the server console (e.g. `forum_server set-role`).
+pub async fn record(
+    db: &PgPool,
+    actor: Option<&AuthUser>,
+    action: &str,
+    target_type: &str,
+    target_id: Option<&str>,
+    before: Option<Value>,
+    after: Option<Value>,
+) {
+    let result = entry(actor, action, target_type, target_id, before, after).execute(db).await;
+    if let Err(e) = result {
+        log::error!("failed to write audit entry for {} on {}: {}", action, target_type, e);
+    }
+}
+
+/// `record` as part of the transaction `tx` that carries out the action, so
+/// the entry is only kept if the action is, and the action fails without it.
+pub async fn record_in(
+    tx: &mut PgConnection,
+    actor: Option<&AuthUser>,
+    action: &str,
+    target_type: &str,
+    target_id: Option<&str>,
+    before: Option<Value>,
+    after: Option<Value>,
+) -> Result<(), sqlx::Error> {
+    entry(actor, action, target_type, target_id, before, after).execute(tx).await.map(|_| ())
+}
+
+fn entry<'q>(
+    actor: Option<&'q AuthUser>,
+    action: &'q str,
+    target_type: &'q str,
+    target_id: Option<&'q str>,
+    before: Option<Value>,
+    after: Option<Value>,
+) -> Query<'q, Postgres, PgArguments> {
+    sqlx::query(
+        r#"INSERT INTO audit_log (id, actor_id, actor, action, target_type, target_id, before, after, created_at)
+           VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)"#
+    )
+    .bind(Uuid::new_v4().to_string())
+    .bind(actor.map(|a| a.id.clone()))
+    .bind(actor.map_or("console", |a| a.username.as_str()))
+    .bind(action)
+    .bind(target_type)
+    .bind(target_id)
+    .bind(before)
+    .bind(after)
+    .bind(Utc::now())
+}
+
+/// JSON snapshot of the row in `table` with the given id, for before/after records.
+pub async fn snapshot(db: &PgPool, table: &str, id: &str) -> Option<Value> {
+    sqlx::query_scalar::<_, Value>(&format!("SELECT to_jsonb(x) FROM {} x WHERE x.id = $1", table))
+        .bind(id)
+        .fetch_optional(db)
+        .await
+        .unwrap_or(None)
+}
+
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct AuditEntry {
+    id: String,
+    actor: String,
+    action: String,
+    target_type: String,
+    target_id: Option<String>,
+    before: Option<Value>,
+    after: Option<Value>,
+    created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AuditQuery {
+    actor: Option<String>,
+    action: Option<String>,
+    target_type: Option<String>,
+    target_id: Option<String>,
+    since: Option<DateTime<Utc>>,
+    until: Option<DateTime<Utc>>,
+    limit: Option<i64>,
+}
+
+#[get("/admin/audit")]
+pub async fn list_audit_log(db: web::Data<PgPool>, _admin: Admin, query: web::Query<AuditQuery>) -> impl Responder {
+    let rows = sqlx::query_as::<_, AuditEntry>(
+        r#"SELECT id, actor, action, target_type, target_id, before, after, created_at
+           FROM audit_log
+           WHERE ($1::TEXT IS NULL OR actor = $1)
+             AND ($2::TEXT IS NULL OR action = $2)
+             AND ($3::TEXT IS NULL OR target_type = $3)
+             AND ($4::TEXT IS NULL OR target_id = $4)
+             AND ($5::TIMESTAMPTZ IS NULL OR created_at >= $5)
+             AND ($6::TIMESTAMPTZ IS NULL OR created_at < $6)
+           ORDER BY created_at DESC
+           LIMIT $7"#
+    )
+    .bind(&query.actor)
+    .bind(&query.action)
+    .bind(&query.target_type)
+    .bind(&query.target_id)
+    .bind(query.since)
+    .bind(query.until)
+    .bind(query.limit.unwrap_or(100).clamp(1, MAX_ENTRIES))
+    .fetch_all(&**db)
+    .await
+    .unwrap_or_else(|_| vec![]);
+
+    HttpResponse::Ok().json(rows)
+}
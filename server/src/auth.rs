@@ -0,0 +1,202 @@
+// auth.rs
+use actix_web::{dev::Payload, error::InternalError, http::header, web, FromRequest, HttpRequest, HttpResponse};
+use chrono::{DateTime, Duration, Utc};
+use sha2::{Digest, Sha256};
+use sqlx::PgPool;
+use std::future::Future;
+use std::pin::Pin;
+use uuid::Uuid;
+
+/// The user behind the `Authorization: Bearer <token>` header of a request.
+///
+/// Use `AuthUser` for endpoints that require a login and `Option<AuthUser>`
+/// for endpoints that only personalise their response.
+#[derive(Debug, Clone)]
+pub struct AuthUser {
+    pub id: String,
+    pub username: String,
+    // "user", "moderator" or "admin"
+    pub role: String,
+}
+
+impl AuthUser {
+    pub fn is_moderator(&self) -> bool {
+        self.role == "moderator" || self.role == "admin"
+    }
+
+    pub fn is_admin(&self) -> bool {
+        self.role == "admin"
+    }
+}
+
+/// An `AuthUser` whose role allows moderation; rejects everyone else with 403.
+#[derive(Debug, Clone)]
+pub struct Moderator(pub AuthUser);
+
+/// An `AuthUser` with the admin role; rejects everyone else with 403.
+#[derive(Debug, Clone)]
+pub struct Admin(pub AuthUser);
+
+pub const ROLES: [&str; 3] = ["user", "moderator", "admin"];
+
+// Sessions last this long from login; after that the user logs in again
+const SESSION_LIFETIME_DAYS: i64 = 30;
+
+/// Sessions started before this have expired.
+fn session_cutoff() -> DateTime<Utc> {
+    Utc::now() - Duration::days(SESSION_LIFETIME_DAYS)
+}
+
+pub async fn ensure_tables(pool: &PgPool) {
+    let _ = sqlx::query("ALTER TABLE users ADD COLUMN IF NOT EXISTS role TEXT NOT NULL DEFAULT 'user'")
+        .execute(pool)
+        .await;
+    let _ = sqlx::query(
+        r#"CREATE TABLE IF NOT EXISTS sessions (
+                token TEXT PRIMARY KEY,
+                user_id TEXT NOT NULL,
+                created_at TIMESTAMPTZ NOT NULL,
+                FOREIGN KEY (user_id) REFERENCES users(id)
+            );"#
+    )
+    .execute(pool)
+    .await;
+}
+
+/// Hex SHA-256 of a password, as stored in `users.password_hash`.
+pub fn hash_password(password: &str) -> String {
+    format!("{:x}", Sha256::digest(password.as_bytes()))
+}
+
+/// Start a new session for `user_id` and return its bearer token. The user's
+/// expired sessions are cleared out on the way.
+pub async fn create_session(pool: &PgPool, user_id: &str) -> Result<String, sqlx::Error> {
+    sqlx::query("DELETE FROM sessions WHERE user_id = $1 AND created_at <= $2")
+        .bind(user_id)
+        .bind(session_cutoff())
+        .execute(pool)
+        .await?;
+    let token = format!("{}{}", Uuid::new_v4().simple(), Uuid::new_v4().simple());
+    sqlx::query(r#"INSERT INTO sessions (token, user_id, created_at) VALUES ($1, $2, $3)"#)
+        .bind(&token)
+        .bind(user_id)
+        .bind(Utc::now())
+        .execute(pool)
+        .await?;
+    Ok(token)
+}
+
+/// Start a session for a user who has just proven who they are, answering with
+/// the user and the new token, as `POST /auth/login` does.
+pub async fn session_response(pool: &PgPool, user_id: &str) -> HttpResponse {
+    let user: Option<(String, DateTime<Utc>, String)> = sqlx::query_as("SELECT username, created_at, role FROM users WHERE id = $1")
+        .bind(user_id)
+        .fetch_optional(pool)
+        .await
+        .unwrap_or(None);
+    let Some((username, created_at, role)) = user else {
+        return HttpResponse::NotFound().finish();
+    };
+    let token = match create_session(pool, user_id).await {
+        Ok(token) => token,
+        Err(_) => return HttpResponse::InternalServerError().json(serde_json::json!({
+            "error": "Could not start session"
+        })),
+    };
+    HttpResponse::Ok().json(serde_json::json!({
+        "id": user_id,
+        "username": username,
+        "created_at": created_at,
+        "role": role,
+        "token": token
+    }))
+}
+
+fn unauthorized(message: &'static str) -> actix_web::Error {
+    InternalError::from_response(
+        message,
+        HttpResponse::Unauthorized().json(serde_json::json!({ "error": message })),
+    )
+    .into()
+}
+
+fn forbidden(message: &'static str) -> actix_web::Error {
+    InternalError::from_response(
+        message,
+        HttpResponse::Forbidden().json(serde_json::json!({ "error": message })),
+    )
+    .into()
+}
+
+/// The session token from the request's `Authorization: Bearer` header.
+pub fn bearer_token(req: &HttpRequest) -> Option<String> {
+    req.headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+        .map(|v| v.trim().to_string())
+}
+
+impl FromRequest for AuthUser {
+    type Error = actix_web::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self, Self::Error>>>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        let db = req.app_data::<web::Data<PgPool>>().cloned();
+        let token = bearer_token(req);
+
+        Box::pin(async move {
+            let (Some(db), Some(token)) = (db, token) else {
+                return Err(unauthorized("Login required"));
+            };
+            let row: Option<(String, String, String)> = sqlx::query_as(
+                r#"SELECT u.id, u.username, u.role FROM sessions s JOIN users u ON s.user_id = u.id
+                   WHERE s.token = $1 AND s.created_at > $2"#
+            )
+            .bind(&token)
+            .bind(session_cutoff())
+            .fetch_optional(&**db)
+            .await
+            .unwrap_or(None);
+
+            match row {
+                Some((id, username, role)) => Ok(AuthUser { id, username, role }),
+                None => Err(unauthorized("Invalid or expired session")),
+            }
+        })
+    }
+}
+
+impl FromRequest for Moderator {
+    type Error = actix_web::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self, Self::Error>>>>;
+
+    fn from_request(req: &HttpRequest, payload: &mut Payload) -> Self::Future {
+        let user = AuthUser::from_request(req, payload);
+        Box::pin(async move {
+            let user = user.await?;
+            if user.is_moderator() {
+                Ok(Moderator(user))
+            } else {
+                Err(forbidden("Moderator access required"))
+            }
+        })
+    }
+}
+
+impl FromRequest for Admin {
+    type Error = actix_web::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self, Self::Error>>>>;
+
+    fn from_request(req: &HttpRequest, payload: &mut Payload) -> Self::Future {
+        let user = AuthUser::from_request(req, payload);
+        Box::pin(async move {
+            let user = user.await?;
+            if user.is_admin() {
+                Ok(Admin(user))
+            } else {
+                Err(forbidden("Admin access required"))
+            }
+        })
+    }
+}
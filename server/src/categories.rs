@@ -0,0 +1,462 @@
+// categories.rs
+use actix_web::{get, post, web, HttpResponse, Responder};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::{PgConnection, PgPool};
+use uuid::Uuid;
+
+use crate::audit;
+use crate::auth::Admin;
+use crate::validation::{self, Validate};
+
+pub async fn ensure_tables(pool: &PgPool) {
+    // Order among siblings, lowest first
+    let _ = sqlx::query("ALTER TABLE categories ADD COLUMN IF NOT EXISTS position INTEGER NOT NULL DEFAULT 0")
+        .execute(pool)
+        .await;
+    // Categories nest at most one level deep
+    let _ = sqlx::query("ALTER TABLE categories ADD COLUMN IF NOT EXISTS parent_id TEXT REFERENCES categories(id)")
+        .execute(pool)
+        .await;
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, sqlx::FromRow)]
+pub struct Category {
+    id: String,
+    name: String,
+    description: Option<String>,
+    created_at: DateTime<Utc>,
+    parent_id: Option<String>,
+    position: i32,
+    thread_count: i64,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct NewCategory {
+    #[serde(default)]
+    name: String,
+    description: Option<String>,
+    parent_id: Option<String>,
+}
+
+impl Validate for NewCategory {
+    fn validate(&mut self) -> Result<(), validation::Errors> {
+        let mut errors = validation::Errors::default();
+        self.name = errors.check(&validation::CATEGORY_NAME, &self.name);
+        self.description = errors.check_optional(&validation::CATEGORY_DESCRIPTION, self.description.as_deref());
+        errors.finish()
+    }
+}
+
+/// Fields left out are unchanged. An empty `description` clears it and an
+/// empty `parent_id` makes the category top-level.
+#[derive(Debug, Deserialize)]
+pub struct CategoryUpdate {
+    name: Option<String>,
+    description: Option<String>,
+    parent_id: Option<String>,
+}
+
+impl Validate for CategoryUpdate {
+    fn validate(&mut self) -> Result<(), validation::Errors> {
+        let mut errors = validation::Errors::default();
+        if let Some(name) = &self.name {
+            self.name = Some(errors.check(&validation::CATEGORY_NAME, name));
+        }
+        if let Some(description) = &self.description {
+            self.description = Some(errors.check(&validation::CATEGORY_DESCRIPTION, description));
+        }
+        errors.finish()
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CategoryOrder {
+    // Sibling categories in their new order
+    ids: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DeleteCategoryQuery {
+    // Category to move the threads to; they become uncategorized without one
+    move_to: Option<String>,
+}
+
+fn bad_request(message: &str) -> HttpResponse {
+    HttpResponse::BadRequest().json(serde_json::json!({ "error": message }))
+}
+
+/// Why `category_id` cannot be placed under `parent_id`, if it cannot.
+async fn parent_problem(conn: &mut PgConnection, category_id: Option<&str>, parent_id: &str) -> Option<&'static str> {
+    if category_id == Some(parent_id) {
+        return Some("A category cannot be its own parent");
+    }
+    let parent: Option<(Option<String>,)> = sqlx::query_as("SELECT parent_id FROM categories WHERE id = $1")
+        .bind(parent_id)
+        .fetch_optional(&mut *conn)
+        .await
+        .unwrap_or(None);
+    match parent {
+        None => Some("Parent category not found"),
+        Some((Some(_),)) => Some("Subcategories cannot have subcategories of their own"),
+        Some((None,)) => {
+            let has_children = match category_id {
+                Some(id) => sqlx::query("SELECT 1 FROM categories WHERE parent_id = $1 LIMIT 1")
+                    .bind(id)
+                    .fetch_optional(conn)
+                    .await
+                    .map(|row| row.is_some())
+                    .unwrap_or(false),
+                None => false,
+            };
+            has_children.then_some("A category with subcategories cannot become a subcategory")
+        }
+    }
+}
+
+/// Why the threads of `category_id` cannot move to `move_to` when it is
+/// deleted, if they cannot. The target must survive the delete: not this
+/// category or one of its subcategories.
+async fn move_target_problem(conn: &mut PgConnection, category_id: &str, move_to: &str) -> Option<&'static str> {
+    let target_parent: Option<Option<String>> = sqlx::query_scalar("SELECT parent_id FROM categories WHERE id = $1")
+        .bind(move_to)
+        .fetch_optional(conn)
+        .await
+        .unwrap_or(None);
+    let survives = move_to != category_id && target_parent.is_some_and(|parent| parent.as_deref() != Some(category_id));
+    (!survives).then_some("move_to must be another existing category")
+}
+
+#[get("/categories")]
+pub async fn list_categories(db: web::Data<PgPool>) -> impl Responder {
+    // Parents before their children, each level in position order
+    let rows = sqlx::query_as::<_, Category>(
+        r#"SELECT c.id, c.name, c.description, c.created_at, c.parent_id, c.position,
+                  (SELECT COUNT(*) FROM threads t WHERE t.category_id = c.id AND t.deleted_at IS NULL) as thread_count
+           FROM categories c
+           LEFT JOIN categories p ON p.id = c.parent_id
+           ORDER BY COALESCE(p.position, c.position), COALESCE(p.name, c.name), c.parent_id IS NOT NULL,
+                    c.position, c.name"#
+    )
+    .fetch_all(&**db)
+    .await
+    .unwrap_or_else(|_| vec![]);
+
+    HttpResponse::Ok().json(rows)
+}
+
+#[post("/categories")]
+pub async fn create_category(db: web::Data<PgPool>, admin: Admin, payload: web::Json<NewCategory>) -> impl Responder {
+    let mut payload = payload.into_inner();
+    if let Err(errors) = payload.validate() {
+        return errors.response();
+    }
+    let parent_id = payload.parent_id.filter(|p| !p.is_empty());
+    if let Some(parent_id) = &parent_id {
+        let Ok(mut conn) = db.acquire().await else {
+            return HttpResponse::InternalServerError().finish();
+        };
+        if let Some(problem) = parent_problem(&mut conn, None, parent_id).await {
+            return bad_request(problem);
+        }
+    }
+    let id = Uuid::new_v4().to_string();
+
+    // New categories go last among their siblings
+    let result = sqlx::query(
+        r#"INSERT INTO categories (id, name, description, created_at, parent_id, position)
+           VALUES ($1, $2, $3, $4, $5,
+                   (SELECT COALESCE(MAX(position) + 1, 0) FROM categories WHERE parent_id IS NOT DISTINCT FROM $5))"#
+    )
+    .bind(&id)
+    .bind(&payload.name)
+    .bind(&payload.description)
+    .bind(Utc::now())
+    .bind(&parent_id)
+    .execute(&**db)
+    .await;
+    if result.is_err() {
+        return HttpResponse::InternalServerError().finish();
+    }
+
+    let after = audit::snapshot(&db, "categories", &id).await;
+    audit::record(&db, Some(&admin.0), "category.create", "category", Some(&id), None, after).await;
+    HttpResponse::Created().json(serde_json::json!({ "id": id }))
+}
+
+#[actix_web::patch("/categories/{id}")]
+pub async fn update_category(
+    db: web::Data<PgPool>,
+    admin: Admin,
+    path: web::Path<String>,
+    payload: web::Json<CategoryUpdate>,
+) -> impl Responder {
+    let category_id = path.into_inner();
+    let mut payload = payload.into_inner();
+    if let Err(errors) = payload.validate() {
+        return errors.response();
+    }
+    let Some(before) = audit::snapshot(&db, "categories", &category_id).await else {
+        return HttpResponse::NotFound().finish();
+    };
+    if let Some(parent_id) = payload.parent_id.as_deref().filter(|p| !p.is_empty()) {
+        let Ok(mut conn) = db.acquire().await else {
+            return HttpResponse::InternalServerError().finish();
+        };
+        if let Some(problem) = parent_problem(&mut conn, Some(&category_id), parent_id).await {
+            return bad_request(problem);
+        }
+    }
+
+    // A category moving to another parent goes last among its new siblings
+    let result = sqlx::query(
+        r#"UPDATE categories SET
+               name = COALESCE($2, name),
+               description = CASE WHEN $3::TEXT IS NULL THEN description ELSE NULLIF($3, '') END,
+               parent_id = CASE WHEN $4::TEXT IS NULL THEN parent_id ELSE NULLIF($4, '') END,
+               position = CASE
+                   WHEN $4::TEXT IS NULL OR NULLIF($4, '') IS NOT DISTINCT FROM parent_id THEN position
+                   ELSE (SELECT COALESCE(MAX(s.position) + 1, 0) FROM categories s
+                         WHERE s.parent_id IS NOT DISTINCT FROM NULLIF($4, ''))
+               END
+           WHERE id = $1"#
+    )
+    .bind(&category_id)
+    .bind(&payload.name)
+    .bind(&payload.description)
+    .bind(&payload.parent_id)
+    .execute(&**db)
+    .await;
+    if result.is_err() {
+        return HttpResponse::InternalServerError().finish();
+    }
+
+    let after = audit::snapshot(&db, "categories", &category_id).await;
+    audit::record(&db, Some(&admin.0), "category.update", "category", Some(&category_id), Some(before), after).await;
+    HttpResponse::Ok().finish()
+}
+
+#[post("/categories/reorder")]
+pub async fn reorder_categories(db: web::Data<PgPool>, admin: Admin, payload: web::Json<CategoryOrder>) -> impl Responder {
+    // The ids must be exactly the children of one parent, each once
+    let parent: Option<Option<String>> = match payload.ids.first() {
+        Some(first) => sqlx::query_scalar("SELECT parent_id FROM categories WHERE id = $1")
+            .bind(first)
+            .fetch_optional(&**db)
+            .await
+            .unwrap_or(None),
+        None => None,
+    };
+    let Some(parent) = parent else {
+        return bad_request("ids must be all the categories with the same parent");
+    };
+    let mut siblings: Vec<String> = sqlx::query_scalar("SELECT id FROM categories WHERE parent_id IS NOT DISTINCT FROM $1")
+        .bind(&parent)
+        .fetch_all(&**db)
+        .await
+        .unwrap_or_default();
+    let mut ids = payload.ids.clone();
+    siblings.sort();
+    ids.sort();
+    if ids != siblings {
+        return bad_request("ids must be all the categories with the same parent");
+    }
+
+    let before: Option<serde_json::Value> = sqlx::query_scalar(
+        "SELECT jsonb_object_agg(id, position) FROM categories WHERE id = ANY($1)"
+    )
+    .bind(&payload.ids)
+    .fetch_one(&**db)
+    .await
+    .unwrap_or(None);
+    // One statement, so a failure leaves the old order intact
+    let result = sqlx::query(
+        r#"UPDATE categories c SET position = (o.n - 1)::INT
+           FROM UNNEST($1::TEXT[]) WITH ORDINALITY AS o(id, n)
+           WHERE c.id = o.id"#
+    )
+    .bind(&payload.ids)
+    .execute(&**db)
+    .await;
+    if result.is_err() {
+        return HttpResponse::InternalServerError().finish();
+    }
+
+    audit::record(&db, Some(&admin.0), "category.reorder", "category", None, before,
+        Some(serde_json::json!({ "order": payload.ids }))).await;
+    HttpResponse::Ok().finish()
+}
+
+#[actix_web::delete("/categories/{id}")]
+pub async fn delete_category(
+    db: web::Data<PgPool>,
+    admin: Admin,
+    path: web::Path<String>,
+    query: web::Query<DeleteCategoryQuery>,
+) -> impl Responder {
+    let category_id = path.into_inner();
+    let Some(before) = audit::snapshot(&db, "categories", &category_id).await else {
+        return HttpResponse::NotFound().finish();
+    };
+    let move_to = query.move_to.as_deref().filter(|m| !m.is_empty());
+    if let Some(move_to) = move_to {
+        let Ok(mut conn) = db.acquire().await else {
+            return HttpResponse::InternalServerError().finish();
+        };
+        if let Some(problem) = move_target_problem(&mut conn, &category_id, move_to).await {
+            return bad_request(problem);
+        }
+    }
+
+    let Ok(moved) = delete_and_move(&db, &category_id, move_to).await else {
+        return HttpResponse::InternalServerError().finish();
+    };
+
+    audit::record(&db, Some(&admin.0), "category.delete", "category", Some(&category_id), Some(before),
+        Some(serde_json::json!({ "threads_moved": moved, "moved_to": move_to }))).await;
+    HttpResponse::Ok().finish()
+}
+
+/// Move the category's threads to `move_to` and its subcategories to the top
+/// level, then delete it, all or nothing. Returns how many threads moved.
+async fn delete_and_move(db: &PgPool, category_id: &str, move_to: Option<&str>) -> Result<u64, sqlx::Error> {
+    let mut tx = db.begin().await?;
+    let moved = move_and_delete(&mut tx, category_id, move_to).await?;
+    tx.commit().await?;
+    Ok(moved)
+}
+
+async fn move_and_delete(conn: &mut PgConnection, category_id: &str, move_to: Option<&str>) -> Result<u64, sqlx::Error> {
+    let moved = sqlx::query("UPDATE threads SET category_id = $2 WHERE category_id = $1")
+        .bind(category_id)
+        .bind(move_to)
+        .execute(&mut *conn)
+        .await?
+        .rows_affected();
+    // Subcategories move up to the top level, after the existing top-level categories
+    sqlx::query(
+        r#"UPDATE categories SET parent_id = NULL,
+               position = position + (SELECT COALESCE(MAX(position) + 1, 0) FROM categories WHERE parent_id IS NULL)
+           WHERE parent_id = $1"#
+    )
+    .bind(category_id)
+    .execute(&mut *conn)
+    .await?;
+    sqlx::query("DELETE FROM categories WHERE id = $1")
+        .bind(category_id)
+        .execute(conn)
+        .await?;
+    Ok(moved)
+}
+
+#[actix_web::delete("/categories")]
+pub async fn delete_all_categories(db: web::Data<PgPool>, admin: Admin) -> impl Responder {
+    let before: Option<serde_json::Value> = sqlx::query_scalar("SELECT jsonb_agg(to_jsonb(c)) FROM categories c")
+        .fetch_one(&**db)
+        .await
+        .unwrap_or(None);
+    if delete_all(&db).await.is_err() {
+        return HttpResponse::InternalServerError().finish();
+    }
+    audit::record(&db, Some(&admin.0), "categories.delete_all", "category", None, before, None).await;
+    HttpResponse::Ok().finish()
+}
+
+/// Uncategorize every thread and delete all categories, all or nothing.
+async fn delete_all(db: &PgPool) -> Result<(), sqlx::Error> {
+    let mut tx = db.begin().await?;
+    sqlx::query("UPDATE threads SET category_id = NULL").execute(&mut *tx).await?;
+    sqlx::query("UPDATE categories SET parent_id = NULL").execute(&mut *tx).await?;
+    sqlx::query("DELETE FROM categories").execute(&mut *tx).await?;
+    tx.commit().await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::scratch_tables;
+
+    // "top" has the subcategories "sub-a" and "sub-b"; "other" stands alone
+    const SCHEMA: &str = r#"
+        CREATE TEMP TABLE categories (id TEXT PRIMARY KEY, parent_id TEXT,
+                                      position INTEGER NOT NULL DEFAULT 0) ON COMMIT DROP;
+        CREATE TEMP TABLE threads (id TEXT PRIMARY KEY, category_id TEXT) ON COMMIT DROP;
+        INSERT INTO categories (id, parent_id, position) VALUES
+            ('top', NULL, 0), ('other', NULL, 1), ('sub-a', 'top', 0), ('sub-b', 'top', 1);
+        INSERT INTO threads (id, category_id) VALUES ('t1', 'top'), ('t2', 'top'), ('t3', 'sub-a');
+    "#;
+
+    #[actix_web::test]
+    async fn parents_must_exist_and_nesting_stops_at_one_level() {
+        let Some(mut tx) = scratch_tables(SCHEMA).await else { return };
+
+        assert_eq!(parent_problem(&mut tx, None, "top").await, None);
+        assert_eq!(parent_problem(&mut tx, Some("other"), "top").await, None);
+        assert_eq!(parent_problem(&mut tx, None, "missing").await, Some("Parent category not found"));
+        assert_eq!(
+            parent_problem(&mut tx, None, "sub-a").await,
+            Some("Subcategories cannot have subcategories of their own")
+        );
+    }
+
+    #[actix_web::test]
+    async fn categories_cannot_end_up_under_themselves() {
+        let Some(mut tx) = scratch_tables(SCHEMA).await else { return };
+
+        assert_eq!(parent_problem(&mut tx, Some("top"), "top").await, Some("A category cannot be its own parent"));
+        // "top" under its own child "sub-a" would be a cycle; "sub-a" already has a parent
+        assert!(parent_problem(&mut tx, Some("top"), "sub-a").await.is_some());
+        assert_eq!(
+            parent_problem(&mut tx, Some("top"), "other").await,
+            Some("A category with subcategories cannot become a subcategory")
+        );
+    }
+
+    #[actix_web::test]
+    async fn deleted_categories_move_threads_only_to_a_survivor() {
+        let Some(mut tx) = scratch_tables(SCHEMA).await else { return };
+
+        assert_eq!(move_target_problem(&mut tx, "top", "other").await, None);
+        assert_eq!(move_target_problem(&mut tx, "sub-a", "top").await, None);
+        for target in ["top", "sub-a", "missing"] {
+            assert_eq!(
+                move_target_problem(&mut tx, "top", target).await,
+                Some("move_to must be another existing category")
+            );
+        }
+    }
+
+    #[actix_web::test]
+    async fn deleting_moves_threads_and_promotes_subcategories() {
+        let Some(mut tx) = scratch_tables(SCHEMA).await else { return };
+
+        assert_eq!(move_and_delete(&mut tx, "top", Some("other")).await.unwrap(), 2);
+        let threads: Vec<(String, Option<String>)> = sqlx::query_as("SELECT id, category_id FROM threads ORDER BY id")
+            .fetch_all(&mut *tx)
+            .await
+            .unwrap();
+        assert_eq!(threads, [
+            ("t1".to_string(), Some("other".to_string())),
+            ("t2".to_string(), Some("other".to_string())),
+            ("t3".to_string(), Some("sub-a".to_string())),
+        ]);
+        // The subcategories follow the remaining top-level category, in their old order
+        let categories: Vec<(String, Option<String>, i32)> =
+            sqlx::query_as("SELECT id, parent_id, position FROM categories ORDER BY position")
+                .fetch_all(&mut *tx)
+                .await
+                .unwrap();
+        assert_eq!(categories, [
+            ("other".to_string(), None, 1),
+            ("sub-a".to_string(), None, 2),
+            ("sub-b".to_string(), None, 3),
+        ]);
+
+        assert_eq!(move_and_delete(&mut tx, "sub-a", None).await.unwrap(), 1);
+        let uncategorized: Option<String> = sqlx::query_scalar("SELECT category_id FROM threads WHERE id = 't3'")
+            .fetch_one(&mut *tx)
+            .await
+            .unwrap();
+        assert_eq!(uncategorized, None);
+    }
+}
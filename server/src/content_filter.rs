@@ -0,0 +1,452 @@
+// content_filter.rs
+use actix_web::{get, post, web, HttpResponse, Responder};
+use chrono::{DateTime, Duration, Utc};
+use regex::{Regex, RegexBuilder};
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use std::sync::{Arc, OnceLock, RwLock};
+use uuid::Uuid;
+
+use crate::audit;
+use crate::auth::{Admin, Moderator};
+use crate::notifications;
+use crate::trash;
+
+// "word": a banned word, matched case-insensitively as a whole word
+// "regex": a regular expression, also case-insensitive unless it starts with (?-i)
+// "max_links": the most links a post may contain
+// "duplicate": minutes within which a user may not post the same text twice
+pub const RULE_KINDS: [&str; 4] = ["word", "regex", "max_links", "duplicate"];
+// "mask" only applies to word and regex rules
+pub const RULE_ACTIONS: [&str; 3] = ["reject", "hold", "mask"];
+
+pub async fn ensure_tables(pool: &PgPool) {
+    let _ = sqlx::query(
+        r#"CREATE TABLE IF NOT EXISTS filter_rules (
+                id TEXT PRIMARY KEY,
+                kind TEXT NOT NULL,
+                value TEXT NOT NULL,
+                action TEXT NOT NULL,
+                created_by TEXT,
+                created_at TIMESTAMPTZ NOT NULL
+            );"#
+    )
+    .execute(pool)
+    .await;
+    // Held posts are only visible to their author until a moderator approves them
+    for table in ["threads", "comments"] {
+        let _ = sqlx::query(&format!("ALTER TABLE {} ADD COLUMN IF NOT EXISTS held BOOLEAN NOT NULL DEFAULT FALSE", table))
+            .execute(pool)
+            .await;
+    }
+}
+
+#[derive(Debug, Clone, Serialize, sqlx::FromRow)]
+pub struct FilterRule {
+    id: String,
+    kind: String,
+    value: String,
+    action: String,
+    created_at: DateTime<Utc>,
+}
+
+/// What to do with a post after running it through the filter.
+pub enum Verdict {
+    Reject(String),
+    // Accept the (possibly masked) text; `held` posts wait for a moderator
+    Accept { texts: Vec<String>, held: bool },
+}
+
+/// Regex matching the rule's text, for word and regex rules.
+fn rule_pattern(kind: &str, value: &str) -> Result<Regex, regex::Error> {
+    let pattern = match kind {
+        // `\b` next to a symbol would demand a word character beside it, so
+        // only edges that are word characters get a boundary ("c++", "@admin")
+        "word" => {
+            let is_word = |c: Option<char>| c.is_some_and(|c| c.is_alphanumeric() || c == '_');
+            let start = if is_word(value.chars().next()) { r"\b" } else { "" };
+            let end = if is_word(value.chars().last()) { r"\b" } else { "" };
+            format!("{}{}{}", start, regex::escape(value), end)
+        }
+        _ => value.to_string(),
+    };
+    RegexBuilder::new(&pattern).case_insensitive(true).build()
+}
+
+/// Replace every match of `pattern` with as many asterisks.
+fn mask(pattern: &Regex, text: &str) -> String {
+    pattern
+        .replace_all(text, |caps: &regex::Captures| "*".repeat(caps[0].chars().count()))
+        .into_owned()
+}
+
+/// A rule with its pattern compiled, if it has one.
+struct CompiledRule {
+    rule: FilterRule,
+    pattern: Option<Regex>,
+}
+
+// Rules with their patterns, so posts don't recompile them; rebuilt when rules change
+static RULES: RwLock<Option<Arc<Vec<CompiledRule>>>> = RwLock::new(None);
+
+/// Load and compile the rules, replacing the cached set. Nothing is cached
+/// when the query fails, so the next post tries again.
+async fn reload_rules(db: &PgPool) -> Arc<Vec<CompiledRule>> {
+    let result = sqlx::query_as::<_, FilterRule>(
+        "SELECT id, kind, value, action, created_at FROM filter_rules ORDER BY created_at"
+    )
+    .fetch_all(db)
+    .await;
+    let Ok(rules) = result else {
+        *RULES.write().unwrap() = None;
+        return Arc::default();
+    };
+    let compiled: Arc<Vec<CompiledRule>> = Arc::new(
+        rules
+            .into_iter()
+            .map(|rule| {
+                let pattern = match rule.kind.as_str() {
+                    "word" | "regex" => rule_pattern(&rule.kind, &rule.value).ok(),
+                    _ => None,
+                };
+                CompiledRule { rule, pattern }
+            })
+            .collect(),
+    );
+    *RULES.write().unwrap() = Some(compiled.clone());
+    compiled
+}
+
+async fn rules(db: &PgPool) -> Arc<Vec<CompiledRule>> {
+    let cached = RULES.read().unwrap().clone();
+    match cached {
+        Some(rules) => rules,
+        None => reload_rules(db).await,
+    }
+}
+
+/// Links in `text`, counted by where they start rather than by word, so links
+/// run together with punctuation still count one each. A `www.` right after
+/// the scheme is part of the same link.
+fn count_links(text: &str) -> usize {
+    static LINK: OnceLock<Regex> = OnceLock::new();
+    let link = LINK.get_or_init(|| Regex::new(r"(?i)\b(?:https?://(?:www\.)?|www\.)").unwrap());
+    link.find_iter(text).count()
+}
+
+/// Lowercased text with runs of whitespace collapsed, for duplicate detection.
+fn normalize(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ").to_lowercase()
+}
+
+/// Whether `user_id` posted the same text as `content` within the last `minutes`.
+async fn is_duplicate(db: &PgPool, user_id: &str, content: &str, minutes: i64) -> bool {
+    let since = Utc::now() - Duration::minutes(minutes);
+    let recent: Vec<String> = sqlx::query_scalar(
+        r#"SELECT content FROM threads WHERE user_id = $1 AND created_at > $2
+           UNION ALL
+           SELECT content FROM comments WHERE user_id = $1 AND created_at > $2"#
+    )
+    .bind(user_id)
+    .bind(since)
+    .fetch_all(db)
+    .await
+    .unwrap_or_default();
+    let content = normalize(content);
+    recent.iter().any(|r| normalize(r) == content)
+}
+
+/// Run the texts of a new post (title and content for threads, content for
+/// comments) through every rule. Rejection wins over holding; masking applies
+/// on top of either of the others. `content` is the last of `texts`.
+pub async fn check(db: &PgPool, user_id: &str, texts: &[&str]) -> Verdict {
+    let rules = rules(db).await;
+
+    let mut texts: Vec<String> = texts.iter().map(|t| t.to_string()).collect();
+    let content = texts.last().cloned().unwrap_or_default();
+    let mut held = false;
+    for CompiledRule { rule, pattern } in rules.iter() {
+        let matched = match rule.kind.as_str() {
+            "word" | "regex" => {
+                let Some(pattern) = pattern else {
+                    continue;
+                };
+                if rule.action == "mask" {
+                    for text in texts.iter_mut() {
+                        *text = mask(pattern, text);
+                    }
+                    continue;
+                }
+                texts.iter().any(|t| pattern.is_match(t))
+            }
+            "max_links" => rule.value.parse::<usize>().is_ok_and(|max| count_links(&content) > max),
+            "duplicate" => match rule.value.parse::<i64>() {
+                Ok(minutes) => is_duplicate(db, user_id, &content, minutes).await,
+                Err(_) => false,
+            },
+            _ => false,
+        };
+        if !matched {
+            continue;
+        }
+        match rule.action.as_str() {
+            "reject" => {
+                let reason = match rule.kind.as_str() {
+                    "max_links" => format!("Posts may contain at most {} links", rule.value),
+                    "duplicate" => "You already posted this recently".to_string(),
+                    _ => "Your post contains blocked content".to_string(),
+                };
+                return Verdict::Reject(reason);
+            }
+            _ => held = true,
+        }
+    }
+    Verdict::Accept { texts, held }
+}
+
+#[get("/admin/filters")]
+pub async fn list_rules(db: web::Data<PgPool>, _admin: Admin) -> impl Responder {
+    let rows = sqlx::query_as::<_, FilterRule>(
+        "SELECT id, kind, value, action, created_at FROM filter_rules ORDER BY created_at"
+    )
+    .fetch_all(&**db)
+    .await
+    .unwrap_or_else(|_| vec![]);
+    HttpResponse::Ok().json(rows)
+}
+
+#[derive(Debug, Deserialize)]
+pub struct NewRule {
+    kind: String,
+    value: String,
+    action: String,
+}
+
+#[post("/admin/filters")]
+pub async fn create_rule(db: web::Data<PgPool>, admin: Admin, payload: web::Json<NewRule>) -> impl Responder {
+    let bad_request = |message: String| HttpResponse::BadRequest().json(serde_json::json!({ "error": message }));
+    if !RULE_KINDS.contains(&payload.kind.as_str()) {
+        return bad_request(format!("kind must be one of: {}", RULE_KINDS.join(", ")));
+    }
+    if !RULE_ACTIONS.contains(&payload.action.as_str()) {
+        return bad_request(format!("action must be one of: {}", RULE_ACTIONS.join(", ")));
+    }
+    let value = payload.value.trim();
+    let valid = match payload.kind.as_str() {
+        "word" => !value.is_empty(),
+        "regex" => rule_pattern("regex", value).is_ok(),
+        "max_links" => value.parse::<usize>().is_ok(),
+        _ => value.parse::<i64>().is_ok_and(|minutes| minutes > 0),
+    };
+    if !valid {
+        return bad_request(match payload.kind.as_str() {
+            "word" => "value must not be empty".to_string(),
+            "regex" => "value is not a valid regular expression".to_string(),
+            "max_links" => "value must be a number of links".to_string(),
+            _ => "value must be a number of minutes".to_string(),
+        });
+    }
+    if payload.action == "mask" && !["word", "regex"].contains(&payload.kind.as_str()) {
+        return bad_request("Only word and regex rules can mask".to_string());
+    }
+
+    let rule = FilterRule {
+        id: Uuid::new_v4().to_string(),
+        kind: payload.kind.clone(),
+        value: value.to_string(),
+        action: payload.action.clone(),
+        created_at: Utc::now(),
+    };
+    let result = sqlx::query(
+        r#"INSERT INTO filter_rules (id, kind, value, action, created_by, created_at)
+           VALUES ($1, $2, $3, $4, $5, $6)"#
+    )
+    .bind(&rule.id)
+    .bind(&rule.kind)
+    .bind(&rule.value)
+    .bind(&rule.action)
+    .bind(&admin.0.id)
+    .bind(rule.created_at)
+    .execute(&**db)
+    .await;
+    if result.is_err() {
+        return HttpResponse::InternalServerError().finish();
+    }
+    reload_rules(&db).await;
+
+    audit::record(&db, Some(&admin.0), "filter.create", "filter_rule", Some(&rule.id), None, serde_json::to_value(&rule).ok()).await;
+    HttpResponse::Created().json(rule)
+}
+
+#[actix_web::delete("/admin/filters/{id}")]
+pub async fn delete_rule(db: web::Data<PgPool>, admin: Admin, path: web::Path<String>) -> impl Responder {
+    let rule_id = path.into_inner();
+    let before = audit::snapshot(&db, "filter_rules", &rule_id).await;
+    let result = sqlx::query("DELETE FROM filter_rules WHERE id = $1")
+        .bind(&rule_id)
+        .execute(&**db)
+        .await;
+
+    match result {
+        Ok(r) if r.rows_affected() > 0 => {
+            reload_rules(&db).await;
+            audit::record(&db, Some(&admin.0), "filter.delete", "filter_rule", Some(&rule_id), before, None).await;
+            HttpResponse::Ok().finish()
+        }
+        Ok(_) => HttpResponse::NotFound().finish(),
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
+
+/// A post waiting for moderator approval.
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct HeldPost {
+    target_type: String,
+    id: String,
+    thread_id: String,
+    thread_title: String,
+    author: String,
+    content: String,
+    created_at: DateTime<Utc>,
+}
+
+#[get("/moderation/held")]
+pub async fn list_held(db: web::Data<PgPool>, _moderator: Moderator) -> impl Responder {
+    let rows = sqlx::query_as::<_, HeldPost>(
+        r#"SELECT 'thread' as target_type, t.id, t.id as thread_id, t.title as thread_title,
+                  u.username as author, t.content, t.created_at
+           FROM threads t JOIN users u ON u.id = t.user_id
+           WHERE t.held AND t.deleted_at IS NULL
+           UNION ALL
+           SELECT 'comment', c.id, c.thread_id, t.title, u.username, c.content, c.created_at
+           FROM comments c JOIN users u ON u.id = c.user_id
+           JOIN threads t ON t.id = c.thread_id
+           WHERE c.held AND c.deleted_at IS NULL
+           ORDER BY created_at ASC"#
+    )
+    .fetch_all(&**db)
+    .await
+    .unwrap_or_else(|_| vec![]);
+    HttpResponse::Ok().json(rows)
+}
+
+#[derive(Debug, Deserialize)]
+pub struct HeldDecision {
+    // "approve" or "reject"
+    action: String,
+}
+
+#[post("/moderation/held/{target_type}/{id}")]
+pub async fn decide_held(
+    db: web::Data<PgPool>,
+    moderator: Moderator,
+    path: web::Path<(String, String)>,
+    payload: web::Json<HeldDecision>,
+) -> impl Responder {
+    let (target_type, id) = path.into_inner();
+    let table = match target_type.as_str() {
+        "thread" => "threads",
+        "comment" => "comments",
+        _ => return HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "target_type must be 'thread' or 'comment'"
+        })),
+    };
+    let before = audit::snapshot(&db, table, &id).await;
+
+    // Rejected posts go to the trash like any other deleted content
+    let result = match payload.action.as_str() {
+        "approve" => sqlx::query(&format!("UPDATE {} SET held = FALSE WHERE id = $1 AND held", table))
+            .bind(&id)
+            .execute(&**db)
+            .await
+            .map(|r| r.rows_affected()),
+        "reject" => trash::soft_delete(&db, &target_type, &id, &moderator.0.id).await,
+        _ => return HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "action must be 'approve' or 'reject'"
+        })),
+    };
+
+    match result {
+        Ok(n) if n > 0 => {
+            if payload.action == "approve" {
+                notifications::notify_post(&db, &target_type, &id).await;
+            }
+            let after = audit::snapshot(&db, table, &id).await;
+            audit::record(&db, Some(&moderator.0), &format!("held.{}", payload.action), &target_type, Some(&id), before, after).await;
+            HttpResponse::Ok().finish()
+        }
+        Ok(_) => HttpResponse::NotFound().finish(),
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn word(value: &str) -> Regex {
+        rule_pattern("word", value).unwrap()
+    }
+
+    #[test]
+    fn words_match_whole_words_only() {
+        let pattern = word("cat");
+        assert!(pattern.is_match("the cat sat"));
+        assert!(pattern.is_match("Cat!"));
+        assert!(pattern.is_match("cat"));
+        assert!(!pattern.is_match("concatenate"));
+        assert!(!pattern.is_match("cats"));
+    }
+
+    #[test]
+    fn words_with_symbol_edges_still_match() {
+        let pattern = word("c++");
+        assert!(pattern.is_match("I write C++ daily"));
+        assert!(pattern.is_match("c++."));
+        assert!(!pattern.is_match("abc++"));
+
+        let pattern = word("@admin");
+        assert!(pattern.is_match("hey @admin, look"));
+        assert!(pattern.is_match("hey@admin"));
+        assert!(!pattern.is_match("@administrator"));
+    }
+
+    #[test]
+    fn words_are_matched_literally() {
+        let pattern = word("a.b");
+        assert!(pattern.is_match("see a.b here"));
+        assert!(!pattern.is_match("see axb here"));
+    }
+
+    #[test]
+    fn regex_rules_are_case_insensitive_unless_opted_out() {
+        assert!(rule_pattern("regex", "fr[e3]e money").unwrap().is_match("FR3E MONEY now"));
+        assert!(!rule_pattern("regex", "(?-i)spam").unwrap().is_match("SPAM"));
+        assert!(rule_pattern("regex", "(unclosed").is_err());
+    }
+
+    #[test]
+    fn masking_keeps_the_length_of_each_match() {
+        assert_eq!(mask(&word("darn"), "Darn it, darn."), "**** it, ****.");
+        assert_eq!(mask(&word("über"), "so über cool"), "so **** cool");
+        assert_eq!(mask(&word("darn"), "darned"), "darned");
+    }
+
+    #[test]
+    fn links_are_counted_by_prefix() {
+        assert_eq!(count_links("see https://a.com and http://b.org or www.c.net"), 3);
+        assert_eq!(count_links("no links, just example.com"), 0);
+    }
+
+    #[test]
+    fn links_count_without_spaces_between_them() {
+        assert_eq!(count_links("https://a.com,https://b.com;(http://c.org)"), 3);
+        assert_eq!(count_links("HTTPS://A.COM and https://www.b.com"), 2);
+        assert_eq!(count_links("xhttps://a.com"), 0);
+    }
+
+    #[test]
+    fn duplicates_ignore_case_and_spacing() {
+        assert_eq!(normalize("  Hello\n  WORLD "), normalize("hello world"));
+    }
+}
@@ -1,23 +1,39 @@
 // server/src/main.rs
+mod account;
+mod audit;
+mod auth;
+mod categories;
+mod content_filter;
+mod messages;
+mod moderation;
+mod notifications;
+mod profiles;
+mod rate_limit;
+mod reactions;
 mod terminal_server;
+mod ssh_keys;
 mod ssh_server;
+mod ssh_tui;
 mod seed;
+mod tags;
+mod trash;
+mod two_factor;
+mod validation;
 
 #[cfg(test)]
 mod test_utils;
 
 use actix_web::{get, post, web, App, HttpResponse, HttpServer, Responder};
 use serde::{Deserialize, Serialize};
-use serde_json;
 use uuid::Uuid;
-use chrono::Utc;
-use sqlx::{PgPool, Row};
-use sha2::{Sha256, Digest};
+use chrono::{DateTime, Utc};
+use sqlx::{PgConnection, PgPool, Row};
 use std::env;
-use std::sync::Arc;
 
 // Re-export terminal server handlers
 pub use terminal_server::{terminal_page, handle_command};
+use auth::{Admin, AuthUser, Moderator};
+use validation::Validate;
 
 #[get("/")]
 async fn index() -> impl Responder {
@@ -40,24 +56,40 @@ struct Thread {
     content: String,
     image_url: Option<String>,
     category_id: Option<String>,
-    created_at: String,
-}
-
-#[derive(Debug, Serialize, Deserialize, Clone, sqlx::FromRow)]
-struct Category {
-    id: String,
-    name: String,
-    description: Option<String>,
-    created_at: String,
+    created_at: DateTime<Utc>,
+    category_name: Option<String>,
+    pinned: bool,
+    locked: bool,
+    archived: bool,
+    comment_count: i64,
+    // Newest of the thread's and its comments' created_at
+    last_activity_at: DateTime<Utc>,
+    // Per-user read state; zero/None for anonymous requests
+    unread_count: i64,
+    last_read_at: Option<DateTime<Utc>>,
+    // Awaiting moderator approval; only ever true for the author's own posts
+    held: bool,
+    tags: Vec<String>,
+    // [{emoji, count, mine}], see reactions::summary_sql
+    reactions: serde_json::Value,
+    // Whether the requesting user gets reply notifications; false for anonymous requests
+    subscribed: bool,
+    author_signature: Option<String>,
+    // Hash of the author's avatar, see profiles::get_avatar
+    author_avatar: Option<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 struct NewThread {
+    // Missing fields fail validation with a field error rather than in the JSON extractor
+    #[serde(default)]
     title: String,
-    author: String,
+    #[serde(default)]
     content: String,
     image_url: Option<String>,
     category_id: Option<String>,
+    #[serde(default)]
+    tags: Vec<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone, sqlx::FromRow)]
@@ -67,26 +99,128 @@ struct Comment {
     author: String,
     content: String,
     image_url: Option<String>,
-    created_at: String,
+    created_at: DateTime<Utc>,
+    // Tombstoned comments keep their place in the thread as "[deleted]"
+    deleted: bool,
+    held: bool,
+    reactions: serde_json::Value,
+    author_signature: Option<String>,
+    author_avatar: Option<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 struct NewComment {
     thread_id: String,
-    author: String,
+    #[serde(default)]
     content: String,
     image_url: Option<String>,
 }
 
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct MarkRead {
+    // created_at of the newest comment the user has seen; defaults to the latest one
+    up_to: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Credentials {
+    #[serde(default)]
+    username: String,
+    #[serde(default)]
+    password: String,
+}
+
+impl Validate for NewThread {
+    fn validate(&mut self) -> Result<(), validation::Errors> {
+        let mut errors = validation::Errors::default();
+        self.title = errors.check(&validation::THREAD_TITLE, &self.title);
+        self.content = errors.check(&validation::THREAD_CONTENT, &self.content);
+        self.tags = tags::check(&mut errors, &self.tags);
+        errors.finish()
+    }
+}
+
+impl Validate for NewComment {
+    fn validate(&mut self) -> Result<(), validation::Errors> {
+        let mut errors = validation::Errors::default();
+        self.content = errors.check(&validation::COMMENT_CONTENT, &self.content);
+        errors.finish()
+    }
+}
+
+impl Validate for Credentials {
+    fn validate(&mut self) -> Result<(), validation::Errors> {
+        let mut errors = validation::Errors::default();
+        self.username = errors.check(&validation::USERNAME, &self.username);
+        self.password = errors.check(&validation::PASSWORD, &self.password);
+        errors.finish()
+    }
+}
+
 type Db = PgPool;
 
+#[derive(Debug, Deserialize)]
+struct ThreadListQuery {
+    // "activity", "newest" (default) or "replies"
+    sort: Option<String>,
+    // List archived threads instead of active ones
+    #[serde(default)]
+    archived: bool,
+    // Comma-separated; only threads carrying all of these tags
+    tags: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ThreadFlags {
+    pinned: Option<bool>,
+    locked: Option<bool>,
+    archived: Option<bool>,
+}
+
 #[get("/threads")]
-async fn list_threads(db: web::Data<Db>) -> impl Responder {
-    let rows = sqlx::query_as::<_, Thread>(
-        r#"SELECT t.id, t.title, u.username as author, t.content, t.image_url, t.category_id, t.created_at
+async fn list_threads(db: web::Data<Db>, user: Option<AuthUser>, query: web::Query<ThreadListQuery>) -> impl Responder {
+    let user_id = user.map(|u| u.id);
+    let order_by = match query.sort.as_deref() {
+        Some("activity") => "last_activity_at DESC",
+        Some("replies") => "comment_count DESC, last_activity_at DESC",
+        _ => "t.created_at DESC",
+    };
+    // Shadow-muted users' posts are only shown to themselves
+    let sql = format!(
+        r#"WITH visible_comments AS (
+               SELECT c.thread_id, c.created_at FROM comments c JOIN users cu ON cu.id = c.user_id
+               WHERE NOT c.hidden AND c.deleted_at IS NULL AND (NOT cu.muted OR cu.id = $1)
+                 AND (NOT c.held OR cu.id = $1)
+           )
+           SELECT t.id, t.title, u.username as author, t.content, t.image_url, t.category_id, t.created_at,
+                  cat.name as category_name, t.pinned, t.locked, t.archived,
+                  COALESCE(stats.comment_count, 0) as comment_count,
+                  GREATEST(t.created_at, stats.last_comment_at) as last_activity_at,
+                  r.last_read_at, t.held,
+                  ARRAY(SELECT tg.tag FROM thread_tags tg WHERE tg.thread_id = t.id ORDER BY tg.tag) as tags,
+                  {} as reactions,
+                  EXISTS(SELECT 1 FROM thread_subscriptions s WHERE s.thread_id = t.id AND s.user_id = $1) as subscribed,
+                  u.signature as author_signature, u.avatar_hash as author_avatar,
+                  (SELECT COUNT(*) FROM visible_comments c
+                   WHERE c.thread_id = t.id AND $1::TEXT IS NOT NULL
+                     AND (r.last_read_at IS NULL OR c.created_at > r.last_read_at)) as unread_count
            FROM threads t JOIN users u ON t.user_id = u.id
-           ORDER BY t.created_at DESC"#
-    )
+           LEFT JOIN categories cat ON cat.id = t.category_id
+           LEFT JOIN (SELECT thread_id, COUNT(*) as comment_count, MAX(created_at) as last_comment_at
+                      FROM visible_comments GROUP BY thread_id) stats ON stats.thread_id = t.id
+           LEFT JOIN thread_reads r ON r.thread_id = t.id AND r.user_id = $1
+           WHERE t.archived = $2 AND NOT t.hidden AND t.deleted_at IS NULL AND (NOT u.muted OR u.id = $1)
+             AND (NOT t.held OR u.id = $1)
+             AND (SELECT COUNT(*) FROM thread_tags tg WHERE tg.thread_id = t.id AND tg.tag = ANY($3))
+                 = cardinality($3::TEXT[])
+           ORDER BY t.pinned DESC, {}"#,
+        reactions::summary_sql("thread", "t.id", "$1"),
+        order_by
+    );
+    let rows = sqlx::query_as::<_, Thread>(&sql)
+    .bind(user_id)
+    .bind(query.archived)
+    .bind(tags::parse_filter(query.tags.as_deref()))
     .fetch_all(&**db)
     .await
     .unwrap_or_else(|_| vec![]);
@@ -94,80 +228,101 @@ async fn list_threads(db: web::Data<Db>) -> impl Responder {
     HttpResponse::Ok().json(rows)
 }
 #[post("/threads")]
-async fn create_thread(db: web::Data<Db>, payload: web::Json<NewThread>) -> impl Responder {
+async fn create_thread(db: web::Data<Db>, user: AuthUser, payload: web::Json<NewThread>) -> impl Responder {
+    let mut payload = payload.into_inner();
+    if let Err(errors) = payload.validate() {
+        return errors.response();
+    }
     let id = Uuid::new_v4().to_string();
-    let created_at = Utc::now().to_rfc3339();
-    // Insert user if not exists (ON CONFLICT DO NOTHING)
-    let _ = sqlx::query(
-        r#"INSERT INTO users (id, username, password_hash, created_at)
-           VALUES ($1, $2, $3, $4)
-           ON CONFLICT (username) DO NOTHING"#
-    )
-    .bind(Uuid::new_v4().to_string())
-    .bind(payload.author.clone())
-    .bind("")
-    .bind(created_at.clone())
-    .execute(&**db)
-    .await;
-    // Retrieve user id
-    let user_row: (String,) = sqlx::query_as(
-        r#"SELECT id FROM users WHERE username = $1"#
-    )
-    .bind(payload.author.clone())
-    .fetch_one(&**db)
-    .await
-    .unwrap();
-    let user_id = user_row.0;
+    let created_at = Utc::now();
+    let user_id = user.id;
+    if let Some(reason) = moderation::account_block(&db, &user_id).await {
+        return HttpResponse::Forbidden().json(serde_json::json!({ "error": reason }));
+    }
+    let (texts, held) = match content_filter::check(&db, &user_id, &[&payload.title, &payload.content]).await {
+        content_filter::Verdict::Reject(reason) => {
+            return HttpResponse::BadRequest().json(serde_json::json!({ "error": reason }));
+        }
+        content_filter::Verdict::Accept { texts, held } => (texts, held),
+    };
     // Insert thread
-    let _ = sqlx::query(
-        r#"INSERT INTO threads (id, title, user_id, content, image_url, category_id, created_at)
-           VALUES ($1, $2, $3, $4, $5, $6, $7)"#
+    let result = sqlx::query(
+        r#"INSERT INTO threads (id, title, user_id, content, image_url, category_id, created_at, held)
+           VALUES ($1, $2, $3, $4, $5, $6, $7, $8)"#
     )
     .bind(id.clone())
-    .bind(payload.title.clone())
-    .bind(user_id)
-    .bind(payload.content.clone())
+    .bind(&texts[0])
+    .bind(&user_id)
+    .bind(&texts[1])
     .bind(payload.image_url.clone())
     .bind(payload.category_id.clone())
-    .bind(created_at.clone())
+    .bind(created_at)
+    .bind(held)
+    .execute(&**db)
+    .await;
+    if let Err(e) = result {
+        log::error!("creating thread failed: {}", e);
+        return HttpResponse::InternalServerError().finish();
+    }
+    let _ = tags::set_thread_tags(&db, &id, &payload.tags).await;
+    let _ = notifications::subscribe(&db, &user_id, &id).await;
+    // Held threads notify once a moderator approves them
+    if !held {
+        notifications::notify_post(&db, "thread", &id).await;
+    }
+    // The author has obviously read their own thread
+    let _ = sqlx::query(
+        r#"INSERT INTO thread_reads (user_id, thread_id, last_read_at) VALUES ($1, $2, $3)"#
+    )
+    .bind(&user_id)
+    .bind(&id)
+    .bind(created_at)
     .execute(&**db)
     .await;
     
-    HttpResponse::Created().finish()
+    HttpResponse::Created().json(serde_json::json!({ "id": id, "held": held }))
 }
 
-#[get("/categories")]
-async fn list_categories(db: web::Data<Db>) -> impl Responder {
-    let rows = sqlx::query_as::<_, Category>(
-        r#"SELECT id, name, description, created_at
-           FROM categories ORDER BY name ASC"#
+#[actix_web::patch("/threads/{id}/flags")]
+async fn set_thread_flags(db: web::Data<Db>, moderator: Moderator, path: web::Path<String>, payload: web::Json<ThreadFlags>) -> impl Responder {
+    let thread_id = path.into_inner();
+    let before: Option<(bool, bool, bool)> = sqlx::query_as(
+        r#"SELECT pinned, locked, archived FROM threads WHERE id = $1"#
     )
-    .fetch_all(&**db)
+    .bind(&thread_id)
+    .fetch_optional(&**db)
     .await
-    .unwrap_or_else(|_| vec![]);
-    
-    HttpResponse::Ok().json(rows)
-}
-
-#[post("/categories")]
-async fn create_category(db: web::Data<Db>, payload: web::Json<serde_json::Value>) -> impl Responder {
-    let id = Uuid::new_v4().to_string();
-    let created_at = Utc::now().to_rfc3339();
-    let name = payload.get("name").and_then(|v| v.as_str()).unwrap_or("General");
-    let description = payload.get("description").and_then(|v| v.as_str());
-    
-    let _ = sqlx::query(
-        r#"INSERT INTO categories (id, name, description, created_at)
-           VALUES ($1, $2, $3, $4)"#
+    .unwrap_or(None);
+    let result = sqlx::query_as::<_, (bool, bool, bool)>(
+        r#"UPDATE threads SET pinned = COALESCE($2, pinned), locked = COALESCE($3, locked), archived = COALESCE($4, archived)
+           WHERE id = $1 RETURNING pinned, locked, archived"#
     )
-    .bind(id)
-    .bind(name)
-    .bind(description)
-    .bind(created_at)
-    .execute(&**db)
+    .bind(&thread_id)
+    .bind(payload.pinned)
+    .bind(payload.locked)
+    .bind(payload.archived)
+    .fetch_optional(&**db)
     .await;
     
-    HttpResponse::Created().finish()
+    match result {
+        Ok(Some((pinned, locked, archived))) => {
+            let flags = |(pinned, locked, archived): (bool, bool, bool)| serde_json::json!({
+                "pinned": pinned,
+                "locked": locked,
+                "archived": archived
+            });
+            audit::record(&db, Some(&moderator.0), "thread.flags", "thread", Some(&thread_id),
+                before.map(flags), Some(flags((pinned, locked, archived)))).await;
+            HttpResponse::Ok().json(serde_json::json!({
+                "id": thread_id,
+                "pinned": pinned,
+                "locked": locked,
+                "archived": archived
+            }))
+        }
+        Ok(None) => HttpResponse::NotFound().finish(),
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
 }
 
 #[get("/auth/check-username/{username}")]
@@ -189,8 +344,10 @@ async fn check_username(db: web::Data<Db>, path: web::Path<String>) -> impl Resp
 }
 
 #[post("/auth/login")]
-async fn login_user(db: web::Data<Db>, payload: web::Json<serde_json::Value>) -> impl Responder {
-    let username = payload.get("username").and_then(|v| v.as_str()).unwrap_or("");
+async fn login_user(db: web::Data<Db>, limiter: web::Data<rate_limit::RateLimiter>, payload: web::Json<serde_json::Value>) -> impl Responder {
+    // Usernames are stored normalized, so look them up the same way
+    let username = validation::normalize(&validation::USERNAME, payload.get("username").and_then(|v| v.as_str()).unwrap_or(""));
+    let username = username.as_str();
     let password = payload.get("password").and_then(|v| v.as_str()).unwrap_or("");
     
     if username.is_empty() || password.is_empty() {
@@ -198,53 +355,55 @@ async fn login_user(db: web::Data<Db>, payload: web::Json<serde_json::Value>) ->
             "error": "Username and password required"
         }));
     }
+    if let Some(response) = limiter.login_refused(username) {
+        return response;
+    }
     
-    // Hash the provided password
-    let mut hasher = Sha256::new();
-    hasher.update(password.as_bytes());
-    let password_hash = format!("{:x}", hasher.finalize());
+    let password_hash = auth::hash_password(password);
     
     // Check if user exists and password matches
     let user_result = sqlx::query(
-        r#"SELECT id, username, password_hash, created_at FROM users WHERE username = $1"#
+        r#"SELECT id, password_hash FROM users WHERE username = $1"#
     )
     .bind(username)
-    .fetch_one(&**db)
-    .await;
+    .fetch_optional(&**db)
+    .await
+    .unwrap_or(None);
     
-    match user_result {
-        Ok(row) => {
-            let stored_hash: String = row.get("password_hash");
-            if stored_hash == password_hash {
-                HttpResponse::Ok().json(serde_json::json!({
-                    "id": row.get::<String, _>("id"),
-                    "username": row.get::<String, _>("username"),
-                    "created_at": row.get::<String, _>("created_at")
-                }))
-            } else {
-                HttpResponse::Unauthorized().json(serde_json::json!({
-                    "error": "Invalid password"
-                }))
-            }
+    // One answer for a wrong password and an unknown name, so it doesn't
+    // tell anyone which usernames exist
+    let Some(row) = user_result.filter(|row| row.get::<String, _>("password_hash") == password_hash) else {
+        limiter.login_failed(username);
+        return HttpResponse::Unauthorized().json(serde_json::json!({
+            "error": "Invalid username or password"
+        }));
+    };
+    let user_id: String = row.get("id");
+    if let Some(reason) = moderation::account_block(&db, &user_id).await {
+        return HttpResponse::Forbidden().json(serde_json::json!({ "error": reason }));
+    }
+    let code = payload.get("code").and_then(|v| v.as_str());
+    if code.is_some() {
+        if let Some(response) = limiter.second_factor_refused(username) {
+            return response;
         }
-        Err(_) => {
-            HttpResponse::Unauthorized().json(serde_json::json!({
-                "error": "User not found"
-            }))
+    }
+    if let Err(response) = two_factor::check_login(&db, &user_id, code).await {
+        if code.is_some() {
+            limiter.second_factor_failed(username);
         }
+        return response;
     }
+    auth::session_response(&db, &user_id).await
 }
 
 #[post("/auth/register")]
-async fn register_user(db: web::Data<Db>, payload: web::Json<serde_json::Value>) -> impl Responder {
-    let username = payload.get("username").and_then(|v| v.as_str()).unwrap_or("");
-    let password = payload.get("password").and_then(|v| v.as_str()).unwrap_or("");
-    
-    if username.is_empty() || password.is_empty() {
-        return HttpResponse::BadRequest().json(serde_json::json!({
-            "error": "Username and password required"
-        }));
+async fn register_user(db: web::Data<Db>, payload: web::Json<Credentials>) -> impl Responder {
+    let mut payload = payload.into_inner();
+    if let Err(errors) = payload.validate() {
+        return errors.response();
     }
+    let (username, password) = (payload.username.as_str(), payload.password.as_str());
     
     // Check if username already exists
     let exists = sqlx::query(
@@ -262,14 +421,11 @@ async fn register_user(db: web::Data<Db>, payload: web::Json<serde_json::Value>)
         }));
     }
     
-    // Hash password
-    let mut hasher = Sha256::new();
-    hasher.update(password.as_bytes());
-    let password_hash = format!("{:x}", hasher.finalize());
+    let password_hash = auth::hash_password(password);
     
     // Create user
     let user_id = Uuid::new_v4().to_string();
-    let created_at = Utc::now().to_rfc3339();
+    let created_at = Utc::now();
     
     let _ = sqlx::query(
         r#"INSERT INTO users (id, username, password_hash, created_at) VALUES ($1, $2, $3, $4)"#
@@ -277,130 +433,275 @@ async fn register_user(db: web::Data<Db>, payload: web::Json<serde_json::Value>)
     .bind(&user_id)
     .bind(username)
     .bind(&password_hash)
-    .bind(&created_at)
+    .bind(created_at)
     .execute(&**db)
     .await;
     
+    let token = match auth::create_session(&db, &user_id).await {
+        Ok(token) => token,
+        Err(_) => return HttpResponse::InternalServerError().json(serde_json::json!({
+            "error": "Could not start session"
+        })),
+    };
+    
     HttpResponse::Created().json(serde_json::json!({
         "id": user_id,
         "username": username,
-        "created_at": created_at
+        "created_at": created_at,
+        "role": "user",
+        "token": token
     }))
 }
 
 #[get("/threads/{id}/comments")]
-async fn list_comments(db: web::Data<Db>, path: web::Path<String>) -> impl Responder {
+async fn list_comments(db: web::Data<Db>, user: Option<AuthUser>, path: web::Path<String>) -> impl Responder {
     let thread_id = path.into_inner();
-    let rows = sqlx::query_as::<_, Comment>(
-        r#"SELECT c.id, $1 as thread_id, u.username as author, c.content, c.image_url, c.created_at
+    // Deleted, hidden and held threads keep their comments to moderators, as the thread list does
+    let is_moderator = user.as_ref().is_some_and(|u| u.is_moderator());
+    let visible: Option<bool> = sqlx::query_scalar(&format!(
+        "SELECT {} FROM threads t JOIN users u ON t.user_id = u.id WHERE t.id = $1",
+        moderation::thread_visible_sql("$2", "$3")
+    ))
+    .bind(&thread_id)
+    .bind(user.as_ref().map(|u| u.id.clone()))
+    .bind(is_moderator)
+    .fetch_optional(&**db)
+    .await
+    .unwrap_or(None);
+    if visible != Some(true) {
+        return HttpResponse::NotFound().json(serde_json::json!({ "error": "Thread not found" }));
+    }
+    let sql = format!(
+        r#"SELECT c.id, $1 as thread_id,
+                  CASE WHEN c.deleted_at IS NULL THEN u.username ELSE '[deleted]' END as author,
+                  CASE WHEN c.deleted_at IS NULL THEN c.content ELSE '[deleted]' END as content,
+                  CASE WHEN c.deleted_at IS NULL THEN c.image_url END as image_url,
+                  c.created_at, c.deleted_at IS NOT NULL as deleted, c.held,
+                  CASE WHEN c.deleted_at IS NULL THEN {} ELSE '[]'::jsonb END as reactions,
+                  CASE WHEN c.deleted_at IS NULL THEN u.signature END as author_signature,
+                  CASE WHEN c.deleted_at IS NULL THEN u.avatar_hash END as author_avatar
            FROM comments c JOIN users u ON c.user_id = u.id
-           WHERE c.thread_id = $2 ORDER BY c.created_at ASC"#
-    )
+           WHERE c.thread_id = $2 AND NOT c.hidden AND (NOT u.muted OR u.id = $3)
+             AND (NOT c.held OR u.id = $3)
+           ORDER BY c.created_at ASC"#,
+        reactions::summary_sql("comment", "c.id", "$3")
+    );
+    let rows = sqlx::query_as::<_, Comment>(&sql)
     .bind(thread_id.clone())
     .bind(thread_id)
+    .bind(user.map(|u| u.id))
     .fetch_all(&**db)
     .await
     .unwrap_or_else(|_| vec![]);
     HttpResponse::Ok().json(rows)
 }
 
-#[post("/comments")]
-async fn create_comment(db: web::Data<Db>, payload: web::Json<NewComment>) -> impl Responder {
-    let id = Uuid::new_v4().to_string();
-    let created_at = Utc::now().to_rfc3339();
-    // Ensure user exists (ON CONFLICT DO NOTHING)
-    let _ = sqlx::query(
-        r#"INSERT INTO users (id, username, password_hash, created_at)
-           VALUES ($1, $2, $3, $4)
-           ON CONFLICT (username) DO NOTHING"#
+#[post("/threads/{id}/read")]
+async fn mark_thread_read(db: web::Data<Db>, user: AuthUser, path: web::Path<String>, payload: web::Json<MarkRead>) -> impl Responder {
+    let thread_id = path.into_inner();
+    // Never move the read position backwards
+    let result = sqlx::query(
+        r#"INSERT INTO thread_reads (user_id, thread_id, last_read_at)
+           SELECT $1, t.id, COALESCE($3, (SELECT MAX(c.created_at) FROM comments c WHERE c.thread_id = t.id), t.created_at)
+           FROM threads t WHERE t.id = $2 AND t.deleted_at IS NULL
+           ON CONFLICT (user_id, thread_id)
+           DO UPDATE SET last_read_at = GREATEST(thread_reads.last_read_at, EXCLUDED.last_read_at)"#
     )
-    .bind(Uuid::new_v4().to_string())
-    .bind(payload.author.clone())
-    .bind("")
-    .bind(created_at.clone())
+    .bind(&user.id)
+    .bind(&thread_id)
+    .bind(payload.up_to)
     .execute(&**db)
     .await;
-    // Retrieve user id
-    let user_row: (String,) = sqlx::query_as(
-        r#"SELECT id FROM users WHERE username = $1"#
-    )
-    .bind(payload.author.clone())
-    .fetch_one(&**db)
+    
+    match result {
+        Ok(r) if r.rows_affected() > 0 => HttpResponse::Ok().finish(),
+        Ok(_) => HttpResponse::NotFound().finish(),
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
+
+#[post("/comments")]
+async fn create_comment(db: web::Data<Db>, user: AuthUser, payload: web::Json<NewComment>) -> impl Responder {
+    let mut payload = payload.into_inner();
+    if let Err(errors) = payload.validate() {
+        return errors.response();
+    }
+    // Only threads the user can see take comments, and locked and archived
+    // ones are read-only. Deleted threads take none, even from moderators.
+    let thread_state: Option<(bool, bool)> = sqlx::query_as(&format!(
+        "SELECT t.locked, t.archived FROM threads t JOIN users u ON t.user_id = u.id
+         WHERE t.id = $1 AND t.deleted_at IS NULL AND {}",
+        moderation::thread_visible_sql("$2", "$3")
+    ))
+    .bind(&payload.thread_id)
+    .bind(&user.id)
+    .bind(user.is_moderator())
+    .fetch_optional(&**db)
     .await
-    .unwrap();
-    let user_id = user_row.0;
+    .unwrap_or(None);
+    match thread_state {
+        None => return HttpResponse::NotFound().json(serde_json::json!({
+            "error": "Thread not found"
+        })),
+        Some((true, _)) => return HttpResponse::Forbidden().json(serde_json::json!({
+            "error": "Thread is locked"
+        })),
+        Some((_, true)) => return HttpResponse::Forbidden().json(serde_json::json!({
+            "error": "Thread is archived"
+        })),
+        Some(_) => {}
+    }
+    
+    let id = Uuid::new_v4().to_string();
+    let created_at = Utc::now();
+    let user_id = user.id;
+    if let Some(reason) = moderation::account_block(&db, &user_id).await {
+        return HttpResponse::Forbidden().json(serde_json::json!({ "error": reason }));
+    }
+    let (texts, held) = match content_filter::check(&db, &user_id, &[&payload.content]).await {
+        content_filter::Verdict::Reject(reason) => {
+            return HttpResponse::BadRequest().json(serde_json::json!({ "error": reason }));
+        }
+        content_filter::Verdict::Accept { texts, held } => (texts, held),
+    };
     // Insert comment
-    let _ = sqlx::query(
-        r#"INSERT INTO comments (id, thread_id, user_id, content, image_url, created_at)
-           VALUES ($1, $2, $3, $4, $5, $6)"#
+    let result = sqlx::query(
+        r#"INSERT INTO comments (id, thread_id, user_id, content, image_url, created_at, held)
+           VALUES ($1, $2, $3, $4, $5, $6, $7)"#
     )
-    .bind(id)
+    .bind(&id)
     .bind(payload.thread_id.clone())
     .bind(user_id)
-    .bind(payload.content.clone())
+    .bind(&texts[0])
     .bind(payload.image_url.clone())
     .bind(created_at)
+    .bind(held)
     .execute(&**db)
     .await;
-    HttpResponse::Created().finish()
+    if let Err(e) = result {
+        log::error!("creating comment failed: {}", e);
+        return HttpResponse::InternalServerError().finish();
+    }
+    if !held {
+        notifications::notify_post(&db, "comment", &id).await;
+    }
+    HttpResponse::Created().json(serde_json::json!({ "id": id, "held": held }))
+}
+
+/// Row counts of the given tables, used as the "before" snapshot of bulk deletes.
+async fn table_counts(conn: &mut PgConnection, tables: &[&str]) -> Result<serde_json::Value, sqlx::Error> {
+    let mut counts = serde_json::Map::new();
+    for table in tables {
+        let count: i64 = sqlx::query_scalar(&format!("SELECT COUNT(*) FROM {}", table))
+            .fetch_one(&mut *conn)
+            .await?;
+        counts.insert(table.to_string(), count.into());
+    }
+    Ok(counts.into())
+}
+
+/// Empty `tables` in order and audit it as `action` with the prior row counts
+/// of `counted`, all in one transaction: either everything goes or nothing does.
+async fn delete_all(
+    db: &PgPool,
+    admin: &AuthUser,
+    action: &str,
+    target_type: &str,
+    counted: &[&str],
+    tables: &[&str],
+) -> Result<(), sqlx::Error> {
+    let mut tx = db.begin().await?;
+    let before = table_counts(&mut tx, counted).await?;
+    for table in tables {
+        sqlx::query(&format!("DELETE FROM {}", table))
+            .execute(&mut *tx)
+            .await?;
+    }
+    audit::record_in(&mut tx, Some(admin), action, target_type, None, Some(before), None).await?;
+    tx.commit().await
 }
 
 #[actix_web::delete("/threads")]
-async fn delete_all_threads(db: web::Data<Db>) -> impl Responder {
-    let _ = sqlx::query("DELETE FROM comments")
-        .execute(&**db)
-        .await;
-    let _ = sqlx::query("DELETE FROM threads")
-        .execute(&**db)
-        .await;
-    HttpResponse::Ok().finish()
+async fn delete_all_threads(db: web::Data<Db>, admin: Admin) -> impl Responder {
+    let tables = ["reactions", "comments", "thread_reads", "threads"];
+    match delete_all(&db, &admin.0, "threads.delete_all", "thread", &["threads", "comments"], &tables).await {
+        Ok(()) => HttpResponse::Ok().finish(),
+        Err(e) => {
+            log::error!("deleting all threads failed: {}", e);
+            HttpResponse::InternalServerError().finish()
+        }
+    }
 }
 
 #[actix_web::delete("/threads/{id}")]
-async fn delete_thread(db: web::Data<Db>, path: web::Path<String>) -> impl Responder {
+async fn delete_thread(db: web::Data<Db>, moderator: Moderator, path: web::Path<String>) -> impl Responder {
     let thread_id = path.into_inner();
+    let before = audit::snapshot(&db, "threads", &thread_id).await;
     
-    // Delete comments for this thread first
-    let _ = sqlx::query("DELETE FROM comments WHERE thread_id = $1")
-        .bind(&thread_id)
-        .execute(&**db)
-        .await;
+    match trash::soft_delete(&db, "thread", &thread_id, &moderator.0.id).await {
+        Ok(n) if n > 0 => {
+            let after = audit::snapshot(&db, "threads", &thread_id).await;
+            audit::record(&db, Some(&moderator.0), "thread.delete", "thread", Some(&thread_id), before, after).await;
+            HttpResponse::Ok().finish()
+        }
+        Ok(_) => HttpResponse::NotFound().finish(),
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
+
+/// Permanently delete a thread along with its comments and read markers.
+/// Moderator deletions only tombstone threads; this is for the purge job.
+async fn remove_thread(conn: &mut PgConnection, thread_id: &str) -> Result<u64, sqlx::Error> {
+    // Delete reactions, comments and read markers for this thread first
+    let comment_ids: Vec<String> = sqlx::query_scalar("SELECT id FROM comments WHERE thread_id = $1")
+        .bind(thread_id)
+        .fetch_all(&mut *conn)
+        .await?;
+    reactions::remove_for(conn, "comment", &comment_ids).await?;
+    reactions::remove_for(conn, "thread", &[thread_id.to_string()]).await?;
+    sqlx::query("DELETE FROM comments WHERE thread_id = $1")
+        .bind(thread_id)
+        .execute(&mut *conn)
+        .await?;
+    sqlx::query("DELETE FROM thread_reads WHERE thread_id = $1")
+        .bind(thread_id)
+        .execute(&mut *conn)
+        .await?;
     
     // Delete the thread
-    let result = sqlx::query("DELETE FROM threads WHERE id = $1")
-        .bind(&thread_id)
-        .execute(&**db)
-        .await;
-    
-    match result {
-        Ok(_) => HttpResponse::Ok().finish(),
-        Err(_) => HttpResponse::NotFound().finish(),
-    }
+    sqlx::query("DELETE FROM threads WHERE id = $1")
+        .bind(thread_id)
+        .execute(conn)
+        .await
+        .map(|r| r.rows_affected())
 }
 
 #[actix_web::delete("/users")]
-async fn delete_all_users(db: web::Data<Db>) -> impl Responder {
-    let _ = sqlx::query("DELETE FROM comments")
-        .execute(&**db)
-        .await;
-    let _ = sqlx::query("DELETE FROM threads")
-        .execute(&**db)
-        .await;
-    let _ = sqlx::query("DELETE FROM users")
-        .execute(&**db)
-        .await;
-    HttpResponse::Ok().finish()
+async fn delete_all_users(db: web::Data<Db>, admin: Admin) -> impl Responder {
+    let tables = ["comments", "thread_reads", "threads", "reports", "user_sanctions", "sessions", "users"];
+    match delete_all(&db, &admin.0, "users.delete_all", "user", &["users", "threads", "comments"], &tables).await {
+        Ok(()) => HttpResponse::Ok().finish(),
+        Err(e) => {
+            log::error!("deleting all users failed: {}", e);
+            HttpResponse::InternalServerError().finish()
+        }
+    }
 }
 
-#[actix_web::delete("/categories")]
-async fn delete_all_categories(db: web::Data<Db>) -> impl Responder {
-    let _ = sqlx::query("UPDATE threads SET category_id = NULL")
-        .execute(&**db)
-        .await;
-    let _ = sqlx::query("DELETE FROM categories")
-        .execute(&**db)
-        .await;
-    HttpResponse::Ok().finish()
+/// Convert a TEXT column holding RFC3339 strings to TIMESTAMPTZ. No-op once converted.
+async fn convert_text_timestamp(pool: &PgPool, table: &str, column: &str) {
+    let sql = format!(
+        r#"DO $$ BEGIN
+               IF EXISTS (SELECT 1 FROM information_schema.columns
+                          WHERE table_name = '{table}' AND column_name = '{column}' AND data_type = 'text') THEN
+                   ALTER TABLE {table} ALTER COLUMN {column} TYPE TIMESTAMPTZ
+                       USING COALESCE(NULLIF({column}, '')::TIMESTAMPTZ, NOW());
+               END IF;
+           END $$;"#
+    );
+    if let Err(e) = sqlx::query(&sql).execute(pool).await {
+        eprintln!("Failed to convert {}.{} to TIMESTAMPTZ: {}", table, column, e);
+    }
 }
 
 #[actix_web::main]
@@ -423,6 +724,55 @@ async fn main() -> std::io::Result<()> {
         println!("Database seeded successfully!");
         return Ok(());
     }
+    
+    if args.len() > 1 && args[1] == "set-role" {
+        // Grant or revoke moderation rights: forum_server set-role <username> <role>
+        let (Some(username), Some(role)) = (args.get(2), args.get(3)) else {
+            eprintln!("Usage: forum_server set-role <username> <{}>", auth::ROLES.join("|"));
+            std::process::exit(1);
+        };
+        if !auth::ROLES.contains(&role.as_str()) {
+            eprintln!("Unknown role '{}', expected one of: {}", role, auth::ROLES.join(", "));
+            std::process::exit(1);
+        }
+        let database_url = std::env::var("DATABASE_URL")
+            .unwrap_or_else(|_| "postgres://localhost/forum_db".to_string());
+        
+        let pool = PgPool::connect(&database_url).await
+            .expect("Failed to connect to database");
+        auth::ensure_tables(&pool).await;
+        
+        audit::ensure_tables(&pool).await;
+        
+        let previous: Option<(String, String)> = sqlx::query_as("SELECT id, role FROM users WHERE username = $1")
+            .bind(username)
+            .fetch_optional(&pool)
+            .await
+            .unwrap_or(None);
+        match sqlx::query("UPDATE users SET role = $1 WHERE username = $2")
+            .bind(role)
+            .bind(username)
+            .execute(&pool)
+            .await
+        {
+            Ok(r) if r.rows_affected() > 0 => {
+                if let Some((user_id, old_role)) = previous {
+                    audit::record(&pool, None, "user.role", "user", Some(&user_id),
+                        Some(serde_json::json!({ "role": old_role })), Some(serde_json::json!({ "role": role }))).await;
+                }
+                println!("{} is now {}", username, role);
+            }
+            Ok(_) => {
+                eprintln!("No such user: {}", username);
+                std::process::exit(1);
+            }
+            Err(e) => {
+                eprintln!("Failed to update role: {}", e);
+                std::process::exit(1);
+            }
+        }
+        return Ok(());
+    }
     env_logger::init();
     
     let database_url = env::var("DATABASE_URL")
@@ -450,13 +800,19 @@ async fn main() -> std::io::Result<()> {
         }
     };
     
-    // SSH server - uncomment when Render SSH access is enabled (paid tier)
-    // let ssh_pool = pool.clone();
-    // let _ssh_handle = tokio::spawn(async {
-    //     if let Err(e) = ssh_server::start_ssh_server(Arc::new(ssh_pool)).await {
-    //         eprintln!("SSH server error: {}", e);
-    //     }
-    // });
+    // Shared by all workers and the SSH server so budgets hold across them
+    let limiter = web::Data::new(rate_limit::RateLimiter::default());
+    
+    // SSH server - only where the port can be reached, which on Render needs a paid tier
+    if std::env::var("SSH_PORT").is_ok() {
+        let ssh_pool = pool.clone();
+        let ssh_limiter = limiter.clone().into_inner();
+        let _ssh_handle = tokio::spawn(async {
+            if let Err(e) = ssh_server::start_ssh_server(std::sync::Arc::new(ssh_pool), ssh_limiter).await {
+                eprintln!("SSH server error: {}", e);
+            }
+        });
+    }
     
     // Run simple migrations to ensure tables exist (executed once at startup)
     let _ = sqlx::query(
@@ -464,7 +820,7 @@ async fn main() -> std::io::Result<()> {
                 id TEXT PRIMARY KEY,
                 username TEXT UNIQUE NOT NULL,
                 password_hash TEXT NOT NULL,
-                created_at TEXT NOT NULL
+                created_at TIMESTAMPTZ NOT NULL
             );"#
     )
     .execute(&pool)
@@ -474,7 +830,7 @@ async fn main() -> std::io::Result<()> {
                 id TEXT PRIMARY KEY,
                 name TEXT NOT NULL,
                 description TEXT,
-                created_at TEXT NOT NULL
+                created_at TIMESTAMPTZ NOT NULL
             );"#
     )
     .execute(&pool)
@@ -487,7 +843,7 @@ async fn main() -> std::io::Result<()> {
                 content TEXT NOT NULL,
                 image_url TEXT,
                 category_id TEXT,
-                created_at TEXT NOT NULL,
+                created_at TIMESTAMPTZ NOT NULL,
                 FOREIGN KEY (user_id) REFERENCES users(id),
                 FOREIGN KEY (category_id) REFERENCES categories(id)
             );"#
@@ -501,7 +857,7 @@ async fn main() -> std::io::Result<()> {
                 user_id TEXT NOT NULL,
                 content TEXT NOT NULL,
                 image_url TEXT,
-                created_at TEXT NOT NULL,
+                created_at TIMESTAMPTZ NOT NULL,
                 FOREIGN KEY (thread_id) REFERENCES threads(id),
                 FOREIGN KEY (user_id) REFERENCES users(id)
             );"#
@@ -509,6 +865,45 @@ async fn main() -> std::io::Result<()> {
     .execute(&pool)
     .await;
     
+    let _ = sqlx::query(
+        r#"CREATE TABLE IF NOT EXISTS thread_reads (
+                user_id TEXT NOT NULL,
+                thread_id TEXT NOT NULL,
+                last_read_at TIMESTAMPTZ NOT NULL,
+                PRIMARY KEY (user_id, thread_id),
+                FOREIGN KEY (user_id) REFERENCES users(id),
+                FOREIGN KEY (thread_id) REFERENCES threads(id)
+            );"#
+    )
+    .execute(&pool)
+    .await;
+    auth::ensure_tables(&pool).await;
+    moderation::ensure_tables(&pool).await;
+    audit::ensure_tables(&pool).await;
+    trash::ensure_tables(&pool).await;
+    content_filter::ensure_tables(&pool).await;
+    categories::ensure_tables(&pool).await;
+    tags::ensure_tables(&pool).await;
+    reactions::ensure_tables(&pool).await;
+    notifications::ensure_tables(&pool).await;
+    messages::ensure_tables(&pool).await;
+    profiles::ensure_tables(&pool).await;
+    account::ensure_tables(&pool).await;
+    two_factor::ensure_tables(&pool).await;
+    ssh_keys::ensure_tables(&pool).await;
+    
+    // Timestamps used to be stored as RFC3339 TEXT; convert them in place
+    for (table, column) in [
+        ("users", "created_at"),
+        ("categories", "created_at"),
+        ("threads", "created_at"),
+        ("comments", "created_at"),
+        ("thread_reads", "last_read_at"),
+        ("sessions", "created_at"),
+    ] {
+        convert_text_timestamp(&pool, table, column).await;
+    }
+    
     // Add image_url columns to existing tables (for backwards compatibility)
     let _ = sqlx::query("ALTER TABLE threads ADD COLUMN IF NOT EXISTS image_url TEXT")
         .execute(&pool)
@@ -519,26 +914,89 @@ async fn main() -> std::io::Result<()> {
     let _ = sqlx::query("ALTER TABLE threads ADD COLUMN IF NOT EXISTS category_id TEXT")
         .execute(&pool)
         .await;
+    for flag in ["pinned", "locked", "archived"] {
+        let _ = sqlx::query(&format!("ALTER TABLE threads ADD COLUMN IF NOT EXISTS {} BOOLEAN NOT NULL DEFAULT FALSE", flag))
+            .execute(&pool)
+            .await;
+    }
     
+    trash::spawn_purge_job(pool.clone());
     
     HttpServer::new(move || {
         App::new()
             .app_data(web::Data::new(pool.clone()))
+            .app_data(limiter.clone())
+            .wrap(actix_web::middleware::from_fn(rate_limit::limit))
             .service(index)
             .service(health)
             .service(list_threads)
             .service(create_thread)
             .service(delete_all_threads)
             .service(delete_thread)
-            .service(list_categories)
-            .service(create_category)
-            .service(delete_all_categories)
+            .service(set_thread_flags)
+            .service(categories::list_categories)
+            .service(categories::create_category)
+            .service(categories::update_category)
+            .service(categories::reorder_categories)
+            .service(categories::delete_category)
+            .service(categories::delete_all_categories)
+            .service(tags::list_tags)
+            .service(reactions::toggle_thread_reaction)
+            .service(reactions::toggle_comment_reaction)
+            .service(notifications::list_notifications)
+            .service(notifications::unread_count)
+            .service(notifications::mark_all_read)
+            .service(notifications::mark_read)
+            .service(notifications::subscribe_thread)
+            .service(notifications::unsubscribe_thread)
+            .service(messages::list_conversations)
+            .service(messages::create_conversation)
+            .service(messages::list_messages)
+            .service(messages::mark_conversation_read)
+            .service(messages::unread_message_count)
+            .service(messages::send_message)
+            .service(messages::list_blocks)
+            .service(messages::block_user)
+            .service(messages::unblock_user)
+            .service(profiles::get_profile)
+            .service(profiles::update_profile)
+            .service(profiles::upload_avatar)
+            .service(profiles::delete_avatar)
+            .service(profiles::get_avatar)
+            .service(account::change_password)
+            .service(account::delete_account)
+            .service(account::export_account)
+            .service(two_factor::status)
+            .service(two_factor::setup)
+            .service(two_factor::enable)
+            .service(two_factor::disable)
+            .service(ssh_keys::list_keys)
+            .service(ssh_keys::add_key)
+            .service(ssh_keys::delete_key)
+            .service(ssh_keys::challenge)
+            .service(ssh_keys::key_login)
             .service(delete_all_users)
             .service(check_username)
             .service(login_user)
             .service(register_user)
             .service(list_comments)
             .service(create_comment)
+            .service(mark_thread_read)
+            .service(moderation::create_report)
+            .service(moderation::list_reports)
+            .service(moderation::resolve_report)
+            .service(moderation::dismiss_report)
+            .service(moderation::sanction_user)
+            .service(moderation::list_sanctions)
+            .service(audit::list_audit_log)
+            .service(trash::delete_comment)
+            .service(trash::list_deleted)
+            .service(trash::restore)
+            .service(content_filter::list_rules)
+            .service(content_filter::create_rule)
+            .service(content_filter::delete_rule)
+            .service(content_filter::list_held)
+            .service(content_filter::decide_held)
             .service(terminal_server::terminal_page)
             .service(terminal_server::handle_command)
             .default_service(web::to(|| async { HttpResponse::Ok().body("Fallback route - server is running!") }))
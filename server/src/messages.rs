@@ -0,0 +1,467 @@
+// messages.rs
+use actix_web::{delete, get, post, web, HttpResponse, Responder};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::{PgConnection, PgPool};
+use uuid::Uuid;
+
+use crate::auth::AuthUser;
+use crate::moderation;
+use crate::validation::{self, Validate};
+
+// Participants of a conversation besides its creator
+const MAX_RECIPIENTS: usize = 9;
+// Default and largest page of message history
+const DEFAULT_LIMIT: i64 = 50;
+const MAX_LIMIT: i64 = 200;
+// Characters of the last message shown in the conversation list
+const EXCERPT_CHARS: i32 = 80;
+
+pub async fn ensure_tables(pool: &PgPool) {
+    let _ = sqlx::query(
+        r#"CREATE TABLE IF NOT EXISTS dm_conversations (
+                id TEXT PRIMARY KEY,
+                created_at TIMESTAMPTZ NOT NULL
+            );"#
+    )
+    .execute(pool)
+    .await;
+    let _ = sqlx::query(
+        r#"CREATE TABLE IF NOT EXISTS dm_participants (
+                conversation_id TEXT NOT NULL REFERENCES dm_conversations(id) ON DELETE CASCADE,
+                user_id TEXT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+                last_read_at TIMESTAMPTZ,
+                PRIMARY KEY (conversation_id, user_id)
+            );"#
+    )
+    .execute(pool)
+    .await;
+    let _ = sqlx::query(
+        r#"CREATE TABLE IF NOT EXISTS dm_messages (
+                id TEXT PRIMARY KEY,
+                conversation_id TEXT NOT NULL REFERENCES dm_conversations(id) ON DELETE CASCADE,
+                sender_id TEXT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+                content TEXT NOT NULL,
+                created_at TIMESTAMPTZ NOT NULL
+            );"#
+    )
+    .execute(pool)
+    .await;
+    let _ = sqlx::query("CREATE INDEX IF NOT EXISTS dm_messages_conversation ON dm_messages (conversation_id, created_at DESC)")
+        .execute(pool)
+        .await;
+    let _ = sqlx::query(
+        r#"CREATE TABLE IF NOT EXISTS user_blocks (
+                blocker_id TEXT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+                blocked_id TEXT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+                created_at TIMESTAMPTZ NOT NULL,
+                PRIMARY KEY (blocker_id, blocked_id)
+            );"#
+    )
+    .execute(pool)
+    .await;
+}
+
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct Conversation {
+    id: String,
+    // Everyone in the conversation except the requesting user
+    participants: Vec<String>,
+    last_sender: Option<String>,
+    last_message: Option<String>,
+    last_message_at: DateTime<Utc>,
+    unread_count: i64,
+}
+
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct Message {
+    id: String,
+    conversation_id: String,
+    sender: String,
+    content: String,
+    created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct NewConversation {
+    // Usernames to talk to, not including the sender
+    #[serde(default)]
+    participants: Vec<String>,
+    #[serde(default)]
+    content: String,
+}
+
+impl Validate for NewConversation {
+    fn validate(&mut self) -> Result<(), validation::Errors> {
+        let mut errors = validation::Errors::default();
+        let mut names: Vec<String> = Vec::new();
+        for name in &self.participants {
+            let name = validation::normalize(&validation::USERNAME, name);
+            if !name.is_empty() && !names.contains(&name) {
+                names.push(name);
+            }
+        }
+        if names.is_empty() {
+            errors.add("participants", "must name at least one user".to_string());
+        } else if names.len() > MAX_RECIPIENTS {
+            errors.add("participants", format!("at most {} users", MAX_RECIPIENTS));
+        }
+        self.participants = names;
+        self.content = errors.check(&validation::MESSAGE_CONTENT, &self.content);
+        errors.finish()
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct NewMessage {
+    conversation_id: String,
+    #[serde(default)]
+    content: String,
+}
+
+impl Validate for NewMessage {
+    fn validate(&mut self) -> Result<(), validation::Errors> {
+        let mut errors = validation::Errors::default();
+        self.content = errors.check(&validation::MESSAGE_CONTENT, &self.content);
+        errors.finish()
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct HistoryQuery {
+    // Id of the oldest message already loaded; only older messages are returned
+    before: Option<String>,
+    limit: Option<i64>,
+}
+
+fn forbidden(message: &str) -> HttpResponse {
+    HttpResponse::Forbidden().json(serde_json::json!({ "error": message }))
+}
+
+async fn is_participant(db: &PgPool, conversation_id: &str, user_id: &str) -> bool {
+    sqlx::query("SELECT 1 FROM dm_participants WHERE conversation_id = $1 AND user_id = $2")
+        .bind(conversation_id)
+        .bind(user_id)
+        .fetch_optional(db)
+        .await
+        .map(|row| row.is_some())
+        .unwrap_or(false)
+}
+
+/// Username of someone among `others` that `user_id` has blocked or is blocked by.
+async fn blocked_between(db: &PgPool, user_id: &str, others: &[String]) -> Option<String> {
+    sqlx::query_scalar(
+        r#"SELECT u.username FROM user_blocks b
+           JOIN users u ON u.id = CASE WHEN b.blocker_id = $1 THEN b.blocked_id ELSE b.blocker_id END
+           WHERE (b.blocker_id = $1 AND b.blocked_id = ANY($2)) OR (b.blocked_id = $1 AND b.blocker_id = ANY($2))
+           LIMIT 1"#
+    )
+    .bind(user_id)
+    .bind(others)
+    .fetch_optional(db)
+    .await
+    .unwrap_or(None)
+}
+
+/// Refuse messages from banned or suspended accounts, and between users where
+/// either has blocked the other.
+async fn send_problem(db: &PgPool, user_id: &str, others: &[String]) -> Option<HttpResponse> {
+    if let Some(reason) = moderation::account_block(db, user_id).await {
+        return Some(forbidden(&reason));
+    }
+    blocked_between(db, user_id, others)
+        .await
+        .map(|name| forbidden(&format!("You cannot message {}", name)))
+}
+
+async fn insert_message(conn: &mut PgConnection, conversation_id: &str, sender_id: &str, content: &str) -> Result<String, sqlx::Error> {
+    let id = Uuid::new_v4().to_string();
+    let now = Utc::now();
+    sqlx::query(
+        r#"INSERT INTO dm_messages (id, conversation_id, sender_id, content, created_at)
+           VALUES ($1, $2, $3, $4, $5)"#
+    )
+    .bind(&id)
+    .bind(conversation_id)
+    .bind(sender_id)
+    .bind(content)
+    .bind(now)
+    .execute(&mut *conn)
+    .await?;
+    // The sender has obviously read everything up to their own message
+    sqlx::query("UPDATE dm_participants SET last_read_at = $3 WHERE conversation_id = $1 AND user_id = $2")
+        .bind(conversation_id)
+        .bind(sender_id)
+        .bind(now)
+        .execute(&mut *conn)
+        .await?;
+    Ok(id)
+}
+
+/// Add the first message to the conversation between exactly `members`,
+/// creating the conversation if there is none yet, all in one transaction.
+/// Returns the conversation and message ids.
+async fn start_conversation(db: &PgPool, members: &[String], sender_id: &str, content: &str) -> Result<(String, String), sqlx::Error> {
+    let mut tx = db.begin().await?;
+    // Serialize requests for the same member set, so two of them racing
+    // can't both find no conversation and create one each
+    let mut key = members.to_vec();
+    key.sort();
+    sqlx::query("SELECT pg_advisory_xact_lock(hashtextextended($1, 0))")
+        .bind(format!("dm:{}", key.join(",")))
+        .execute(&mut *tx)
+        .await?;
+
+    let existing: Option<String> = sqlx::query_scalar(
+        r#"SELECT conversation_id FROM dm_participants GROUP BY conversation_id
+           HAVING COUNT(*) = cardinality($1::TEXT[]) AND bool_and(user_id = ANY($1))
+           LIMIT 1"#
+    )
+    .bind(members)
+    .fetch_optional(&mut *tx)
+    .await?;
+    let conversation_id = match existing {
+        Some(id) => id,
+        None => {
+            let id = Uuid::new_v4().to_string();
+            sqlx::query("INSERT INTO dm_conversations (id, created_at) VALUES ($1, $2)")
+                .bind(&id)
+                .bind(Utc::now())
+                .execute(&mut *tx)
+                .await?;
+            sqlx::query("INSERT INTO dm_participants (conversation_id, user_id) SELECT $1, unnest($2::TEXT[])")
+                .bind(&id)
+                .bind(members)
+                .execute(&mut *tx)
+                .await?;
+            id
+        }
+    };
+
+    let message_id = insert_message(&mut tx, &conversation_id, sender_id, content).await?;
+    tx.commit().await?;
+    Ok((conversation_id, message_id))
+}
+
+#[get("/conversations")]
+pub async fn list_conversations(db: web::Data<PgPool>, user: AuthUser) -> impl Responder {
+    let sql = format!(
+        r#"SELECT dc.id,
+                  ARRAY(SELECT u.username FROM dm_participants op JOIN users u ON u.id = op.user_id
+                        WHERE op.conversation_id = dc.id AND op.user_id <> $1 ORDER BY u.username) as participants,
+                  lu.username as last_sender,
+                  LEFT(lm.content, {}) as last_message,
+                  COALESCE(lm.created_at, dc.created_at) as last_message_at,
+                  (SELECT COUNT(*) FROM dm_messages m
+                   WHERE m.conversation_id = dc.id AND m.sender_id <> $1
+                     AND (p.last_read_at IS NULL OR m.created_at > p.last_read_at)) as unread_count
+           FROM dm_participants p
+           JOIN dm_conversations dc ON dc.id = p.conversation_id
+           LEFT JOIN LATERAL (SELECT m.sender_id, m.content, m.created_at FROM dm_messages m
+                              WHERE m.conversation_id = dc.id ORDER BY m.created_at DESC LIMIT 1) lm ON true
+           LEFT JOIN users lu ON lu.id = lm.sender_id
+           WHERE p.user_id = $1
+           ORDER BY last_message_at DESC"#,
+        EXCERPT_CHARS
+    );
+    let rows = sqlx::query_as::<_, Conversation>(&sql)
+        .bind(&user.id)
+        .fetch_all(&**db)
+        .await
+        .unwrap_or_else(|_| vec![]);
+
+    HttpResponse::Ok().json(rows)
+}
+
+/// Start a conversation with the first message. Writing to the same set of
+/// users again continues the existing conversation instead.
+#[post("/conversations")]
+pub async fn create_conversation(db: web::Data<PgPool>, user: AuthUser, payload: web::Json<NewConversation>) -> impl Responder {
+    let mut payload = payload.into_inner();
+    if let Err(errors) = payload.validate() {
+        return errors.response();
+    }
+    let found: Vec<(String, String)> = sqlx::query_as("SELECT id, username FROM users WHERE username = ANY($1)")
+        .bind(&payload.participants)
+        .fetch_all(&**db)
+        .await
+        .unwrap_or_default();
+    let mut errors = validation::Errors::default();
+    if let Some(missing) = payload.participants.iter().find(|name| !found.iter().any(|(_, n)| n == *name)) {
+        errors.add("participants", format!("no user named {}", missing));
+    } else if found.iter().any(|(id, _)| *id == user.id) {
+        errors.add("participants", "cannot include yourself".to_string());
+    }
+    if let Err(errors) = errors.finish() {
+        return errors.response();
+    }
+    let others: Vec<String> = found.into_iter().map(|(id, _)| id).collect();
+    if let Some(response) = send_problem(&db, &user.id, &others).await {
+        return response;
+    }
+
+    let mut members = others;
+    members.push(user.id.clone());
+    match start_conversation(&db, &members, &user.id, &payload.content).await {
+        Ok((conversation_id, message_id)) => {
+            HttpResponse::Created().json(serde_json::json!({ "id": conversation_id, "message_id": message_id }))
+        }
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
+
+/// A page of a conversation's history, oldest first. Pass the id of the
+/// oldest loaded message as `before` to get the page before it.
+#[get("/conversations/{id}/messages")]
+pub async fn list_messages(
+    db: web::Data<PgPool>,
+    user: AuthUser,
+    path: web::Path<String>,
+    query: web::Query<HistoryQuery>,
+) -> impl Responder {
+    let conversation_id = path.into_inner();
+    if !is_participant(&db, &conversation_id, &user.id).await {
+        return HttpResponse::NotFound().finish();
+    }
+    let limit = query.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT);
+    let rows = sqlx::query_as::<_, Message>(
+        r#"SELECT * FROM (
+               SELECT m.id, m.conversation_id, u.username as sender, m.content, m.created_at
+               FROM dm_messages m JOIN users u ON u.id = m.sender_id
+               WHERE m.conversation_id = $1
+                 AND ($2::TEXT IS NULL OR (m.created_at, m.id) <
+                      (SELECT b.created_at, b.id FROM dm_messages b WHERE b.id = $2 AND b.conversation_id = $1))
+               ORDER BY m.created_at DESC, m.id DESC
+               LIMIT $3
+           ) page ORDER BY created_at, id"#
+    )
+    .bind(&conversation_id)
+    .bind(&query.before)
+    .bind(limit)
+    .fetch_all(&**db)
+    .await
+    .unwrap_or_else(|_| vec![]);
+
+    HttpResponse::Ok().json(rows)
+}
+
+#[post("/messages")]
+pub async fn send_message(db: web::Data<PgPool>, user: AuthUser, payload: web::Json<NewMessage>) -> impl Responder {
+    let mut payload = payload.into_inner();
+    if let Err(errors) = payload.validate() {
+        return errors.response();
+    }
+    if !is_participant(&db, &payload.conversation_id, &user.id).await {
+        return HttpResponse::NotFound().finish();
+    }
+    let others: Vec<String> = sqlx::query_scalar(
+        "SELECT user_id FROM dm_participants WHERE conversation_id = $1 AND user_id <> $2"
+    )
+    .bind(&payload.conversation_id)
+    .bind(&user.id)
+    .fetch_all(&**db)
+    .await
+    .unwrap_or_default();
+    if let Some(response) = send_problem(&db, &user.id, &others).await {
+        return response;
+    }
+
+    let Ok(mut conn) = db.acquire().await else {
+        return HttpResponse::InternalServerError().finish();
+    };
+    match insert_message(&mut conn, &payload.conversation_id, &user.id, &payload.content).await {
+        Ok(id) => HttpResponse::Created().json(serde_json::json!({ "id": id })),
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
+
+#[post("/conversations/{id}/read")]
+pub async fn mark_conversation_read(db: web::Data<PgPool>, user: AuthUser, path: web::Path<String>) -> impl Responder {
+    let result = sqlx::query("UPDATE dm_participants SET last_read_at = $3 WHERE conversation_id = $1 AND user_id = $2")
+        .bind(path.into_inner())
+        .bind(&user.id)
+        .bind(Utc::now())
+        .execute(&**db)
+        .await;
+
+    match result {
+        Ok(r) if r.rows_affected() > 0 => HttpResponse::Ok().finish(),
+        Ok(_) => HttpResponse::NotFound().finish(),
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
+
+#[get("/messages/unread_count")]
+pub async fn unread_message_count(db: web::Data<PgPool>, user: AuthUser) -> impl Responder {
+    let count: i64 = sqlx::query_scalar(
+        r#"SELECT COUNT(*) FROM dm_participants p
+           JOIN dm_messages m ON m.conversation_id = p.conversation_id
+           WHERE p.user_id = $1 AND m.sender_id <> $1
+             AND (p.last_read_at IS NULL OR m.created_at > p.last_read_at)"#
+    )
+    .bind(&user.id)
+    .fetch_one(&**db)
+    .await
+    .unwrap_or(0);
+    HttpResponse::Ok().json(serde_json::json!({ "count": count }))
+}
+
+#[get("/blocks")]
+pub async fn list_blocks(db: web::Data<PgPool>, user: AuthUser) -> impl Responder {
+    let names: Vec<String> = sqlx::query_scalar(
+        r#"SELECT u.username FROM user_blocks b JOIN users u ON u.id = b.blocked_id
+           WHERE b.blocker_id = $1 ORDER BY u.username"#
+    )
+    .bind(&user.id)
+    .fetch_all(&**db)
+    .await
+    .unwrap_or_default();
+    HttpResponse::Ok().json(names)
+}
+
+/// Block a user: neither of you can message the other until it is lifted.
+#[post("/blocks/{username}")]
+pub async fn block_user(db: web::Data<PgPool>, user: AuthUser, path: web::Path<String>) -> impl Responder {
+    let blocked_id: Option<String> = sqlx::query_scalar("SELECT id FROM users WHERE username = $1")
+        .bind(path.into_inner())
+        .fetch_optional(&**db)
+        .await
+        .unwrap_or(None);
+    let Some(blocked_id) = blocked_id else {
+        return HttpResponse::NotFound().finish();
+    };
+    if blocked_id == user.id {
+        return HttpResponse::BadRequest().json(serde_json::json!({ "error": "You cannot block yourself" }));
+    }
+    let result = sqlx::query(
+        r#"INSERT INTO user_blocks (blocker_id, blocked_id, created_at) VALUES ($1, $2, $3)
+           ON CONFLICT DO NOTHING"#
+    )
+    .bind(&user.id)
+    .bind(&blocked_id)
+    .bind(Utc::now())
+    .execute(&**db)
+    .await;
+
+    match result {
+        Ok(_) => HttpResponse::Ok().finish(),
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
+
+#[delete("/blocks/{username}")]
+pub async fn unblock_user(db: web::Data<PgPool>, user: AuthUser, path: web::Path<String>) -> impl Responder {
+    let result = sqlx::query(
+        r#"DELETE FROM user_blocks WHERE blocker_id = $1
+           AND blocked_id = (SELECT id FROM users WHERE username = $2)"#
+    )
+    .bind(&user.id)
+    .bind(path.into_inner())
+    .execute(&**db)
+    .await;
+
+    match result {
+        Ok(_) => HttpResponse::Ok().finish(),
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
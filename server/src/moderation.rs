@@ -0,0 +1,446 @@
+// moderation.rs
+use actix_web::{get, post, web, HttpResponse, Responder};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::audit;
+use crate::trash;
+use crate::auth::{AuthUser, Moderator};
+
+pub async fn ensure_tables(pool: &PgPool) {
+    let _ = sqlx::query(
+        r#"CREATE TABLE IF NOT EXISTS reports (
+                id TEXT PRIMARY KEY,
+                reporter_id TEXT NOT NULL,
+                target_type TEXT NOT NULL,
+                target_id TEXT NOT NULL,
+                reason TEXT NOT NULL,
+                status TEXT NOT NULL DEFAULT 'open',
+                resolution TEXT,
+                resolved_by TEXT,
+                resolved_at TIMESTAMPTZ,
+                created_at TIMESTAMPTZ NOT NULL,
+                FOREIGN KEY (reporter_id) REFERENCES users(id),
+                FOREIGN KEY (resolved_by) REFERENCES users(id)
+            );"#
+    )
+    .execute(pool)
+    .await;
+    let _ = sqlx::query("CREATE INDEX IF NOT EXISTS idx_reports_status ON reports(status)")
+        .execute(pool)
+        .await;
+    // Content hidden by a moderator is left out of listings
+    let _ = sqlx::query("ALTER TABLE threads ADD COLUMN IF NOT EXISTS hidden BOOLEAN NOT NULL DEFAULT FALSE")
+        .execute(pool)
+        .await;
+    let _ = sqlx::query("ALTER TABLE comments ADD COLUMN IF NOT EXISTS hidden BOOLEAN NOT NULL DEFAULT FALSE")
+        .execute(pool)
+        .await;
+
+    // Account sanctions and the record of who applied them
+    let _ = sqlx::query("ALTER TABLE users ADD COLUMN IF NOT EXISTS banned BOOLEAN NOT NULL DEFAULT FALSE")
+        .execute(pool)
+        .await;
+    let _ = sqlx::query("ALTER TABLE users ADD COLUMN IF NOT EXISTS suspended_until TIMESTAMPTZ")
+        .execute(pool)
+        .await;
+    let _ = sqlx::query("ALTER TABLE users ADD COLUMN IF NOT EXISTS muted BOOLEAN NOT NULL DEFAULT FALSE")
+        .execute(pool)
+        .await;
+    let _ = sqlx::query(
+        r#"CREATE TABLE IF NOT EXISTS user_sanctions (
+                id TEXT PRIMARY KEY,
+                user_id TEXT NOT NULL,
+                moderator_id TEXT NOT NULL,
+                action TEXT NOT NULL,
+                reason TEXT NOT NULL,
+                until TIMESTAMPTZ,
+                created_at TIMESTAMPTZ NOT NULL,
+                FOREIGN KEY (user_id) REFERENCES users(id),
+                FOREIGN KEY (moderator_id) REFERENCES users(id)
+            );"#
+    )
+    .execute(pool)
+    .await;
+}
+
+/// Why `user_id` may not log in or post right now, if anything stops them.
+pub async fn account_block(db: &PgPool, user_id: &str) -> Option<String> {
+    let row: Option<(bool, Option<DateTime<Utc>>)> = sqlx::query_as(
+        r#"SELECT banned, suspended_until FROM users WHERE id = $1"#
+    )
+    .bind(user_id)
+    .fetch_optional(db)
+    .await
+    .unwrap_or(None);
+
+    match row {
+        Some((true, _)) => Some("This account has been banned".to_string()),
+        Some((_, Some(until))) if until > Utc::now() => Some(format!(
+            "This account is suspended until {}",
+            until.format("%Y-%m-%d %H:%M UTC")
+        )),
+        _ => None,
+    }
+}
+
+/// SQL condition for whether a thread `t`, joined with its author as `u`, can
+/// be seen: deleted and hidden threads only by moderators, held ones and those
+/// of muted authors by moderators and the author. `viewer` is the parameter
+/// holding the requesting user's id and `moderator` whether they moderate.
+pub fn thread_visible_sql(viewer: &str, moderator: &str) -> String {
+    format!(
+        r#"({moderator} OR (t.deleted_at IS NULL AND NOT t.hidden AND (NOT u.muted OR u.id = {viewer})
+                           AND (NOT t.held OR u.id = {viewer})))"#
+    )
+}
+
+#[derive(Debug, Deserialize)]
+pub struct NewReport {
+    // "thread" or "comment"
+    target_type: String,
+    target_id: String,
+    reason: String,
+}
+
+/// An open or closed report together with enough context to act on it.
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct Report {
+    id: String,
+    target_type: String,
+    target_id: String,
+    // Thread the reported content lives in (the target itself for threads)
+    thread_id: Option<String>,
+    thread_title: Option<String>,
+    target_author: Option<String>,
+    target_content: Option<String>,
+    reporter: String,
+    reason: String,
+    status: String,
+    resolution: Option<String>,
+    created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ReportQuery {
+    // "open" (default), "resolved" or "dismissed"
+    status: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Resolution {
+    // "keep" (default), "hide" or "delete"
+    action: Option<String>,
+}
+
+#[post("/reports")]
+pub async fn create_report(db: web::Data<PgPool>, user: AuthUser, payload: web::Json<NewReport>) -> impl Responder {
+    let reason = payload.reason.trim();
+    if reason.is_empty() {
+        return HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "A reason is required"
+        }));
+    }
+    let table = match payload.target_type.as_str() {
+        "thread" => "threads",
+        "comment" => "comments",
+        _ => return HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "target_type must be 'thread' or 'comment'"
+        })),
+    };
+    let exists = sqlx::query(&format!("SELECT 1 FROM {} WHERE id = $1 AND deleted_at IS NULL", table))
+        .bind(&payload.target_id)
+        .fetch_optional(&**db)
+        .await
+        .map(|row| row.is_some())
+        .unwrap_or(false);
+    if !exists {
+        return HttpResponse::NotFound().json(serde_json::json!({
+            "error": "Reported content not found"
+        }));
+    }
+
+    let id = Uuid::new_v4().to_string();
+    let result = sqlx::query(
+        r#"INSERT INTO reports (id, reporter_id, target_type, target_id, reason, created_at)
+           VALUES ($1, $2, $3, $4, $5, $6)"#
+    )
+    .bind(&id)
+    .bind(&user.id)
+    .bind(&payload.target_type)
+    .bind(&payload.target_id)
+    .bind(reason)
+    .bind(Utc::now())
+    .execute(&**db)
+    .await;
+
+    match result {
+        Ok(_) => HttpResponse::Created().json(serde_json::json!({ "id": id })),
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
+
+#[get("/moderation/reports")]
+pub async fn list_reports(db: web::Data<PgPool>, _moderator: Moderator, query: web::Query<ReportQuery>) -> impl Responder {
+    let status = query.status.clone().unwrap_or_else(|| "open".to_string());
+    let rows = sqlx::query_as::<_, Report>(
+        r#"SELECT r.id, r.target_type, r.target_id,
+                  COALESCE(t.id, c.thread_id) as thread_id,
+                  COALESCE(t.title, ct.title) as thread_title,
+                  COALESCE(tu.username, cu.username) as target_author,
+                  COALESCE(t.content, c.content) as target_content,
+                  ru.username as reporter, r.reason, r.status, r.resolution, r.created_at
+           FROM reports r
+           JOIN users ru ON ru.id = r.reporter_id
+           LEFT JOIN threads t ON r.target_type = 'thread' AND t.id = r.target_id
+           LEFT JOIN users tu ON tu.id = t.user_id
+           LEFT JOIN comments c ON r.target_type = 'comment' AND c.id = r.target_id
+           LEFT JOIN users cu ON cu.id = c.user_id
+           LEFT JOIN threads ct ON ct.id = c.thread_id
+           WHERE r.status = $1
+           ORDER BY r.created_at ASC"#
+    )
+    .bind(status)
+    .fetch_all(&**db)
+    .await
+    .unwrap_or_else(|_| vec![]);
+
+    HttpResponse::Ok().json(rows)
+}
+
+#[post("/moderation/reports/{id}/resolve")]
+pub async fn resolve_report(
+    db: web::Data<PgPool>,
+    moderator: Moderator,
+    path: web::Path<String>,
+    payload: web::Json<Resolution>,
+) -> impl Responder {
+    let report_id = path.into_inner();
+    let action = payload.action.clone().unwrap_or_else(|| "keep".to_string());
+    if !["keep", "hide", "delete"].contains(&action.as_str()) {
+        return HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "action must be 'keep', 'hide' or 'delete'"
+        }));
+    }
+
+    let target: Option<(String, String)> = sqlx::query_as(
+        r#"SELECT target_type, target_id FROM reports WHERE id = $1 AND status = 'open'"#
+    )
+    .bind(&report_id)
+    .fetch_optional(&**db)
+    .await
+    .unwrap_or(None);
+    let Some((target_type, target_id)) = target else {
+        return HttpResponse::NotFound().json(serde_json::json!({
+            "error": "No open report with that id"
+        }));
+    };
+
+    let table = if target_type == "thread" { "threads" } else { "comments" };
+    let before = audit::snapshot(&db, table, &target_id).await;
+
+    let applied = match (action.as_str(), target_type.as_str()) {
+        ("hide", "thread") => sqlx::query("UPDATE threads SET hidden = TRUE WHERE id = $1")
+            .bind(&target_id)
+            .execute(&**db)
+            .await
+            .map(|_| ()),
+        ("hide", _) => sqlx::query("UPDATE comments SET hidden = TRUE WHERE id = $1")
+            .bind(&target_id)
+            .execute(&**db)
+            .await
+            .map(|_| ()),
+        ("delete", _) => trash::soft_delete(&db, &target_type, &target_id, &moderator.0.id).await.map(|_| ()),
+        _ => Ok(()),
+    };
+    if applied.is_err() {
+        return HttpResponse::InternalServerError().finish();
+    }
+
+    // Acting on the content settles every open report about it
+    let _ = sqlx::query(
+        r#"UPDATE reports SET status = 'resolved', resolution = $1, resolved_by = $2, resolved_at = $3
+           WHERE status = 'open' AND target_type = $4 AND target_id = $5"#
+    )
+    .bind(&action)
+    .bind(&moderator.0.id)
+    .bind(Utc::now())
+    .bind(&target_type)
+    .bind(&target_id)
+    .execute(&**db)
+    .await;
+
+    let after = audit::snapshot(&db, table, &target_id).await;
+    audit::record(&db, Some(&moderator.0), &format!("report.{}", action), &target_type, Some(&target_id), before, after).await;
+
+    HttpResponse::Ok().finish()
+}
+
+#[post("/moderation/reports/{id}/dismiss")]
+pub async fn dismiss_report(db: web::Data<PgPool>, moderator: Moderator, path: web::Path<String>) -> impl Responder {
+    let report_id = path.into_inner();
+    let before = audit::snapshot(&db, "reports", &report_id).await;
+    let result = sqlx::query(
+        r#"UPDATE reports SET status = 'dismissed', resolved_by = $2, resolved_at = $3
+           WHERE id = $1 AND status = 'open'"#
+    )
+    .bind(&report_id)
+    .bind(&moderator.0.id)
+    .bind(Utc::now())
+    .execute(&**db)
+    .await;
+
+    match result {
+        Ok(r) if r.rows_affected() > 0 => {
+            let after = audit::snapshot(&db, "reports", &report_id).await;
+            audit::record(&db, Some(&moderator.0), "report.dismiss", "report", Some(&report_id), before, after).await;
+            HttpResponse::Ok().finish()
+        }
+        Ok(_) => HttpResponse::NotFound().finish(),
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct NewSanction {
+    // "ban", "unban", "suspend", "unsuspend", "mute" or "unmute"
+    action: String,
+    reason: String,
+    // Required for "suspend"
+    until: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct Sanction {
+    id: String,
+    username: String,
+    moderator: String,
+    action: String,
+    reason: String,
+    until: Option<DateTime<Utc>>,
+    created_at: DateTime<Utc>,
+}
+
+#[post("/moderation/users/{username}/sanctions")]
+pub async fn sanction_user(
+    db: web::Data<PgPool>,
+    moderator: Moderator,
+    path: web::Path<String>,
+    payload: web::Json<NewSanction>,
+) -> impl Responder {
+    let username = path.into_inner();
+    let reason = payload.reason.trim();
+    if reason.is_empty() {
+        return HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "A reason is required"
+        }));
+    }
+
+    let target: Option<(String, String)> = sqlx::query_as(r#"SELECT id, role FROM users WHERE username = $1"#)
+        .bind(&username)
+        .fetch_optional(&**db)
+        .await
+        .unwrap_or(None);
+    let Some((user_id, role)) = target else {
+        return HttpResponse::NotFound().json(serde_json::json!({
+            "error": "User not found"
+        }));
+    };
+    // Only admins may act against other staff
+    if role != "user" && moderator.0.role != "admin" {
+        return HttpResponse::Forbidden().json(serde_json::json!({
+            "error": "Only admins can sanction moderators"
+        }));
+    }
+
+    let before = user_status(&db, &user_id).await;
+
+    let update = match payload.action.as_str() {
+        "ban" => "UPDATE users SET banned = TRUE WHERE id = $1",
+        "unban" => "UPDATE users SET banned = FALSE WHERE id = $1",
+        "suspend" => match payload.until {
+            Some(until) if until > Utc::now() => "UPDATE users SET suspended_until = $2 WHERE id = $1",
+            _ => return HttpResponse::BadRequest().json(serde_json::json!({
+                "error": "Suspensions need an 'until' time in the future"
+            })),
+        },
+        "unsuspend" => "UPDATE users SET suspended_until = NULL WHERE id = $1",
+        "mute" => "UPDATE users SET muted = TRUE WHERE id = $1",
+        "unmute" => "UPDATE users SET muted = FALSE WHERE id = $1",
+        _ => return HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "Unknown action"
+        })),
+    };
+    if sqlx::query(update)
+        .bind(&user_id)
+        .bind(payload.until)
+        .execute(&**db)
+        .await
+        .is_err()
+    {
+        return HttpResponse::InternalServerError().finish();
+    }
+
+    // Banned and suspended users are logged out everywhere
+    if payload.action == "ban" || payload.action == "suspend" {
+        let _ = sqlx::query("DELETE FROM sessions WHERE user_id = $1")
+            .bind(&user_id)
+            .execute(&**db)
+            .await;
+    }
+
+    let _ = sqlx::query(
+        r#"INSERT INTO user_sanctions (id, user_id, moderator_id, action, reason, until, created_at)
+           VALUES ($1, $2, $3, $4, $5, $6, $7)"#
+    )
+    .bind(Uuid::new_v4().to_string())
+    .bind(&user_id)
+    .bind(&moderator.0.id)
+    .bind(&payload.action)
+    .bind(reason)
+    .bind(payload.until.filter(|_| payload.action == "suspend"))
+    .bind(Utc::now())
+    .execute(&**db)
+    .await;
+
+    let mut after = user_status(&db, &user_id).await;
+    if let Some(serde_json::Value::Object(fields)) = after.as_mut() {
+        fields.insert("reason".to_string(), reason.into());
+    }
+    audit::record(&db, Some(&moderator.0), &format!("user.{}", payload.action), "user", Some(&user_id), before, after).await;
+
+    HttpResponse::Ok().finish()
+}
+
+/// The sanction-related columns of a user, for audit before/after records.
+async fn user_status(db: &PgPool, user_id: &str) -> Option<serde_json::Value> {
+    sqlx::query_scalar(
+        r#"SELECT jsonb_build_object('username', username, 'banned', banned,
+                                     'suspended_until', suspended_until, 'muted', muted)
+           FROM users WHERE id = $1"#
+    )
+    .bind(user_id)
+    .fetch_optional(db)
+    .await
+    .unwrap_or(None)
+}
+
+#[get("/moderation/users/{username}/sanctions")]
+pub async fn list_sanctions(db: web::Data<PgPool>, _moderator: Moderator, path: web::Path<String>) -> impl Responder {
+    let rows = sqlx::query_as::<_, Sanction>(
+        r#"SELECT s.id, u.username, m.username as moderator, s.action, s.reason, s.until, s.created_at
+           FROM user_sanctions s
+           JOIN users u ON u.id = s.user_id
+           JOIN users m ON m.id = s.moderator_id
+           WHERE u.username = $1
+           ORDER BY s.created_at DESC"#
+    )
+    .bind(path.into_inner())
+    .fetch_all(&**db)
+    .await
+    .unwrap_or_else(|_| vec![]);
+
+    HttpResponse::Ok().json(rows)
+}
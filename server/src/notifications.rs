@@ -0,0 +1,335 @@
+// notifications.rs
+use actix_web::{delete, get, post, web, HttpResponse, Responder};
+use chrono::{DateTime, Utc};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use std::sync::OnceLock;
+use uuid::Uuid;
+
+use crate::auth::AuthUser;
+
+// Default and largest number of notifications returned at once
+const DEFAULT_LIMIT: i64 = 50;
+const MAX_LIMIT: i64 = 200;
+// Characters of the post quoted in a notification
+const EXCERPT_CHARS: i32 = 120;
+
+pub async fn ensure_tables(pool: &PgPool) {
+    let _ = sqlx::query(
+        r#"CREATE TABLE IF NOT EXISTS notifications (
+                id TEXT PRIMARY KEY,
+                user_id TEXT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+                kind TEXT NOT NULL,
+                actor_id TEXT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+                thread_id TEXT NOT NULL REFERENCES threads(id) ON DELETE CASCADE,
+                comment_id TEXT REFERENCES comments(id) ON DELETE CASCADE,
+                created_at TIMESTAMPTZ NOT NULL,
+                read_at TIMESTAMPTZ
+            );"#
+    )
+    .execute(pool)
+    .await;
+    let _ = sqlx::query("CREATE INDEX IF NOT EXISTS notifications_user ON notifications (user_id, created_at DESC)")
+        .execute(pool)
+        .await;
+
+    let existed: bool = sqlx::query_scalar("SELECT to_regclass('thread_subscriptions') IS NOT NULL")
+        .fetch_one(pool)
+        .await
+        .unwrap_or(true);
+    let _ = sqlx::query(
+        r#"CREATE TABLE IF NOT EXISTS thread_subscriptions (
+                user_id TEXT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+                thread_id TEXT NOT NULL REFERENCES threads(id) ON DELETE CASCADE,
+                created_at TIMESTAMPTZ NOT NULL,
+                PRIMARY KEY (user_id, thread_id)
+            );"#
+    )
+    .execute(pool)
+    .await;
+    // Authors of threads created before subscriptions existed are subscribed once
+    if !existed {
+        let _ = sqlx::query(
+            "INSERT INTO thread_subscriptions (user_id, thread_id, created_at) SELECT user_id, id, NOW() FROM threads"
+        )
+        .execute(pool)
+        .await;
+    }
+}
+
+/// Subscribe `user_id` to replies in `thread_id`; a no-op if already subscribed.
+pub async fn subscribe(db: &PgPool, user_id: &str, thread_id: &str) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        r#"INSERT INTO thread_subscriptions (user_id, thread_id, created_at) VALUES ($1, $2, $3)
+           ON CONFLICT DO NOTHING"#
+    )
+    .bind(user_id)
+    .bind(thread_id)
+    .bind(Utc::now())
+    .execute(db)
+    .await?;
+    Ok(())
+}
+
+/// Usernames mentioned as `@name` in `text`, without duplicates. An `@` inside
+/// a word (e.g. an email address) is not a mention. The name runs as far as
+/// username characters go, so a trailing '-' is part of it, and a run too long
+/// for a username mentions nobody rather than a shortened name.
+pub fn mentions(text: &str) -> Vec<String> {
+    static MENTION: OnceLock<Regex> = OnceLock::new();
+    let mention = MENTION.get_or_init(|| Regex::new(r"(?:^|[^A-Za-z0-9_@])@([A-Za-z0-9_-]+)").unwrap());
+    let mut names: Vec<String> = Vec::new();
+    for caps in mention.captures_iter(text) {
+        let name = caps[1].to_string();
+        if (3..=32).contains(&name.len()) && !names.contains(&name) {
+            names.push(name);
+        }
+    }
+    names
+}
+
+/// Create the notifications for a new, visible thread or comment: a mention
+/// for every user it @mentions and, for comments, a reply for every other
+/// subscriber of the thread. Called when the post is created, or when a
+/// moderator approves it if the content filter held it.
+pub async fn notify_post(db: &PgPool, target_type: &str, id: &str) {
+    let post: Option<(String, String, String, bool)> = match target_type {
+        "thread" => sqlx::query_as(
+            r#"SELECT t.user_id, t.id, t.title || E'\n' || t.content, u.muted
+               FROM threads t JOIN users u ON u.id = t.user_id WHERE t.id = $1"#
+        ),
+        _ => sqlx::query_as(
+            r#"SELECT c.user_id, c.thread_id, c.content, u.muted
+               FROM comments c JOIN users u ON u.id = c.user_id WHERE c.id = $1"#
+        ),
+    }
+    .bind(id)
+    .fetch_optional(db)
+    .await
+    .unwrap_or(None);
+    // Shadow-muted users' posts are invisible to everyone else, so they notify no one
+    let Some((author_id, thread_id, text, false)) = post else {
+        return;
+    };
+    let comment_id = (target_type == "comment").then_some(id);
+
+    let names = mentions(&text);
+    let mentioned: Vec<String> = if names.is_empty() {
+        Vec::new()
+    } else {
+        sqlx::query_scalar("SELECT id FROM users WHERE username = ANY($1) AND id <> $2")
+            .bind(&names)
+            .bind(&author_id)
+            .fetch_all(db)
+            .await
+            .unwrap_or_default()
+    };
+    for user_id in &mentioned {
+        notify(db, user_id, "mention", &author_id, &thread_id, comment_id).await;
+    }
+
+    if comment_id.is_none() {
+        return;
+    }
+    // Subscribers who were also mentioned already have a notification for this comment
+    let subscribers: Vec<String> = sqlx::query_scalar(
+        "SELECT user_id FROM thread_subscriptions WHERE thread_id = $1 AND user_id <> $2 AND NOT user_id = ANY($3)"
+    )
+    .bind(&thread_id)
+    .bind(&author_id)
+    .bind(&mentioned)
+    .fetch_all(db)
+    .await
+    .unwrap_or_default();
+    for user_id in &subscribers {
+        notify(db, user_id, "reply", &author_id, &thread_id, comment_id).await;
+    }
+}
+
+async fn notify(db: &PgPool, user_id: &str, kind: &str, actor_id: &str, thread_id: &str, comment_id: Option<&str>) {
+    let result = sqlx::query(
+        r#"INSERT INTO notifications (id, user_id, kind, actor_id, thread_id, comment_id, created_at)
+           VALUES ($1, $2, $3, $4, $5, $6, $7)"#
+    )
+    .bind(Uuid::new_v4().to_string())
+    .bind(user_id)
+    .bind(kind)
+    .bind(actor_id)
+    .bind(thread_id)
+    .bind(comment_id)
+    .bind(Utc::now())
+    .execute(db)
+    .await;
+    if let Err(e) = result {
+        log::error!("failed to create {} notification for {}: {}", kind, user_id, e);
+    }
+}
+
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct Notification {
+    id: String,
+    // "mention" or "reply"
+    kind: String,
+    actor: String,
+    thread_id: String,
+    thread_title: String,
+    comment_id: Option<String>,
+    // Start of the post that caused the notification
+    excerpt: String,
+    created_at: DateTime<Utc>,
+    read: bool,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct NotificationQuery {
+    // Only unread notifications
+    #[serde(default)]
+    unread: bool,
+    limit: Option<i64>,
+}
+
+// Notifications about content that has since been deleted or hidden are left out
+const VISIBLE: &str = r#"t.deleted_at IS NULL AND NOT t.hidden
+    AND (n.comment_id IS NULL OR (c.deleted_at IS NULL AND NOT c.hidden))"#;
+
+#[post("/threads/{id}/subscription")]
+pub async fn subscribe_thread(db: web::Data<PgPool>, user: AuthUser, path: web::Path<String>) -> impl Responder {
+    let thread_id = path.into_inner();
+    let exists: Option<i32> = sqlx::query_scalar("SELECT 1 FROM threads WHERE id = $1 AND deleted_at IS NULL")
+        .bind(&thread_id)
+        .fetch_optional(&**db)
+        .await
+        .unwrap_or(None);
+    if exists.is_none() {
+        return HttpResponse::NotFound().finish();
+    }
+    match subscribe(&db, &user.id, &thread_id).await {
+        Ok(()) => HttpResponse::Ok().json(serde_json::json!({ "subscribed": true })),
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
+
+#[delete("/threads/{id}/subscription")]
+pub async fn unsubscribe_thread(db: web::Data<PgPool>, user: AuthUser, path: web::Path<String>) -> impl Responder {
+    let result = sqlx::query("DELETE FROM thread_subscriptions WHERE user_id = $1 AND thread_id = $2")
+        .bind(&user.id)
+        .bind(path.into_inner())
+        .execute(&**db)
+        .await;
+
+    match result {
+        Ok(_) => HttpResponse::Ok().json(serde_json::json!({ "subscribed": false })),
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
+
+#[get("/notifications")]
+pub async fn list_notifications(db: web::Data<PgPool>, user: AuthUser, query: web::Query<NotificationQuery>) -> impl Responder {
+    let limit = query.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT);
+    let sql = format!(
+        r#"SELECT n.id, n.kind, a.username as actor, n.thread_id, t.title as thread_title, n.comment_id,
+                  LEFT(COALESCE(c.content, t.content), {}) as excerpt, n.created_at, n.read_at IS NOT NULL as read
+           FROM notifications n
+           JOIN users a ON a.id = n.actor_id
+           JOIN threads t ON t.id = n.thread_id
+           LEFT JOIN comments c ON c.id = n.comment_id
+           WHERE n.user_id = $1 AND (NOT $2 OR n.read_at IS NULL) AND {}
+           ORDER BY n.created_at DESC
+           LIMIT $3"#,
+        EXCERPT_CHARS, VISIBLE
+    );
+    let rows = sqlx::query_as::<_, Notification>(&sql)
+        .bind(&user.id)
+        .bind(query.unread)
+        .bind(limit)
+        .fetch_all(&**db)
+        .await
+        .unwrap_or_else(|_| vec![]);
+
+    HttpResponse::Ok().json(rows)
+}
+
+#[get("/notifications/unread_count")]
+pub async fn unread_count(db: web::Data<PgPool>, user: AuthUser) -> impl Responder {
+    let sql = format!(
+        r#"SELECT COUNT(*) FROM notifications n
+           JOIN threads t ON t.id = n.thread_id
+           LEFT JOIN comments c ON c.id = n.comment_id
+           WHERE n.user_id = $1 AND n.read_at IS NULL AND {}"#,
+        VISIBLE
+    );
+    let count: i64 = sqlx::query_scalar(&sql)
+        .bind(&user.id)
+        .fetch_one(&**db)
+        .await
+        .unwrap_or(0);
+    HttpResponse::Ok().json(serde_json::json!({ "count": count }))
+}
+
+#[post("/notifications/{id}/read")]
+pub async fn mark_read(db: web::Data<PgPool>, user: AuthUser, path: web::Path<String>) -> impl Responder {
+    let result = sqlx::query(
+        "UPDATE notifications SET read_at = COALESCE(read_at, $1) WHERE id = $2 AND user_id = $3"
+    )
+    .bind(Utc::now())
+    .bind(path.into_inner())
+    .bind(&user.id)
+    .execute(&**db)
+    .await;
+
+    match result {
+        Ok(r) if r.rows_affected() > 0 => HttpResponse::Ok().finish(),
+        Ok(_) => HttpResponse::NotFound().finish(),
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
+
+#[post("/notifications/read")]
+pub async fn mark_all_read(db: web::Data<PgPool>, user: AuthUser) -> impl Responder {
+    let result = sqlx::query("UPDATE notifications SET read_at = $1 WHERE user_id = $2 AND read_at IS NULL")
+        .bind(Utc::now())
+        .bind(&user.id)
+        .execute(&**db)
+        .await;
+
+    match result {
+        Ok(_) => HttpResponse::Ok().finish(),
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_mentions_at_start_and_after_punctuation() {
+        assert_eq!(mentions("@alice look, (@bob_2) and @carol-x!"), ["alice", "bob_2", "carol-x"]);
+        assert_eq!(mentions("first line\n@dave"), ["dave"]);
+    }
+
+    #[test]
+    fn ignores_emails_and_doubled_at_signs() {
+        assert!(mentions("mail me at someone@example.com").is_empty());
+        assert!(mentions("@@alice").is_empty());
+    }
+
+    #[test]
+    fn respects_username_length() {
+        assert!(mentions("hi @ab").is_empty());
+        assert_eq!(mentions(&format!("@{}", "a".repeat(32))), ["a".repeat(32)]);
+        assert!(mentions(&format!("@{}", "a".repeat(33))).is_empty());
+    }
+
+    #[test]
+    fn names_keep_their_trailing_dashes() {
+        assert_eq!(mentions("hi @alice- there"), ["alice-"]);
+        assert_eq!(mentions("thanks @bob--, @carol."), ["bob--", "carol"]);
+    }
+
+    #[test]
+    fn lists_each_name_once() {
+        assert_eq!(mentions("@alice @bob @alice"), ["alice", "bob"]);
+    }
+}
@@ -0,0 +1,274 @@
+// profiles.rs
+use actix_web::{delete, get, http::header, patch, put, web, HttpResponse, Responder};
+use base64::Engine;
+use chrono::{DateTime, Utc};
+use image::imageops::FilterType;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use sqlx::PgPool;
+use std::io::Cursor;
+
+use crate::auth::AuthUser;
+use crate::validation::{self, Validate};
+
+// Posts listed under "recent activity"
+const RECENT_ACTIVITY: i64 = 10;
+// Characters of each post shown in the activity list
+const EXCERPT_CHARS: i32 = 120;
+// Largest accepted upload, and the largest image dimensions we will decode
+const MAX_AVATAR_BYTES: usize = 1024 * 1024;
+const MAX_AVATAR_DIMENSION: u32 = 4096;
+// Avatars are stored as square PNGs of this many pixels
+const AVATAR_SIZE: u32 = 64;
+
+pub async fn ensure_tables(pool: &PgPool) {
+    for column in ["display_name", "bio", "signature", "location"] {
+        let _ = sqlx::query(&format!("ALTER TABLE users ADD COLUMN IF NOT EXISTS {} TEXT", column))
+            .execute(pool)
+            .await;
+    }
+    let _ = sqlx::query("ALTER TABLE users ADD COLUMN IF NOT EXISTS avatar BYTEA")
+        .execute(pool)
+        .await;
+    // Changes whenever the avatar does, so clients can cache avatars by it
+    let _ = sqlx::query("ALTER TABLE users ADD COLUMN IF NOT EXISTS avatar_hash TEXT")
+        .execute(pool)
+        .await;
+}
+
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct Profile {
+    username: String,
+    display_name: Option<String>,
+    bio: Option<String>,
+    signature: Option<String>,
+    location: Option<String>,
+    // Hash of the avatar image, see GET /users/{username}/avatar
+    avatar: Option<String>,
+    role: String,
+    created_at: DateTime<Utc>,
+    thread_count: i64,
+    comment_count: i64,
+    #[sqlx(skip)]
+    recent_activity: Vec<Activity>,
+}
+
+/// A thread or comment the user posted.
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct Activity {
+    // "thread" or "comment"
+    kind: String,
+    thread_id: String,
+    thread_title: String,
+    comment_id: Option<String>,
+    excerpt: String,
+    created_at: DateTime<Utc>,
+}
+
+/// Fields left out are unchanged; an empty string clears a field.
+#[derive(Debug, Deserialize)]
+pub struct ProfileUpdate {
+    display_name: Option<String>,
+    bio: Option<String>,
+    signature: Option<String>,
+    location: Option<String>,
+}
+
+impl Validate for ProfileUpdate {
+    fn validate(&mut self) -> Result<(), validation::Errors> {
+        let mut errors = validation::Errors::default();
+        let fields = [
+            (&mut self.display_name, &validation::DISPLAY_NAME),
+            (&mut self.bio, &validation::BIO),
+            (&mut self.signature, &validation::SIGNATURE),
+            (&mut self.location, &validation::LOCATION),
+        ];
+        for (value, field) in fields {
+            if let Some(v) = value {
+                *value = Some(errors.check(field, v));
+            }
+        }
+        errors.finish()
+    }
+}
+
+// Posts the viewer ($2) can see: shadow-muted users' posts only show to themselves
+const VISIBLE_THREADS: &str = "t.deleted_at IS NULL AND NOT t.hidden AND NOT t.held AND (NOT u.muted OR u.id = $2)";
+const VISIBLE_COMMENTS: &str = r#"c.deleted_at IS NULL AND NOT c.hidden AND NOT c.held AND (NOT u.muted OR u.id = $2)
+    AND t.deleted_at IS NULL AND NOT t.hidden"#;
+
+async fn load_profile(db: &PgPool, username: &str, viewer_id: Option<&str>) -> Option<Profile> {
+    let sql = format!(
+        r#"SELECT u.username, u.display_name, u.bio, u.signature, u.location, u.avatar_hash as avatar, u.role, u.created_at,
+                  (SELECT COUNT(*) FROM threads t WHERE t.user_id = u.id AND {}) as thread_count,
+                  (SELECT COUNT(*) FROM comments c JOIN threads t ON t.id = c.thread_id
+                   WHERE c.user_id = u.id AND {}) as comment_count
+           FROM users u WHERE u.username = $1"#,
+        VISIBLE_THREADS, VISIBLE_COMMENTS
+    );
+    let mut profile = sqlx::query_as::<_, Profile>(&sql)
+        .bind(username)
+        .bind(viewer_id)
+        .fetch_optional(db)
+        .await
+        .unwrap_or(None)?;
+
+    let sql = format!(
+        r#"SELECT * FROM (
+               SELECT 'thread' as kind, t.id as thread_id, t.title as thread_title, NULL::TEXT as comment_id,
+                      LEFT(t.content, {excerpt}) as excerpt, t.created_at
+               FROM threads t JOIN users u ON u.id = t.user_id
+               WHERE u.username = $1 AND {threads}
+               UNION ALL
+               SELECT 'comment', t.id, t.title, c.id, LEFT(c.content, {excerpt}), c.created_at
+               FROM comments c JOIN threads t ON t.id = c.thread_id JOIN users u ON u.id = c.user_id
+               WHERE u.username = $1 AND {comments}
+           ) activity
+           ORDER BY created_at DESC
+           LIMIT $3"#,
+        excerpt = EXCERPT_CHARS,
+        threads = VISIBLE_THREADS,
+        comments = VISIBLE_COMMENTS
+    );
+    profile.recent_activity = sqlx::query_as::<_, Activity>(&sql)
+        .bind(username)
+        .bind(viewer_id)
+        .bind(RECENT_ACTIVITY)
+        .fetch_all(db)
+        .await
+        .unwrap_or_default();
+    Some(profile)
+}
+
+#[get("/users/{username}")]
+pub async fn get_profile(db: web::Data<PgPool>, user: Option<AuthUser>, path: web::Path<String>) -> impl Responder {
+    let username = validation::normalize(&validation::USERNAME, &path.into_inner());
+    match load_profile(&db, &username, user.as_ref().map(|u| u.id.as_str())).await {
+        Some(profile) => HttpResponse::Ok().json(profile),
+        None => HttpResponse::NotFound().finish(),
+    }
+}
+
+#[patch("/users/me")]
+pub async fn update_profile(db: web::Data<PgPool>, user: AuthUser, payload: web::Json<ProfileUpdate>) -> impl Responder {
+    let mut payload = payload.into_inner();
+    if let Err(errors) = payload.validate() {
+        return errors.response();
+    }
+    let result = sqlx::query(
+        r#"UPDATE users SET
+               display_name = CASE WHEN $2::TEXT IS NULL THEN display_name ELSE NULLIF($2, '') END,
+               bio = CASE WHEN $3::TEXT IS NULL THEN bio ELSE NULLIF($3, '') END,
+               signature = CASE WHEN $4::TEXT IS NULL THEN signature ELSE NULLIF($4, '') END,
+               location = CASE WHEN $5::TEXT IS NULL THEN location ELSE NULLIF($5, '') END
+           WHERE id = $1"#
+    )
+    .bind(&user.id)
+    .bind(&payload.display_name)
+    .bind(&payload.bio)
+    .bind(&payload.signature)
+    .bind(&payload.location)
+    .execute(&**db)
+    .await;
+    if result.is_err() {
+        return HttpResponse::InternalServerError().finish();
+    }
+
+    match load_profile(&db, &user.username, Some(&user.id)).await {
+        Some(profile) => HttpResponse::Ok().json(profile),
+        None => HttpResponse::NotFound().finish(),
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AvatarUpload {
+    // "data:image/png;base64,...", as produced for post images
+    data_url: String,
+}
+
+fn avatar_error(message: String) -> HttpResponse {
+    let mut errors = validation::Errors::default();
+    errors.add("avatar", message);
+    errors.response()
+}
+
+/// Decode an uploaded image and turn it into the stored square PNG.
+fn process_avatar(bytes: &[u8]) -> Result<Vec<u8>, String> {
+    let mut reader = image::io::Reader::new(Cursor::new(bytes))
+        .with_guessed_format()
+        .map_err(|e| e.to_string())?;
+    let mut limits = image::io::Limits::default();
+    limits.max_image_width = Some(MAX_AVATAR_DIMENSION);
+    limits.max_image_height = Some(MAX_AVATAR_DIMENSION);
+    reader.limits(limits);
+    let image = reader.decode().map_err(|_| "is not a PNG, JPEG, GIF or WebP image".to_string())?;
+
+    // Crop to the centered square so faces don't get squashed
+    let side = image.width().min(image.height());
+    let square = image.crop_imm((image.width() - side) / 2, (image.height() - side) / 2, side, side);
+    let mut png = Vec::new();
+    square
+        .resize_exact(AVATAR_SIZE, AVATAR_SIZE, FilterType::Triangle)
+        .write_to(&mut Cursor::new(&mut png), image::ImageOutputFormat::Png)
+        .map_err(|e| e.to_string())?;
+    Ok(png)
+}
+
+#[put("/users/me/avatar")]
+pub async fn upload_avatar(db: web::Data<PgPool>, user: AuthUser, payload: web::Json<AvatarUpload>) -> impl Responder {
+    let Some((_, data)) = payload.data_url.split_once(";base64,") else {
+        return avatar_error("must be a base64 data URL".to_string());
+    };
+    let Ok(bytes) = base64::engine::general_purpose::STANDARD.decode(data.trim()) else {
+        return avatar_error("must be a base64 data URL".to_string());
+    };
+    if bytes.len() > MAX_AVATAR_BYTES {
+        return avatar_error(format!("must be at most {} KB", MAX_AVATAR_BYTES / 1024));
+    }
+    let png = match web::block(move || process_avatar(&bytes)).await {
+        Ok(Ok(png)) => png,
+        Ok(Err(message)) => return avatar_error(message),
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
+    let hash: String = Sha256::digest(&png).iter().take(8).map(|b| format!("{:02x}", b)).collect();
+
+    let result = sqlx::query("UPDATE users SET avatar = $2, avatar_hash = $3 WHERE id = $1")
+        .bind(&user.id)
+        .bind(&png)
+        .bind(&hash)
+        .execute(&**db)
+        .await;
+    match result {
+        Ok(_) => HttpResponse::Ok().json(serde_json::json!({ "avatar": hash })),
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
+
+#[delete("/users/me/avatar")]
+pub async fn delete_avatar(db: web::Data<PgPool>, user: AuthUser) -> impl Responder {
+    let result = sqlx::query("UPDATE users SET avatar = NULL, avatar_hash = NULL WHERE id = $1")
+        .bind(&user.id)
+        .execute(&**db)
+        .await;
+    match result {
+        Ok(_) => HttpResponse::Ok().finish(),
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
+
+/// The user's avatar as a PNG, with its hash as the ETag.
+#[get("/users/{username}/avatar")]
+pub async fn get_avatar(db: web::Data<PgPool>, path: web::Path<String>) -> impl Responder {
+    let row: Option<(Option<Vec<u8>>, Option<String>)> = sqlx::query_as("SELECT avatar, avatar_hash FROM users WHERE username = $1")
+        .bind(path.into_inner())
+        .fetch_optional(&**db)
+        .await
+        .unwrap_or(None);
+    match row {
+        Some((Some(png), Some(hash))) => HttpResponse::Ok()
+            .content_type("image/png")
+            .insert_header((header::ETAG, format!("\"{}\"", hash)))
+            .body(png),
+        _ => HttpResponse::NotFound().finish(),
+    }
+}
@@ -0,0 +1,582 @@
+// rate_limit.rs
+use actix_web::{
+    body::{BoxBody, MessageBody},
+    dev::{ServiceRequest, ServiceResponse},
+    http::{header, header::HeaderMap, Method},
+    middleware::Next,
+    web, Error, HttpResponse,
+};
+use sqlx::PgPool;
+use std::collections::{HashMap, VecDeque};
+use std::net::IpAddr;
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+/// How many requests of one kind a client may make within `window`.
+struct Budget {
+    name: &'static str,
+    method: Method,
+    path: &'static str,
+    max: usize,
+    window: Duration,
+    // Message shown to the client, followed by the cooldown
+    message: &'static str,
+}
+
+fn budgets() -> [Budget; 13] {
+    [
+        login_budget(),
+        // Key login takes two requests per attempt
+        Budget {
+            name: "key_challenge",
+            method: Method::POST,
+            path: "/auth/key/challenge",
+            max: 10,
+            window: Duration::from_secs(5 * 60),
+            message: "Too many login attempts",
+        },
+        Budget {
+            name: "key_login",
+            method: Method::POST,
+            path: "/auth/key/login",
+            max: 10,
+            window: Duration::from_secs(5 * 60),
+            message: "Too many login attempts",
+        },
+        Budget {
+            name: "register",
+            method: Method::POST,
+            path: "/auth/register",
+            max: 5,
+            window: Duration::from_secs(60 * 60),
+            message: "Too many registrations",
+        },
+        Budget {
+            name: "thread",
+            method: Method::POST,
+            path: "/threads",
+            max: 5,
+            window: Duration::from_secs(10 * 60),
+            message: "You are creating threads too quickly",
+        },
+        Budget {
+            name: "comment",
+            method: Method::POST,
+            path: "/comments",
+            max: 10,
+            window: Duration::from_secs(60),
+            message: "You are posting too quickly",
+        },
+        Budget {
+            name: "conversation",
+            method: Method::POST,
+            path: "/conversations",
+            max: 10,
+            window: Duration::from_secs(10 * 60),
+            message: "You are starting conversations too quickly",
+        },
+        Budget {
+            name: "message",
+            method: Method::POST,
+            path: "/messages",
+            max: 20,
+            window: Duration::from_secs(60),
+            message: "You are sending messages too quickly",
+        },
+        // Both check the current password, so they are limited like logins
+        Budget {
+            name: "password",
+            method: Method::PUT,
+            path: "/users/me/password",
+            max: 5,
+            window: Duration::from_secs(10 * 60),
+            message: "Too many password changes",
+        },
+        Budget {
+            name: "account_delete",
+            method: Method::DELETE,
+            path: "/users/me",
+            max: 5,
+            window: Duration::from_secs(10 * 60),
+            message: "Too many attempts to delete the account",
+        },
+        Budget {
+            name: "export",
+            method: Method::GET,
+            path: "/users/me/export",
+            max: 5,
+            window: Duration::from_secs(60 * 60),
+            message: "Too many exports",
+        },
+        // Six-digit codes must not be guessable by trying them all
+        Budget {
+            name: "2fa_enable",
+            method: Method::POST,
+            path: "/auth/2fa/enable",
+            max: 5,
+            window: Duration::from_secs(10 * 60),
+            message: "Too many two-factor codes",
+        },
+        Budget {
+            name: "2fa_disable",
+            method: Method::POST,
+            path: "/auth/2fa/disable",
+            max: 5,
+            window: Duration::from_secs(10 * 60),
+            message: "Too many two-factor codes",
+        },
+    ]
+}
+
+/// Password logins per address, over HTTP and SSH alike.
+fn login_budget() -> Budget {
+    Budget {
+        name: "login",
+        method: Method::POST,
+        path: "/auth/login",
+        max: 10,
+        window: Duration::from_secs(5 * 60),
+        message: "Too many login attempts",
+    }
+}
+
+/// Failed logins at one account from any address, checked by the login handler
+/// once it knows the username, so spreading guesses over addresses doesn't help.
+fn account_budget() -> Budget {
+    Budget {
+        name: "login_account",
+        method: Method::POST,
+        path: "/auth/login",
+        max: 20,
+        window: Duration::from_secs(15 * 60),
+        message: "Too many login attempts for this account",
+    }
+}
+
+/// Wrong two-factor codes tried at one account; the password alone gets this far.
+fn second_factor_budget() -> Budget {
+    Budget {
+        name: "login_2fa",
+        method: Method::POST,
+        path: "/auth/login",
+        max: 5,
+        window: Duration::from_secs(10 * 60),
+        message: "Too many two-factor codes",
+    }
+}
+
+// Sweep idle keys once the table grows past this many
+const SWEEP_THRESHOLD: usize = 10_000;
+
+/// Sliding-window request log per (budget, client) key, shared by all workers.
+#[derive(Default)]
+pub struct RateLimiter {
+    hits: Mutex<HashMap<(&'static str, String), VecDeque<Instant>>>,
+}
+
+impl RateLimiter {
+    /// Record a hit for every key, or return how long until the first key that
+    /// is over budget frees up. Nothing is recorded when the request is refused.
+    fn check(&self, budget: &Budget, keys: &[String]) -> Option<Duration> {
+        let now = Instant::now();
+        let mut hits = self.hits.lock().unwrap();
+        if hits.len() > SWEEP_THRESHOLD {
+            hits.retain(|_, log| log.back().is_some_and(|t| now.duration_since(*t) < Duration::from_secs(60 * 60)));
+        }
+
+        for key in keys {
+            let log = hits.entry((budget.name, key.clone())).or_default();
+            while log.front().is_some_and(|t| now.duration_since(*t) >= budget.window) {
+                log.pop_front();
+            }
+            if log.len() >= budget.max {
+                let oldest = *log.front().unwrap();
+                return Some(budget.window.saturating_sub(now.duration_since(oldest)));
+            }
+        }
+        for key in keys {
+            hits.entry((budget.name, key.clone())).or_default().push_back(now);
+        }
+        None
+    }
+
+    /// How long until `key` is back under budget, without recording a hit.
+    fn wait(&self, budget: &Budget, key: &str) -> Option<Duration> {
+        let now = Instant::now();
+        let hits = self.hits.lock().unwrap();
+        let log = hits.get(&(budget.name, key.to_string()))?;
+        let recent: Vec<&Instant> = log.iter().filter(|t| now.duration_since(**t) < budget.window).collect();
+        if recent.len() < budget.max {
+            return None;
+        }
+        Some(budget.window.saturating_sub(now.duration_since(*recent[0])))
+    }
+
+    fn record(&self, budget: &Budget, key: &str) {
+        let now = Instant::now();
+        let mut hits = self.hits.lock().unwrap();
+        let log = hits.entry((budget.name, key.to_string())).or_default();
+        while log.front().is_some_and(|t| now.duration_since(*t) >= budget.window) {
+            log.pop_front();
+        }
+        log.push_back(now);
+    }
+
+    /// The refusal if `username` has had too many failed logins. Only failures
+    /// count (see `login_failed`), so logging in correctly never locks anyone out.
+    pub fn login_refused(&self, username: &str) -> Option<HttpResponse> {
+        self.account_wait(&account_budget(), username).map(|wait| too_many(&account_budget(), wait))
+    }
+
+    /// Count a wrong password, or a login at an account that doesn't exist.
+    pub fn login_failed(&self, username: &str) {
+        self.record(&account_budget(), &account_key(username));
+    }
+
+    /// The refusal if too many wrong two-factor codes were tried at `username`.
+    pub fn second_factor_refused(&self, username: &str) -> Option<HttpResponse> {
+        self.account_wait(&second_factor_budget(), username)
+            .map(|wait| too_many(&second_factor_budget(), wait))
+    }
+
+    /// Count a wrong two-factor code.
+    pub fn second_factor_failed(&self, username: &str) {
+        self.record(&second_factor_budget(), &account_key(username));
+    }
+
+    /// Count an SSH password login from `ip` against the same address budget
+    /// as HTTP logins, and check the account's failed logins; the message to
+    /// show when it is refused.
+    pub fn ssh_login_attempt(&self, ip: IpAddr, username: &str) -> Option<String> {
+        let budget = login_budget();
+        if let Some(wait) = self.check(&budget, &[format!("ip:{}", ip.to_canonical())]) {
+            log::warn!("rate limited ssh {} for {}", budget.name, ip);
+            return Some(refusal(&budget, wait));
+        }
+        self.account_wait(&account_budget(), username).map(|wait| refusal(&account_budget(), wait))
+    }
+
+    /// The message to show if too many wrong two-factor codes were tried at
+    /// `username`, over SSH.
+    pub fn ssh_second_factor_refused(&self, username: &str) -> Option<String> {
+        self.account_wait(&second_factor_budget(), username)
+            .map(|wait| refusal(&second_factor_budget(), wait))
+    }
+
+    fn account_wait(&self, budget: &Budget, username: &str) -> Option<Duration> {
+        let wait = self.wait(budget, &account_key(username))?;
+        log::warn!("rate limited {} for account {}", budget.name, username);
+        Some(wait)
+    }
+}
+
+fn account_key(username: &str) -> String {
+    format!("account:{}", username)
+}
+
+/// What to tell a refused client.
+fn refusal(budget: &Budget, wait: Duration) -> String {
+    format!("{}; try again in {}s", budget.message, wait.as_secs().max(1))
+}
+
+/// 429 response for a refused request, with `Retry-After`.
+fn too_many(budget: &Budget, wait: Duration) -> HttpResponse {
+    let seconds = wait.as_secs().max(1);
+    HttpResponse::TooManyRequests()
+        .insert_header((header::RETRY_AFTER, seconds.to_string()))
+        .json(serde_json::json!({
+            "error": refusal(budget, wait),
+            "retry_after": seconds
+        }))
+}
+
+/// An address range from `TRUSTED_PROXIES`, written `ip` or `ip/prefix`.
+struct ProxyRange {
+    network: IpAddr,
+    prefix: u32,
+}
+
+impl ProxyRange {
+    fn parse(text: &str) -> Option<Self> {
+        let (ip, prefix) = match text.split_once('/') {
+            Some((ip, prefix)) => (ip, Some(prefix)),
+            None => (text, None),
+        };
+        let network = ip.trim().parse::<IpAddr>().ok()?.to_canonical();
+        let width = bits(network).1;
+        let prefix = match prefix {
+            Some(prefix) => prefix.trim().parse().ok().filter(|p| *p <= width)?,
+            None => width,
+        };
+        Some(Self { network, prefix })
+    }
+
+    fn contains(&self, ip: IpAddr) -> bool {
+        let ((network, width), (ip, ip_width)) = (bits(self.network), bits(ip.to_canonical()));
+        let shift = width - self.prefix;
+        width == ip_width && network.checked_shr(shift).unwrap_or(0) == ip.checked_shr(shift).unwrap_or(0)
+    }
+}
+
+fn bits(ip: IpAddr) -> (u128, u32) {
+    match ip {
+        IpAddr::V4(ip) => (u32::from(ip) as u128, 32),
+        IpAddr::V6(ip) => (u128::from(ip), 128),
+    }
+}
+
+/// Proxies whose `X-Forwarded-For` is believed, from the comma-separated
+/// `TRUSTED_PROXIES`. Loopback is always trusted: the SSH server forwards
+/// its sessions' addresses through it.
+fn trusted_proxies() -> &'static [ProxyRange] {
+    static PROXIES: OnceLock<Vec<ProxyRange>> = OnceLock::new();
+    PROXIES.get_or_init(|| {
+        let list = std::env::var("TRUSTED_PROXIES").unwrap_or_default();
+        list.split(',')
+            .map(str::trim)
+            .filter(|entry| !entry.is_empty())
+            .filter_map(|entry| {
+                let range = ProxyRange::parse(entry);
+                if range.is_none() {
+                    log::warn!("ignoring invalid TRUSTED_PROXIES entry {:?}", entry);
+                }
+                range
+            })
+            .collect()
+    })
+}
+
+/// Address of the client behind a connection from `peer`.
+pub fn client_ip(peer: IpAddr, headers: &HeaderMap) -> IpAddr {
+    let forwarded_for = headers
+        .get_all("x-forwarded-for")
+        .filter_map(|value| value.to_str().ok())
+        .collect::<Vec<_>>()
+        .join(",");
+    forwarded_client(peer, &forwarded_for, trusted_proxies())
+}
+
+/// Walk `X-Forwarded-For` from the right for as long as the hop that added the
+/// entry is trusted. Anything a client wrote itself sits left of its own
+/// address and so is never reached.
+fn forwarded_client(peer: IpAddr, forwarded_for: &str, trusted: &[ProxyRange]) -> IpAddr {
+    let is_trusted = |ip: IpAddr| ip.is_loopback() || trusted.iter().any(|range| range.contains(ip));
+    let mut client = peer.to_canonical();
+    for hop in forwarded_for.rsplit(',').map(str::trim).filter(|hop| !hop.is_empty()) {
+        if !is_trusted(client) {
+            break;
+        }
+        match hop.parse::<IpAddr>() {
+            Ok(ip) => client = ip.to_canonical(),
+            Err(_) => break,
+        }
+    }
+    client
+}
+
+/// Id of the user behind the request's bearer token, if any.
+async fn session_user(req: &ServiceRequest) -> Option<String> {
+    let token = req
+        .headers()
+        .get(header::AUTHORIZATION)?
+        .to_str()
+        .ok()?
+        .strip_prefix("Bearer ")?
+        .trim()
+        .to_string();
+    let db = req.app_data::<web::Data<PgPool>>()?;
+    sqlx::query_scalar("SELECT user_id FROM sessions WHERE token = $1")
+        .bind(token)
+        .fetch_optional(&***db)
+        .await
+        .unwrap_or(None)
+}
+
+/// Middleware refusing requests over their budget with 429 and `Retry-After`.
+/// Each budget is counted per client IP and, for logged-in requests, per user.
+pub async fn limit(req: ServiceRequest, next: Next<impl MessageBody + 'static>) -> Result<ServiceResponse<BoxBody>, Error> {
+    let budget = budgets()
+        .into_iter()
+        .find(|b| b.method == *req.method() && b.path == req.path());
+    let (Some(budget), Some(limiter)) = (budget, req.app_data::<web::Data<RateLimiter>>().cloned()) else {
+        return next.call(req).await.map(|res| res.map_into_boxed_body());
+    };
+
+    let ip = req
+        .peer_addr()
+        .map_or_else(|| "unknown".to_string(), |peer| client_ip(peer.ip(), req.headers()).to_string());
+    let mut keys = vec![format!("ip:{}", ip)];
+    if let Some(user_id) = session_user(&req).await {
+        keys.push(format!("user:{}", user_id));
+    }
+
+    if let Some(wait) = limiter.check(&budget, &keys) {
+        log::warn!("rate limited {} for {} ({})", budget.name, ip, keys.join(", "));
+        return Ok(req.into_response(too_many(&budget, wait)));
+    }
+    next.call(req).await.map(|res| res.map_into_boxed_body())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn budget(max: usize, window: Duration) -> Budget {
+        Budget {
+            name: "test",
+            method: Method::POST,
+            path: "/test",
+            max,
+            window,
+            message: "Too many tests",
+        }
+    }
+
+    fn ip(text: &str) -> IpAddr {
+        text.parse().unwrap()
+    }
+
+    #[test]
+    fn allows_up_to_max_then_refuses() {
+        let limiter = RateLimiter::default();
+        let budget = budget(3, Duration::from_secs(60));
+        let keys = ["ip:1.2.3.4".to_string()];
+        for _ in 0..3 {
+            assert_eq!(limiter.check(&budget, &keys), None);
+        }
+        let wait = limiter.check(&budget, &keys).expect("fourth hit refused");
+        assert!(wait <= budget.window && wait > Duration::from_secs(59));
+    }
+
+    #[test]
+    fn keys_and_budgets_are_counted_apart() {
+        let limiter = RateLimiter::default();
+        let budget = budget(1, Duration::from_secs(60));
+        assert_eq!(limiter.check(&budget, &["ip:a".to_string()]), None);
+        assert_eq!(limiter.check(&budget, &["ip:b".to_string()]), None);
+        assert!(limiter.check(&budget, &["ip:a".to_string()]).is_some());
+
+        let other = Budget { name: "other", ..budget };
+        assert_eq!(limiter.check(&other, &["ip:a".to_string()]), None);
+    }
+
+    #[test]
+    fn any_key_over_budget_refuses_and_refusals_are_not_recorded() {
+        let limiter = RateLimiter::default();
+        let budget = budget(1, Duration::from_secs(60));
+        assert_eq!(limiter.check(&budget, &["user:1".to_string()]), None);
+
+        // The user is spent, so the new address is refused without being charged
+        let both = ["ip:fresh".to_string(), "user:1".to_string()];
+        assert!(limiter.check(&budget, &both).is_some());
+        assert_eq!(limiter.check(&budget, &["ip:fresh".to_string()]), None);
+    }
+
+    #[test]
+    fn hits_expire_after_the_window() {
+        let limiter = RateLimiter::default();
+        let budget = budget(1, Duration::ZERO);
+        let keys = ["ip:a".to_string()];
+        assert_eq!(limiter.check(&budget, &keys), None);
+        assert_eq!(limiter.check(&budget, &keys), None);
+    }
+
+    #[test]
+    fn only_failed_logins_count_against_the_account() {
+        let limiter = RateLimiter::default();
+        // Checking doesn't charge, so correct logins never use up the budget
+        for _ in 0..account_budget().max * 2 {
+            assert!(limiter.login_refused("alice").is_none());
+        }
+        for _ in 0..account_budget().max {
+            limiter.login_failed("alice");
+        }
+        let refused = limiter.login_refused("alice").unwrap();
+        assert_eq!(refused.status(), actix_web::http::StatusCode::TOO_MANY_REQUESTS);
+        assert!(refused.headers().contains_key(header::RETRY_AFTER));
+        assert!(limiter.login_refused("bob").is_none());
+        assert!(limiter.second_factor_refused("alice").is_none());
+    }
+
+    #[test]
+    fn wrong_second_factor_codes_count_against_the_account() {
+        let limiter = RateLimiter::default();
+        for _ in 0..second_factor_budget().max - 1 {
+            limiter.second_factor_failed("alice");
+        }
+        assert!(limiter.second_factor_refused("alice").is_none());
+        limiter.second_factor_failed("alice");
+        assert!(limiter.second_factor_refused("alice").is_some());
+        assert!(limiter.login_refused("alice").is_none());
+    }
+
+    #[test]
+    fn ssh_logins_share_the_http_budgets() {
+        let limiter = RateLimiter::default();
+        let address = ip("198.51.100.7");
+        let keys = [format!("ip:{}", address)];
+        for _ in 0..login_budget().max - 1 {
+            assert!(limiter.check(&login_budget(), &keys).is_none());
+        }
+        assert_eq!(limiter.ssh_login_attempt(address, "alice"), None);
+        let refused = limiter.ssh_login_attempt(address, "alice").unwrap();
+        assert!(refused.starts_with("Too many login attempts; try again in "));
+
+        // Only failures count against the account, from HTTP or SSH alike
+        let elsewhere = ip("198.51.100.8");
+        for _ in 0..account_budget().max {
+            limiter.login_failed("alice");
+        }
+        let refused = limiter.ssh_login_attempt(elsewhere, "alice").unwrap();
+        assert!(refused.starts_with("Too many login attempts for this account"));
+        assert_eq!(limiter.ssh_login_attempt(elsewhere, "bob"), None);
+
+        for _ in 0..second_factor_budget().max {
+            limiter.second_factor_failed("bob");
+        }
+        assert!(limiter.ssh_second_factor_refused("bob").is_some());
+        assert!(limiter.ssh_second_factor_refused("alice").is_none());
+    }
+
+    #[test]
+    fn proxy_ranges_parse_and_match() {
+        let range = ProxyRange::parse("10.0.0.0/8").unwrap();
+        assert!(range.contains(ip("10.200.3.4")));
+        assert!(range.contains(ip("::ffff:10.1.1.1")));
+        assert!(!range.contains(ip("11.0.0.1")));
+        assert!(!range.contains(ip("::1")));
+
+        let single = ProxyRange::parse(" 192.168.1.5 ").unwrap();
+        assert!(single.contains(ip("192.168.1.5")));
+        assert!(!single.contains(ip("192.168.1.6")));
+
+        assert!(ProxyRange::parse("fd00::/8").unwrap().contains(ip("fd12::1")));
+        assert!(ProxyRange::parse("0.0.0.0/0").unwrap().contains(ip("8.8.8.8")));
+        assert!(ProxyRange::parse("10.0.0.0/33").is_none());
+        assert!(ProxyRange::parse("proxy.local").is_none());
+    }
+
+    #[test]
+    fn forwarded_for_is_ignored_from_untrusted_peers() {
+        let trusted = [ProxyRange::parse("10.0.0.0/8").unwrap()];
+        assert_eq!(forwarded_client(ip("203.0.113.9"), "1.1.1.1", &trusted), ip("203.0.113.9"));
+        assert_eq!(forwarded_client(ip("203.0.113.9"), "", &[]), ip("203.0.113.9"));
+    }
+
+    #[test]
+    fn forwarded_for_is_walked_from_the_right_through_trusted_hops() {
+        let trusted = [ProxyRange::parse("10.0.0.0/8").unwrap()];
+        // The client prepended a fake entry; the proxy appended the real address
+        assert_eq!(forwarded_client(ip("10.0.0.2"), "6.6.6.6, 198.51.100.7", &trusted), ip("198.51.100.7"));
+        // Two proxies in a row
+        assert_eq!(forwarded_client(ip("10.0.0.2"), "198.51.100.7, 10.0.0.3", &trusted), ip("198.51.100.7"));
+        // An unparsable hop stops at the last trusted address
+        assert_eq!(forwarded_client(ip("10.0.0.2"), "unknown", &trusted), ip("10.0.0.2"));
+    }
+
+    #[test]
+    fn loopback_is_always_trusted() {
+        assert_eq!(forwarded_client(ip("127.0.0.1"), "198.51.100.7", &[]), ip("198.51.100.7"));
+        assert_eq!(forwarded_client(ip("::1"), "198.51.100.7", &[]), ip("198.51.100.7"));
+    }
+}
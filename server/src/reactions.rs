@@ -0,0 +1,226 @@
+// reactions.rs
+use actix_web::{post, web, HttpResponse, Responder};
+use chrono::Utc;
+use serde::Deserialize;
+use sqlx::{PgConnection, PgPool};
+
+use crate::auth::AuthUser;
+use crate::moderation;
+
+// The emoji users can react with
+pub const EMOJI: [&str; 8] = ["👍", "👎", "❤️", "😂", "🎉", "😮", "😢", "🚀"];
+
+pub async fn ensure_tables(pool: &PgPool) {
+    let _ = sqlx::query(
+        r#"CREATE TABLE IF NOT EXISTS reactions (
+                user_id TEXT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+                target_type TEXT NOT NULL,
+                target_id TEXT NOT NULL,
+                emoji TEXT NOT NULL,
+                created_at TIMESTAMPTZ NOT NULL,
+                PRIMARY KEY (user_id, target_type, target_id, emoji)
+            );"#
+    )
+    .execute(pool)
+    .await;
+    let _ = sqlx::query("CREATE INDEX IF NOT EXISTS reactions_target ON reactions (target_type, target_id)")
+        .execute(pool)
+        .await;
+}
+
+/// SQL expression for the reaction counts of a post, as a JSON array of
+/// `{emoji, count, mine}` in the order the emoji were first used. `id` is the
+/// post's id column and `viewer` the parameter holding the requesting user's id.
+pub fn summary_sql(target_type: &str, id: &str, viewer: &str) -> String {
+    format!(
+        r#"COALESCE((SELECT jsonb_agg(jsonb_build_object('emoji', rc.emoji, 'count', rc.count, 'mine', rc.mine) ORDER BY rc.first)
+                     FROM (SELECT emoji, COUNT(*) as count, COALESCE(bool_or(user_id = {viewer}), FALSE) as mine,
+                                  MIN(created_at) as first
+                           FROM reactions WHERE target_type = '{target_type}' AND target_id = {id}
+                           GROUP BY emoji) rc), '[]'::jsonb)"#
+    )
+}
+
+/// Remove the reactions on the given posts, when they are deleted for good.
+pub async fn remove_for(conn: &mut PgConnection, target_type: &str, ids: &[String]) -> Result<u64, sqlx::Error> {
+    sqlx::query("DELETE FROM reactions WHERE target_type = $1 AND target_id = ANY($2)")
+        .bind(target_type)
+        .bind(ids)
+        .execute(conn)
+        .await
+        .map(|r| r.rows_affected())
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ReactionToggle {
+    emoji: String,
+}
+
+/// Add the user's reaction to a thread or comment, or take it back if it is
+/// already there. Responds with the post's updated counts.
+async fn toggle(db: &PgPool, user: &AuthUser, target_type: &str, target_id: &str, emoji: &str) -> HttpResponse {
+    if !EMOJI.contains(&emoji) {
+        return HttpResponse::BadRequest().json(serde_json::json!({
+            "error": format!("emoji must be one of: {}", EMOJI.join(" "))
+        }));
+    }
+    if let Some(reason) = moderation::account_block(db, &user.id).await {
+        return HttpResponse::Forbidden().json(serde_json::json!({ "error": reason }));
+    }
+    let result = match db.acquire().await {
+        Ok(mut conn) => toggle_on(&mut conn, user, target_type, target_id, emoji).await,
+        Err(e) => Err(e),
+    };
+    match result {
+        Ok(Some(reactions)) => HttpResponse::Ok().json(reactions),
+        Ok(None) => HttpResponse::NotFound().finish(),
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
+
+/// The database side of `toggle`: the post's counts afterwards, or `None` if
+/// there is no such post for the user to react to. Deleted, hidden and held
+/// posts take no reactions, nor do posts in a thread the user can't see.
+async fn toggle_on(
+    conn: &mut PgConnection,
+    user: &AuthUser,
+    target_type: &str,
+    target_id: &str,
+    emoji: &str,
+) -> Result<Option<serde_json::Value>, sqlx::Error> {
+    let post = if target_type == "thread" {
+        "threads t JOIN users u ON t.user_id = u.id
+         WHERE t.id = $1 AND t.deleted_at IS NULL AND NOT t.hidden AND NOT t.held"
+    } else {
+        "comments c JOIN threads t ON c.thread_id = t.id JOIN users u ON t.user_id = u.id
+         WHERE c.id = $1 AND c.deleted_at IS NULL AND NOT c.hidden AND NOT c.held"
+    };
+    let exists = sqlx::query(&format!("SELECT 1 FROM {} AND {}", post, moderation::thread_visible_sql("$2", "$3")))
+        .bind(target_id)
+        .bind(&user.id)
+        .bind(user.is_moderator())
+        .fetch_optional(&mut *conn)
+        .await?;
+    if exists.is_none() {
+        return Ok(None);
+    }
+
+    let removed = sqlx::query(
+        "DELETE FROM reactions WHERE user_id = $1 AND target_type = $2 AND target_id = $3 AND emoji = $4"
+    )
+    .bind(&user.id)
+    .bind(target_type)
+    .bind(target_id)
+    .bind(emoji)
+    .execute(&mut *conn)
+    .await?
+    .rows_affected();
+    if removed == 0 {
+        sqlx::query(
+            r#"INSERT INTO reactions (user_id, target_type, target_id, emoji, created_at)
+               VALUES ($1, $2, $3, $4, $5) ON CONFLICT DO NOTHING"#
+        )
+        .bind(&user.id)
+        .bind(target_type)
+        .bind(target_id)
+        .bind(emoji)
+        .bind(Utc::now())
+        .execute(&mut *conn)
+        .await?;
+    }
+
+    sqlx::query_scalar(&format!("SELECT {}", summary_sql(target_type, "$1", "$2")))
+        .bind(target_id)
+        .bind(&user.id)
+        .fetch_one(conn)
+        .await
+        .map(Some)
+}
+
+#[post("/threads/{id}/reactions")]
+pub async fn toggle_thread_reaction(
+    db: web::Data<PgPool>,
+    user: AuthUser,
+    path: web::Path<String>,
+    payload: web::Json<ReactionToggle>,
+) -> impl Responder {
+    toggle(&db, &user, "thread", &path.into_inner(), &payload.emoji).await
+}
+
+#[post("/comments/{id}/reactions")]
+pub async fn toggle_comment_reaction(
+    db: web::Data<PgPool>,
+    user: AuthUser,
+    path: web::Path<String>,
+    payload: web::Json<ReactionToggle>,
+) -> impl Responder {
+    toggle(&db, &user, "comment", &path.into_inner(), &payload.emoji).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::scratch_tables;
+
+    // Just the columns reactions depend on
+    const SCHEMA: &str = r#"
+        CREATE TEMP TABLE users (id TEXT PRIMARY KEY, muted BOOLEAN NOT NULL DEFAULT FALSE) ON COMMIT DROP;
+        CREATE TEMP TABLE threads (id TEXT PRIMARY KEY, user_id TEXT NOT NULL, deleted_at TIMESTAMPTZ,
+                                   hidden BOOLEAN NOT NULL DEFAULT FALSE, held BOOLEAN NOT NULL DEFAULT FALSE) ON COMMIT DROP;
+        CREATE TEMP TABLE comments (id TEXT PRIMARY KEY, thread_id TEXT NOT NULL, deleted_at TIMESTAMPTZ,
+                                    hidden BOOLEAN NOT NULL DEFAULT FALSE, held BOOLEAN NOT NULL DEFAULT FALSE) ON COMMIT DROP;
+        CREATE TEMP TABLE reactions (user_id TEXT NOT NULL, target_type TEXT NOT NULL, target_id TEXT NOT NULL,
+                                     emoji TEXT NOT NULL, created_at TIMESTAMPTZ NOT NULL,
+                                     PRIMARY KEY (user_id, target_type, target_id, emoji)) ON COMMIT DROP;
+        INSERT INTO users (id) VALUES ('alice'), ('bob'), ('mod');
+        INSERT INTO threads (id, user_id) VALUES ('t1', 'alice');
+        INSERT INTO comments (id, thread_id) VALUES ('c1', 't1');
+    "#;
+
+    fn user(id: &str, role: &str) -> AuthUser {
+        AuthUser { id: id.to_string(), username: id.to_string(), role: role.to_string() }
+    }
+
+    #[actix_web::test]
+    async fn reacting_again_takes_the_reaction_back() {
+        let Some(mut tx) = scratch_tables(SCHEMA).await else { return };
+        let (alice, bob) = (user("alice", "user"), user("bob", "user"));
+
+        let counts = toggle_on(&mut tx, &bob, "thread", "t1", "👍").await.unwrap();
+        assert_eq!(counts, Some(serde_json::json!([{ "emoji": "👍", "count": 1, "mine": true }])));
+        toggle_on(&mut tx, &alice, "thread", "t1", "👍").await.unwrap();
+        let counts = toggle_on(&mut tx, &alice, "thread", "t1", "🎉").await.unwrap();
+        assert_eq!(counts, Some(serde_json::json!([
+            { "emoji": "👍", "count": 2, "mine": true },
+            { "emoji": "🎉", "count": 1, "mine": true },
+        ])));
+
+        let counts = toggle_on(&mut tx, &bob, "thread", "t1", "👍").await.unwrap();
+        assert_eq!(counts, Some(serde_json::json!([
+            { "emoji": "👍", "count": 1, "mine": false },
+            { "emoji": "🎉", "count": 1, "mine": false },
+        ])));
+        let counts = toggle_on(&mut tx, &bob, "comment", "c1", "❤️").await.unwrap();
+        assert_eq!(counts, Some(serde_json::json!([{ "emoji": "❤️", "count": 1, "mine": true }])));
+    }
+
+    #[actix_web::test]
+    async fn posts_in_threads_the_user_cannot_see_take_no_reactions() {
+        let Some(mut tx) = scratch_tables(SCHEMA).await else { return };
+        let (alice, bob, moderator) = (user("alice", "user"), user("bob", "user"), user("mod", "moderator"));
+
+        sqlx::query("UPDATE threads SET held = TRUE").execute(&mut *tx).await.unwrap();
+        assert_eq!(toggle_on(&mut tx, &bob, "comment", "c1", "👍").await.unwrap(), None);
+        assert!(toggle_on(&mut tx, &alice, "comment", "c1", "👍").await.unwrap().is_some());
+
+        sqlx::query("UPDATE threads SET held = FALSE, hidden = TRUE").execute(&mut *tx).await.unwrap();
+        assert_eq!(toggle_on(&mut tx, &alice, "comment", "c1", "👍").await.unwrap(), None);
+        assert!(toggle_on(&mut tx, &moderator, "comment", "c1", "👍").await.unwrap().is_some());
+
+        sqlx::query("UPDATE threads SET hidden = FALSE").execute(&mut *tx).await.unwrap();
+        sqlx::query("UPDATE users SET muted = TRUE WHERE id = 'alice'").execute(&mut *tx).await.unwrap();
+        assert_eq!(toggle_on(&mut tx, &bob, "thread", "t1", "👍").await.unwrap(), None);
+        assert_eq!(toggle_on(&mut tx, &bob, "comment", "c1", "👍").await.unwrap(), None);
+        assert_eq!(toggle_on(&mut tx, &bob, "comment", "missing", "👍").await.unwrap(), None);
+    }
+}
@@ -10,9 +10,21 @@ pub async fn seed_database(pool: &PgPool) -> Result<(), sqlx::Error> {
     sqlx::query("DELETE FROM comments")
         .execute(pool)
         .await?;
+    sqlx::query("DELETE FROM thread_reads")
+        .execute(pool)
+        .await?;
     sqlx::query("DELETE FROM threads")
         .execute(pool)
         .await?;
+    sqlx::query("DELETE FROM reports")
+        .execute(pool)
+        .await?;
+    sqlx::query("DELETE FROM user_sanctions")
+        .execute(pool)
+        .await?;
+    sqlx::query("DELETE FROM sessions")
+        .execute(pool)
+        .await?;
     sqlx::query("DELETE FROM users")
         .execute(pool)
         .await?;
@@ -25,13 +37,15 @@ pub async fn seed_database(pool: &PgPool) -> Result<(), sqlx::Error> {
         ("rust_dev", "Rust Programmer"),
     ];
     
+    crate::profiles::ensure_tables(pool).await;
     for (username, bio) in users {
         let user_id = Uuid::new_v4().to_string();
-        sqlx::query("INSERT INTO users (id, username, password_hash, created_at) VALUES ($1, $2, $3, $4)")
+        sqlx::query("INSERT INTO users (id, username, password_hash, created_at, bio) VALUES ($1, $2, $3, $4, $5)")
             .bind(&user_id)
             .bind(username)
             .bind("hashed_password")
-            .bind(Utc::now().to_rfc3339())
+            .bind(Utc::now())
+            .bind(bio)
             .execute(pool)
             .await?;
     }
@@ -63,23 +77,25 @@ pub async fn seed_database(pool: &PgPool) -> Result<(), sqlx::Error> {
     
     // Create sample threads
     let threads = vec![
-        ("Welcome to TERNIMAL!", "This is the official forum for the TERNIMAL terminal forum client. Feel free to discuss features, report bugs, or share your terminal setups!", &arch_user_id),
-        ("Best terminal emulators?", "What's your favorite terminal emulator? I've been using Alacritty lately but curious what others prefer.", &linux_admin_id),
-        ("Rust in terminal apps", "Building terminal apps with Rust is amazing! The performance and safety are unmatched. What terminal apps have you built?", &rust_dev_id),
-        ("Productivity tips", "Share your best terminal productivity tips! I'll start: tmux + vim + fzf is my holy trinity.", &terminal_ninja_id),
-        ("Arch vs other distros", "Why did you choose Arch Linux? Was it the AUR, the rolling release, or something else?", &arch_user_id),
+        ("Welcome to TERNIMAL!", "This is the official forum for the TERNIMAL terminal forum client. Feel free to discuss features, report bugs, or share your terminal setups!", &arch_user_id, vec!["ternimal", "meta"]),
+        ("Best terminal emulators?", "What's your favorite terminal emulator? I've been using Alacritty lately but curious what others prefer.", &linux_admin_id, vec!["terminals", "alacritty"]),
+        ("Rust in terminal apps", "Building terminal apps with Rust is amazing! The performance and safety are unmatched. What terminal apps have you built?", &rust_dev_id, vec!["rust", "tui"]),
+        ("Productivity tips", "Share your best terminal productivity tips! I'll start: tmux + vim + fzf is my holy trinity.", &terminal_ninja_id, vec!["productivity", "tmux", "vim"]),
+        ("Arch vs other distros", "Why did you choose Arch Linux? Was it the AUR, the rolling release, or something else?", &arch_user_id, vec!["arch", "linux"]),
     ];
     
-    for (title, content, user_id) in threads {
+    for (title, content, user_id, tags) in threads {
         let thread_id = Uuid::new_v4().to_string();
         sqlx::query("INSERT INTO threads (id, title, user_id, content, created_at) VALUES ($1, $2, $3, $4, $5)")
             .bind(&thread_id)
             .bind(title)
             .bind(user_id)
             .bind(content)
-            .bind(Utc::now().to_rfc3339())
+            .bind(Utc::now())
             .execute(pool)
             .await?;
+        let tags: Vec<String> = tags.into_iter().map(str::to_string).collect();
+        crate::tags::set_thread_tags(pool, &thread_id, &tags).await?;
         
         // Add some comments to threads
         if title == "Best terminal emulators?" {
@@ -108,7 +124,7 @@ async fn add_comment(pool: &PgPool, thread_id: &str, content: &str, user_id: &st
         .bind(thread_id)
         .bind(user_id)
         .bind(content)
-        .bind(Utc::now().to_rfc3339())
+        .bind(Utc::now())
         .execute(pool)
         .await?;
     Ok(())
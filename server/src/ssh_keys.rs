@@ -0,0 +1,294 @@
+// ssh_keys.rs
+use actix_web::{delete, get, post, web, HttpResponse, Responder};
+use chrono::{DateTime, Duration, Utc};
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use ssh_key::{Algorithm, HashAlg, PublicKey, SshSig};
+use uuid::Uuid;
+
+use crate::auth::{self, AuthUser};
+use crate::moderation;
+use crate::validation::{self, Validate};
+
+// Signatures over a login challenge are made for this namespace, so they are
+// no good for anything else a key signs (see `ssh-keygen -Y sign`)
+pub const NAMESPACE: &str = "ternimal-login";
+// How long a challenge may be answered
+const CHALLENGE_SECONDS: i64 = 120;
+const NONCE_BYTES: usize = 32;
+const MAX_KEYS: i64 = 20;
+
+pub async fn ensure_tables(pool: &PgPool) {
+    // A key belongs to one account, so the fingerprint alone identifies it
+    let _ = sqlx::query(
+        r#"CREATE TABLE IF NOT EXISTS ssh_keys (
+                id TEXT PRIMARY KEY,
+                user_id TEXT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+                name TEXT NOT NULL,
+                algorithm TEXT NOT NULL,
+                public_key TEXT NOT NULL,
+                fingerprint TEXT UNIQUE NOT NULL,
+                created_at TIMESTAMPTZ NOT NULL,
+                last_used_at TIMESTAMPTZ
+            );"#
+    )
+    .execute(pool)
+    .await;
+    // Issued for any username, so they don't reveal which accounts exist
+    let _ = sqlx::query(
+        r#"CREATE TABLE IF NOT EXISTS login_challenges (
+                nonce TEXT PRIMARY KEY,
+                username TEXT NOT NULL,
+                created_at TIMESTAMPTZ NOT NULL
+            );"#
+    )
+    .execute(pool)
+    .await;
+}
+
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct SshKey {
+    id: String,
+    name: String,
+    algorithm: String,
+    // "SHA256:...", as `ssh-keygen -l` prints it
+    fingerprint: String,
+    created_at: DateTime<Utc>,
+    last_used_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct KeyUpload {
+    // A line of an authorized_keys file: "ssh-ed25519 AAAA... comment"
+    #[serde(default)]
+    public_key: String,
+    // Defaults to the key's comment
+    name: Option<String>,
+}
+
+impl Validate for KeyUpload {
+    fn validate(&mut self) -> Result<(), validation::Errors> {
+        let mut errors = validation::Errors::default();
+        if let Some(name) = &self.name {
+            self.name = Some(errors.check(&validation::KEY_NAME, name));
+        }
+        errors.finish()
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ChallengeRequest {
+    #[serde(default)]
+    username: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct KeyLogin {
+    #[serde(default)]
+    username: String,
+    #[serde(default)]
+    nonce: String,
+    // The nonce signed for NAMESPACE, armored as by `ssh-keygen -Y sign`
+    #[serde(default)]
+    signature: String,
+}
+
+fn field_error(field: &'static str, message: &str) -> HttpResponse {
+    let mut errors = validation::Errors::default();
+    errors.add(field, message.to_string());
+    errors.response()
+}
+
+fn unauthorized(message: &str) -> HttpResponse {
+    HttpResponse::Unauthorized().json(serde_json::json!({ "error": message }))
+}
+
+/// The account `username` whose registered keys include `key`, if any. Marks
+/// the key used. Shared by HTTP key login and the SSH server.
+pub async fn user_for_key(db: &PgPool, username: &str, key: &PublicKey) -> Option<String> {
+    if key.algorithm() != Algorithm::Ed25519 {
+        return None;
+    }
+    sqlx::query_scalar(
+        r#"UPDATE ssh_keys k SET last_used_at = $3 FROM users u
+           WHERE u.id = k.user_id AND k.fingerprint = $1 AND u.username = $2
+           RETURNING u.id"#
+    )
+    .bind(key.fingerprint(HashAlg::Sha256).to_string())
+    .bind(username)
+    .bind(Utc::now())
+    .fetch_optional(db)
+    .await
+    .unwrap_or(None)
+}
+
+/// Whether `key` is registered to `username`, without marking it used. SSH
+/// clients ask this before signing with a key.
+pub async fn is_registered(db: &PgPool, username: &str, key: &PublicKey) -> bool {
+    if key.algorithm() != Algorithm::Ed25519 {
+        return false;
+    }
+    sqlx::query_scalar::<_, i64>(
+        r#"SELECT COUNT(*) FROM ssh_keys k JOIN users u ON u.id = k.user_id
+           WHERE k.fingerprint = $1 AND u.username = $2"#
+    )
+    .bind(key.fingerprint(HashAlg::Sha256).to_string())
+    .bind(username)
+    .fetch_one(db)
+    .await
+    .is_ok_and(|count| count > 0)
+}
+
+#[get("/users/me/keys")]
+pub async fn list_keys(db: web::Data<PgPool>, user: AuthUser) -> impl Responder {
+    let keys = sqlx::query_as::<_, SshKey>(
+        r#"SELECT id, name, algorithm, fingerprint, created_at, last_used_at
+           FROM ssh_keys WHERE user_id = $1 ORDER BY created_at"#
+    )
+    .bind(&user.id)
+    .fetch_all(&**db)
+    .await;
+    match keys {
+        Ok(keys) => HttpResponse::Ok().json(keys),
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
+
+#[post("/users/me/keys")]
+pub async fn add_key(db: web::Data<PgPool>, user: AuthUser, payload: web::Json<KeyUpload>) -> impl Responder {
+    let mut payload = payload.into_inner();
+    if let Err(errors) = payload.validate() {
+        return errors.response();
+    }
+    let Ok(key) = PublicKey::from_openssh(payload.public_key.trim()) else {
+        return field_error("public_key", "is not an OpenSSH public key");
+    };
+    if key.algorithm() != Algorithm::Ed25519 {
+        return field_error("public_key", "must be an ssh-ed25519 key");
+    }
+    let count: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM ssh_keys WHERE user_id = $1")
+        .bind(&user.id)
+        .fetch_one(&**db)
+        .await
+        .unwrap_or(0);
+    if count >= MAX_KEYS {
+        return field_error("public_key", &format!("cannot be added: you already have {} keys", MAX_KEYS));
+    }
+
+    let name = match payload.name.filter(|n| !n.is_empty()) {
+        Some(name) => name,
+        None => validation::normalize(&validation::KEY_NAME, key.comment()).chars().take(validation::KEY_NAME.max).collect(),
+    };
+    let mut stored = key.clone();
+    stored.set_comment("");
+    let ssh_key = SshKey {
+        id: Uuid::new_v4().to_string(),
+        name: if name.is_empty() { "key".to_string() } else { name },
+        algorithm: key.algorithm().to_string(),
+        fingerprint: key.fingerprint(HashAlg::Sha256).to_string(),
+        created_at: Utc::now(),
+        last_used_at: None,
+    };
+    let result = sqlx::query(
+        r#"INSERT INTO ssh_keys (id, user_id, name, algorithm, public_key, fingerprint, created_at)
+           VALUES ($1, $2, $3, $4, $5, $6, $7)"#
+    )
+    .bind(&ssh_key.id)
+    .bind(&user.id)
+    .bind(&ssh_key.name)
+    .bind(&ssh_key.algorithm)
+    .bind(stored.to_openssh().unwrap_or_default())
+    .bind(&ssh_key.fingerprint)
+    .bind(ssh_key.created_at)
+    .execute(&**db)
+    .await;
+    match result {
+        Ok(_) => HttpResponse::Created().json(ssh_key),
+        Err(sqlx::Error::Database(e)) if e.is_unique_violation() => HttpResponse::Conflict().json(serde_json::json!({
+            "error": "This key is already registered"
+        })),
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
+
+#[delete("/users/me/keys/{id}")]
+pub async fn delete_key(db: web::Data<PgPool>, user: AuthUser, path: web::Path<String>) -> impl Responder {
+    let result = sqlx::query("DELETE FROM ssh_keys WHERE id = $1 AND user_id = $2")
+        .bind(path.into_inner())
+        .bind(&user.id)
+        .execute(&**db)
+        .await;
+    match result {
+        Ok(r) if r.rows_affected() > 0 => HttpResponse::Ok().finish(),
+        Ok(_) => HttpResponse::NotFound().finish(),
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
+
+/// First step of key login: a one-time nonce for the client to sign.
+#[post("/auth/key/challenge")]
+pub async fn challenge(db: web::Data<PgPool>, payload: web::Json<ChallengeRequest>) -> impl Responder {
+    let username = validation::normalize(&validation::USERNAME, &payload.username);
+    if username.is_empty() {
+        return field_error("username", "is required");
+    }
+    let mut bytes = [0u8; NONCE_BYTES];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    let nonce: String = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+
+    let expired = Utc::now() - Duration::seconds(CHALLENGE_SECONDS);
+    let _ = sqlx::query("DELETE FROM login_challenges WHERE created_at < $1")
+        .bind(expired)
+        .execute(&**db)
+        .await;
+    let result = sqlx::query("INSERT INTO login_challenges (nonce, username, created_at) VALUES ($1, $2, $3)")
+        .bind(&nonce)
+        .bind(&username)
+        .bind(Utc::now())
+        .execute(&**db)
+        .await;
+    match result {
+        Ok(_) => HttpResponse::Ok().json(serde_json::json!({
+            "nonce": nonce,
+            "namespace": NAMESPACE,
+            "expires_in": CHALLENGE_SECONDS
+        })),
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
+
+/// Second step: the signed nonce. Answers like `POST /auth/login`.
+#[post("/auth/key/login")]
+pub async fn key_login(db: web::Data<PgPool>, payload: web::Json<KeyLogin>) -> impl Responder {
+    let username = validation::normalize(&validation::USERNAME, &payload.username);
+    let Ok(signature) = SshSig::from_pem(payload.signature.trim()) else {
+        return field_error("signature", "is not an SSH signature");
+    };
+    // Each challenge is answered at most once, right or wrong
+    let expired = Utc::now() - Duration::seconds(CHALLENGE_SECONDS);
+    let issued: Option<String> = sqlx::query_scalar(
+        "DELETE FROM login_challenges WHERE nonce = $1 AND username = $2 AND created_at >= $3 RETURNING nonce"
+    )
+    .bind(&payload.nonce)
+    .bind(&username)
+    .bind(expired)
+    .fetch_optional(&**db)
+    .await
+    .unwrap_or(None);
+    if issued.is_none() {
+        return unauthorized("Challenge expired; request a new one");
+    }
+
+    let key = PublicKey::from(signature.public_key().clone());
+    if key.verify(NAMESPACE, payload.nonce.as_bytes(), &signature).is_err() {
+        return unauthorized("Invalid signature");
+    }
+    let Some(user_id) = user_for_key(&db, &username, &key).await else {
+        return unauthorized("Key not registered for this account");
+    };
+    if let Some(reason) = moderation::account_block(&db, &user_id).await {
+        return HttpResponse::Forbidden().json(serde_json::json!({ "error": reason }));
+    }
+    auth::session_response(&db, &user_id).await
+}
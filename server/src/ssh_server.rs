@@ -1,303 +1,542 @@
 // ssh_server.rs
-use std::io::{Read, Write};
-use std::net::TcpListener;
+// Forum access for plain `ssh`: accounts log in with their password (plus the
+// two-factor code, if they have one) or a registered key, and each session
+// channel with a terminal gets the forum UI (see ssh_tui.rs). The protocol
+// itself (key exchange, encryption, channels) is russh's.
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::path::Path;
 use std::sync::Arc;
-use sqlx::{PgPool, Row};
-
-pub async fn start_ssh_server(db_pool: Arc<PgPool>) -> Result<(), Box<dyn std::error::Error>> {
-    let listener = TcpListener::bind("0.0.0.0:2222")?;
-    println!("SSH Forum Server listening on port 80");
-    
-    for stream in listener.incoming() {
-        match stream {
-            Ok(stream) => {
-                let pool = db_pool.clone();
-                std::thread::spawn(move || {
-                    if let Err(e) = handle_client(stream, pool) {
-                        eprintln!("Error handling client: {}", e);
-                    }
-                });
+use std::time::Duration;
+
+use anyhow::{anyhow, Context};
+use rand::RngCore;
+use russh::keys::ssh_key::private::Ed25519Keypair;
+use russh::keys::ssh_key::LineEnding;
+use russh::keys::{HashAlg, PrivateKey, PublicKey};
+use russh::server::{Auth, Config, Handler, Msg, Response, Session};
+use russh::{Channel, ChannelId, ChannelOpenFailure, Disconnect, MethodKind, MethodSet, SshId};
+use sqlx::PgPool;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::mpsc::error::TrySendError;
+use tokio::sync::{mpsc, oneshot, Semaphore};
+use tokio::time::{timeout_at, Instant};
+
+use crate::rate_limit::RateLimiter;
+use crate::ssh_keys;
+use crate::ssh_tui;
+use crate::{auth, moderation, two_factor, validation};
+
+const SERVER_VERSION: &str = "SSH-2.0-ArchForum_1.0";
+const DEFAULT_PORT: u16 = 2222;
+const DEFAULT_HOST_KEY: &str = "ssh_host_ed25519_key";
+// Failed attempts before the connection is dropped, as OpenSSH's MaxAuthTries
+const MAX_AUTH_ATTEMPTS: usize = 6;
+// Every rejection takes this long, so timing doesn't tell which check failed
+const AUTH_REJECTION_TIME: Duration = Duration::from_secs(1);
+const MAX_CHANNELS: usize = 10;
+// How much the client may send on a channel before we make room for more
+const WINDOW_SIZE: u32 = 2 * 1024 * 1024;
+const MAX_DATA: u32 = 32 * 1024;
+// Connections served at once; more are closed straight after accepting
+const MAX_CONNECTIONS: usize = 100;
+// Time from connecting to being logged in, as OpenSSH's LoginGraceTime
+const LOGIN_GRACE_TIME: Duration = Duration::from_secs(120);
+// Clients that stop answering keepalives are dropped after this many. The
+// interval outlasts the grace time: clients treat a keepalive that arrives
+// while they are still logging in as a protocol error.
+const KEEPALIVE_INTERVAL: Duration = Duration::from_secs(150);
+const KEEPALIVE_MAX: usize = 3;
+// Input messages a shell may fall behind by before more are dropped
+const INPUT_QUEUE: usize = 256;
+
+/// Accept SSH connections on `SSH_PORT` (2222 by default). The host key is kept
+/// at `SSH_HOST_KEY`, created on first start. Logins count against the HTTP
+/// server's rate limits through the shared `limiter`.
+pub async fn start_ssh_server(db_pool: Arc<PgPool>, limiter: Arc<RateLimiter>) -> anyhow::Result<()> {
+    let port = std::env::var("SSH_PORT").ok().and_then(|p| p.parse().ok()).unwrap_or(DEFAULT_PORT);
+    let key_path = std::env::var("SSH_HOST_KEY").unwrap_or_else(|_| DEFAULT_HOST_KEY.to_string());
+    let host_key = load_or_create_host_key(Path::new(&key_path))?;
+    let fingerprint = host_key.public_key().fingerprint(HashAlg::Sha256);
+    let config = Arc::new(Config {
+        server_id: SshId::Standard(SERVER_VERSION.into()),
+        methods: auth_methods(),
+        auth_rejection_time: AUTH_REJECTION_TIME,
+        // "none" is how clients ask which methods there are
+        auth_rejection_time_initial: Some(Duration::ZERO),
+        keys: vec![host_key],
+        max_auth_attempts: MAX_AUTH_ATTEMPTS,
+        window_size: WINDOW_SIZE,
+        maximum_packet_size: MAX_DATA,
+        // Reading the forum can mean a long time without typing
+        inactivity_timeout: None,
+        keepalive_interval: Some(KEEPALIVE_INTERVAL),
+        keepalive_max: KEEPALIVE_MAX,
+        nodelay: true,
+        ..Default::default()
+    });
+    let listener = TcpListener::bind(("0.0.0.0", port)).await?;
+    println!("SSH Forum Server listening on port {} (host key {})", port, fingerprint);
+    let slots = Arc::new(Semaphore::new(MAX_CONNECTIONS));
+
+    loop {
+        let (stream, address) = match listener.accept().await {
+            Ok(connection) => connection,
+            Err(e) => {
+                eprintln!("Connection error: {}", e);
+                continue;
             }
-            Err(e) => eprintln!("Connection error: {}", e),
+        };
+        let Ok(slot) = slots.clone().try_acquire_owned() else {
+            log::warn!("SSH connection from {} refused: {} connections open", address, MAX_CONNECTIONS);
+            continue;
+        };
+        let (logged_in, on_login) = oneshot::channel();
+        let connection = Connection::new(db_pool.clone(), limiter.clone(), address, logged_in);
+        let config = config.clone();
+        tokio::spawn(async move {
+            if let Err(e) = serve_connection(config, stream, connection, on_login).await {
+                log::info!("SSH connection from {} closed: {}", address, e);
+            }
+            drop(slot);
+        });
+    }
+}
+
+/// Run one connection, dropping it if it hasn't logged in within `LOGIN_GRACE_TIME`.
+async fn serve_connection(
+    config: Arc<Config>,
+    stream: TcpStream,
+    connection: Connection,
+    on_login: oneshot::Receiver<()>,
+) -> anyhow::Result<()> {
+    let deadline = Instant::now() + LOGIN_GRACE_TIME;
+    let mut session = timeout_at(deadline, russh::server::run_stream(config, stream, connection))
+        .await
+        .map_err(|_| anyhow!("no version exchange"))??;
+    tokio::select! {
+        result = &mut session => return result,
+        login = timeout_at(deadline, on_login) => {
+            if login.is_err() {
+                session
+                    .handle()
+                    .disconnect(Disconnect::ByApplication, "Login timed out".into(), String::new())
+                    .await?;
+            }
+        }
+    }
+    session.await
+}
+
+/// The ed25519 key stored in OpenSSH format at `path`, generated there on first start.
+fn load_or_create_host_key(path: &Path) -> anyhow::Result<PrivateKey> {
+    if path.exists() {
+        let key = PrivateKey::read_openssh_file(path).with_context(|| format!("reading host key {}", path.display()))?;
+        if key.key_data().ed25519().is_none() {
+            return Err(anyhow!("host key {} is not an ed25519 key", path.display()));
         }
+        return Ok(key);
     }
-    Ok(())
+    let mut seed = [0u8; 32];
+    rand::rngs::OsRng.fill_bytes(&mut seed);
+    let key = PrivateKey::new(Ed25519Keypair::from_seed(&seed).into(), "forum host key")?;
+    key.write_openssh_file(path, LineEnding::LF)
+        .with_context(|| format!("writing host key {}", path.display()))?;
+    Ok(key)
 }
 
-fn handle_client(stream: std::net::TcpStream, db_pool: Arc<PgPool>) -> Result<(), Box<dyn std::error::Error>> {
-    let logged_in_user: Option<String> = None;
-    let rt = tokio::runtime::Runtime::new()?;
-    rt.block_on(async {
-        handle_client_async(stream, db_pool, logged_in_user).await
-    })
+fn auth_methods() -> MethodSet {
+    MethodSet::from(&[MethodKind::PublicKey, MethodKind::KeyboardInteractive, MethodKind::Password][..])
 }
 
-async fn handle_client_async(mut stream: std::net::TcpStream, db_pool: Arc<PgPool>, mut logged_in_user: Option<String>) -> Result<(), Box<dyn std::error::Error>> {
-    stream.write_all(b"Welcome to Arch Forum SSH Server\r\n")?;
-    stream.write_all(b"Arch Linux verification required...\r\n")?;
-    
-    let mut buffer = [0; 1024];
-    let bytes_read = stream.read(&mut buffer)?;
-    let input = String::from_utf8_lossy(&buffer[..bytes_read]);
-    
-    if input.contains("arch") || input.contains("linux") || input.trim().len() > 0 {
-        stream.write_all(b"Arch Linux verified! Welcome.\r\n")?;
-        stream.write_all(b"\r\n=== ARCH FORUM ===\r\n")?;
-        stream.write_all(b"Commands: login, list, post, reply, help, quit\r\n")?;
-        
-        loop {
-            stream.write_all(b"forum> ")?;
-            
-            let mut cmd_buffer = [0; 256];
-            let bytes_read = stream.read(&mut cmd_buffer)?;
-            if bytes_read == 0 { break; }
-            
-            let command_str = String::from_utf8_lossy(&cmd_buffer[..bytes_read]);
-            let command = command_str.trim();
-            
-            match command {
-                "list" => {
-                    stream.write_all(b"\r\nRecent threads:\r\n")?;
-                    if let Ok(threads) = get_threads_from_db(&db_pool) {
-                        let mut result = String::new();
-                        for (i, thread) in threads.iter().take(10).enumerate() {
-                            result.push_str(&format!("[{}] {}\n", i + 1, thread.0));
-                            let line = format!("[{}] {}\r\n", i + 1, thread.0);
-                            stream.write_all(line.as_bytes())?;
-                        }
-                    } else {
-                        stream.write_all(b"Error loading threads\r\n")?;
-                    }
-                }
-                "login" => {
-                    if logged_in_user.is_some() {
-                        stream.write_all(b"Already logged in.\r\n")?;
-                    } else {
-                        stream.write_all(b"Username: ")?;
-                        let mut user_buf = [0; 64];
-                        let bytes_read = stream.read(&mut user_buf)?;
-                        let username_str = String::from_utf8_lossy(&user_buf[..bytes_read]);
-                        let username = username_str.trim();
-                        
-                        stream.write_all(b"Password: ")?;
-                        let mut pass_buf = [0; 64];
-                        let bytes_read = stream.read(&mut pass_buf)?;
-                        let _password = String::from_utf8_lossy(&pass_buf[..bytes_read]).trim();
-                        
-                        // For now, accept any login (you can add real auth later)
-                        logged_in_user = Some(username.to_string());
-                        stream.write_all(b"Login successful!\r\n")?;
-                    }
-                }
-                "help" => {
-                    stream.write_all(b"\r\nCommands:\r\n")?;
-                    if logged_in_user.is_some() {
-                        stream.write_all(b"  list  - Show recent threads\r\n")?;
-                        stream.write_all(b"  post  - Create new thread\r\n")?;
-                        stream.write_all(b"  reply - Reply to thread\r\n")?;
-                    } else {
-                        stream.write_all(b"  list  - Show recent threads (read-only)\r\n")?;
-                        stream.write_all(b"  login - Login to post\r\n")?;
-                    }
-                    stream.write_all(b"  help  - Show this help\r\n")?;
-                    stream.write_all(b"  quit  - Exit forum\r\n")?;
-                }
-                cmd if cmd.starts_with("post") => {
-                    if let Some(ref user) = logged_in_user {
-                        let title = &cmd[5..];
-                        stream.write_all(b"Enter thread content (end with '.' on new line):\r\n")?;
-                        
-                        let mut content_lines = Vec::new();
-                        loop {
-                            stream.write_all(b"> ")?;
-                            let mut line_buf = [0; 256];
-                            let bytes_read = stream.read(&mut line_buf)?;
-                            let line_str = String::from_utf8_lossy(&line_buf[..bytes_read]);
-                            let line = line_str.trim();
-                            if line == "." { break; }
-                            content_lines.push(line.to_string());
-                        }
-                        
-                        let content = content_lines.join("\n");
-                        if create_thread_in_db(&db_pool, title, &content, user) {
-                            stream.write_all(b"Thread created successfully!\r\n")?;
-                        } else {
-                            stream.write_all(b"Error creating thread\r\n")?;
-                        }
-                    } else {
-                        stream.write_all(b"Please login to post. Type 'login'.\r\n")?;
-                    }
-                }
-                "reply" => {
-                    if let Some(ref user) = logged_in_user {
-                        stream.write_all(b"Reply to thread ID: ")?;
-                        let mut id_buf = [0; 64];
-                        let bytes_read = stream.read(&mut id_buf)?;
-                        let thread_id_str = String::from_utf8_lossy(&id_buf[..bytes_read]);
-                        let thread_id = thread_id_str.trim();
-                        
-                        stream.write_all(b"Enter reply (end with '.' on new line):\r\n")?;
-                        
-                        let mut reply_lines = Vec::new();
-                        loop {
-                            stream.write_all(b"> ")?;
-                            let mut line_buf = [0; 256];
-                            let bytes_read = stream.read(&mut line_buf)?;
-                            let line_str = String::from_utf8_lossy(&line_buf[..bytes_read]);
-                            let line = line_str.trim();
-                            if line == "." { break; }
-                            reply_lines.push(line.to_string());
-                        }
-                        
-                        let reply = reply_lines.join("\n");
-                        if create_comment_in_db(&db_pool, &thread_id, &reply, user).await {
-                            stream.write_all(b"Reply posted successfully!\r\n")?;
-                        } else {
-                            stream.write_all(b"Error posting reply\r\n")?;
-                        }
-                    } else {
-                        stream.write_all(b"Please login to reply. Type 'login'.\r\n")?;
-                    }
-                }
-                "quit" | "exit" => {
-                    stream.write_all(b"Goodbye!\r\n")?;
-                    break;
-                }
-                _ => {
-                    stream.write_all(b"Unknown command. Type 'help'.\r\n")?;
-                }
+/// A rejected password. A plain rejection would take password off the list
+/// of methods, where clients expect to be asked again.
+fn wrong_password() -> Auth {
+    Auth::Reject { proceed_with_methods: Some(auth_methods()), partial_success: false }
+}
+
+/// The account a connection logged in as.
+#[derive(Clone)]
+pub struct SshUser {
+    pub id: String,
+    pub username: String,
+}
+
+/// How a password (and code) check went.
+enum Login {
+    Accepted(SshUser),
+    // With a message to show the user, when there is more to say than "denied"
+    Rejected(Option<String>),
+}
+
+/// Where a keyboard-interactive login is.
+#[derive(Default)]
+enum Prompt {
+    #[default]
+    None,
+    Password,
+    Code { password: String },
+    // A refusal was shown in the instructions; the answer is a formality
+    Refused,
+}
+
+/// What a channel's shell receives.
+pub enum Input {
+    Data(Vec<u8>),
+    Resize { cols: u32, rows: u32 },
+}
+
+/// Terminal the client asked for with `pty-req`.
+#[derive(Clone)]
+pub struct Pty {
+    // The client's TERM, e.g. "xterm-256color"
+    pub term: String,
+    pub cols: u32,
+    pub rows: u32,
+}
+
+#[derive(Default)]
+struct ChannelState {
+    pty: Option<Pty>,
+    // Open while the shell runs and the client may still send
+    input: Option<mpsc::Sender<Input>>,
+    shell_started: bool,
+}
+
+/// One client connection: authentication as RFC 4252's `ssh-userauth`, then
+/// the session channels of RFC 4254 for the logged-in user.
+struct Connection {
+    db_pool: Arc<PgPool>,
+    limiter: Arc<RateLimiter>,
+    address: SocketAddr,
+    user: Option<SshUser>,
+    prompt: Prompt,
+    channels: HashMap<ChannelId, ChannelState>,
+    // Told once the user is in, which ends the login grace time
+    logged_in: Option<oneshot::Sender<()>>,
+}
+
+impl Connection {
+    fn new(db_pool: Arc<PgPool>, limiter: Arc<RateLimiter>, address: SocketAddr, logged_in: oneshot::Sender<()>) -> Connection {
+        Connection {
+            db_pool,
+            limiter,
+            address,
+            user: None,
+            prompt: Prompt::None,
+            channels: HashMap::new(),
+            logged_in: Some(logged_in),
+        }
+    }
+
+    fn accept(&mut self, user: SshUser) -> Auth {
+        self.user = Some(user);
+        if let Some(logged_in) = self.logged_in.take() {
+            let _ = logged_in.send(());
+        }
+        Auth::Accept
+    }
+
+    /// Pass `input` on to the channel's shell. The session can't wait for a
+    /// shell that has fallen behind, so past `INPUT_QUEUE` the input is dropped.
+    fn forward(&mut self, id: ChannelId, input: Input) -> anyhow::Result<()> {
+        if let Some(sender) = &self.channel(id)?.input {
+            if let Err(TrySendError::Full(_)) = sender.try_send(input) {
+                log::warn!("SSH input from {} dropped: shell is behind", self.address);
             }
         }
-    } else {
-        stream.write_all(b"Access denied: Arch Linux required\r\n")?;
+        Ok(())
+    }
+
+    /// Show `message` to a keyboard-interactive user, then reject whatever they answer.
+    fn refuse(&mut self, message: String) -> Auth {
+        self.prompt = Prompt::Refused;
+        Auth::Partial { name: Cow::Borrowed(""), instructions: Cow::Owned(message), prompts: Cow::Borrowed(&[]) }
+    }
+
+    fn ask(&mut self, prompt: Prompt, question: &'static str, echo: bool) -> Auth {
+        self.prompt = prompt;
+        Auth::Partial {
+            name: Cow::Borrowed(""),
+            instructions: Cow::Borrowed(""),
+            prompts: Cow::Owned(vec![(Cow::Borrowed(question), echo)]),
+        }
+    }
+
+    fn finish(&mut self, login: Login) -> Auth {
+        match login {
+            Login::Accepted(user) => self.accept(user),
+            Login::Rejected(Some(message)) => self.refuse(message),
+            Login::Rejected(None) => Auth::reject(),
+        }
+    }
+
+    fn channel(&mut self, id: ChannelId) -> anyhow::Result<&mut ChannelState> {
+        self.channels.get_mut(&id).ok_or_else(|| anyhow!("unknown channel {}", id))
     }
-    
-    Ok(())
 }
 
-fn get_threads_from_db(pool: &PgPool) -> Result<Vec<(String, String)>, Box<dyn std::error::Error>> {
-    use std::thread;
-    use std::sync::mpsc;
-    
-    let (tx, rx) = mpsc::channel();
-    let pool_clone = pool.clone();
-    
-    thread::spawn(move || {
-        let rt = tokio::runtime::Runtime::new().unwrap();
-        let result = rt.block_on(async {
-            sqlx::query("SELECT title, author FROM threads ORDER BY created_at DESC LIMIT 10")
-                .fetch_all(&pool_clone)
-                .await
-        });
-        
-        match result {
-            Ok(rows) => {
-                let threads: Vec<(String, String)> = rows.iter()
-                    .map(|row| (
-                        row.get::<String, _>("title"),
-                        row.get::<String, _>("author")
-                    ))
-                    .collect();
-                tx.send(Ok(threads)).unwrap();
+/// A key as `ssh_keys` stores it; `None` for the kinds it doesn't take.
+fn registered_form(key: &PublicKey) -> Option<ssh_key::PublicKey> {
+    ssh_key::PublicKey::from_bytes(&key.to_bytes().ok()?).ok()
+}
+
+impl Handler for Connection {
+    type Error = anyhow::Error;
+
+    /// A key we would accept, before the client proves it holds it.
+    async fn auth_publickey_offered(&mut self, user: &str, public_key: &PublicKey) -> anyhow::Result<Auth> {
+        let username = validation::normalize(&validation::USERNAME, user);
+        let Some(key) = registered_form(public_key) else {
+            return Ok(Auth::reject());
+        };
+        if ssh_keys::is_registered(&self.db_pool, &username, &key).await {
+            Ok(Auth::Accept)
+        } else {
+            Ok(Auth::reject())
+        }
+    }
+
+    /// The same keys users register for key login over HTTP, which stand in
+    /// for the second factor too.
+    async fn auth_publickey(&mut self, user: &str, public_key: &PublicKey) -> anyhow::Result<Auth> {
+        let username = validation::normalize(&validation::USERNAME, user);
+        let Some(key) = registered_form(public_key) else {
+            return Ok(Auth::reject());
+        };
+        let Some(user_id) = ssh_keys::user_for_key(&self.db_pool, &username, &key).await else {
+            return Ok(Auth::reject());
+        };
+        if let Some(reason) = moderation::account_block(&self.db_pool, &user_id).await {
+            log::info!("SSH key login for {} refused: {}", username, reason);
+            return Ok(Auth::reject());
+        }
+        Ok(self.accept(SshUser { id: user_id, username }))
+    }
+
+    /// Plain password authentication has nowhere to show a message, so
+    /// accounts with a second factor are sent on to keyboard-interactive.
+    async fn auth_password(&mut self, user: &str, password: &str) -> anyhow::Result<Auth> {
+        let username = validation::normalize(&validation::USERNAME, user);
+        if let Some(message) = self.limiter.ssh_login_attempt(self.address.ip(), &username) {
+            log::info!("SSH password login for {} refused: {}", username, message);
+            return Ok(wrong_password());
+        }
+        let Some(user_id) = check_password(&self.db_pool, &self.limiter, &username, password).await else {
+            return Ok(wrong_password());
+        };
+        // The password was right, but the code has to come another way
+        if has_second_factor(&self.db_pool, &user_id).await {
+            return Ok(Auth::Reject {
+                proceed_with_methods: Some(MethodSet::from(&[MethodKind::PublicKey, MethodKind::KeyboardInteractive][..])),
+                partial_success: true,
+            });
+        }
+        match password_auth(&self.db_pool, &self.limiter, &username, password, None).await {
+            Login::Accepted(user) => Ok(self.accept(user)),
+            Login::Rejected(message) => {
+                log::info!("SSH password login for {} refused: {}", username, message.unwrap_or_default());
+                Ok(wrong_password())
             }
-            Err(e) => {
-                tx.send(Err(Box::new(e))).unwrap();
+        }
+    }
+
+    /// Password, then the two-factor code for accounts that have one. Refusals
+    /// are shown as the instructions of one last, empty round.
+    async fn auth_keyboard_interactive<'a>(
+        &'a mut self,
+        user: &str,
+        _submethods: &str,
+        response: Option<Response<'a>>,
+    ) -> anyhow::Result<Auth> {
+        let username = validation::normalize(&validation::USERNAME, user);
+        let mut answers = response.into_iter().flatten();
+        let answer = answers.next().map(|a| String::from_utf8_lossy(&a).into_owned());
+        match (std::mem::take(&mut self.prompt), answer) {
+            (Prompt::None, _) => match self.limiter.ssh_login_attempt(self.address.ip(), &username) {
+                Some(message) => Ok(self.refuse(message)),
+                None => Ok(self.ask(Prompt::Password, "Password: ", false)),
+            },
+            (Prompt::Password, Some(password)) => {
+                let Some(user_id) = check_password(&self.db_pool, &self.limiter, &username, &password).await else {
+                    return Ok(Auth::reject());
+                };
+                if has_second_factor(&self.db_pool, &user_id).await {
+                    return Ok(self.ask(Prompt::Code { password }, "Two-factor code: ", true));
+                }
+                let login = password_auth(&self.db_pool, &self.limiter, &username, &password, None).await;
+                Ok(self.finish(login))
+            }
+            (Prompt::Code { password }, Some(code)) => {
+                let login = password_auth(&self.db_pool, &self.limiter, &username, &password, Some(&code)).await;
+                Ok(self.finish(login))
             }
+            // Cancelled, or the answer to a refusal
+            _ => Ok(Auth::reject()),
         }
-    });
-    
-    Ok(rx.recv()??.into())
-}
+    }
+
+    async fn channel_open_session(
+        &mut self,
+        channel: Channel<Msg>,
+        reply: russh::server::ChannelOpenHandle,
+        _session: &mut Session,
+    ) -> anyhow::Result<()> {
+        if self.channels.len() >= MAX_CHANNELS {
+            reply.reject(ChannelOpenFailure::ResourceShortage).await;
+            return Ok(());
+        }
+        self.channels.insert(channel.id(), ChannelState::default());
+        reply.accept().await;
+        Ok(())
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    async fn pty_request(
+        &mut self,
+        id: ChannelId,
+        term: &str,
+        cols: u32,
+        rows: u32,
+        _pixel_width: u32,
+        _pixel_height: u32,
+        _modes: &[(russh::Pty, u32)],
+        session: &mut Session,
+    ) -> anyhow::Result<()> {
+        self.channel(id)?.pty = Some(Pty { term: term.to_string(), cols, rows });
+        session.channel_success(id)?;
+        Ok(())
+    }
+
+    async fn window_change_request(
+        &mut self,
+        id: ChannelId,
+        cols: u32,
+        rows: u32,
+        _pixel_width: u32,
+        _pixel_height: u32,
+        session: &mut Session,
+    ) -> anyhow::Result<()> {
+        let channel = self.channel(id)?;
+        if let Some(pty) = &mut channel.pty {
+            pty.cols = cols;
+            pty.rows = rows;
+        }
+        self.forward(id, Input::Resize { cols, rows })?;
+        session.channel_success(id)?;
+        Ok(())
+    }
 
-fn create_thread_in_db(pool: &PgPool, title: &str, content: &str, author: &str) -> bool {
-    use std::thread;
-    use std::sync::mpsc;
-    use uuid::Uuid;
-    use chrono::Utc;
-    
-    let (tx, rx) = mpsc::channel();
-    let pool_clone = pool.clone();
-    let title = title.to_string();
-    let content = content.to_string();
-    let author = author.to_string();
-    
-    thread::spawn(move || {
-        let rt = tokio::runtime::Runtime::new().unwrap();
-        let result = rt.block_on(async {
-            // First ensure user exists
-            let user_id = Uuid::new_v4().to_string();
-            let _ = sqlx::query("INSERT INTO users (id, username, password_hash, created_at) VALUES ($1, $2, $3, $4) ON CONFLICT (username) DO NOTHING")
-                .bind(&user_id)
-                .bind(&author)
-                .bind("")
-                .bind(Utc::now().to_rfc3339())
-                .execute(&pool_clone)
-                .await;
-            
-            // Get actual user ID
-            if let Ok(user_row) = sqlx::query("SELECT id FROM users WHERE username = $1")
-                .bind(&author)
-                .fetch_one(&pool_clone)
-                .await {
-                let actual_user_id: String = user_row.get("id");
-                
-                // Create thread
-                let thread_id = Uuid::new_v4().to_string();
-                sqlx::query("INSERT INTO threads (id, title, user_id, content, created_at, ssh_user) VALUES ($1, $2, $3, $4, $5, $6)")
-                    .bind(&thread_id)
-                    .bind(&title)
-                    .bind(&actual_user_id)
-                    .bind(&content)
-                    .bind(Utc::now().to_rfc3339())
-                    .bind(true)
-                    .execute(&pool_clone)
-                    .await
-            } else {
-                Err(sqlx::Error::RowNotFound)
+    async fn shell_request(&mut self, id: ChannelId, session: &mut Session) -> anyhow::Result<()> {
+        let db_pool = self.db_pool.clone();
+        let user = self.user.clone().ok_or_else(|| anyhow!("shell before login"))?;
+        let address = self.address;
+        let channel = self.channel(id)?;
+        if channel.shell_started {
+            session.channel_failure(id)?;
+            return Ok(());
+        }
+        channel.shell_started = true;
+        session.channel_success(id)?;
+        match channel.pty.clone() {
+            Some(pty) => {
+                let (input_sender, input) = mpsc::channel(INPUT_QUEUE);
+                channel.input = Some(input_sender);
+                tokio::spawn(ssh_tui::run(db_pool, user, address, pty, session.handle(), id, input));
             }
-        });
-        
-        tx.send(result.is_ok()).unwrap();
-    });
-    
-    rx.recv().unwrap_or(false)
+            // `ssh -T`, or input from a pipe: nothing to draw the UI in
+            None => {
+                session.data(id, &b"The forum needs a terminal; connect with ssh -t\r\n"[..])?;
+                session.exit_status_request(id, 1)?;
+                session.eof(id)?;
+                session.close(id)?;
+            }
+        }
+        Ok(())
+    }
+
+    // No commands, subsystems or environment variables
+    async fn exec_request(&mut self, id: ChannelId, _data: &[u8], session: &mut Session) -> anyhow::Result<()> {
+        session.channel_failure(id)?;
+        Ok(())
+    }
+
+    async fn subsystem_request(&mut self, id: ChannelId, _name: &str, session: &mut Session) -> anyhow::Result<()> {
+        session.channel_failure(id)?;
+        Ok(())
+    }
+
+    async fn env_request(
+        &mut self,
+        id: ChannelId,
+        _variable_name: &str,
+        _variable_value: &str,
+        session: &mut Session,
+    ) -> anyhow::Result<()> {
+        session.channel_failure(id)?;
+        Ok(())
+    }
+
+    async fn data(&mut self, id: ChannelId, data: &[u8], _session: &mut Session) -> anyhow::Result<()> {
+        self.forward(id, Input::Data(data.to_vec()))
+    }
+
+    // The client sends no more; a shell reading piped input ends here
+    async fn channel_eof(&mut self, id: ChannelId, _session: &mut Session) -> anyhow::Result<()> {
+        self.channel(id)?.input = None;
+        Ok(())
+    }
+
+    async fn channel_close(&mut self, id: ChannelId, _session: &mut Session) -> anyhow::Result<()> {
+        self.channels.remove(&id);
+        Ok(())
+    }
+}
+
+/// The id of `username` if `password` is theirs. Wrong guesses count against
+/// the account's failed logins.
+async fn check_password(db_pool: &PgPool, limiter: &RateLimiter, username: &str, password: &str) -> Option<String> {
+    let row: Option<(String, String)> = sqlx::query_as("SELECT id, password_hash FROM users WHERE username = $1")
+        .bind(username)
+        .fetch_optional(db_pool)
+        .await
+        .unwrap_or(None);
+    // Deleted accounts have an empty hash, which no password matches
+    let user_id = row
+        .filter(|(_, hash)| !hash.is_empty() && *hash == auth::hash_password(password))
+        .map(|(id, _)| id);
+    if user_id.is_none() {
+        limiter.login_failed(username);
+    }
+    user_id
+}
+
+async fn has_second_factor(db_pool: &PgPool, user_id: &str) -> bool {
+    sqlx::query_scalar("SELECT totp_enabled FROM users WHERE id = $1")
+        .bind(user_id)
+        .fetch_one(db_pool)
+        .await
+        .unwrap_or(false)
 }
 
-async fn create_comment_in_db(pool: &PgPool, thread_id: &str, content: &str, author: &str) -> bool {
-    use uuid::Uuid;
-    use chrono::Utc;
-    
-    let comment_id = Uuid::new_v4().to_string();
-    let created_at = Utc::now().to_rfc3339();
-    
-    // First ensure user exists
-    let user_id = Uuid::new_v4().to_string();
-    let _ = sqlx::query("INSERT INTO users (id, username, password_hash, created_at) VALUES ($1, $2, $3, $4) ON CONFLICT (username) DO NOTHING")
-        .bind(&user_id)
-        .bind(author)
-        .bind("")
-        .bind(&created_at)
-        .execute(pool)
-        .await;
-    
-    // Get actual user ID
-    if let Ok(user_row) = sqlx::query("SELECT id FROM users WHERE username = $1")
-        .bind(author)
-        .fetch_one(pool)
-        .await {
-        let actual_user_id: String = user_row.get("id");
-        
-        // Create comment
-        sqlx::query("INSERT INTO comments (id, thread_id, user_id, content, created_at) VALUES ($1, $2, $3, $4, $5)")
-            .bind(&comment_id)
-            .bind(thread_id)
-            .bind(&actual_user_id)
-            .bind(content)
-            .bind(&created_at)
-            .execute(pool)
-            .await
-            .is_ok()
-    } else {
-        false
+async fn password_auth(db_pool: &PgPool, limiter: &RateLimiter, username: &str, password: &str, code: Option<&str>) -> Login {
+    let Some(user_id) = check_password(db_pool, limiter, username, password).await else {
+        return Login::Rejected(None);
+    };
+    if let Some(reason) = moderation::account_block(db_pool, &user_id).await {
+        return Login::Rejected(Some(reason));
+    }
+    if code.is_some() {
+        if let Some(message) = limiter.ssh_second_factor_refused(username) {
+            return Login::Rejected(Some(message));
+        }
+    }
+    if two_factor::check_login(db_pool, &user_id, code).await.is_err() {
+        if code.is_some() {
+            limiter.second_factor_failed(username);
+        }
+        return Login::Rejected(Some("Invalid two-factor code".to_string()));
     }
+    Login::Accepted(SshUser { id: user_id, username: username.to_string() })
 }
@@ -0,0 +1,536 @@
+// ssh_tui.rs
+// The ternimal client's forum UI, run for each SSH session channel that has a
+// terminal. The app talks to our own HTTP API as any client would, logged in
+// with a session made for the SSH user, and draws into the channel: escape
+// sequences out, keys in.
+use std::io::{self, Write};
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+
+use crossterm::cursor::Show;
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use crossterm::execute;
+use crossterm::terminal::{EnterAlternateScreen, LeaveAlternateScreen};
+use ratatui::backend::{Backend, ClearType, CrosstermBackend, WindowSize};
+use ratatui::buffer::Cell;
+use ratatui::layout::{Rect, Size};
+use ratatui::Terminal;
+use russh::server::Handle;
+use russh::ChannelId;
+use sqlx::PgPool;
+use tokio::sync::mpsc;
+use ternimal::{api, app::App, ui};
+
+use crate::auth;
+use crate::ssh_server::{Input, Pty, SshUser};
+
+// The HTTP API the app uses, which is this process (see `main`)
+const LOCAL_API: &str = "http://127.0.0.1:8080";
+// An escape sequence longer than this without its final byte is dropped
+const MAX_SEQUENCE: usize = 32;
+
+/// Run the forum UI for `user` on channel `id` until they quit or the channel
+/// closes, then report the exit status.
+pub async fn run(
+    db_pool: Arc<PgPool>,
+    user: SshUser,
+    address: SocketAddr,
+    pty: Pty,
+    handle: Handle,
+    id: ChannelId,
+    input: mpsc::Receiver<Input>,
+) {
+    let status = match serve(&db_pool, &user, address, pty, &handle, id, input).await {
+        Ok(()) => 0,
+        Err(e) => {
+            log::warn!("SSH forum UI for {} failed: {}", user.username, e);
+            let _ = handle.data(id, format!("{}\r\n", e).into_bytes()).await;
+            1
+        }
+    };
+    let _ = handle.exit_status_request(id, status).await;
+    let _ = handle.eof(id).await;
+    let _ = handle.close(id).await;
+}
+
+/// Log the app in with a session of its own, which ends with the channel.
+async fn serve(
+    db_pool: &PgPool,
+    user: &SshUser,
+    address: SocketAddr,
+    pty: Pty,
+    handle: &Handle,
+    id: ChannelId,
+    input: mpsc::Receiver<Input>,
+) -> anyhow::Result<()> {
+    api::set_base_url(LOCAL_API);
+    let (created_at, role) = sqlx::query_as("SELECT created_at, role FROM users WHERE id = $1")
+        .bind(&user.id)
+        .fetch_one(db_pool)
+        .await?;
+    let token = auth::create_session(db_pool, &user.id).await?;
+    let session_user = api::User {
+        id: user.id.clone(),
+        username: user.username.clone(),
+        created_at,
+        role,
+        token: Some(token.clone()),
+    };
+
+    let output = ChannelOutput { handle: handle.clone(), id, written: Arc::default() };
+    let result = api::with_remote_session(address.ip().to_string(), draw_loop(session_user, pty, output, input)).await;
+    let _ = sqlx::query("DELETE FROM sessions WHERE token = $1")
+        .bind(&token)
+        .execute(db_pool)
+        .await;
+    result
+}
+
+/// The client's `run_app`, with keys and window sizes from the channel.
+async fn draw_loop(user: api::User, pty: Pty, output: ChannelOutput, mut input: mpsc::Receiver<Input>) -> anyhow::Result<()> {
+    let mut app = App::remote(&pty.term);
+    app.resume_session(user).await;
+    let mut terminal = Terminal::new(ChannelBackend::new(output.writer(), pty.cols, pty.rows))?;
+    execute!(terminal.backend_mut(), EnterAlternateScreen)?;
+    terminal.clear()?;
+
+    let mut keys = KeyParser::default();
+    let result = loop {
+        app.auto_refresh().await;
+        terminal.draw(|f| ui::draw(f, &app))?;
+        // Images go on top of the frame that was just drawn
+        app.avatars.flush(terminal.backend_mut())?;
+        app.flush_clipboard(terminal.backend_mut())?;
+        output.send().await?;
+
+        match input.recv().await {
+            Some(Input::Data(data)) => {
+                let mut handled = Ok(());
+                for key in keys.parse(&data) {
+                    handled = ui::handle_key(key, &mut app).await;
+                    if handled.is_err() || app.should_quit {
+                        break;
+                    }
+                }
+                if handled.is_err() || app.should_quit {
+                    break handled;
+                }
+            }
+            Some(Input::Resize { cols, rows }) => terminal.backend_mut().resize(cols, rows),
+            // The client hung up, so there is no screen left to restore
+            None => return Ok(()),
+        }
+    };
+
+    // Nothing was drawn since the last flush, so this takes any images off the screen
+    app.avatars.flush(terminal.backend_mut())?;
+    execute!(terminal.backend_mut(), LeaveAlternateScreen, Show)?;
+    output.send().await?;
+    result
+}
+
+/// Where a channel's screen output goes: the terminal writes it into a
+/// buffer, and each frame is sent on as a whole. Sending waits while the
+/// client's window is full, so a client that stops reading stops the UI
+/// rather than piling up output.
+struct ChannelOutput {
+    handle: Handle,
+    id: ChannelId,
+    written: Arc<Mutex<Vec<u8>>>,
+}
+
+impl ChannelOutput {
+    fn writer(&self) -> ChannelWriter {
+        ChannelWriter(self.written.clone())
+    }
+
+    async fn send(&self) -> anyhow::Result<()> {
+        let data = std::mem::take(&mut *self.written.lock().unwrap());
+        if data.is_empty() {
+            return Ok(());
+        }
+        self.handle.data(self.id, data).await.map_err(|_| anyhow::anyhow!("channel closed"))
+    }
+}
+
+/// Collects what the terminal is sent, for `ChannelOutput::send`.
+struct ChannelWriter(Arc<Mutex<Vec<u8>>>);
+
+impl Write for ChannelWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// The crossterm backend writing to a channel. The terminal is on the client's
+/// machine, so its size is what the client last reported rather than asked of
+/// a local tty.
+struct ChannelBackend {
+    inner: CrosstermBackend<ChannelWriter>,
+    size: Rect,
+}
+
+impl ChannelBackend {
+    fn new(writer: ChannelWriter, cols: u32, rows: u32) -> ChannelBackend {
+        let mut backend = ChannelBackend { inner: CrosstermBackend::new(writer), size: Rect::default() };
+        backend.resize(cols, rows);
+        backend
+    }
+
+    /// Take a new window size, which the next draw adapts to.
+    fn resize(&mut self, cols: u32, rows: u32) {
+        let clamp = |n: u32| n.clamp(1, u16::MAX as u32) as u16;
+        self.size = Rect::new(0, 0, clamp(cols), clamp(rows));
+    }
+}
+
+impl Write for ChannelBackend {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.inner.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Write::flush(&mut self.inner)
+    }
+}
+
+impl Backend for ChannelBackend {
+    fn draw<'a, I>(&mut self, content: I) -> io::Result<()>
+    where
+        I: Iterator<Item = (u16, u16, &'a Cell)>,
+    {
+        self.inner.draw(content)
+    }
+
+    fn hide_cursor(&mut self) -> io::Result<()> {
+        self.inner.hide_cursor()
+    }
+
+    fn show_cursor(&mut self) -> io::Result<()> {
+        self.inner.show_cursor()
+    }
+
+    fn get_cursor(&mut self) -> io::Result<(u16, u16)> {
+        Err(io::Error::new(io::ErrorKind::Unsupported, "the cursor position is not known over SSH"))
+    }
+
+    fn set_cursor(&mut self, x: u16, y: u16) -> io::Result<()> {
+        self.inner.set_cursor(x, y)
+    }
+
+    fn clear(&mut self) -> io::Result<()> {
+        self.inner.clear()
+    }
+
+    fn clear_region(&mut self, clear_type: ClearType) -> io::Result<()> {
+        self.inner.clear_region(clear_type)
+    }
+
+    fn size(&self) -> io::Result<Rect> {
+        Ok(self.size)
+    }
+
+    fn window_size(&mut self) -> io::Result<WindowSize> {
+        Ok(WindowSize {
+            columns_rows: Size { width: self.size.width, height: self.size.height },
+            pixels: Size::default(),
+        })
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Backend::flush(&mut self.inner)
+    }
+}
+
+/// One step of reading keys from terminal input.
+enum Parsed {
+    // A key, from this many bytes
+    Key(KeyEvent, usize),
+    // Bytes that are no key we know (mouse reports, invalid UTF-8)
+    Skip(usize),
+    // The start of a character or escape sequence whose rest has not arrived
+    Incomplete,
+}
+
+/// Turns what the client's terminal sends into key events, as crossterm does
+/// for a local one.
+#[derive(Default)]
+struct KeyParser {
+    pending: Vec<u8>,
+}
+
+impl KeyParser {
+    fn parse(&mut self, data: &[u8]) -> Vec<KeyEvent> {
+        self.pending.extend_from_slice(data);
+        let mut keys = Vec::new();
+        let mut rest = self.pending.as_slice();
+        while !rest.is_empty() {
+            match parse_key(rest) {
+                Parsed::Key(key, len) => {
+                    keys.push(key);
+                    rest = &rest[len..];
+                }
+                Parsed::Skip(len) => rest = &rest[len..],
+                Parsed::Incomplete => break,
+            }
+        }
+        self.pending = rest.to_vec();
+        keys
+    }
+}
+
+fn key(code: KeyCode, modifiers: KeyModifiers, len: usize) -> Parsed {
+    Parsed::Key(KeyEvent::new(code, modifiers), len)
+}
+
+/// The key at the start of `bytes`. Terminals send an escape sequence in one
+/// go, so an escape ending the input is the Esc key itself.
+fn parse_key(bytes: &[u8]) -> Parsed {
+    match bytes {
+        [] => Parsed::Incomplete,
+        [0x1b] => key(KeyCode::Esc, KeyModifiers::NONE, 1),
+        [0x1b, b'[', rest @ ..] => match csi(rest) {
+            Parsed::Key(key, len) => Parsed::Key(key, len + 2),
+            Parsed::Skip(len) => Parsed::Skip(len + 2),
+            Parsed::Incomplete => Parsed::Incomplete,
+        },
+        [0x1b, b'O'] => Parsed::Incomplete,
+        [0x1b, b'O', c, ..] => match c {
+            b'A'..=b'D' | b'H' | b'F' => key(cursor_key(*c), KeyModifiers::NONE, 3),
+            b'P'..=b'S' => key(KeyCode::F(c - b'P' + 1), KeyModifiers::NONE, 3),
+            _ => Parsed::Skip(3),
+        },
+        // Escape before a key is how terminals send it with Alt
+        [0x1b, rest @ ..] => match parse_key(rest) {
+            Parsed::Key(mut key, len) => {
+                key.modifiers |= KeyModifiers::ALT;
+                Parsed::Key(key, len + 1)
+            }
+            Parsed::Skip(len) => Parsed::Skip(len + 1),
+            Parsed::Incomplete => Parsed::Incomplete,
+        },
+        [b'\r' | b'\n', ..] => key(KeyCode::Enter, KeyModifiers::NONE, 1),
+        [b'\t', ..] => key(KeyCode::Tab, KeyModifiers::NONE, 1),
+        [0x7f | 0x08, ..] => key(KeyCode::Backspace, KeyModifiers::NONE, 1),
+        [0, ..] => key(KeyCode::Char(' '), KeyModifiers::CONTROL, 1),
+        [c @ 0x01..=0x1a, ..] => key(KeyCode::Char((c - 0x01 + b'a') as char), KeyModifiers::CONTROL, 1),
+        [c @ 0x1c..=0x1f, ..] => key(KeyCode::Char((c - 0x1c + b'4') as char), KeyModifiers::CONTROL, 1),
+        [first, ..] => {
+            let len = match first {
+                0xc0..=0xdf => 2,
+                0xe0..=0xef => 3,
+                0xf0..=0xf7 => 4,
+                _ => 1,
+            };
+            if bytes.len() < len {
+                return Parsed::Incomplete;
+            }
+            match std::str::from_utf8(&bytes[..len]).ok().and_then(|s| s.chars().next()) {
+                Some(c) if c.is_uppercase() => key(KeyCode::Char(c), KeyModifiers::SHIFT, len),
+                Some(c) => key(KeyCode::Char(c), KeyModifiers::NONE, len),
+                None => Parsed::Skip(1),
+            }
+        }
+    }
+}
+
+fn cursor_key(c: u8) -> KeyCode {
+    match c {
+        b'A' => KeyCode::Up,
+        b'B' => KeyCode::Down,
+        b'C' => KeyCode::Right,
+        b'D' => KeyCode::Left,
+        b'H' => KeyCode::Home,
+        _ => KeyCode::End,
+    }
+}
+
+/// A control sequence, after its `ESC [`: parameters, then one final byte.
+fn csi(bytes: &[u8]) -> Parsed {
+    let Some(end) = bytes.iter().position(|b| (0x40..=0x7e).contains(b)) else {
+        return if bytes.len() < MAX_SEQUENCE { Parsed::Incomplete } else { Parsed::Skip(bytes.len()) };
+    };
+    let params: Vec<u32> = std::str::from_utf8(&bytes[..end])
+        .unwrap_or_default()
+        .split(';')
+        .map(|p| p.parse().unwrap_or(0))
+        .collect();
+    // The second parameter, less one, has a bit for each modifier held
+    let held = params.get(1).map_or(0, |m| m.saturating_sub(1));
+    let mut modifiers = KeyModifiers::NONE;
+    if held & 1 != 0 {
+        modifiers |= KeyModifiers::SHIFT;
+    }
+    if held & 2 != 0 {
+        modifiers |= KeyModifiers::ALT;
+    }
+    if held & 4 != 0 {
+        modifiers |= KeyModifiers::CONTROL;
+    }
+
+    let code = match bytes[end] {
+        c @ (b'A'..=b'D' | b'H' | b'F') => cursor_key(c),
+        b'Z' => return key(KeyCode::BackTab, KeyModifiers::SHIFT, end + 1),
+        b'~' => match params[0] {
+            1 | 7 => KeyCode::Home,
+            2 => KeyCode::Insert,
+            3 => KeyCode::Delete,
+            4 | 8 => KeyCode::End,
+            5 => KeyCode::PageUp,
+            6 => KeyCode::PageDown,
+            n @ 11..=15 => KeyCode::F((n - 10) as u8),
+            n @ 17..=21 => KeyCode::F((n - 11) as u8),
+            n @ 23..=24 => KeyCode::F((n - 12) as u8),
+            _ => return Parsed::Skip(end + 1),
+        },
+        _ => return Parsed::Skip(end + 1),
+    };
+    key(code, modifiers, end + 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keys(data: &[u8]) -> Vec<KeyEvent> {
+        KeyParser::default().parse(data)
+    }
+
+    fn plain(code: KeyCode) -> KeyEvent {
+        KeyEvent::new(code, KeyModifiers::NONE)
+    }
+
+    #[test]
+    fn control_bytes_are_named_keys() {
+        assert_eq!(
+            keys(b"\r\n\t\x7f\x08"),
+            vec![
+                plain(KeyCode::Enter),
+                plain(KeyCode::Enter),
+                plain(KeyCode::Tab),
+                plain(KeyCode::Backspace),
+                plain(KeyCode::Backspace),
+            ]
+        );
+        assert_eq!(
+            keys(b"\x03\x00\x1c"),
+            vec![
+                KeyEvent::new(KeyCode::Char('c'), KeyModifiers::CONTROL),
+                KeyEvent::new(KeyCode::Char(' '), KeyModifiers::CONTROL),
+                KeyEvent::new(KeyCode::Char('4'), KeyModifiers::CONTROL),
+            ]
+        );
+    }
+
+    #[test]
+    fn characters_decode_as_utf8_with_shift_for_capitals() {
+        assert_eq!(
+            keys("aZé😀".as_bytes()),
+            vec![
+                plain(KeyCode::Char('a')),
+                KeyEvent::new(KeyCode::Char('Z'), KeyModifiers::SHIFT),
+                plain(KeyCode::Char('é')),
+                plain(KeyCode::Char('😀')),
+            ]
+        );
+    }
+
+    #[test]
+    fn invalid_utf8_is_skipped() {
+        assert_eq!(keys(b"\xffa\xc3("), vec![plain(KeyCode::Char('a')), plain(KeyCode::Char('('))]);
+    }
+
+    #[test]
+    fn escape_alone_is_esc_and_before_a_key_is_alt() {
+        assert_eq!(keys(b"\x1b"), vec![plain(KeyCode::Esc)]);
+        assert_eq!(
+            keys(b"\x1bx\x1b\r"),
+            vec![
+                KeyEvent::new(KeyCode::Char('x'), KeyModifiers::ALT),
+                KeyEvent::new(KeyCode::Enter, KeyModifiers::ALT),
+            ]
+        );
+    }
+
+    #[test]
+    fn cursor_keys_in_both_modes() {
+        assert_eq!(
+            keys(b"\x1b[A\x1b[B\x1bOC\x1bOD\x1b[H\x1bOF"),
+            vec![
+                plain(KeyCode::Up),
+                plain(KeyCode::Down),
+                plain(KeyCode::Right),
+                plain(KeyCode::Left),
+                plain(KeyCode::Home),
+                plain(KeyCode::End),
+            ]
+        );
+        assert_eq!(keys(b"\x1bOP\x1bOS"), vec![plain(KeyCode::F(1)), plain(KeyCode::F(4))]);
+    }
+
+    #[test]
+    fn csi_modifier_parameter_sets_modifiers() {
+        assert_eq!(
+            keys(b"\x1b[1;5A\x1b[1;2B\x1b[1;3C\x1b[1;8D"),
+            vec![
+                KeyEvent::new(KeyCode::Up, KeyModifiers::CONTROL),
+                KeyEvent::new(KeyCode::Down, KeyModifiers::SHIFT),
+                KeyEvent::new(KeyCode::Right, KeyModifiers::ALT),
+                KeyEvent::new(KeyCode::Left, KeyModifiers::SHIFT | KeyModifiers::ALT | KeyModifiers::CONTROL),
+            ]
+        );
+    }
+
+    #[test]
+    fn tilde_sequences_name_editing_and_function_keys() {
+        assert_eq!(
+            keys(b"\x1b[2~\x1b[3~\x1b[5~\x1b[6~\x1b[1~\x1b[4~\x1b[15~\x1b[24~\x1b[3;5~"),
+            vec![
+                plain(KeyCode::Insert),
+                plain(KeyCode::Delete),
+                plain(KeyCode::PageUp),
+                plain(KeyCode::PageDown),
+                plain(KeyCode::Home),
+                plain(KeyCode::End),
+                plain(KeyCode::F(5)),
+                plain(KeyCode::F(12)),
+                KeyEvent::new(KeyCode::Delete, KeyModifiers::CONTROL),
+            ]
+        );
+    }
+
+    #[test]
+    fn back_tab_has_shift() {
+        assert_eq!(keys(b"\x1b[Z"), vec![KeyEvent::new(KeyCode::BackTab, KeyModifiers::SHIFT)]);
+    }
+
+    #[test]
+    fn unknown_sequences_are_skipped_whole() {
+        assert_eq!(keys(b"\x1b[<0;10;5Mq\x1b[99~\x1bOxq"), vec![plain(KeyCode::Char('q')), plain(KeyCode::Char('q'))]);
+    }
+
+    #[test]
+    fn split_input_waits_for_the_rest() {
+        let mut parser = KeyParser::default();
+        assert_eq!(parser.parse(b"a\x1b[1;"), vec![plain(KeyCode::Char('a'))]);
+        assert_eq!(parser.parse(b"5A"), vec![KeyEvent::new(KeyCode::Up, KeyModifiers::CONTROL)]);
+        assert_eq!(parser.parse(b"\xe2\x82"), vec![]);
+        assert_eq!(parser.parse(b"\xac"), vec![plain(KeyCode::Char('€'))]);
+        assert_eq!(parser.parse(b"\x1bO"), vec![]);
+        assert_eq!(parser.parse(b"A"), vec![plain(KeyCode::Up)]);
+    }
+
+    #[test]
+    fn overlong_sequence_is_dropped() {
+        let mut data = b"\x1b[".to_vec();
+        data.extend(std::iter::repeat_n(b'1', MAX_SEQUENCE));
+        let mut parser = KeyParser::default();
+        assert_eq!(parser.parse(&data), vec![]);
+        assert_eq!(parser.parse(b"x"), vec![plain(KeyCode::Char('x'))]);
+    }
+}
@@ -0,0 +1,154 @@
+// tags.rs
+use actix_web::{get, web, HttpResponse, Responder};
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+
+use crate::validation;
+
+// Most tags a thread can carry
+pub const MAX_TAGS: usize = 5;
+// Default and largest number of tags returned by GET /tags
+const DEFAULT_LIMIT: i64 = 50;
+const MAX_LIMIT: i64 = 500;
+
+pub async fn ensure_tables(pool: &PgPool) {
+    let _ = sqlx::query(
+        r#"CREATE TABLE IF NOT EXISTS thread_tags (
+                thread_id TEXT NOT NULL REFERENCES threads(id) ON DELETE CASCADE,
+                tag TEXT NOT NULL,
+                PRIMARY KEY (thread_id, tag)
+            );"#
+    )
+    .execute(pool)
+    .await;
+    let _ = sqlx::query("CREATE INDEX IF NOT EXISTS thread_tags_tag ON thread_tags (tag)")
+        .execute(pool)
+        .await;
+}
+
+/// Canonical form of a tag: lowercase, without a leading '#', with runs of
+/// whitespace turned into '-'. "Rust Lang" and "#rust-lang" are the same tag.
+pub fn normalize(tag: &str) -> String {
+    let tag = validation::normalize(&validation::TAG, tag).to_lowercase();
+    tag.trim_start_matches('#').split_whitespace().collect::<Vec<_>>().join("-")
+}
+
+/// Normalize and deduplicate the tags of a new thread, recording an error for
+/// the first invalid tag or when there are more than `MAX_TAGS`.
+pub fn check(errors: &mut validation::Errors, tags: &[String]) -> Vec<String> {
+    let mut cleaned: Vec<String> = Vec::new();
+    for tag in tags {
+        let tag = errors.check(&validation::TAG, &normalize(tag));
+        if errors.has(validation::TAG.name) {
+            return cleaned;
+        }
+        if !cleaned.contains(&tag) {
+            cleaned.push(tag);
+        }
+    }
+    if cleaned.len() > MAX_TAGS {
+        errors.add(validation::TAG.name, format!("at most {} tags are allowed", MAX_TAGS));
+    }
+    cleaned
+}
+
+/// Parse a comma-separated `tags` query parameter into distinct canonical tags.
+pub fn parse_filter(tags: Option<&str>) -> Vec<String> {
+    let mut parsed: Vec<String> = Vec::new();
+    for tag in tags.unwrap_or("").split(',').map(normalize).filter(|t| !t.is_empty()) {
+        if !parsed.contains(&tag) {
+            parsed.push(tag);
+        }
+    }
+    parsed
+}
+
+pub async fn set_thread_tags(db: &PgPool, thread_id: &str, tags: &[String]) -> Result<(), sqlx::Error> {
+    sqlx::query("DELETE FROM thread_tags WHERE thread_id = $1")
+        .bind(thread_id)
+        .execute(db)
+        .await?;
+    sqlx::query("INSERT INTO thread_tags (thread_id, tag) SELECT $1, UNNEST($2::TEXT[]) ON CONFLICT DO NOTHING")
+        .bind(thread_id)
+        .bind(tags)
+        .execute(db)
+        .await?;
+    Ok(())
+}
+
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct TagCount {
+    name: String,
+    // Visible threads carrying the tag
+    count: i64,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TagQuery {
+    // Only tags starting with this, for autocomplete
+    prefix: Option<String>,
+    limit: Option<i64>,
+}
+
+#[get("/tags")]
+pub async fn list_tags(db: web::Data<PgPool>, query: web::Query<TagQuery>) -> impl Responder {
+    let prefix = query.prefix.as_deref().map(normalize).unwrap_or_default();
+    let limit = query.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT);
+    // LIKE wildcards in the prefix are matched literally
+    let pattern = format!("{}%", prefix.replace('\\', "\\\\").replace('%', "\\%").replace('_', "\\_"));
+    let rows = sqlx::query_as::<_, TagCount>(
+        r#"SELECT tg.tag as name, COUNT(*) as count
+           FROM thread_tags tg JOIN threads t ON t.id = tg.thread_id
+           WHERE tg.tag LIKE $1 AND t.deleted_at IS NULL AND NOT t.hidden AND NOT t.held
+           GROUP BY tg.tag
+           ORDER BY count DESC, name
+           LIMIT $2"#
+    )
+    .bind(pattern)
+    .bind(limit)
+    .fetch_all(&**db)
+    .await
+    .unwrap_or_else(|_| vec![]);
+
+    HttpResponse::Ok().json(rows)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn normalize_lowercases_and_joins_words() {
+        assert_eq!(normalize("  Rust Lang "), "rust-lang");
+        assert_eq!(normalize("#Help"), "help");
+        assert_eq!(normalize("C++"), "c++");
+        assert_eq!(normalize("ÉTÉ"), "été");
+    }
+
+    #[test]
+    fn check_deduplicates_and_reports_the_first_bad_tag() {
+        let mut errors = validation::Errors::default();
+        let tags = check(&mut errors, &["Rust".to_string(), "#rust".to_string(), "web dev".to_string()]);
+        assert_eq!(tags, ["rust", "web-dev"]);
+        assert!(errors.finish().is_ok());
+
+        let mut errors = validation::Errors::default();
+        let tags = check(&mut errors, &["ok".to_string(), "a/b".to_string(), "later".to_string()]);
+        assert_eq!(tags, ["ok"]);
+        assert!(errors.has("tags"));
+    }
+
+    #[test]
+    fn check_limits_the_number_of_tags() {
+        let mut errors = validation::Errors::default();
+        let many: Vec<String> = (0..=MAX_TAGS).map(|i| format!("tag{}", i)).collect();
+        check(&mut errors, &many);
+        assert_eq!(errors.summary(), format!("tags: at most {} tags are allowed", MAX_TAGS));
+    }
+
+    #[test]
+    fn parse_filter_skips_empty_and_repeated_tags() {
+        assert_eq!(parse_filter(Some("Rust, ,rust,#web")), ["rust", "web"]);
+        assert!(parse_filter(None).is_empty());
+    }
+}
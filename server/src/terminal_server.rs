@@ -2,7 +2,6 @@
 use actix_web::{get, post, web, HttpResponse, Responder};
 use std::sync::Arc;
 use sqlx::{PgPool, Row};
-use serde_json;
 use anyhow::Result;
 
 #[get("/terminal")]
@@ -103,7 +102,7 @@ async fn create_thread_in_db(pool: &PgPool, title: &str, content: &str, author:
     use chrono::Utc;
     
     let thread_id = Uuid::new_v4().to_string();
-    let created_at = Utc::now().to_rfc3339();
+    let created_at = Utc::now();
     
     // Create user if needed
     let user_id = Uuid::new_v4().to_string();
@@ -111,7 +110,7 @@ async fn create_thread_in_db(pool: &PgPool, title: &str, content: &str, author:
         .bind(&user_id)
         .bind(author)
         .bind("")
-        .bind(&created_at)
+        .bind(created_at)
         .execute(pool)
         .await;
     
@@ -128,7 +127,7 @@ async fn create_thread_in_db(pool: &PgPool, title: &str, content: &str, author:
             .bind(title)
             .bind(&actual_user_id)
             .bind(content)
-            .bind(&created_at)
+            .bind(created_at)
             .execute(pool)
             .await.is_ok())
     } else {
@@ -1,6 +1,10 @@
+// Shared helpers for integration tests
+#![allow(dead_code)]
+
 use actix_web::dev::Service;
 use actix_web::{test, web, App};
 use sqlx::postgres::PgPoolOptions;
+use sqlx::{Executor, PgPool, Postgres, Transaction};
 use std::env;
 
 pub async fn test_app() -> impl Service<
@@ -30,11 +34,26 @@ pub async fn test_app() -> impl Service<
             .service(crate::health)
             .service(crate::list_threads)
             .service(crate::create_thread)
-            .service(crate::list_categories)
-            .service(crate::create_category)
+            .service(crate::categories::list_categories)
+            .service(crate::categories::create_category)
             .service(crate::check_username)
             .service(crate::register_user)
             .service(crate::list_comments)
             .service(crate::create_comment)
     ).await
 }
+
+/// A transaction that has run `schema`, for tests to work in and never
+/// commit. `schema` creates temporary tables, which shadow the real ones of
+/// the same name. `None` without a `DATABASE_URL` to run against, which skips
+/// the test.
+pub async fn scratch_tables(schema: &str) -> Option<Transaction<'static, Postgres>> {
+    let Ok(url) = env::var("DATABASE_URL") else {
+        eprintln!("DATABASE_URL not set; skipping");
+        return None;
+    };
+    let pool = PgPool::connect(&url).await.expect("connect to DATABASE_URL");
+    let mut tx = pool.begin().await.expect("begin");
+    (&mut *tx).execute(schema).await.expect("create test tables");
+    Some(tx)
+}
@@ -0,0 +1,229 @@
+// trash.rs
+use actix_web::{get, post, web, HttpResponse, Responder};
+use chrono::{DateTime, Duration, Utc};
+use serde::Serialize;
+use sqlx::PgPool;
+
+use crate::audit;
+use crate::auth::Moderator;
+
+// How long deleted content is kept for restoring, unless TRASH_RETENTION_DAYS says otherwise
+const DEFAULT_RETENTION_DAYS: i64 = 30;
+// A century; anything longer is as good as keeping it forever
+const MAX_RETENTION_DAYS: i64 = 36_500;
+// How often the purge job looks for expired content
+const PURGE_INTERVAL_SECS: u64 = 60 * 60;
+
+pub async fn ensure_tables(pool: &PgPool) {
+    // Deleted threads and comments are tombstoned until the purge job removes them
+    for table in ["threads", "comments"] {
+        let _ = sqlx::query(&format!("ALTER TABLE {} ADD COLUMN IF NOT EXISTS deleted_at TIMESTAMPTZ", table))
+            .execute(pool)
+            .await;
+        let _ = sqlx::query(&format!("ALTER TABLE {} ADD COLUMN IF NOT EXISTS deleted_by TEXT", table))
+            .execute(pool)
+            .await;
+    }
+}
+
+/// Table holding content of the given target type ("thread" or "comment").
+fn table_for(target_type: &str) -> Option<&'static str> {
+    match target_type {
+        "thread" => Some("threads"),
+        "comment" => Some("comments"),
+        _ => None,
+    }
+}
+
+/// Tombstone a thread or comment. Returns the number of rows affected, which is
+/// zero if it does not exist or is already deleted.
+pub async fn soft_delete(db: &PgPool, target_type: &str, id: &str, deleted_by: &str) -> Result<u64, sqlx::Error> {
+    let Some(table) = table_for(target_type) else {
+        return Ok(0);
+    };
+    sqlx::query(&format!(
+        "UPDATE {} SET deleted_at = $1, deleted_by = $2 WHERE id = $3 AND deleted_at IS NULL",
+        table
+    ))
+    .bind(Utc::now())
+    .bind(deleted_by)
+    .bind(id)
+    .execute(db)
+    .await
+    .map(|r| r.rows_affected())
+}
+
+#[actix_web::delete("/comments/{id}")]
+pub async fn delete_comment(db: web::Data<PgPool>, moderator: Moderator, path: web::Path<String>) -> impl Responder {
+    let comment_id = path.into_inner();
+    let before = audit::snapshot(&db, "comments", &comment_id).await;
+
+    match soft_delete(&db, "comment", &comment_id, &moderator.0.id).await {
+        Ok(n) if n > 0 => {
+            let after = audit::snapshot(&db, "comments", &comment_id).await;
+            audit::record(&db, Some(&moderator.0), "comment.delete", "comment", Some(&comment_id), before, after).await;
+            HttpResponse::Ok().finish()
+        }
+        Ok(_) => HttpResponse::NotFound().finish(),
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
+
+/// A tombstoned thread or comment awaiting restore or purge.
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct DeletedItem {
+    target_type: String,
+    id: String,
+    thread_id: String,
+    thread_title: String,
+    author: String,
+    content: String,
+    deleted_by: Option<String>,
+    deleted_at: DateTime<Utc>,
+}
+
+#[get("/moderation/deleted")]
+pub async fn list_deleted(db: web::Data<PgPool>, _moderator: Moderator) -> impl Responder {
+    let rows = sqlx::query_as::<_, DeletedItem>(
+        r#"SELECT 'thread' as target_type, t.id, t.id as thread_id, t.title as thread_title,
+                  u.username as author, t.content, d.username as deleted_by, t.deleted_at
+           FROM threads t JOIN users u ON u.id = t.user_id
+           LEFT JOIN users d ON d.id = t.deleted_by
+           WHERE t.deleted_at IS NOT NULL
+           UNION ALL
+           SELECT 'comment', c.id, c.thread_id, t.title, u.username, c.content, d.username, c.deleted_at
+           FROM comments c JOIN users u ON u.id = c.user_id
+           JOIN threads t ON t.id = c.thread_id
+           LEFT JOIN users d ON d.id = c.deleted_by
+           WHERE c.deleted_at IS NOT NULL
+           ORDER BY deleted_at DESC"#
+    )
+    .fetch_all(&**db)
+    .await
+    .unwrap_or_else(|_| vec![]);
+
+    HttpResponse::Ok().json(rows)
+}
+
+#[post("/moderation/{target_type}/{id}/restore")]
+pub async fn restore(db: web::Data<PgPool>, moderator: Moderator, path: web::Path<(String, String)>) -> impl Responder {
+    let (target_type, id) = path.into_inner();
+    let Some(table) = table_for(&target_type) else {
+        return HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "target_type must be 'thread' or 'comment'"
+        }));
+    };
+    let before = audit::snapshot(&db, table, &id).await;
+
+    let result = sqlx::query(&format!(
+        "UPDATE {} SET deleted_at = NULL, deleted_by = NULL WHERE id = $1 AND deleted_at IS NOT NULL",
+        table
+    ))
+    .bind(&id)
+    .execute(&**db)
+    .await;
+
+    match result {
+        Ok(r) if r.rows_affected() > 0 => {
+            let after = audit::snapshot(&db, table, &id).await;
+            audit::record(&db, Some(&moderator.0), &format!("{}.restore", target_type), &target_type, Some(&id), before, after).await;
+            HttpResponse::Ok().finish()
+        }
+        Ok(_) => HttpResponse::NotFound().json(serde_json::json!({
+            "error": "No deleted content with that id"
+        })),
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
+
+/// Retention period for deleted content, from TRASH_RETENTION_DAYS. Values
+/// that aren't a whole number of days from 1 to `MAX_RETENTION_DAYS` are
+/// ignored with a warning, as 0 or less would purge deletions straight away.
+pub fn retention() -> Duration {
+    let days = match std::env::var("TRASH_RETENTION_DAYS") {
+        Ok(value) => retention_days(&value).unwrap_or_else(|| {
+            log::warn!(
+                "TRASH_RETENTION_DAYS={} is not a number of days from 1 to {}; keeping deleted content for {} days",
+                value, MAX_RETENTION_DAYS, DEFAULT_RETENTION_DAYS
+            );
+            DEFAULT_RETENTION_DAYS
+        }),
+        Err(_) => DEFAULT_RETENTION_DAYS,
+    };
+    Duration::days(days)
+}
+
+fn retention_days(value: &str) -> Option<i64> {
+    value.trim().parse().ok().filter(|days| (1..=MAX_RETENTION_DAYS).contains(days))
+}
+
+/// Permanently remove content that was deleted more than `retention` ago.
+/// Returns the number of threads and comments removed.
+pub async fn purge_expired(db: &PgPool, retention: Duration) -> Result<(u64, u64), sqlx::Error> {
+    let cutoff = Utc::now() - retention;
+    let mut tx = db.begin().await?;
+    let expired_comments: Vec<String> = sqlx::query_scalar("SELECT id FROM comments WHERE deleted_at < $1")
+        .bind(cutoff)
+        .fetch_all(&mut *tx)
+        .await?;
+    crate::reactions::remove_for(&mut tx, "comment", &expired_comments).await?;
+    let comments = sqlx::query("DELETE FROM comments WHERE deleted_at < $1")
+        .bind(cutoff)
+        .execute(&mut *tx)
+        .await?
+        .rows_affected();
+
+    let expired: Vec<String> = sqlx::query_scalar("SELECT id FROM threads WHERE deleted_at < $1")
+        .bind(cutoff)
+        .fetch_all(&mut *tx)
+        .await?;
+    for thread_id in &expired {
+        crate::remove_thread(&mut tx, thread_id).await?;
+    }
+    let threads = expired.len() as u64;
+    if threads > 0 || comments > 0 {
+        audit::record_in(&mut tx, None, "trash.purge", "trash", None, None, Some(serde_json::json!({
+            "threads": threads,
+            "comments": comments,
+            "deleted_before": cutoff
+        }))).await?;
+    }
+    tx.commit().await?;
+    Ok((threads, comments))
+}
+
+/// Run `purge_expired` in the background every `PURGE_INTERVAL_SECS`.
+pub fn spawn_purge_job(pool: PgPool) {
+    actix_web::rt::spawn(async move {
+        let mut interval = actix_web::rt::time::interval(std::time::Duration::from_secs(PURGE_INTERVAL_SECS));
+        loop {
+            interval.tick().await;
+            match purge_expired(&pool, retention()).await {
+                Ok((0, 0)) => {}
+                Ok((threads, comments)) => log::info!("purged {} deleted threads and {} deleted comments", threads, comments),
+                Err(e) => log::error!("purging deleted content failed: {}", e),
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn retention_takes_whole_days_in_range() {
+        assert_eq!(retention_days("7"), Some(7));
+        assert_eq!(retention_days(" 1 "), Some(1));
+        assert_eq!(retention_days("36500"), Some(MAX_RETENTION_DAYS));
+    }
+
+    #[test]
+    fn retention_rejects_values_that_would_purge_at_once_or_overflow() {
+        assert_eq!(retention_days("0"), None);
+        assert_eq!(retention_days("-5"), None);
+        assert_eq!(retention_days("36501"), None);
+        assert_eq!(retention_days("9223372036854775807"), None);
+        assert_eq!(retention_days("a week"), None);
+    }
+}
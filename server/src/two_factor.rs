@@ -0,0 +1,362 @@
+// two_factor.rs
+use actix_web::{get, post, web, HttpResponse, Responder};
+use chrono::Utc;
+use hmac::{Hmac, Mac};
+use rand::{Rng, RngCore};
+use serde::Deserialize;
+use sha1::Sha1;
+use sqlx::PgPool;
+
+use crate::account;
+use crate::audit;
+use crate::auth::{self, AuthUser};
+use crate::validation;
+
+// Shown as the account's issuer in authenticator apps
+const ISSUER: &str = "Ternimal";
+// RFC 6238 defaults, which every authenticator app supports
+const STEP_SECONDS: i64 = 30;
+const DIGITS: u32 = 6;
+// Codes from one step either side of now are accepted, for clock drift
+const DRIFT_STEPS: i64 = 1;
+const SECRET_BYTES: usize = 20;
+const RECOVERY_CODES: usize = 10;
+const RECOVERY_CODE_ALPHABET: &[u8] = b"abcdefghjkmnpqrstuvwxyz23456789";
+
+pub async fn ensure_tables(pool: &PgPool) {
+    // Base32 secret; only in force once `totp_enabled` is set by a confirmed code
+    let _ = sqlx::query("ALTER TABLE users ADD COLUMN IF NOT EXISTS totp_secret TEXT")
+        .execute(pool)
+        .await;
+    let _ = sqlx::query("ALTER TABLE users ADD COLUMN IF NOT EXISTS totp_enabled BOOLEAN NOT NULL DEFAULT FALSE")
+        .execute(pool)
+        .await;
+    // Last time step a code was accepted for, so a code cannot be used twice
+    let _ = sqlx::query("ALTER TABLE users ADD COLUMN IF NOT EXISTS totp_last_step BIGINT")
+        .execute(pool)
+        .await;
+    let _ = sqlx::query(
+        r#"CREATE TABLE IF NOT EXISTS recovery_codes (
+                user_id TEXT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+                code_hash TEXT NOT NULL,
+                used_at TIMESTAMPTZ,
+                PRIMARY KEY (user_id, code_hash)
+            );"#
+    )
+    .execute(pool)
+    .await;
+}
+
+/// The RFC 6238 code for `secret` at time step `step`.
+fn code_at(secret: &[u8], step: i64) -> String {
+    let mut mac = Hmac::<Sha1>::new_from_slice(secret).expect("HMAC takes keys of any size");
+    mac.update(&step.to_be_bytes());
+    let hash = mac.finalize().into_bytes();
+    let offset = (hash[hash.len() - 1] & 0x0f) as usize;
+    let value = u32::from_be_bytes([hash[offset], hash[offset + 1], hash[offset + 2], hash[offset + 3]]) & 0x7fff_ffff;
+    format!("{:0width$}", value % 10u32.pow(DIGITS), width = DIGITS as usize)
+}
+
+fn current_step() -> i64 {
+    Utc::now().timestamp() / STEP_SECONDS
+}
+
+/// Time step `code` is valid for under `secret` at step `now`. Steps up to
+/// `last_used` are skipped, so an accepted code can't be used again.
+fn matching_step(secret: &str, code: &str, now: i64, last_used: Option<i64>) -> Option<i64> {
+    let secret = base32::decode(base32::Alphabet::Rfc4648 { padding: false }, secret)?;
+    (now - DRIFT_STEPS..=now + DRIFT_STEPS)
+        .filter(|step| last_used.is_none_or(|last| *step > last))
+        .find(|step| code_at(&secret, *step) == code)
+}
+
+/// Recovery codes are compared without case, spaces or dashes.
+fn hash_recovery_code(code: &str) -> String {
+    let code: String = code.chars().filter(|c| c.is_ascii_alphanumeric()).collect();
+    auth::hash_password(&code.to_ascii_lowercase())
+}
+
+/// Check a TOTP code or, failing that, an unused recovery code, and use it up.
+async fn verify(db: &PgPool, user_id: &str, secret: &str, code: &str) -> bool {
+    let code: String = code.chars().filter(|c| !c.is_whitespace()).collect();
+    if code.len() == DIGITS as usize && code.chars().all(|c| c.is_ascii_digit()) {
+        let last_used: Option<i64> = sqlx::query_scalar("SELECT totp_last_step FROM users WHERE id = $1")
+            .bind(user_id)
+            .fetch_optional(db)
+            .await
+            .unwrap_or(None)
+            .flatten();
+        let Some(step) = matching_step(secret, &code, current_step(), last_used) else {
+            return false;
+        };
+        // Checked again here, in case a concurrent login used the same code
+        return sqlx::query("UPDATE users SET totp_last_step = $2 WHERE id = $1 AND (totp_last_step IS NULL OR totp_last_step < $2)")
+            .bind(user_id)
+            .bind(step)
+            .execute(db)
+            .await
+            .is_ok_and(|r| r.rows_affected() > 0);
+    }
+    sqlx::query("UPDATE recovery_codes SET used_at = $3 WHERE user_id = $1 AND code_hash = $2 AND used_at IS NULL")
+        .bind(user_id)
+        .bind(hash_recovery_code(&code))
+        .bind(Utc::now())
+        .execute(db)
+        .await
+        .is_ok_and(|r| r.rows_affected() > 0)
+}
+
+/// The second login step: `Err` with the response to send unless the user has
+/// no second factor or `code` is valid.
+pub async fn check_login(db: &PgPool, user_id: &str, code: Option<&str>) -> Result<(), HttpResponse> {
+    let secret: Option<String> = sqlx::query_scalar("SELECT totp_secret FROM users WHERE id = $1 AND totp_enabled")
+        .bind(user_id)
+        .fetch_optional(db)
+        .await
+        .unwrap_or(None)
+        .flatten();
+    let Some(secret) = secret else {
+        return Ok(());
+    };
+    let error = match code.map(str::trim).filter(|c| !c.is_empty()) {
+        None => "Two-factor code required",
+        Some(code) if verify(db, user_id, &secret, code).await => return Ok(()),
+        Some(_) => "Invalid two-factor code",
+    };
+    Err(HttpResponse::Unauthorized().json(serde_json::json!({
+        "error": error,
+        "two_factor_required": true
+    })))
+}
+
+/// Replace the user's recovery codes with fresh ones and return them.
+async fn new_recovery_codes(db: &PgPool, user_id: &str) -> Result<Vec<String>, sqlx::Error> {
+    let mut rng = rand::thread_rng();
+    let codes: Vec<String> = (0..RECOVERY_CODES)
+        .map(|_| {
+            let chars: String = (0..10)
+                .map(|_| RECOVERY_CODE_ALPHABET[rng.gen_range(0..RECOVERY_CODE_ALPHABET.len())] as char)
+                .collect();
+            format!("{}-{}", &chars[..5], &chars[5..])
+        })
+        .collect();
+    sqlx::query("DELETE FROM recovery_codes WHERE user_id = $1")
+        .bind(user_id)
+        .execute(db)
+        .await?;
+    for code in &codes {
+        sqlx::query("INSERT INTO recovery_codes (user_id, code_hash) VALUES ($1, $2) ON CONFLICT DO NOTHING")
+            .bind(user_id)
+            .bind(hash_recovery_code(code))
+            .execute(db)
+            .await?;
+    }
+    Ok(codes)
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CodeConfirmation {
+    #[serde(default)]
+    code: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TwoFactorDisable {
+    #[serde(default)]
+    password: String,
+    // A current code or an unused recovery code
+    #[serde(default)]
+    code: String,
+}
+
+fn field_error(field: &'static str, message: &str) -> HttpResponse {
+    let mut errors = validation::Errors::default();
+    errors.add(field, message.to_string());
+    errors.response()
+}
+
+#[get("/auth/2fa")]
+pub async fn status(db: web::Data<PgPool>, user: AuthUser) -> impl Responder {
+    let row: Option<(bool, i64)> = sqlx::query_as(
+        r#"SELECT u.totp_enabled,
+                  (SELECT COUNT(*) FROM recovery_codes r WHERE r.user_id = u.id AND r.used_at IS NULL)
+           FROM users u WHERE u.id = $1"#
+    )
+    .bind(&user.id)
+    .fetch_optional(&**db)
+    .await
+    .unwrap_or(None);
+    match row {
+        Some((enabled, recovery_codes_left)) => HttpResponse::Ok().json(serde_json::json!({
+            "enabled": enabled,
+            "recovery_codes_left": recovery_codes_left
+        })),
+        None => HttpResponse::NotFound().finish(),
+    }
+}
+
+/// Start enrollment: a new secret, to be confirmed with `POST /auth/2fa/enable`.
+#[post("/auth/2fa/setup")]
+pub async fn setup(db: web::Data<PgPool>, user: AuthUser) -> impl Responder {
+    let mut bytes = [0u8; SECRET_BYTES];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    let secret = base32::encode(base32::Alphabet::Rfc4648 { padding: false }, &bytes);
+
+    let result = sqlx::query("UPDATE users SET totp_secret = $2 WHERE id = $1 AND NOT totp_enabled")
+        .bind(&user.id)
+        .bind(&secret)
+        .execute(&**db)
+        .await;
+    match result {
+        Ok(r) if r.rows_affected() > 0 => {
+            let url = format!(
+                "otpauth://totp/{issuer}:{user}?secret={secret}&issuer={issuer}&algorithm=SHA1&digits={digits}&period={period}",
+                issuer = ISSUER,
+                user = user.username,
+                secret = secret,
+                digits = DIGITS,
+                period = STEP_SECONDS
+            );
+            HttpResponse::Ok().json(serde_json::json!({ "secret": secret, "otpauth_url": url }))
+        }
+        Ok(_) => HttpResponse::Conflict().json(serde_json::json!({
+            "error": "Two-factor authentication is already enabled"
+        })),
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
+
+/// Finish enrollment with a code from the new secret; returns the recovery codes.
+#[post("/auth/2fa/enable")]
+pub async fn enable(db: web::Data<PgPool>, user: AuthUser, payload: web::Json<CodeConfirmation>) -> impl Responder {
+    let secret: Option<String> = sqlx::query_scalar("SELECT totp_secret FROM users WHERE id = $1 AND NOT totp_enabled")
+        .bind(&user.id)
+        .fetch_optional(&**db)
+        .await
+        .unwrap_or(None)
+        .flatten();
+    let Some(secret) = secret else {
+        return HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "Start two-factor setup first"
+        }));
+    };
+    let code: String = payload.code.chars().filter(|c| !c.is_whitespace()).collect();
+    let Some(step) = matching_step(&secret, &code, current_step(), None) else {
+        return field_error("code", "is not valid; check your device's clock");
+    };
+
+    let result = sqlx::query("UPDATE users SET totp_enabled = TRUE, totp_last_step = $2 WHERE id = $1")
+        .bind(&user.id)
+        .bind(step)
+        .execute(&**db)
+        .await;
+    if result.is_err() {
+        return HttpResponse::InternalServerError().finish();
+    }
+    match new_recovery_codes(&db, &user.id).await {
+        Ok(codes) => {
+            audit::record(&db, Some(&user), "account.2fa_enable", "user", Some(&user.id), None, None).await;
+            HttpResponse::Ok().json(serde_json::json!({ "recovery_codes": codes }))
+        }
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
+
+#[post("/auth/2fa/disable")]
+pub async fn disable(db: web::Data<PgPool>, user: AuthUser, payload: web::Json<TwoFactorDisable>) -> impl Responder {
+    if !account::password_matches(&db, &user.id, &payload.password).await {
+        return field_error("password", "is incorrect");
+    }
+    let secret: Option<String> = sqlx::query_scalar("SELECT totp_secret FROM users WHERE id = $1 AND totp_enabled")
+        .bind(&user.id)
+        .fetch_optional(&**db)
+        .await
+        .unwrap_or(None)
+        .flatten();
+    let Some(secret) = secret else {
+        return HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "Two-factor authentication is not enabled"
+        }));
+    };
+    if !verify(&db, &user.id, &secret, &payload.code).await {
+        return field_error("code", "is not valid");
+    }
+
+    let result = sqlx::query("UPDATE users SET totp_enabled = FALSE, totp_secret = NULL, totp_last_step = NULL WHERE id = $1")
+        .bind(&user.id)
+        .execute(&**db)
+        .await;
+    if result.is_err() {
+        return HttpResponse::InternalServerError().finish();
+    }
+    let _ = sqlx::query("DELETE FROM recovery_codes WHERE user_id = $1")
+        .bind(&user.id)
+        .execute(&**db)
+        .await;
+    audit::record(&db, Some(&user), "account.2fa_disable", "user", Some(&user.id), None, None).await;
+    HttpResponse::Ok().finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The SHA-1 secret from RFC 6238 appendix B, "12345678901234567890"
+    const RFC_SECRET: &[u8] = b"12345678901234567890";
+
+    fn base32_secret() -> String {
+        base32::encode(base32::Alphabet::Rfc4648 { padding: false }, RFC_SECRET)
+    }
+
+    #[test]
+    fn codes_match_rfc_6238_vectors() {
+        // The RFC lists 8-digit codes; six digits are their last six
+        for (time, code) in [
+            (59, "287082"),
+            (1_111_111_109, "081804"),
+            (1_111_111_111, "050471"),
+            (1_234_567_890, "005924"),
+            (2_000_000_000, "279037"),
+            (20_000_000_000, "353130"),
+        ] {
+            assert_eq!(code_at(RFC_SECRET, time / STEP_SECONDS), code, "T = {}", time);
+        }
+    }
+
+    #[test]
+    fn codes_from_one_step_either_side_are_accepted() {
+        let secret = base32_secret();
+        let now = 1_234_567_890 / STEP_SECONDS;
+        for step in [now - 1, now, now + 1] {
+            let code = code_at(RFC_SECRET, step);
+            assert_eq!(matching_step(&secret, &code, now, None), Some(step));
+        }
+        for step in [now - 2, now + 2] {
+            let code = code_at(RFC_SECRET, step);
+            assert_eq!(matching_step(&secret, &code, now, None), None);
+        }
+    }
+
+    #[test]
+    fn used_steps_are_not_accepted_again() {
+        let secret = base32_secret();
+        let now = 1_234_567_890 / STEP_SECONDS;
+        let code = code_at(RFC_SECRET, now);
+        assert_eq!(matching_step(&secret, &code, now, Some(now - 1)), Some(now));
+        assert_eq!(matching_step(&secret, &code, now, Some(now)), None);
+        // An older code is refused once a newer one has been used
+        let earlier = code_at(RFC_SECRET, now - 1);
+        assert_eq!(matching_step(&secret, &earlier, now, Some(now)), None);
+    }
+
+    #[test]
+    fn invalid_secrets_match_nothing() {
+        assert_eq!(matching_step("not base32!", "287082", 1, None), None);
+    }
+
+    #[test]
+    fn recovery_codes_ignore_case_and_separators() {
+        assert_eq!(hash_recovery_code("abcd-efgh"), hash_recovery_code(" ABCD EFGH "));
+        assert_ne!(hash_recovery_code("abcd-efgh"), hash_recovery_code("abcd-efgj"));
+    }
+}
@@ -0,0 +1,246 @@
+// validation.rs
+use actix_web::HttpResponse;
+use unicode_normalization::UnicodeNormalization;
+
+type CharCheck = fn(char) -> bool;
+
+/// Rules for one incoming text field.
+pub struct Field {
+    pub name: &'static str,
+    // Length bounds in characters, after normalization and trimming
+    pub min: usize,
+    pub max: usize,
+    // Allow line breaks and tabs
+    pub multiline: bool,
+    // Leading/trailing whitespace is significant (passwords)
+    pub keep_whitespace: bool,
+    // Characters allowed beyond the general rules, with a description for the error
+    pub charset: Option<(CharCheck, &'static str)>,
+    // Start kept for values the server makes up, compared ignoring ASCII case
+    pub reserved_prefix: Option<&'static str>,
+}
+
+impl Field {
+    const fn line(name: &'static str, min: usize, max: usize) -> Field {
+        Field { name, min, max, multiline: false, keep_whitespace: false, charset: None, reserved_prefix: None }
+    }
+
+    const fn text(name: &'static str, min: usize, max: usize) -> Field {
+        Field { name, min, max, multiline: true, keep_whitespace: false, charset: None, reserved_prefix: None }
+    }
+}
+
+fn is_username_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_' || c == '-'
+}
+
+fn is_tag_char(c: char) -> bool {
+    c.is_alphanumeric() || matches!(c, '-' | '_' | '+' | '.' | '#')
+}
+
+/// What deleted accounts are renamed to, followed by their id.
+pub const DELETED_USERNAME_PREFIX: &str = "deleted-";
+
+pub const USERNAME: Field = Field {
+    charset: Some((is_username_char, "letters, digits, '_' and '-'")),
+    reserved_prefix: Some(DELETED_USERNAME_PREFIX),
+    ..Field::line("username", 3, 32)
+};
+pub const PASSWORD: Field = Field { keep_whitespace: true, ..Field::line("password", 8, 128) };
+pub const NEW_PASSWORD: Field = Field { name: "new_password", ..PASSWORD };
+pub const THREAD_TITLE: Field = Field::line("title", 1, 200);
+pub const THREAD_CONTENT: Field = Field::text("content", 1, 20_000);
+pub const COMMENT_CONTENT: Field = Field::text("content", 1, 10_000);
+pub const MESSAGE_CONTENT: Field = Field::text("content", 1, 5_000);
+pub const DISPLAY_NAME: Field = Field::line("display_name", 0, 50);
+pub const BIO: Field = Field::text("bio", 0, 1_000);
+pub const SIGNATURE: Field = Field::text("signature", 0, 200);
+pub const LOCATION: Field = Field::line("location", 0, 100);
+pub const KEY_NAME: Field = Field::line("name", 0, 50);
+pub const CATEGORY_NAME: Field = Field::line("name", 1, 50);
+pub const CATEGORY_DESCRIPTION: Field = Field::text("description", 0, 500);
+pub const TAG: Field = Field {
+    charset: Some((is_tag_char, "letters, digits, '-', '_', '+', '.' and '#'")),
+    ..Field::line("tags", 1, 30)
+};
+
+/// NFC-normalize `value`, unify line endings and, unless the field keeps it,
+/// strip surrounding whitespace.
+pub fn normalize(field: &Field, value: &str) -> String {
+    let value: String = value.nfc().collect::<String>().replace("\r\n", "\n");
+    if field.keep_whitespace {
+        value
+    } else {
+        value.trim().to_string()
+    }
+}
+
+/// Incoming payloads clean their fields in place, or report what is wrong with them.
+pub trait Validate {
+    fn validate(&mut self) -> Result<(), Errors>;
+}
+
+/// Field name and error message pairs, in the order the fields were checked.
+#[derive(Debug, Default)]
+pub struct Errors(Vec<(&'static str, String)>);
+
+impl Errors {
+    /// Normalize and check `value` against `field`, recording any error and
+    /// returning the cleaned value.
+    pub fn check(&mut self, field: &Field, value: &str) -> String {
+        let value = normalize(field, value);
+        if let Some(message) = problem(field, &value) {
+            self.0.push((field.name, message));
+        }
+        value
+    }
+
+    /// Like `check`, for optional fields; empty values become `None`.
+    pub fn check_optional(&mut self, field: &Field, value: Option<&str>) -> Option<String> {
+        let value = self.check(field, value.unwrap_or(""));
+        (!value.is_empty()).then_some(value)
+    }
+
+    /// Record an error that is not about a single value, e.g. too many items.
+    pub fn add(&mut self, field: &'static str, message: String) {
+        self.0.push((field, message));
+    }
+
+    pub fn has(&self, field: &str) -> bool {
+        self.0.iter().any(|(name, _)| *name == field)
+    }
+
+    /// `Err(self)` if any field failed its checks.
+    pub fn finish(self) -> Result<(), Errors> {
+        if self.0.is_empty() {
+            Ok(())
+        } else {
+            Err(self)
+        }
+    }
+
+    /// The first error, as "field: message".
+    pub fn summary(&self) -> String {
+        self.0
+            .first()
+            .map(|(name, message)| format!("{}: {}", name, message))
+            .unwrap_or_default()
+    }
+
+    /// 400 response with the first error as `error` and all of them under `fields`.
+    pub fn response(&self) -> HttpResponse {
+        let summary = self.summary();
+        let fields: serde_json::Map<String, serde_json::Value> = self
+            .0
+            .iter()
+            .map(|(name, message)| (name.to_string(), message.clone().into()))
+            .collect();
+        HttpResponse::BadRequest().json(serde_json::json!({
+            "error": summary,
+            "fields": fields
+        }))
+    }
+}
+
+fn problem(field: &Field, value: &str) -> Option<String> {
+    let length = value.chars().count();
+    if length < field.min {
+        return Some(if field.min == 1 {
+            "must not be empty".to_string()
+        } else {
+            format!("must be at least {} characters", field.min)
+        });
+    }
+    if length > field.max {
+        return Some(format!("must be at most {} characters", field.max));
+    }
+    let allowed_control = |c: char| field.multiline && (c == '\n' || c == '\t');
+    if value.chars().any(|c| c.is_control() && !allowed_control(c)) {
+        return Some(if field.multiline {
+            "must not contain control characters".to_string()
+        } else {
+            "must be a single line without control characters".to_string()
+        });
+    }
+    if let Some((allowed, description)) = field.charset {
+        if !value.chars().all(allowed) {
+            return Some(format!("may only contain {}", description));
+        }
+    }
+    if let Some(prefix) = field.reserved_prefix {
+        if value.to_ascii_lowercase().starts_with(prefix) {
+            return Some(format!("must not start with '{}'", prefix));
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn normalize_trims_unless_whitespace_is_kept() {
+        assert_eq!(normalize(&THREAD_TITLE, "  hello  "), "hello");
+        assert_eq!(normalize(&PASSWORD, "  secret  "), "  secret  ");
+    }
+
+    #[test]
+    fn normalize_composes_and_unifies_line_endings() {
+        // "e" followed by a combining acute accent becomes a single "é"
+        assert_eq!(normalize(&THREAD_TITLE, "cafe\u{301}"), "caf\u{e9}");
+        assert_eq!(normalize(&COMMENT_CONTENT, "one\r\ntwo\r\n"), "one\ntwo");
+    }
+
+    #[test]
+    fn lengths_are_counted_in_characters() {
+        assert_eq!(problem(&USERNAME, "ab"), Some("must be at least 3 characters".to_string()));
+        assert_eq!(problem(&USERNAME, "abc"), None);
+        assert_eq!(problem(&USERNAME, &"a".repeat(33)), Some("must be at most 32 characters".to_string()));
+        assert_eq!(problem(&THREAD_TITLE, ""), Some("must not be empty".to_string()));
+        assert_eq!(problem(&THREAD_TITLE, &"é".repeat(200)), None);
+        assert_eq!(problem(&BIO, ""), None);
+    }
+
+    #[test]
+    fn line_breaks_are_only_allowed_in_multiline_fields() {
+        assert_eq!(problem(&COMMENT_CONTENT, "one\n\ttwo"), None);
+        assert_eq!(
+            problem(&THREAD_TITLE, "one\ntwo"),
+            Some("must be a single line without control characters".to_string())
+        );
+        assert_eq!(problem(&COMMENT_CONTENT, "bell\u{7}"), Some("must not contain control characters".to_string()));
+    }
+
+    #[test]
+    fn charset_restricts_characters() {
+        assert_eq!(problem(&USERNAME, "good_name-1"), None);
+        assert_eq!(
+            problem(&USERNAME, "bad name"),
+            Some("may only contain letters, digits, '_' and '-'".to_string())
+        );
+        assert_eq!(problem(&TAG, "c++"), None);
+        assert!(problem(&TAG, "a/b").is_some());
+    }
+
+    #[test]
+    fn deleted_account_names_cannot_be_registered() {
+        assert_eq!(problem(&USERNAME, "deleted-1234abcd"), Some("must not start with 'deleted-'".to_string()));
+        assert!(problem(&USERNAME, "Deleted-x").is_some());
+        assert_eq!(problem(&USERNAME, "deleted_user"), None);
+        assert_eq!(problem(&USERNAME, "undeleted-1"), None);
+    }
+
+    #[test]
+    fn errors_collect_in_order() {
+        let mut errors = Errors::default();
+        assert_eq!(errors.check(&THREAD_TITLE, "  ok "), "ok");
+        assert_eq!(errors.check_optional(&BIO, Some("   ")), None);
+        errors.check(&USERNAME, "x");
+        errors.check(&PASSWORD, "short");
+        assert!(errors.has("username") && errors.has("password") && !errors.has("title"));
+        assert_eq!(errors.summary(), "username: must be at least 3 characters");
+        assert!(errors.finish().is_err());
+        assert!(Errors::default().finish().is_ok());
+    }
+}
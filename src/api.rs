@@ -1,11 +1,31 @@
 // src/api.rs
 use anyhow::Result;
-use reqwest::Client;
+use reqwest::{header, Client};
 use serde::{Deserialize, Serialize};
 use base64::Engine;
+use chrono::{DateTime, Utc};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::{OnceLock, RwLock};
 
-// Base URL of the server
-const BASE_URL: &str = "https://cyber-forum.onrender.com";
+// Base URL of the server, unless `set_base_url` says otherwise
+const DEFAULT_BASE_URL: &str = "https://cyber-forum.onrender.com";
+static BASE_URL: OnceLock<String> = OnceLock::new();
+
+// Bearer token of the logged-in user, sent with every request once set
+static SESSION_TOKEN: RwLock<Option<String>> = RwLock::new(None);
+
+/// The session of one of the server's SSH users, who share its process.
+struct RemoteSession {
+    token: RefCell<Option<String>>,
+    // The user's address, so the server counts rate limits against them
+    address: String,
+}
+
+tokio::task_local! {
+    static REMOTE_SESSION: RemoteSession;
+}
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Thread {
@@ -15,7 +35,50 @@ pub struct Thread {
     pub content: String,
     pub image_url: Option<String>,
     pub category_id: Option<String>,
-    pub created_at: String,
+    pub created_at: DateTime<Utc>,
+    #[serde(default)]
+    pub category_name: Option<String>,
+    #[serde(default)]
+    pub pinned: bool,
+    #[serde(default)]
+    pub locked: bool,
+    #[serde(default)]
+    pub archived: bool,
+    #[serde(default)]
+    pub comment_count: i64,
+    #[serde(default)]
+    pub last_activity_at: DateTime<Utc>,
+    #[serde(default)]
+    pub unread_count: i64,
+    #[serde(default)]
+    pub last_read_at: Option<DateTime<Utc>>,
+    // Held by the content filter; only the author sees it until approved
+    #[serde(default)]
+    pub held: bool,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    #[serde(default)]
+    pub reactions: Vec<Reaction>,
+    // Whether we get a notification for every reply
+    #[serde(default)]
+    pub subscribed: bool,
+    #[serde(default)]
+    pub author_signature: Option<String>,
+    // Hash of the author's avatar, see get_avatar
+    #[serde(default)]
+    pub author_avatar: Option<String>,
+}
+
+impl Thread {
+    /// True for threads never opened, or with comments newer than the last visit.
+    pub fn has_unread(&self) -> bool {
+        self.unread_count > 0 || self.last_read_at.is_none()
+    }
+
+    /// Locked and archived threads accept no new comments.
+    pub fn is_read_only(&self) -> bool {
+        self.locked || self.archived
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -23,16 +86,29 @@ pub struct Category {
     pub id: String,
     pub name: String,
     pub description: Option<String>,
-    pub created_at: String,
+    pub created_at: DateTime<Utc>,
+    // Set for subcategories; categories nest one level deep
+    #[serde(default)]
+    pub parent_id: Option<String>,
+    #[serde(default)]
+    pub position: i32,
+    #[serde(default)]
+    pub thread_count: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct TagCount {
+    pub name: String,
+    pub count: i64,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct NewThread {
     pub title: String,
-    pub author: String,
     pub content: String,
     pub image_url: Option<String>,
     pub category_id: Option<String>,
+    pub tags: Vec<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -42,13 +118,97 @@ pub struct Comment {
     pub author: String,
     pub content: String,
     pub image_url: Option<String>,
-    pub created_at: String,
+    pub created_at: DateTime<Utc>,
+    // Deleted by a moderator; author and content read "[deleted]"
+    #[serde(default)]
+    pub deleted: bool,
+    #[serde(default)]
+    pub held: bool,
+    #[serde(default)]
+    pub reactions: Vec<Reaction>,
+    #[serde(default)]
+    pub author_signature: Option<String>,
+    // Hash of the author's avatar, see get_avatar
+    #[serde(default)]
+    pub author_avatar: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Profile {
+    pub username: String,
+    pub display_name: Option<String>,
+    pub bio: Option<String>,
+    pub signature: Option<String>,
+    pub location: Option<String>,
+    #[serde(default)]
+    pub avatar: Option<String>,
+    pub role: String,
+    pub created_at: DateTime<Utc>,
+    pub thread_count: i64,
+    pub comment_count: i64,
+    pub recent_activity: Vec<Activity>,
+}
+
+/// A thread or comment listed on a profile.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Activity {
+    // "thread" or "comment"
+    pub kind: String,
+    pub thread_id: String,
+    pub thread_title: String,
+    pub comment_id: Option<String>,
+    pub excerpt: String,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Notification {
+    pub id: String,
+    // "mention" or "reply"
+    pub kind: String,
+    pub actor: String,
+    pub thread_id: String,
+    pub thread_title: String,
+    // The comment to jump to; None when it is about the thread's opening post
+    pub comment_id: Option<String>,
+    pub excerpt: String,
+    pub created_at: DateTime<Utc>,
+    pub read: bool,
+}
+
+/// A private conversation, as seen by the logged-in user.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Conversation {
+    pub id: String,
+    // Everyone else in the conversation
+    pub participants: Vec<String>,
+    pub last_sender: Option<String>,
+    pub last_message: Option<String>,
+    pub last_message_at: DateTime<Utc>,
+    pub unread_count: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Message {
+    pub id: String,
+    pub conversation_id: String,
+    pub sender: String,
+    pub content: String,
+    pub created_at: DateTime<Utc>,
+}
+
+/// How many users reacted to a post with one emoji.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Reaction {
+    pub emoji: String,
+    pub count: i64,
+    // Whether the logged-in user is one of them
+    pub mine: bool,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct NewComment {
     pub thread_id: String,
-    pub author: String,
     pub content: String,
     pub image_url: Option<String>,
 }
@@ -57,46 +217,267 @@ pub struct NewComment {
 pub struct User {
     pub id: String,
     pub username: String,
-    pub created_at: String,
+    pub created_at: DateTime<Utc>,
+    #[serde(default)]
+    pub role: String,
+    #[serde(default)]
+    pub token: Option<String>,
+}
+
+impl User {
+    pub fn is_moderator(&self) -> bool {
+        self.role == "moderator" || self.role == "admin"
+    }
+
+    pub fn is_admin(&self) -> bool {
+        self.role == "admin"
+    }
+}
+
+/// Talk to the server at `url` instead of the public one. Only the first call counts.
+pub fn set_base_url(url: &str) {
+    let _ = BASE_URL.set(url.trim_end_matches('/').to_string());
+}
+
+fn base_url() -> &'static str {
+    BASE_URL.get().map_or(DEFAULT_BASE_URL, String::as_str)
+}
+
+pub fn set_session_token(token: Option<String>) {
+    let remote = REMOTE_SESSION.try_with(|session| *session.token.borrow_mut() = token.clone());
+    if remote.is_err() {
+        *SESSION_TOKEN.write().unwrap() = token;
+    }
+}
+
+/// Run `f` with a session of its own rather than the process's, for a user
+/// connected from `address`: how the server runs the app for each SSH session.
+pub async fn with_remote_session<F: Future>(address: String, f: F) -> F::Output {
+    let session = RemoteSession { token: RefCell::new(None), address };
+    REMOTE_SESSION.scope(session, f).await
+}
+
+/// A rejected request body, with a message for each offending field.
+#[derive(Debug)]
+pub struct InvalidInput {
+    pub message: String,
+    pub fields: HashMap<String, String>,
+}
+
+impl std::fmt::Display for InvalidInput {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl std::error::Error for InvalidInput {}
+
+/// A login refused until a two-factor code (or recovery code) is sent along.
+#[derive(Debug)]
+pub struct TwoFactorRequired {
+    pub message: String,
+}
+
+impl std::fmt::Display for TwoFactorRequired {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl std::error::Error for TwoFactorRequired {}
+
+/// "45s", "4m 59s" or "1h 0m".
+fn format_cooldown(seconds: u64) -> String {
+    match seconds {
+        0..=59 => format!("{}s", seconds),
+        60..=3599 => format!("{}m {}s", seconds / 60, seconds % 60),
+        _ => format!("{}h {}m", seconds / 3600, seconds % 3600 / 60),
+    }
+}
+
+/// Turn a non-2xx response into an error carrying the server's `error` message.
+async fn check_status(resp: reqwest::Response) -> Result<reqwest::Response> {
+    let status = resp.status();
+    if status.is_success() {
+        return Ok(resp);
+    }
+    if status == reqwest::StatusCode::TOO_MANY_REQUESTS {
+        let retry_after = resp
+            .headers()
+            .get(header::RETRY_AFTER)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.parse::<u64>().ok());
+        return Err(match retry_after {
+            Some(seconds) => anyhow::anyhow!("Slow down! You can try again in {}", format_cooldown(seconds)),
+            None => anyhow::anyhow!("Slow down! Please wait a moment and try again"),
+        });
+    }
+    let body: serde_json::Value = resp.json().await.unwrap_or_default();
+    let message = body
+        .get("error")
+        .and_then(|v| v.as_str())
+        .map(str::to_string)
+        .unwrap_or_else(|| status.to_string());
+    if let Some(fields) = body.get("fields").and_then(|v| v.as_object()) {
+        let fields = fields
+            .iter()
+            .map(|(name, error)| (name.clone(), error.as_str().unwrap_or_default().to_string()))
+            .collect();
+        return Err(InvalidInput { message, fields }.into());
+    }
+    if body.get("two_factor_required").and_then(|v| v.as_bool()) == Some(true) {
+        return Err(TwoFactorRequired { message }.into());
+    }
+    Err(anyhow::anyhow!(message))
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Report {
+    pub id: String,
+    pub target_type: String,
+    pub target_id: String,
+    pub thread_id: Option<String>,
+    pub thread_title: Option<String>,
+    pub target_author: Option<String>,
+    pub target_content: Option<String>,
+    pub reporter: String,
+    pub reason: String,
+    pub status: String,
+    pub resolution: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct AuditEntry {
+    pub id: String,
+    pub actor: String,
+    pub action: String,
+    pub target_type: String,
+    pub target_id: Option<String>,
+    pub before: Option<serde_json::Value>,
+    pub after: Option<serde_json::Value>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// A deleted thread or comment that moderators can still restore.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct DeletedItem {
+    pub target_type: String,
+    pub id: String,
+    pub thread_id: String,
+    pub thread_title: String,
+    pub author: String,
+    pub content: String,
+    pub deleted_by: Option<String>,
+    pub deleted_at: DateTime<Utc>,
+}
+
+/// A post held by the content filter until a moderator approves or rejects it.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct HeldPost {
+    pub target_type: String,
+    pub id: String,
+    pub thread_id: String,
+    pub thread_title: String,
+    pub author: String,
+    pub content: String,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Server reply to creating a thread or comment.
+#[derive(Debug, Deserialize)]
+struct Created {
+    #[serde(default)]
+    held: bool,
 }
 
 fn client() -> Client {
-    Client::new()
+    let mut headers = header::HeaderMap::new();
+    let (token, address) = REMOTE_SESSION
+        .try_with(|session| (session.token.borrow().clone(), Some(session.address.clone())))
+        .unwrap_or_else(|_| (SESSION_TOKEN.read().unwrap().clone(), None));
+    if let Some(token) = token {
+        if let Ok(value) = header::HeaderValue::from_str(&format!("Bearer {}", token)) {
+            headers.insert(header::AUTHORIZATION, value);
+        }
+    }
+    if let Some(value) = address.and_then(|a| header::HeaderValue::from_str(&a).ok()) {
+        headers.insert(header::HeaderName::from_static("x-forwarded-for"), value);
+    }
+    Client::builder()
+        .default_headers(headers)
+        .build()
+        .unwrap_or_default()
 }
 
-pub async fn list_threads() -> Result<Vec<Thread>> {
-    let resp = client()
-        .get(&format!("{}/threads", BASE_URL))
+/// Threads in `sort` order; with `tags`, only those carrying all of them.
+pub async fn list_threads(sort: &str, archived: bool, tags: &[String]) -> Result<Vec<Thread>> {
+    let mut request = client()
+        .get(format!("{}/threads", base_url()))
+        .query(&[("sort", sort), ("archived", if archived { "true" } else { "false" })]);
+    if !tags.is_empty() {
+        request = request.query(&[("tags", tags.join(","))]);
+    }
+    let resp = request
         .send()
         .await?;
     let threads = resp.json::<Vec<Thread>>().await?;
     Ok(threads)
 }
 
-pub async fn create_thread(new: NewThread) -> Result<()> {
-    client()
-        .post(&format!("{}/threads", BASE_URL))
+/// Returns whether the thread was held for moderator approval.
+pub async fn create_thread(new: NewThread) -> Result<bool> {
+    let resp = client()
+        .post(format!("{}/threads", base_url()))
         .json(&new)
         .send()
         .await?;
-    Ok(())
+    let created = check_status(resp).await?.json::<Created>().await?;
+    Ok(created.held)
 }
 
 pub async fn list_comments(thread_id: &str) -> Result<Vec<Comment>> {
     let resp = client()
-        .get(&format!("{}/threads/{}/comments", BASE_URL, thread_id))
+        .get(format!("{}/threads/{}/comments", base_url(), thread_id))
         .send()
         .await?;
     let comments = resp.json::<Vec<Comment>>().await?;
     Ok(comments)
 }
 
-pub async fn create_comment(new: NewComment) -> Result<()> {
-    client()
-        .post(&format!("{}/comments", BASE_URL))
+/// Returns whether the comment was held for moderator approval.
+pub async fn create_comment(new: NewComment) -> Result<bool> {
+    let resp = client()
+        .post(format!("{}/comments", base_url()))
         .json(&new)
         .send()
         .await?;
+    let created = check_status(resp).await?.json::<Created>().await?;
+    Ok(created.held)
+}
+
+/// Moderators only: change any of a thread's pinned/locked/archived flags.
+pub async fn set_thread_flags(thread_id: &str, pinned: Option<bool>, locked: Option<bool>, archived: Option<bool>) -> Result<()> {
+    let resp = client()
+        .patch(format!("{}/threads/{}/flags", base_url(), thread_id))
+        .json(&serde_json::json!({
+            "pinned": pinned,
+            "locked": locked,
+            "archived": archived
+        }))
+        .send()
+        .await?;
+    check_status(resp).await?;
+    Ok(())
+}
+
+pub async fn mark_thread_read(thread_id: &str, up_to: Option<DateTime<Utc>>) -> Result<()> {
+    client()
+        .post(format!("{}/threads/{}/read", base_url(), thread_id))
+        .json(&serde_json::json!({ "up_to": up_to }))
+        .send()
+        .await?
+        .error_for_status()?;
     Ok(())
 }
 
@@ -117,70 +498,471 @@ pub fn create_data_url(image_path: &str) -> Result<String> {
     Ok(format!("data:{};base64,{}", mime_type, base64_data))
 }
 
+/// Tags in use, most used first; with `prefix`, only those starting with it.
+pub async fn list_tags(prefix: Option<&str>) -> Result<Vec<TagCount>> {
+    let mut request = client().get(format!("{}/tags", base_url()));
+    if let Some(prefix) = prefix {
+        request = request.query(&[("prefix", prefix)]);
+    }
+    let resp = request.send().await?;
+    let tags = check_status(resp).await?.json::<Vec<TagCount>>().await?;
+    Ok(tags)
+}
+
+/// Add or take back the user's `emoji` reaction on a thread or comment.
+/// Returns the post's updated reaction counts.
+pub async fn toggle_reaction(target_type: &str, id: &str, emoji: &str) -> Result<Vec<Reaction>> {
+    let resp = client()
+        .post(format!("{}/{}s/{}/reactions", base_url(), target_type, id))
+        .json(&serde_json::json!({ "emoji": emoji }))
+        .send()
+        .await?;
+    let reactions = check_status(resp).await?.json::<Vec<Reaction>>().await?;
+    Ok(reactions)
+}
+
+/// Subscribe to, or unsubscribe from, reply notifications for a thread.
+pub async fn set_thread_subscription(thread_id: &str, subscribed: bool) -> Result<()> {
+    let url = format!("{}/threads/{}/subscription", base_url(), thread_id);
+    let request = if subscribed { client().post(url) } else { client().delete(url) };
+    check_status(request.send().await?).await?;
+    Ok(())
+}
+
+/// The logged-in user's notifications, newest first.
+pub async fn list_notifications() -> Result<Vec<Notification>> {
+    let resp = client()
+        .get(format!("{}/notifications", base_url()))
+        .send()
+        .await?;
+    let notifications = check_status(resp).await?.json::<Vec<Notification>>().await?;
+    Ok(notifications)
+}
+
+pub async fn unread_notification_count() -> Result<i64> {
+    let resp = client()
+        .get(format!("{}/notifications/unread_count", base_url()))
+        .send()
+        .await?;
+    let body: serde_json::Value = check_status(resp).await?.json().await?;
+    Ok(body.get("count").and_then(|v| v.as_i64()).unwrap_or(0))
+}
+
+pub async fn mark_notification_read(id: &str) -> Result<()> {
+    let resp = client()
+        .post(format!("{}/notifications/{}/read", base_url(), id))
+        .send()
+        .await?;
+    check_status(resp).await?;
+    Ok(())
+}
+
+pub async fn mark_all_notifications_read() -> Result<()> {
+    let resp = client()
+        .post(format!("{}/notifications/read", base_url()))
+        .send()
+        .await?;
+    check_status(resp).await?;
+    Ok(())
+}
+
+pub async fn list_conversations() -> Result<Vec<Conversation>> {
+    let resp = client()
+        .get(format!("{}/conversations", base_url()))
+        .send()
+        .await?;
+    let conversations = check_status(resp).await?.json::<Vec<Conversation>>().await?;
+    Ok(conversations)
+}
+
+/// Send the first message to `participants`, returning the conversation id.
+/// Messaging the same users again continues the existing conversation.
+pub async fn create_conversation(participants: &[String], content: &str) -> Result<String> {
+    let resp = client()
+        .post(format!("{}/conversations", base_url()))
+        .json(&serde_json::json!({ "participants": participants, "content": content }))
+        .send()
+        .await?;
+    let body: serde_json::Value = check_status(resp).await?.json().await?;
+    Ok(body.get("id").and_then(|v| v.as_str()).unwrap_or_default().to_string())
+}
+
+/// A page of history, oldest first; `before` is the oldest message already loaded.
+pub async fn list_messages(conversation_id: &str, before: Option<&str>) -> Result<Vec<Message>> {
+    let mut request = client().get(format!("{}/conversations/{}/messages", base_url(), conversation_id));
+    if let Some(before) = before {
+        request = request.query(&[("before", before)]);
+    }
+    let messages = check_status(request.send().await?).await?.json::<Vec<Message>>().await?;
+    Ok(messages)
+}
+
+pub async fn send_message(conversation_id: &str, content: &str) -> Result<()> {
+    let resp = client()
+        .post(format!("{}/messages", base_url()))
+        .json(&serde_json::json!({ "conversation_id": conversation_id, "content": content }))
+        .send()
+        .await?;
+    check_status(resp).await?;
+    Ok(())
+}
+
+pub async fn mark_conversation_read(conversation_id: &str) -> Result<()> {
+    let resp = client()
+        .post(format!("{}/conversations/{}/read", base_url(), conversation_id))
+        .send()
+        .await?;
+    check_status(resp).await?;
+    Ok(())
+}
+
+pub async fn unread_message_count() -> Result<i64> {
+    let resp = client()
+        .get(format!("{}/messages/unread_count", base_url()))
+        .send()
+        .await?;
+    let body: serde_json::Value = check_status(resp).await?.json().await?;
+    Ok(body.get("count").and_then(|v| v.as_i64()).unwrap_or(0))
+}
+
+/// Usernames the logged-in user has blocked.
+pub async fn list_blocks() -> Result<Vec<String>> {
+    let resp = client()
+        .get(format!("{}/blocks", base_url()))
+        .send()
+        .await?;
+    let names = check_status(resp).await?.json::<Vec<String>>().await?;
+    Ok(names)
+}
+
+pub async fn set_blocked(username: &str, blocked: bool) -> Result<()> {
+    let url = format!("{}/blocks/{}", base_url(), username);
+    let request = if blocked { client().post(url) } else { client().delete(url) };
+    check_status(request.send().await?).await?;
+    Ok(())
+}
+
+pub async fn get_profile(username: &str) -> Result<Profile> {
+    let resp = client()
+        .get(format!("{}/users/{}", base_url(), username))
+        .send()
+        .await?;
+    let profile = check_status(resp).await?.json::<Profile>().await?;
+    Ok(profile)
+}
+
+/// Set one of the logged-in user's profile fields; an empty value clears it.
+pub async fn update_profile(field: &str, value: &str) -> Result<Profile> {
+    let resp = client()
+        .patch(format!("{}/users/me", base_url()))
+        .json(&serde_json::json!({ field: value }))
+        .send()
+        .await?;
+    let profile = check_status(resp).await?.json::<Profile>().await?;
+    Ok(profile)
+}
+
+/// The user's avatar as a PNG.
+pub async fn get_avatar(username: &str) -> Result<Vec<u8>> {
+    let resp = client()
+        .get(format!("{}/users/{}/avatar", base_url(), username))
+        .send()
+        .await?;
+    let png = check_status(resp).await?.bytes().await?;
+    Ok(png.to_vec())
+}
+
+/// Upload an image, as a data URL, as the logged-in user's avatar.
+pub async fn upload_avatar(data_url: &str) -> Result<()> {
+    let resp = client()
+        .put(format!("{}/users/me/avatar", base_url()))
+        .json(&serde_json::json!({ "data_url": data_url }))
+        .send()
+        .await?;
+    check_status(resp).await?;
+    Ok(())
+}
+
+pub async fn delete_avatar() -> Result<()> {
+    let resp = client()
+        .delete(format!("{}/users/me/avatar", base_url()))
+        .send()
+        .await?;
+    check_status(resp).await?;
+    Ok(())
+}
+
+/// Returns how many other sessions were signed out.
+pub async fn change_password(current_password: &str, new_password: &str) -> Result<i64> {
+    let resp = client()
+        .put(format!("{}/users/me/password", base_url()))
+        .json(&serde_json::json!({
+            "current_password": current_password,
+            "new_password": new_password
+        }))
+        .send()
+        .await?;
+    let body = check_status(resp).await?.json::<serde_json::Value>().await?;
+    Ok(body["sessions_revoked"].as_i64().unwrap_or(0))
+}
+
+/// Delete the logged-in user's account, removing their posts or leaving them
+/// under a placeholder name.
+pub async fn delete_account(password: &str, delete_posts: bool) -> Result<()> {
+    let resp = client()
+        .delete(format!("{}/users/me", base_url()))
+        .json(&serde_json::json!({
+            "password": password,
+            "posts": if delete_posts { "delete" } else { "anonymize" }
+        }))
+        .send()
+        .await?;
+    check_status(resp).await?;
+    Ok(())
+}
+
+/// Everything the server stores about the logged-in user, as JSON text.
+pub async fn export_account() -> Result<String> {
+    let resp = client()
+        .get(format!("{}/users/me/export", base_url()))
+        .send()
+        .await?;
+    let archive = check_status(resp).await?.text().await?;
+    Ok(archive)
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct TwoFactorStatus {
+    pub enabled: bool,
+    pub recovery_codes_left: i64,
+}
+
+/// A new, not yet confirmed TOTP secret and the URL authenticator apps scan.
+#[derive(Debug, Deserialize, Clone)]
+pub struct TwoFactorSetup {
+    pub secret: String,
+    pub otpauth_url: String,
+}
+
+pub async fn two_factor_status() -> Result<TwoFactorStatus> {
+    let resp = client()
+        .get(format!("{}/auth/2fa", base_url()))
+        .send()
+        .await?;
+    let status = check_status(resp).await?.json::<TwoFactorStatus>().await?;
+    Ok(status)
+}
+
+pub async fn setup_two_factor() -> Result<TwoFactorSetup> {
+    let resp = client()
+        .post(format!("{}/auth/2fa/setup", base_url()))
+        .send()
+        .await?;
+    let setup = check_status(resp).await?.json::<TwoFactorSetup>().await?;
+    Ok(setup)
+}
+
+/// Confirm the secret from `setup_two_factor` with a code from it; returns the
+/// one-time recovery codes.
+pub async fn enable_two_factor(code: &str) -> Result<Vec<String>> {
+    let resp = client()
+        .post(format!("{}/auth/2fa/enable", base_url()))
+        .json(&serde_json::json!({ "code": code }))
+        .send()
+        .await?;
+    let body = check_status(resp).await?.json::<serde_json::Value>().await?;
+    let codes = body["recovery_codes"]
+        .as_array()
+        .map(|codes| codes.iter().filter_map(|c| c.as_str().map(str::to_string)).collect())
+        .unwrap_or_default();
+    Ok(codes)
+}
+
+/// `code` may be a current code or an unused recovery code.
+pub async fn disable_two_factor(password: &str, code: &str) -> Result<()> {
+    let resp = client()
+        .post(format!("{}/auth/2fa/disable", base_url()))
+        .json(&serde_json::json!({ "password": password, "code": code }))
+        .send()
+        .await?;
+    check_status(resp).await?;
+    Ok(())
+}
+
+/// A public key registered for key login.
+#[derive(Debug, Deserialize, Clone)]
+pub struct SshKey {
+    pub id: String,
+    pub name: String,
+    pub algorithm: String,
+    pub fingerprint: String,
+    pub created_at: DateTime<Utc>,
+    pub last_used_at: Option<DateTime<Utc>>,
+}
+
+/// A nonce to sign for `namespace` and send back to `key_login`.
+#[derive(Debug, Deserialize, Clone)]
+pub struct KeyChallenge {
+    pub nonce: String,
+    pub namespace: String,
+}
+
+pub async fn list_ssh_keys() -> Result<Vec<SshKey>> {
+    let resp = client()
+        .get(format!("{}/users/me/keys", base_url()))
+        .send()
+        .await?;
+    let keys = check_status(resp).await?.json::<Vec<SshKey>>().await?;
+    Ok(keys)
+}
+
+/// Register an OpenSSH public key line; the name defaults to the key's comment.
+pub async fn add_ssh_key(public_key: &str, name: Option<&str>) -> Result<SshKey> {
+    let resp = client()
+        .post(format!("{}/users/me/keys", base_url()))
+        .json(&serde_json::json!({ "public_key": public_key, "name": name }))
+        .send()
+        .await?;
+    let key = check_status(resp).await?.json::<SshKey>().await?;
+    Ok(key)
+}
+
+pub async fn delete_ssh_key(id: &str) -> Result<()> {
+    let resp = client()
+        .delete(format!("{}/users/me/keys/{}", base_url(), id))
+        .send()
+        .await?;
+    check_status(resp).await?;
+    Ok(())
+}
+
+pub async fn key_challenge(username: &str) -> Result<KeyChallenge> {
+    let resp = client()
+        .post(format!("{}/auth/key/challenge", base_url()))
+        .json(&serde_json::json!({ "username": username }))
+        .send()
+        .await?;
+    let challenge = check_status(resp).await?.json::<KeyChallenge>().await?;
+    Ok(challenge)
+}
+
+/// Log in with the challenge's nonce signed by a registered key.
+pub async fn key_login(username: &str, nonce: &str, signature: &str) -> Result<User> {
+    let resp = client()
+        .post(format!("{}/auth/key/login", base_url()))
+        .json(&serde_json::json!({
+            "username": username,
+            "nonce": nonce,
+            "signature": signature
+        }))
+        .send()
+        .await?;
+    let user = check_status(resp).await?.json::<User>().await?;
+    Ok(user)
+}
+
 pub async fn list_categories() -> Result<Vec<Category>> {
     let resp = client()
-        .get(&format!("{}/categories", BASE_URL))
+        .get(format!("{}/categories", base_url()))
         .send()
         .await?;
     let categories = resp.json::<Vec<Category>>().await?;
     Ok(categories)
 }
 
-#[allow(dead_code)]
-pub async fn create_category(name: String, description: Option<String>) -> Result<()> {
+/// Admins only: add a category, under `parent_id` for a subcategory.
+pub async fn create_category(name: &str, description: Option<&str>, parent_id: Option<&str>) -> Result<()> {
     let payload = serde_json::json!({
         "name": name,
-        "description": description
+        "description": description,
+        "parent_id": parent_id
     });
     
-    client()
-        .post(&format!("{}/categories", BASE_URL))
+    let resp = client()
+        .post(format!("{}/categories", base_url()))
         .json(&payload)
         .send()
         .await?;
+    check_status(resp).await?;
+    Ok(())
+}
+
+/// Admins only: change the given fields of a category. An empty `description`
+/// clears it and an empty `parent_id` moves the category to the top level.
+pub async fn update_category(id: &str, name: Option<&str>, description: Option<&str>, parent_id: Option<&str>) -> Result<()> {
+    let resp = client()
+        .patch(format!("{}/categories/{}", base_url(), id))
+        .json(&serde_json::json!({
+            "name": name,
+            "description": description,
+            "parent_id": parent_id
+        }))
+        .send()
+        .await?;
+    check_status(resp).await?;
+    Ok(())
+}
+
+/// Admins only: put sibling categories in the order of `ids`.
+pub async fn reorder_categories(ids: &[String]) -> Result<()> {
+    let resp = client()
+        .post(format!("{}/categories/reorder", base_url()))
+        .json(&serde_json::json!({ "ids": ids }))
+        .send()
+        .await?;
+    check_status(resp).await?;
+    Ok(())
+}
+
+/// Admins only: delete a category, moving its threads to `move_to` or leaving
+/// them uncategorized. Its subcategories become top-level categories.
+pub async fn delete_category(id: &str, move_to: Option<&str>) -> Result<()> {
+    let mut request = client().delete(format!("{}/categories/{}", base_url(), id));
+    if let Some(move_to) = move_to {
+        request = request.query(&[("move_to", move_to)]);
+    }
+    let resp = request.send().await?;
+    check_status(resp).await?;
     Ok(())
 }
 
 #[allow(dead_code)]
 pub async fn check_username_available(username: &str) -> Result<bool> {
     let resp = client()
-        .get(&format!("{}/auth/check-username/{}", BASE_URL, username))
+        .get(format!("{}/auth/check-username/{}", base_url(), username))
         .send()
         .await?;
     let result: serde_json::Value = resp.json().await?;
     Ok(result.get("available").and_then(|v| v.as_bool()).unwrap_or(false))
 }
 
+#[allow(dead_code)]
 pub async fn delete_all_threads() -> Result<()> {
     client()
-        .delete(&format!("{}/threads", BASE_URL))
+        .delete(format!("{}/threads", base_url()))
         .send()
         .await?;
     Ok(())
 }
 
-pub async fn login_user(username: &str, password: &str) -> Result<User> {
+/// `code` is the two-factor code, for accounts that have it enabled.
+pub async fn login_user(username: &str, password: &str, code: Option<&str>) -> Result<User> {
     let payload = serde_json::json!({
         "username": username,
-        "password": password
+        "password": password,
+        "code": code
     });
     
     let resp = client()
-        .post(&format!("{}/auth/login", BASE_URL))
+        .post(format!("{}/auth/login", base_url()))
         .json(&payload)
         .send()
         .await?;
     
-    let status = resp.status();
-    if status.is_success() {
-        let text = resp.text().await?;
-        println!("Raw login response: {}", text);
-        let user = serde_json::from_str::<User>(&text)?;
-        Ok(user)
-    } else {
-        let error_text = resp.text().await.unwrap_or_else(|_| "No error body".to_string());
-        Err(anyhow::anyhow!("Login failed: {} - {}", status, error_text))
-    }
+    // Errors carry the server's message, e.g. "Invalid username or password" or a ban notice;
+    // `TwoFactorRequired` when the account wants a code
+    let user = check_status(resp).await?.json::<User>().await?;
+    Ok(user)
 }
 
 pub async fn register_user(username: &str, password: &str) -> Result<User> {
@@ -190,20 +972,132 @@ pub async fn register_user(username: &str, password: &str) -> Result<User> {
     });
     
     let resp = client()
-        .post(&format!("{}/auth/register", BASE_URL))
+        .post(format!("{}/auth/register", base_url()))
         .json(&payload)
         .send()
         .await?;
     
-    let status = resp.status();
-    println!("Registration response status: {}", status);
-    
-    if status.is_success() {
-        let user = resp.json::<User>().await?;
-        Ok(user)
-    } else {
-        let error_text = resp.text().await.unwrap_or_else(|_| "No error body".to_string());
-        println!("Registration error: {} - {}", status, error_text);
-        Err(anyhow::anyhow!("Registration failed: {} - {}", status, error_text))
-    }
+    let user = check_status(resp).await?.json::<User>().await?;
+    Ok(user)
+}
+
+pub async fn create_report(target_type: &str, target_id: &str, reason: &str) -> Result<()> {
+    let resp = client()
+        .post(format!("{}/reports", base_url()))
+        .json(&serde_json::json!({
+            "target_type": target_type,
+            "target_id": target_id,
+            "reason": reason
+        }))
+        .send()
+        .await?;
+    check_status(resp).await?;
+    Ok(())
+}
+
+/// Moderators only: open reports, oldest first.
+pub async fn list_reports() -> Result<Vec<Report>> {
+    let resp = client()
+        .get(format!("{}/moderation/reports", base_url()))
+        .send()
+        .await?;
+    let reports = check_status(resp).await?.json::<Vec<Report>>().await?;
+    Ok(reports)
+}
+
+/// Moderators only: close a report, applying `action` ("keep", "hide" or "delete") to the content.
+pub async fn resolve_report(report_id: &str, action: &str) -> Result<()> {
+    let resp = client()
+        .post(format!("{}/moderation/reports/{}/resolve", base_url(), report_id))
+        .json(&serde_json::json!({ "action": action }))
+        .send()
+        .await?;
+    check_status(resp).await?;
+    Ok(())
+}
+
+pub async fn dismiss_report(report_id: &str) -> Result<()> {
+    let resp = client()
+        .post(format!("{}/moderation/reports/{}/dismiss", base_url(), report_id))
+        .send()
+        .await?;
+    check_status(resp).await?;
+    Ok(())
+}
+
+/// Moderators only: apply or lift a ban, suspension or mute on `username`.
+pub async fn sanction_user(username: &str, action: &str, reason: &str, until: Option<DateTime<Utc>>) -> Result<()> {
+    let resp = client()
+        .post(format!("{}/moderation/users/{}/sanctions", base_url(), username))
+        .json(&serde_json::json!({
+            "action": action,
+            "reason": reason,
+            "until": until
+        }))
+        .send()
+        .await?;
+    check_status(resp).await?;
+    Ok(())
+}
+
+/// Admins only: audit log entries, newest first. `filters` are passed through as
+/// query parameters (actor, action, target_type, target_id, since, until, limit).
+pub async fn list_audit_log(filters: &[(String, String)]) -> Result<Vec<AuditEntry>> {
+    let resp = client()
+        .get(format!("{}/admin/audit", base_url()))
+        .query(filters)
+        .send()
+        .await?;
+    let entries = check_status(resp).await?.json::<Vec<AuditEntry>>().await?;
+    Ok(entries)
+}
+
+/// Moderators only: delete a thread or comment. It can be restored until it is purged.
+pub async fn delete_post(target_type: &str, id: &str) -> Result<()> {
+    let resp = client()
+        .delete(format!("{}/{}s/{}", base_url(), target_type, id))
+        .send()
+        .await?;
+    check_status(resp).await?;
+    Ok(())
+}
+
+/// Moderators only: deleted threads and comments, most recently deleted first.
+pub async fn list_deleted() -> Result<Vec<DeletedItem>> {
+    let resp = client()
+        .get(format!("{}/moderation/deleted", base_url()))
+        .send()
+        .await?;
+    let items = check_status(resp).await?.json::<Vec<DeletedItem>>().await?;
+    Ok(items)
+}
+
+pub async fn restore_post(target_type: &str, id: &str) -> Result<()> {
+    let resp = client()
+        .post(format!("{}/moderation/{}/{}/restore", base_url(), target_type, id))
+        .send()
+        .await?;
+    check_status(resp).await?;
+    Ok(())
+}
+
+/// Moderators only: posts held by the content filter, oldest first.
+pub async fn list_held() -> Result<Vec<HeldPost>> {
+    let resp = client()
+        .get(format!("{}/moderation/held", base_url()))
+        .send()
+        .await?;
+    let posts = check_status(resp).await?.json::<Vec<HeldPost>>().await?;
+    Ok(posts)
+}
+
+/// Moderators only: `action` is "approve" or "reject".
+pub async fn decide_held(target_type: &str, id: &str, action: &str) -> Result<()> {
+    let resp = client()
+        .post(format!("{}/moderation/held/{}/{}", base_url(), target_type, id))
+        .json(&serde_json::json!({ "action": action }))
+        .send()
+        .await?;
+    check_status(resp).await?;
+    Ok(())
 }
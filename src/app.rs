@@ -1,39 +1,221 @@
 // src/app.rs
-use crate::api::{self, Thread, NewThread, NewComment, User, Comment, Category, delete_all_threads};
+use crate::avatar::{AvatarCache, Graphics};
+use crate::ssh_auth;
+use crate::api::{self, Thread, NewThread, NewComment, User, Comment, Category, Report, AuditEntry, DeletedItem, HeldPost, Conversation, Message, Notification, Profile, Reaction, TagCount, delete_all_threads};
+use base64::Engine;
+use std::collections::HashMap;
+use chrono::{DateTime, Utc};
 // use crate::models::{User, Comment};
 // use ratatui::widgets::ListState;
 
+// Length of suspensions applied from the moderation screen
+pub const SUSPENSION_DAYS: i64 = 7;
+
+const HELD_MESSAGE: &str = "Your post is awaiting moderator approval";
+
+// Messages per page of conversation history (the server's default)
+const DM_PAGE_SIZE: usize = 50;
+
+// The emoji the server accepts as reactions, in picker order
+pub const REACTION_EMOJI: [&str; 8] = ["👍", "👎", "❤️", "😂", "🎉", "😮", "😢", "🚀"];
+
 #[derive(PartialEq)]
 pub enum AppState {
     Login,
     Forum,
+    // Moderators only: queue of open reports
+    Moderation,
+    // Admins only: audit log of moderation and administrative actions
+    Audit,
+    // Moderators only: deleted threads and comments that can be restored
+    Trash,
+    // Moderators only: posts held by the content filter
+    Held,
+    // Admins only: create, edit, reorder and delete categories
+    Categories,
+    // Mentions of the logged-in user
+    Notifications,
+    // Private conversations with other users
+    Messages,
+    // A user's profile and recent posts
+    Profile,
+    // Password change, two-factor login, data export and account deletion
+    Settings,
+    // Public keys registered for key login
+    SshKeys,
 }
 
+// Filter keys understood by GET /admin/audit
+pub const AUDIT_FILTER_KEYS: [&str; 7] = ["actor", "action", "target_type", "target_id", "since", "until", "limit"];
+
 #[derive(PartialEq, Clone, Copy)]
 pub enum CurrentFocus {
     Username,
     Password,
+    // Login screen: the code from an authenticator app, once the server asks for it
+    TwoFactorCode,
     ThreadList,
     Conversation,
     NewThread,
     Reply,
+    // Entering the reason for reporting the selected post
+    Report,
+    // Moderation screen: entering the reason for a ban/suspension/mute
+    Sanction,
+    // Audit screen: editing the key=value filter
+    AuditFilter,
+    // Categories screen: typing a name or description
+    CategoryInput,
+    // Categories screen: choosing a parent, or where to move threads
+    CategoryChoice,
+    // New-thread sub-focus: the tags input
+    NewThreadTags,
+    // Thread list: editing the tag filter
+    TagFilter,
+    // Conversation: picking an emoji to react to the selected post with
+    ReactionPicker,
+    // Messages screen: typing who a new conversation is with
+    MessageRecipients,
+    // Messages screen: typing a message
+    MessageInput,
+}
+
+/// What the categories screen's input or choice list is for.
+#[derive(PartialEq, Clone)]
+pub enum CategoryAction {
+    // New category under the given parent, or at the top level
+    Create(Option<String>),
+    Rename,
+    Describe,
+    SetParent,
+    Delete,
+}
+
+/// Server-side ordering of the thread list.
+#[derive(PartialEq, Clone, Copy)]
+pub enum SortMode {
+    LatestActivity,
+    Newest,
+    MostReplies,
+}
+
+impl SortMode {
+    pub fn as_param(self) -> &'static str {
+        match self {
+            SortMode::LatestActivity => "activity",
+            SortMode::Newest => "newest",
+            SortMode::MostReplies => "replies",
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            SortMode::LatestActivity => "latest activity",
+            SortMode::Newest => "newest",
+            SortMode::MostReplies => "most replies",
+        }
+    }
+
+    pub fn next(self) -> SortMode {
+        match self {
+            SortMode::LatestActivity => SortMode::Newest,
+            SortMode::Newest => SortMode::MostReplies,
+            SortMode::MostReplies => SortMode::LatestActivity,
+        }
+    }
+}
+
+/// What can be done from the settings screen, in the order it lists them.
+#[derive(PartialEq, Clone, Copy)]
+pub enum SettingsAction {
+    ChangePassword,
+    // Only one of these two is listed, depending on whether two-factor login is on
+    EnableTwoFactor,
+    DisableTwoFactor,
+    // Opens the SSH keys screen rather than a form
+    SshKeys,
+    ExportData,
+    DeleteAccount,
+}
+
+impl SettingsAction {
+    pub fn label(self) -> &'static str {
+        match self {
+            SettingsAction::ChangePassword => "Change password",
+            SettingsAction::EnableTwoFactor => "Enable two-factor",
+            SettingsAction::DisableTwoFactor => "Disable two-factor",
+            SettingsAction::SshKeys => "SSH keys",
+            SettingsAction::ExportData => "Export my data",
+            SettingsAction::DeleteAccount => "Delete account",
+        }
+    }
+
+    pub fn description(self) -> &'static str {
+        match self {
+            SettingsAction::ChangePassword => "Signs you out everywhere else",
+            SettingsAction::EnableTwoFactor => "Ask for a code from an authenticator app when you log in",
+            SettingsAction::DisableTwoFactor => "Log in with your password alone",
+            SettingsAction::SshKeys => "Log in with an ed25519 key from ssh-agent or ~/.ssh instead of a password",
+            SettingsAction::ExportData => "Everything stored about you, saved as a JSON file",
+            SettingsAction::DeleteAccount => "Permanently; your posts are removed or left under a placeholder name",
+        }
+    }
+
+    /// The form's inputs as (title, server field name). Those named `*password` are masked.
+    pub fn fields(self) -> &'static [(&'static str, &'static str)] {
+        match self {
+            SettingsAction::ChangePassword => &[
+                ("Current password", "current_password"),
+                ("New password", "new_password"),
+                ("Repeat new password", "confirm_password"),
+            ],
+            SettingsAction::EnableTwoFactor => &[("Code from your authenticator app", "code")],
+            SettingsAction::DisableTwoFactor => &[("Password", "password"), ("Code, or a recovery code", "code")],
+            SettingsAction::SshKeys => &[],
+            SettingsAction::ExportData => &[("Save to", "path")],
+            SettingsAction::DeleteAccount => &[("Password", "password")],
+        }
+    }
+}
+
+#[derive(PartialEq, Clone, Copy)]
+pub enum ThreadFlag {
+    Pinned,
+    Locked,
+    Archived,
 }
 
 pub struct App {
     pub state: AppState,
     pub focus: CurrentFocus,
     pub should_quit: bool,
+    // Run by the server for an SSH session: the files, keys and config around
+    // us are the server's, so nothing is read from or written to them
+    pub remote: bool,
     
     // Login state
     pub username_input: String,
     pub password_input: String,
+    // Shown once the server has asked for a two-factor code
+    pub two_factor_prompt: bool,
+    pub two_factor_input: String,
     pub current_user: Option<User>,
     
     // Forum state
     pub threads: Vec<Thread>,
     pub categories: Vec<Category>,
     pub selected_thread: usize,
+    pub sort_mode: SortMode,
+    // Browse archived threads instead of active ones
+    pub show_archived: bool,
+    // Only list threads carrying all of these tags
+    pub tag_filter: Vec<String>,
+    pub tag_filter_input: String,
+    // Existing tags matching the one being typed, most used first
+    pub tag_suggestions: Vec<TagCount>,
     pub selected_category: usize,
+    // Selected post in the conversation pane: 0 is the opening post,
+    // 1.. are the comments in order
     pub selected_comment: usize,
     pub current_thread_id: Option<String>,
     pub comments: Vec<Comment>, // Store comments for the open thread
@@ -42,27 +224,208 @@ pub struct App {
     pub new_thread_title: String,
     pub new_thread_content: String,
     pub new_thread_image_path: String,
+    // Space- or comma-separated tags for the new thread
+    pub new_thread_tags: String,
     pub reply_content: String,
     pub reply_image_path: String,
     // Sub‑focus within NewThread mode (Title vs Content)
     pub new_thread_focus: CurrentFocus,
     
+    pub report_reason: String,
+    // Highlighted emoji in the reaction picker, an index into REACTION_EMOJI
+    pub selected_reaction: usize,
+    
+    // Moderation queue
+    pub reports: Vec<Report>,
+    pub selected_report: usize,
+    // Action ("ban", "suspend", "mute") awaiting a reason, and that reason
+    pub pending_sanction: Option<&'static str>,
+    pub sanction_reason: String,
+    
+    // Posts awaiting approval
+    pub held_posts: Vec<HeldPost>,
+    pub selected_held: usize,
+    
+    // Deleted content
+    pub deleted_items: Vec<DeletedItem>,
+    pub selected_deleted: usize,
+    
+    // Audit log
+    pub audit_entries: Vec<AuditEntry>,
+    pub selected_audit: usize,
+    // Space-separated key=value filters, e.g. "actor=alice action=user.ban"
+    pub audit_filter: String,
+    
+    // Category management; rows follow the server's tree order
+    pub selected_category_row: usize,
+    pub category_action: Option<CategoryAction>,
+    pub category_input: String,
+    // Choices for SetParent or Delete: category id (None for "no category") and label
+    pub category_choices: Vec<(Option<String>, String)>,
+    pub selected_choice: usize,
+    
+    // Notification inbox; the unread count is shown in the status bar
+    pub notifications: Vec<Notification>,
+    pub selected_notification: usize,
+    pub unread_notifications: i64,
+    
+    // Private messages: the conversation list, and the selected conversation's loaded history
+    pub conversations: Vec<Conversation>,
+    pub selected_conversation: usize,
+    pub dm_messages: Vec<Message>,
+    // Older messages than the first loaded one exist
+    pub dm_has_more: bool,
+    // Comma-separated usernames for a new conversation; empty when replying
+    pub dm_recipients: String,
+    pub dm_input: String,
+    pub blocked_users: Vec<String>,
+    pub unread_messages: i64,
+    
+    // Profile screen; `profile_field` is the field being edited, if any.
+    // The forum's focus is left alone so Esc returns to where we were
+    pub profile: Option<Profile>,
+    pub selected_activity: usize,
+    pub profile_field: Option<&'static str>,
+    pub profile_input: String,
+    
+    // Settings screen; `settings_action` is the form being filled in, if any
+    pub selected_setting: usize,
+    pub settings_action: Option<SettingsAction>,
+    pub settings_inputs: Vec<String>,
+    pub settings_field: usize,
+    // Account deletion removes our posts instead of anonymizing them
+    pub delete_posts: bool,
+    // Two-factor state, fetched when settings open; the secret being enrolled;
+    // recovery codes to show once, right after enabling
+    pub two_factor: Option<api::TwoFactorStatus>,
+    pub two_factor_setup: Option<api::TwoFactorSetup>,
+    pub recovery_codes: Vec<String>,
+    
+    // SSH keys screen; `ssh_key_input` holds the key file being added, if any
+    pub ssh_keys: Vec<api::SshKey>,
+    pub selected_ssh_key: usize,
+    pub ssh_key_input: Option<String>,
+    
+    // Downloaded avatars, and how this terminal can draw them
+    pub avatars: AvatarCache,
+    // Text copied with `y`, for the terminal to put on the clipboard after the next frame
+    clipboard: Option<String>,
+    
+    // Per-field messages from the last rejected form, keyed by the server's field name
+    pub field_errors: HashMap<String, String>,
+    
+    // One-line feedback shown in the status bar (e.g. "Copied to clipboard")
+    pub status_message: Option<String>,
+    
     // Auto-refresh timer
     pub last_refresh: std::time::Instant,
 }
 
+/// A post in the conversation pane: either the thread's opening post or one of its comments.
+#[derive(Clone, Copy)]
+pub enum Post<'a> {
+    Thread(&'a Thread),
+    Comment(&'a Comment),
+}
+
+impl<'a> Post<'a> {
+    pub fn author(&self) -> &'a str {
+        match self {
+            Post::Thread(t) => &t.author,
+            Post::Comment(c) => &c.author,
+        }
+    }
+
+    pub fn content(&self) -> &'a str {
+        match self {
+            Post::Thread(t) => &t.content,
+            Post::Comment(c) => &c.content,
+        }
+    }
+
+    /// Target type and id as expected by the reports API.
+    pub fn report_target(&self) -> (&'static str, &'a str) {
+        match self {
+            Post::Thread(t) => ("thread", &t.id),
+            Post::Comment(c) => ("comment", &c.id),
+        }
+    }
+
+    pub fn created_at(&self) -> DateTime<Utc> {
+        match self {
+            Post::Thread(t) => t.created_at,
+            Post::Comment(c) => c.created_at,
+        }
+    }
+
+    pub fn is_deleted(&self) -> bool {
+        matches!(self, Post::Comment(c) if c.deleted)
+    }
+
+    pub fn reactions(&self) -> &'a [Reaction] {
+        match self {
+            Post::Thread(t) => &t.reactions,
+            Post::Comment(c) => &c.reactions,
+        }
+    }
+
+    pub fn signature(&self) -> Option<&'a str> {
+        match self {
+            Post::Thread(t) => t.author_signature.as_deref(),
+            Post::Comment(c) => c.author_signature.as_deref(),
+        }
+    }
+
+    pub fn avatar(&self) -> Option<&'a str> {
+        match self {
+            Post::Thread(t) => t.author_avatar.as_deref(),
+            Post::Comment(c) => c.author_avatar.as_deref(),
+        }
+    }
+}
+
+/// Tags in a tags input, separated by spaces or commas.
+pub fn parse_tags(input: &str) -> Vec<String> {
+    input
+        .split(|c: char| c.is_whitespace() || c == ',')
+        .filter(|t| !t.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+/// The tag still being typed at the end of a tags input, if any.
+fn partial_tag(input: &str) -> Option<&str> {
+    let start = input.rfind(|c: char| c.is_whitespace() || c == ',').map_or(0, |i| i + 1);
+    let partial = input[start..].trim_start_matches('#');
+    (!partial.is_empty()).then_some(partial)
+}
+
+impl Default for App {
+    fn default() -> App {
+        App::new()
+    }
+}
+
 impl App {
     pub fn new() -> App {
         App {
             state: AppState::Login,
             focus: CurrentFocus::Username,
             should_quit: false,
+            remote: false,
             username_input: String::new(),
             password_input: String::new(),
+            two_factor_prompt: false,
+            two_factor_input: String::new(),
             current_user: None,
             threads: Vec::new(),
             categories: Vec::new(),
             selected_thread: 0,
+            sort_mode: SortMode::LatestActivity,
+            show_archived: false,
+            tag_filter: Vec::new(),
+            tag_filter_input: String::new(),
+            tag_suggestions: Vec::new(),
             selected_category: 0,
             selected_comment: 0,
             current_thread_id: None,
@@ -70,13 +433,71 @@ impl App {
             new_thread_title: String::new(),
             new_thread_content: String::new(),
             new_thread_image_path: String::new(),
+            new_thread_tags: String::new(),
             reply_content: String::new(),
             reply_image_path: String::new(),
             new_thread_focus: CurrentFocus::Username, // reuse enum for sub‑focus (Title)
+            report_reason: String::new(),
+            selected_reaction: 0,
+            reports: Vec::new(),
+            selected_report: 0,
+            pending_sanction: None,
+            sanction_reason: String::new(),
+            held_posts: Vec::new(),
+            selected_held: 0,
+            deleted_items: Vec::new(),
+            selected_deleted: 0,
+            audit_entries: Vec::new(),
+            selected_audit: 0,
+            audit_filter: String::new(),
+            selected_category_row: 0,
+            category_action: None,
+            category_input: String::new(),
+            category_choices: Vec::new(),
+            selected_choice: 0,
+            notifications: Vec::new(),
+            selected_notification: 0,
+            unread_notifications: 0,
+            conversations: Vec::new(),
+            selected_conversation: 0,
+            dm_messages: Vec::new(),
+            dm_has_more: false,
+            dm_recipients: String::new(),
+            dm_input: String::new(),
+            blocked_users: Vec::new(),
+            unread_messages: 0,
+            profile: None,
+            selected_activity: 0,
+            profile_field: None,
+            profile_input: String::new(),
+            selected_setting: 0,
+            settings_action: None,
+            settings_inputs: Vec::new(),
+            settings_field: 0,
+            delete_posts: false,
+            two_factor: None,
+            two_factor_setup: None,
+            recovery_codes: Vec::new(),
+            ssh_keys: Vec::new(),
+            selected_ssh_key: 0,
+            ssh_key_input: None,
+            avatars: AvatarCache::new(),
+            clipboard: None,
+            field_errors: HashMap::new(),
+            status_message: None,
             last_refresh: std::time::Instant::now(),
         }
     }
 
+    /// The app the server runs for an SSH session, drawing into a terminal of
+    /// type `term` (the client's TERM) on the user's machine.
+    pub fn remote(term: &str) -> App {
+        let mut app = App::new();
+        app.remote = true;
+        app.avatars.graphics = Graphics::for_term(term);
+        app
+    }
+
     pub fn load_config(&mut self) {
         if let Ok(home) = std::env::var("HOME") {
             let config_path = std::path::Path::new(&home).join(".config/ternimal/config.json");
@@ -97,20 +518,36 @@ impl App {
         println!("Attempting login for user: {}", self.username_input);
         
         // Try login first
-        match api::login_user(&self.username_input, &self.password_input).await {
+        let code = Some(self.two_factor_input.as_str()).filter(|_| self.two_factor_prompt);
+        match api::login_user(&self.username_input, &self.password_input, code).await {
             Ok(user) => {
                 println!("Login successful!");
+                api::set_session_token(user.token.clone());
                 self.current_user = Some(user);
             }
-            Err(e) => {
-                println!("Login failed with error: {}", e);
+            // Right password; ask for the code and send it along next time
+            Err(e) if e.is::<api::TwoFactorRequired>() => {
+                self.two_factor_prompt = true;
+                self.two_factor_input.clear();
+                self.focus = CurrentFocus::TwoFactorCode;
+                return Err(e);
+            }
+            // Wrong password, banned, suspended...: registering would not help
+            Err(e) if e.to_string() != "Invalid username or password" => return Err(e),
+            Err(login_error) => {
+                println!("Login failed with error: {}", login_error);
                 println!("Trying registration...");
                 // Try to register new user
                 match api::register_user(&self.username_input, &self.password_input).await {
                     Ok(user) => {
                         println!("Registration successful!");
+                        api::set_session_token(user.token.clone());
                         self.current_user = Some(user);
                     }
+                    // Field errors are shown next to the inputs as they are
+                    Err(e) if e.is::<api::InvalidInput>() => return Err(e),
+                    // The name exists, so it was the password that was wrong
+                    Err(e) if e.to_string() == "Username already taken" => return Err(login_error),
                     Err(e) => {
                         println!("Registration failed with error: {}", e);
                         return Err(anyhow::anyhow!("Invalid username/password or registration failed: {}", e));
@@ -119,8 +556,55 @@ impl App {
             }
         }
         
+        self.enter_forum().await;
+        Ok(())
+    }
+
+    /// Log in as `username_input` by signing a challenge with an SSH key,
+    /// trying each key from ssh-agent and ~/.ssh until one is registered.
+    pub async fn key_login(&mut self) -> anyhow::Result<()> {
+        if self.remote {
+            anyhow::bail!("Key login needs the ternimal client; log in with your password");
+        }
+        let mut refused = None;
+        for signer in ssh_auth::signers()? {
+            let challenge = api::key_challenge(&self.username_input).await?;
+            let signature = signer.sign(&challenge.namespace, &challenge.nonce)?;
+            match api::key_login(&self.username_input, &challenge.nonce, &signature).await {
+                Ok(user) => {
+                    api::set_session_token(user.token.clone());
+                    self.current_user = Some(user);
+                    self.enter_forum().await;
+                    return Ok(());
+                }
+                Err(e) if e.to_string() == "Key not registered for this account" => refused = Some(signer.describe()),
+                Err(e) => return Err(e),
+            }
+        }
+        Err(anyhow::anyhow!(
+            "No key registered for {}; tried {}",
+            self.username_input,
+            refused.unwrap_or_default()
+        ))
+    }
+
+    /// Carry on as `user`, who logged in elsewhere (the SSH server checked their
+    /// password or key) and holds a session token for us.
+    pub async fn resume_session(&mut self, user: User) {
+        api::set_session_token(user.token.clone());
+        self.username_input = user.username.clone();
+        self.current_user = Some(user);
+        self.enter_forum().await;
+    }
+
+    /// Switch to the forum once logged in, and load what it shows.
+    async fn enter_forum(&mut self) {
         self.state = AppState::Forum;
         self.focus = CurrentFocus::ThreadList;
+        self.two_factor_prompt = false;
+        self.two_factor_input.clear();
+        self.status_message = None;
+        self.field_errors.clear();
         
         // Load threads with error handling
         if let Err(e) = self.load_threads().await {
@@ -133,7 +617,8 @@ impl App {
             eprintln!("Failed to load categories: {}", e);
         }
         
-        Ok(())
+        self.unread_notifications = api::unread_notification_count().await.unwrap_or(0);
+        self.unread_messages = api::unread_message_count().await.unwrap_or(0);
     }
 
     pub async fn load_categories(&mut self) -> anyhow::Result<()> {
@@ -149,6 +634,19 @@ impl App {
             } else {
                 self.last_refresh = std::time::Instant::now();
             }
+            if self.current_user.is_some() {
+                if let Ok(count) = api::unread_notification_count().await {
+                    self.unread_notifications = count;
+                }
+                if let Ok(count) = api::unread_message_count().await {
+                    self.unread_messages = count;
+                }
+            }
+            if self.state == AppState::Messages {
+                if let Err(e) = self.refresh_messages().await {
+                    self.status_message = Some(format!("Failed to refresh messages: {}", e));
+                }
+            }
             
             // Also refresh comments if we have a thread open
             if let Some(thread_id) = self.current_thread_id.clone() {
@@ -161,12 +659,168 @@ impl App {
 
     pub async fn refresh_comments(&mut self, thread_id: &str) -> anyhow::Result<()> {
         self.comments = api::list_comments(thread_id).await?;
-        Ok(())
+        // Comments may have disappeared since the last refresh
+        self.selected_comment = self.selected_comment.min(self.post_count().saturating_sub(1));
+        self.load_avatars().await;
+        // The open thread is on screen, so anything new in it has been seen
+        self.mark_current_thread_read().await
+    }
+
+    /// Fetch the avatars of the open thread's authors and of the open profile
+    /// that aren't cached yet.
+    pub async fn load_avatars(&mut self) {
+        let mut wanted: Vec<(String, String)> = self
+            .posts()
+            .iter()
+            .filter_map(|p| p.avatar().map(|hash| (p.author().to_string(), hash.to_string())))
+            .collect();
+        if let Some(profile) = &self.profile {
+            if let Some(hash) = &profile.avatar {
+                wanted.push((profile.username.clone(), hash.clone()));
+            }
+        }
+        for (username, hash) in wanted {
+            if !self.avatars.needs(&hash) {
+                continue;
+            }
+            match api::get_avatar(&username).await {
+                Ok(png) => self.avatars.insert(&hash, png),
+                Err(_) => self.avatars.mark_failed(&hash),
+            }
+        }
     }
 
     pub async fn load_threads(&mut self) -> anyhow::Result<()> {
-        let threads = api::list_threads().await?;
+        let threads = api::list_threads(self.sort_mode.as_param(), self.show_archived, &self.tag_filter).await?;
+        // Keep the same thread selected even if the ordering changed
+        let selected_id = self.threads.get(self.selected_thread).map(|t| t.id.clone());
         self.threads = threads;
+        self.selected_thread = selected_id
+            .and_then(|id| self.threads.iter().position(|t| t.id == id))
+            .unwrap_or(0);
+        Ok(())
+    }
+
+    pub async fn cycle_sort_mode(&mut self) -> anyhow::Result<()> {
+        self.sort_mode = self.sort_mode.next();
+        self.load_threads().await
+    }
+
+    pub async fn toggle_archived_view(&mut self) -> anyhow::Result<()> {
+        self.show_archived = !self.show_archived;
+        self.selected_thread = 0;
+        self.load_threads().await
+    }
+
+    /// The tags input being edited, if any.
+    fn tag_input_mut(&mut self) -> Option<&mut String> {
+        match self.focus {
+            CurrentFocus::TagFilter => Some(&mut self.tag_filter_input),
+            CurrentFocus::NewThread if self.new_thread_focus == CurrentFocus::NewThreadTags => Some(&mut self.new_thread_tags),
+            _ => None,
+        }
+    }
+
+    /// Look up existing tags starting with the one being typed.
+    pub async fn update_tag_suggestions(&mut self) {
+        let Some(input) = self.tag_input_mut().map(|i| i.clone()) else {
+            return;
+        };
+        let entered = parse_tags(&input);
+        self.tag_suggestions = match partial_tag(&input) {
+            Some(partial) => api::list_tags(Some(partial))
+                .await
+                .unwrap_or_default()
+                .into_iter()
+                .filter(|t| !entered.contains(&t.name))
+                .collect(),
+            None => Vec::new(),
+        };
+    }
+
+    /// Replace the tag being typed with the top suggestion. Returns false if
+    /// there was nothing to complete.
+    pub fn complete_tag(&mut self) -> bool {
+        let Some(suggestion) = self.tag_suggestions.first().map(|t| t.name.clone()) else {
+            return false;
+        };
+        let Some(input) = self.tag_input_mut() else {
+            return false;
+        };
+        let Some(partial) = partial_tag(input) else {
+            return false;
+        };
+        let keep = input.len() - partial.len();
+        input.truncate(keep);
+        input.push_str(&suggestion);
+        input.push(' ');
+        self.tag_suggestions.clear();
+        true
+    }
+
+    pub fn start_tag_filter(&mut self) {
+        self.tag_filter_input = self.tag_filter.join(" ");
+        if !self.tag_filter_input.is_empty() {
+            self.tag_filter_input.push(' ');
+        }
+        self.tag_suggestions.clear();
+        self.focus = CurrentFocus::TagFilter;
+    }
+
+    /// Apply the tags typed in the filter input; an empty input shows all threads.
+    pub async fn apply_tag_filter(&mut self) -> anyhow::Result<()> {
+        self.focus = CurrentFocus::ThreadList;
+        self.tag_filter = parse_tags(&self.tag_filter_input)
+            .into_iter()
+            .map(|t| t.trim_start_matches('#').to_lowercase())
+            .collect();
+        self.selected_thread = 0;
+        self.load_threads().await
+    }
+
+    /// The thread moderation keys act on: the open one in the conversation
+    /// pane, otherwise the one highlighted in the list.
+    fn target_thread(&self) -> Option<&Thread> {
+        match self.focus {
+            CurrentFocus::Conversation => self.get_current_thread(),
+            _ => self.threads.get(self.selected_thread),
+        }
+    }
+
+    pub fn is_moderator(&self) -> bool {
+        self.current_user.as_ref().is_some_and(|u| u.is_moderator())
+    }
+
+    /// Moderators only: flip the pinned, locked or archived flag of the target thread.
+    pub async fn toggle_thread_flag(&mut self, flag: ThreadFlag) -> anyhow::Result<()> {
+        if !self.is_moderator() {
+            return Ok(());
+        }
+        let Some(thread) = self.target_thread() else {
+            return Ok(());
+        };
+        let id = thread.id.clone();
+        let (pinned, locked, archived) = match flag {
+            ThreadFlag::Pinned => (Some(!thread.pinned), None, None),
+            ThreadFlag::Locked => (None, Some(!thread.locked), None),
+            ThreadFlag::Archived => (None, None, Some(!thread.archived)),
+        };
+        api::set_thread_flags(&id, pinned, locked, archived).await?;
+        self.load_threads().await
+    }
+
+    /// Subscribe to the current thread's replies, or stop.
+    pub async fn toggle_subscription(&mut self) -> anyhow::Result<()> {
+        let Some(thread) = self.threads.iter_mut().find(|t| Some(&t.id) == self.current_thread_id.as_ref()) else {
+            return Ok(());
+        };
+        api::set_thread_subscription(&thread.id, !thread.subscribed).await?;
+        thread.subscribed = !thread.subscribed;
+        self.status_message = Some(if thread.subscribed {
+            "Subscribed: you will be notified of replies".to_string()
+        } else {
+            "Unsubscribed from this thread".to_string()
+        });
         Ok(())
     }
 
@@ -175,21 +829,969 @@ impl App {
             self.current_thread_id = Some(thread.id.clone());
             self.comments = api::list_comments(&thread.id).await?;
             self.focus = CurrentFocus::Conversation;
-            self.selected_comment = 0;
+            // Jump to the first comment newer than the last visit, if any
+            self.selected_comment = match &thread.last_read_at {
+                Some(last_read) => self
+                    .comments
+                    .iter()
+                    .position(|c| c.created_at > *last_read)
+                    .map_or(0, |i| i + 1),
+                None => 0,
+            };
+            self.load_avatars().await;
+            self.mark_current_thread_read().await?;
         }
         Ok(())
     }
 
+    /// Record that everything currently loaded in the open thread has been read.
+    async fn mark_current_thread_read(&mut self) -> anyhow::Result<()> {
+        let Some(thread_id) = self.current_thread_id.clone() else {
+            return Ok(());
+        };
+        let newest = self.comments.last().map(|c| c.created_at);
+        let Some(thread) = self.threads.iter_mut().find(|t| t.id == thread_id) else {
+            return Ok(());
+        };
+        let up_to = newest.unwrap_or(thread.created_at);
+        if thread.unread_count == 0 && thread.last_read_at >= Some(up_to) {
+            return Ok(());
+        }
+        thread.unread_count = 0;
+        thread.last_read_at = Some(up_to);
+        api::mark_thread_read(&thread_id, Some(up_to)).await
+    }
+
+
     pub fn get_current_thread(&self) -> Option<&Thread> {
         self.current_thread_id.as_ref().and_then(|id| {
             self.threads.iter().find(|t| &t.id == id)
         })
     }
 
+    /// Number of selectable posts in the open thread (opening post plus comments).
+    pub fn post_count(&self) -> usize {
+        if self.get_current_thread().is_some() {
+            self.comments.len() + 1
+        } else {
+            0
+        }
+    }
+
+    pub fn posts(&self) -> Vec<Post<'_>> {
+        match self.get_current_thread() {
+            Some(thread) => std::iter::once(Post::Thread(thread))
+                .chain(self.comments.iter().map(Post::Comment))
+                .collect(),
+            None => Vec::new(),
+        }
+    }
+
+    pub fn selected_post(&self) -> Option<Post<'_>> {
+        self.posts().get(self.selected_comment).copied()
+    }
+
+    /// Move the conversation selection by `delta` posts, clamped to the thread.
+    pub fn move_post_selection(&mut self, delta: isize) {
+        let last = self.post_count().saturating_sub(1);
+        self.selected_comment = self.selected_comment.saturating_add_signed(delta).min(last);
+    }
+
+    pub fn select_last_post(&mut self) {
+        self.selected_comment = self.post_count().saturating_sub(1);
+    }
+
+    /// Refuse to open the reply editor on a locked or archived thread.
+    fn can_reply(&mut self) -> bool {
+        match self.get_current_thread() {
+            Some(t) if t.is_read_only() => {
+                let state = if t.archived { "archived" } else { "locked" };
+                self.status_message = Some(format!("This thread is {}; replies are closed", state));
+                false
+            }
+            _ => true,
+        }
+    }
+
+    /// Open the reply editor, addressing the selected comment's author.
+    pub fn start_reply(&mut self) {
+        if !self.can_reply() {
+            return;
+        }
+        self.reply_content.clear();
+        self.field_errors.clear();
+        if let Some(Post::Comment(comment)) = self.selected_post() {
+            let is_own = self.current_user.as_ref().is_some_and(|u| u.username == comment.author);
+            if !is_own && !comment.deleted {
+                self.reply_content = format!("@{} ", comment.author);
+            }
+        }
+        self.focus = CurrentFocus::Reply;
+    }
+
+    /// Open the reply editor with the selected post quoted.
+    pub fn start_quote(&mut self) {
+        if !self.can_reply() {
+            return;
+        }
+        self.reply_content.clear();
+        self.field_errors.clear();
+        if let Some(post) = self.selected_post().filter(|p| !p.is_deleted()) {
+            let quoted = format!("{} wrote:\n{}", post.author(), post.content());
+            for line in quoted.lines() {
+                self.reply_content.push_str("> ");
+                self.reply_content.push_str(line);
+                self.reply_content.push('\n');
+            }
+            self.reply_content.push('\n');
+        }
+        self.focus = CurrentFocus::Reply;
+    }
+
+    pub fn start_report(&mut self) {
+        if self.selected_post().is_some_and(|p| !p.is_deleted()) {
+            self.report_reason.clear();
+            self.focus = CurrentFocus::Report;
+        }
+    }
+
+    pub fn start_reaction_picker(&mut self) {
+        if self.selected_post().is_some_and(|p| !p.is_deleted()) {
+            self.focus = CurrentFocus::ReactionPicker;
+        }
+    }
+
+    /// Toggle the highlighted picker emoji on the selected post.
+    pub async fn react_to_selected(&mut self) -> anyhow::Result<()> {
+        self.focus = CurrentFocus::Conversation;
+        let Some(post) = self.selected_post() else {
+            return Ok(());
+        };
+        let (target_type, id) = post.report_target();
+        let id = id.to_string();
+        let emoji = REACTION_EMOJI[self.selected_reaction.min(REACTION_EMOJI.len() - 1)];
+        let reactions = api::toggle_reaction(target_type, &id, emoji).await?;
+        if target_type == "thread" {
+            if let Some(thread) = self.threads.iter_mut().find(|t| t.id == id) {
+                thread.reactions = reactions;
+            }
+        } else if let Some(comment) = self.comments.iter_mut().find(|c| c.id == id) {
+            comment.reactions = reactions;
+        }
+        Ok(())
+    }
+
+    pub async fn submit_report(&mut self) -> anyhow::Result<()> {
+        let Some(post) = self.selected_post() else {
+            return Ok(());
+        };
+        let (target_type, target_id) = post.report_target();
+        api::create_report(target_type, target_id, &self.report_reason).await?;
+        self.status_message = Some("Report sent to the moderators".to_string());
+        Ok(())
+    }
+
+    pub async fn open_moderation(&mut self) -> anyhow::Result<()> {
+        if !self.is_moderator() {
+            return Ok(());
+        }
+        self.reports = api::list_reports().await?;
+        self.selected_report = 0;
+        self.state = AppState::Moderation;
+        Ok(())
+    }
+
+    /// Resolve (`Some(action)`) or dismiss (`None`) the selected report, then reload the queue.
+    pub async fn settle_selected_report(&mut self, action: Option<&str>) -> anyhow::Result<()> {
+        let Some(report) = self.reports.get(self.selected_report) else {
+            return Ok(());
+        };
+        let id = report.id.clone();
+        match action {
+            Some(action) => api::resolve_report(&id, action).await?,
+            None => api::dismiss_report(&id).await?,
+        }
+        self.reports = api::list_reports().await?;
+        self.selected_report = self.selected_report.min(self.reports.len().saturating_sub(1));
+        Ok(())
+    }
+
+    /// Ask for a reason before sanctioning the author of the selected report.
+    pub fn start_sanction(&mut self, action: &'static str) {
+        let has_author = self
+            .reports
+            .get(self.selected_report)
+            .is_some_and(|r| r.target_author.is_some());
+        if has_author {
+            self.pending_sanction = Some(action);
+            self.sanction_reason.clear();
+            self.focus = CurrentFocus::Sanction;
+        }
+    }
+
+    pub async fn submit_sanction(&mut self) -> anyhow::Result<()> {
+        self.focus = CurrentFocus::ThreadList;
+        let Some(action) = self.pending_sanction.take() else {
+            return Ok(());
+        };
+        let Some(author) = self.reports.get(self.selected_report).and_then(|r| r.target_author.clone()) else {
+            return Ok(());
+        };
+        let until = (action == "suspend").then(|| Utc::now() + chrono::Duration::days(SUSPENSION_DAYS));
+        api::sanction_user(&author, action, &self.sanction_reason, until).await?;
+        self.status_message = Some(format!("{}: {} applied", author, action));
+        Ok(())
+    }
+
+    /// Moderators only: delete the selected post. Deleting the opening post
+    /// deletes the whole thread.
+    pub async fn delete_selected_post(&mut self) -> anyhow::Result<()> {
+        if !self.is_moderator() {
+            return Ok(());
+        }
+        let Some(post) = self.selected_post().filter(|p| !p.is_deleted()) else {
+            return Ok(());
+        };
+        let (target_type, id) = post.report_target();
+        let id = id.to_string();
+        api::delete_post(target_type, &id).await?;
+        self.status_message = Some(format!("{} deleted; restore it from the trash (M, then t)", target_type));
+        if target_type == "thread" {
+            self.current_thread_id = None;
+            self.comments.clear();
+            self.selected_comment = 0;
+            self.focus = CurrentFocus::ThreadList;
+            self.load_threads().await
+        } else if let Some(thread_id) = self.current_thread_id.clone() {
+            self.refresh_comments(&thread_id).await
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Moderators only: switch to the queue of posts held by the content filter.
+    pub async fn open_held(&mut self) -> anyhow::Result<()> {
+        if !self.is_moderator() {
+            return Ok(());
+        }
+        self.held_posts = api::list_held().await?;
+        self.selected_held = self.selected_held.min(self.held_posts.len().saturating_sub(1));
+        self.state = AppState::Held;
+        Ok(())
+    }
+
+    /// Approve or reject the selected held post, then reload the queue.
+    pub async fn decide_selected_held(&mut self, action: &str) -> anyhow::Result<()> {
+        let Some(post) = self.held_posts.get(self.selected_held) else {
+            return Ok(());
+        };
+        let (target_type, id) = (post.target_type.clone(), post.id.clone());
+        api::decide_held(&target_type, &id, action).await?;
+        self.open_held().await
+    }
+
+    /// Moderators only: switch to the list of deleted content.
+    pub async fn open_trash(&mut self) -> anyhow::Result<()> {
+        if !self.is_moderator() {
+            return Ok(());
+        }
+        self.deleted_items = api::list_deleted().await?;
+        self.selected_deleted = self.selected_deleted.min(self.deleted_items.len().saturating_sub(1));
+        self.state = AppState::Trash;
+        Ok(())
+    }
+
+    pub async fn restore_selected(&mut self) -> anyhow::Result<()> {
+        let Some(item) = self.deleted_items.get(self.selected_deleted) else {
+            return Ok(());
+        };
+        let (target_type, id) = (item.target_type.clone(), item.id.clone());
+        api::restore_post(&target_type, &id).await?;
+        self.status_message = Some(format!("{} restored", target_type));
+        self.open_trash().await
+    }
+
+    /// Keep the per-field messages of a rejected form, if `error` has any.
+    pub fn set_field_errors(&mut self, error: &anyhow::Error) {
+        self.field_errors = error
+            .downcast_ref::<api::InvalidInput>()
+            .map(|e| e.fields.clone())
+            .unwrap_or_default();
+    }
+
+    pub fn field_error(&self, field: &str) -> Option<&str> {
+        self.field_errors.get(field).map(String::as_str)
+    }
+
+    pub fn is_admin(&self) -> bool {
+        self.current_user.as_ref().is_some_and(|u| u.is_admin())
+    }
+
+    /// Admins only: switch to the audit log, applying the current filter.
+    pub async fn open_audit_log(&mut self) -> anyhow::Result<()> {
+        if !self.is_admin() {
+            return Ok(());
+        }
+        let mut filters = Vec::new();
+        for token in self.audit_filter.split_whitespace() {
+            match token.split_once('=') {
+                Some((key, value)) if AUDIT_FILTER_KEYS.contains(&key) => {
+                    filters.push((key.to_string(), value.to_string()));
+                }
+                _ => anyhow::bail!("Unknown filter '{}'; keys are {}", token, AUDIT_FILTER_KEYS.join(", ")),
+            }
+        }
+        self.audit_entries = api::list_audit_log(&filters).await?;
+        self.selected_audit = 0;
+        self.state = AppState::Audit;
+        Ok(())
+    }
+
+    /// Admins only: switch to category management.
+    pub async fn open_categories(&mut self) -> anyhow::Result<()> {
+        if !self.is_admin() {
+            return Ok(());
+        }
+        self.load_categories().await?;
+        self.selected_category_row = self.selected_category_row.min(self.categories.len().saturating_sub(1));
+        self.state = AppState::Categories;
+        Ok(())
+    }
+
+    /// Reload the categories, keeping the row of `id` selected.
+    async fn reload_categories(&mut self, id: Option<String>) -> anyhow::Result<()> {
+        self.load_categories().await?;
+        self.selected_category_row = id
+            .and_then(|id| self.categories.iter().position(|c| c.id == id))
+            .unwrap_or(self.selected_category_row)
+            .min(self.categories.len().saturating_sub(1));
+        Ok(())
+    }
+
+    fn selected_managed_category(&self) -> Option<&Category> {
+        self.categories.get(self.selected_category_row)
+    }
+
+    /// Open the name or description input for `action`, prefilled when editing.
+    pub fn start_category_input(&mut self, action: CategoryAction) {
+        let current = self.selected_managed_category();
+        let prefill = match &action {
+            CategoryAction::Rename => current.map(|c| c.name.clone()),
+            CategoryAction::Describe => current.map(|c| c.description.clone().unwrap_or_default()),
+            _ => Some(String::new()),
+        };
+        let Some(prefill) = prefill else {
+            return;
+        };
+        self.category_input = prefill;
+        self.category_action = Some(action);
+        self.field_errors.clear();
+        self.focus = CurrentFocus::CategoryInput;
+    }
+
+    /// Start a new category. Subcategories go under the selected category, or
+    /// under its parent when a subcategory is selected.
+    pub fn start_new_category(&mut self, subcategory: bool) {
+        let parent = if subcategory {
+            let Some(selected) = self.selected_managed_category() else {
+                return;
+            };
+            Some(selected.parent_id.clone().unwrap_or_else(|| selected.id.clone()))
+        } else {
+            None
+        };
+        self.start_category_input(CategoryAction::Create(parent));
+    }
+
+    pub async fn submit_category_input(&mut self) -> anyhow::Result<()> {
+        let Some(action) = self.category_action.clone() else {
+            return Ok(());
+        };
+        let id = self.selected_managed_category().map(|c| c.id.clone());
+        let input = self.category_input.clone();
+        match (&action, &id) {
+            (CategoryAction::Create(parent), _) => api::create_category(&input, None, parent.as_deref()).await?,
+            (CategoryAction::Rename, Some(id)) => api::update_category(id, Some(&input), None, None).await?,
+            (CategoryAction::Describe, Some(id)) => api::update_category(id, None, Some(&input), None).await?,
+            _ => {}
+        }
+        self.category_action = None;
+        self.focus = CurrentFocus::ThreadList;
+        self.field_errors.clear();
+        match action {
+            // Select the new category; names are unique enough to find it again
+            CategoryAction::Create(_) => {
+                self.load_categories().await?;
+                let trimmed = input.trim();
+                if let Some(row) = self.categories.iter().rposition(|c| c.name == trimmed) {
+                    self.selected_category_row = row;
+                }
+                Ok(())
+            }
+            _ => self.reload_categories(id).await,
+        }
+    }
+
+    /// Open the choice list for picking a new parent or where the threads of a
+    /// deleted category should go.
+    pub fn start_category_choice(&mut self, action: CategoryAction) {
+        let Some(selected) = self.selected_managed_category() else {
+            return;
+        };
+        let has_children = self.categories.iter().any(|c| c.parent_id.as_deref() == Some(&selected.id));
+        let mut choices = Vec::new();
+        match action {
+            CategoryAction::SetParent => {
+                if has_children {
+                    self.status_message = Some(format!("{} has subcategories and must stay at the top level", selected.name));
+                    return;
+                }
+                choices.push((None, "(top level)".to_string()));
+                choices.extend(
+                    self.categories
+                        .iter()
+                        .filter(|c| c.parent_id.is_none() && c.id != selected.id)
+                        .map(|c| (Some(c.id.clone()), c.name.clone())),
+                );
+            }
+            CategoryAction::Delete => {
+                choices.push((None, "(leave threads uncategorized)".to_string()));
+                choices.extend(
+                    self.categories
+                        .iter()
+                        .filter(|c| c.id != selected.id && c.parent_id.as_deref() != Some(&selected.id))
+                        .map(|c| (Some(c.id.clone()), format!("Move threads to {}", c.name))),
+                );
+            }
+            _ => return,
+        }
+        // Start on the current parent when re-parenting
+        self.selected_choice = match action {
+            CategoryAction::SetParent => choices.iter().position(|(id, _)| *id == selected.parent_id).unwrap_or(0),
+            _ => 0,
+        };
+        self.category_choices = choices;
+        self.category_action = Some(action);
+        self.focus = CurrentFocus::CategoryChoice;
+    }
+
+    pub async fn submit_category_choice(&mut self) -> anyhow::Result<()> {
+        self.focus = CurrentFocus::ThreadList;
+        let (Some(action), Some(category)) = (self.category_action.take(), self.selected_managed_category()) else {
+            return Ok(());
+        };
+        let Some((choice, _)) = self.category_choices.get(self.selected_choice) else {
+            return Ok(());
+        };
+        let (id, name) = (category.id.clone(), category.name.clone());
+        match action {
+            CategoryAction::SetParent => {
+                api::update_category(&id, None, None, Some(choice.as_deref().unwrap_or(""))).await?;
+                self.reload_categories(Some(id)).await
+            }
+            CategoryAction::Delete => {
+                api::delete_category(&id, choice.as_deref()).await?;
+                self.status_message = Some(format!("Category {} deleted", name));
+                self.reload_categories(None).await
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Move the selected category up (`-1`) or down (`1`) among its siblings.
+    pub async fn move_selected_category(&mut self, delta: isize) -> anyhow::Result<()> {
+        let Some(selected) = self.selected_managed_category() else {
+            return Ok(());
+        };
+        let (id, parent_id) = (selected.id.clone(), selected.parent_id.clone());
+        let mut siblings: Vec<String> = self
+            .categories
+            .iter()
+            .filter(|c| c.parent_id == parent_id)
+            .map(|c| c.id.clone())
+            .collect();
+        let Some(index) = siblings.iter().position(|s| *s == id) else {
+            return Ok(());
+        };
+        let Some(target) = index.checked_add_signed(delta).filter(|t| *t < siblings.len()) else {
+            return Ok(());
+        };
+        siblings.swap(index, target);
+        api::reorder_categories(&siblings).await?;
+        self.reload_categories(Some(id)).await
+    }
+
+    pub async fn open_notifications(&mut self) -> anyhow::Result<()> {
+        self.notifications = api::list_notifications().await?;
+        self.unread_notifications = self.notifications.iter().filter(|n| !n.read).count() as i64;
+        self.selected_notification = self.selected_notification.min(self.notifications.len().saturating_sub(1));
+        self.state = AppState::Notifications;
+        Ok(())
+    }
+
+    /// Mark the selected notification read.
+    pub async fn mark_selected_notification_read(&mut self) -> anyhow::Result<()> {
+        let Some(notification) = self.notifications.get_mut(self.selected_notification) else {
+            return Ok(());
+        };
+        if !notification.read {
+            api::mark_notification_read(&notification.id).await?;
+            notification.read = true;
+            self.unread_notifications = (self.unread_notifications - 1).max(0);
+        }
+        Ok(())
+    }
+
+    pub async fn mark_all_notifications_read(&mut self) -> anyhow::Result<()> {
+        api::mark_all_notifications_read().await?;
+        for notification in &mut self.notifications {
+            notification.read = true;
+        }
+        self.unread_notifications = 0;
+        Ok(())
+    }
+
+    /// Mark the selected notification read and jump to the post it is about.
+    pub async fn open_selected_notification(&mut self) -> anyhow::Result<()> {
+        self.mark_selected_notification_read().await?;
+        let Some(notification) = self.notifications.get(self.selected_notification) else {
+            return Ok(());
+        };
+        let (thread_id, comment_id) = (notification.thread_id.clone(), notification.comment_id.clone());
+        self.show_thread(&thread_id, comment_id.as_deref()).await
+    }
+
+    /// Switch to the forum with `thread_id` open, selecting `comment_id` if given.
+    pub async fn show_thread(&mut self, thread_id: &str, comment_id: Option<&str>) -> anyhow::Result<()> {
+        self.state = AppState::Forum;
+        // The thread may be filtered out by tags, or archived
+        if !self.threads.iter().any(|t| t.id == thread_id) {
+            self.tag_filter.clear();
+            self.show_archived = false;
+            self.load_threads().await?;
+        }
+        if !self.threads.iter().any(|t| t.id == thread_id) {
+            self.show_archived = true;
+            self.load_threads().await?;
+        }
+        let Some(index) = self.threads.iter().position(|t| t.id == thread_id) else {
+            self.show_archived = false;
+            self.load_threads().await?;
+            anyhow::bail!("That thread is no longer available");
+        };
+        self.selected_thread = index;
+        self.open_thread(index).await?;
+        if let Some(i) = comment_id.and_then(|id| self.comments.iter().position(|c| c.id == id)) {
+            self.selected_comment = i + 1;
+        }
+        Ok(())
+    }
+
+    pub async fn open_messages(&mut self) -> anyhow::Result<()> {
+        self.blocked_users = api::list_blocks().await?;
+        self.load_conversations().await?;
+        self.state = AppState::Messages;
+        self.focus = CurrentFocus::ThreadList;
+        self.load_selected_conversation().await
+    }
+
+    async fn load_conversations(&mut self) -> anyhow::Result<()> {
+        let conversations = api::list_conversations().await?;
+        // Keep the same conversation selected even if the ordering changed
+        let selected_id = self.selected_conversation().map(|c| c.id.clone());
+        self.conversations = conversations;
+        self.selected_conversation = selected_id
+            .and_then(|id| self.conversations.iter().position(|c| c.id == id))
+            .unwrap_or(0);
+        Ok(())
+    }
+
+    pub fn selected_conversation(&self) -> Option<&Conversation> {
+        self.conversations.get(self.selected_conversation)
+    }
+
+    /// Load the newest page of the selected conversation and mark it read.
+    pub async fn load_selected_conversation(&mut self) -> anyhow::Result<()> {
+        let Some(conversation) = self.conversations.get_mut(self.selected_conversation) else {
+            self.dm_messages.clear();
+            self.dm_has_more = false;
+            return Ok(());
+        };
+        let id = conversation.id.clone();
+        self.dm_messages = api::list_messages(&id, None).await?;
+        self.dm_has_more = self.dm_messages.len() >= DM_PAGE_SIZE;
+        if conversation.unread_count > 0 {
+            api::mark_conversation_read(&id).await?;
+            self.unread_messages = (self.unread_messages - conversation.unread_count).max(0);
+            conversation.unread_count = 0;
+        }
+        Ok(())
+    }
+
+    pub async fn select_conversation(&mut self, index: usize) -> anyhow::Result<()> {
+        if index < self.conversations.len() && index != self.selected_conversation {
+            self.selected_conversation = index;
+            self.load_selected_conversation().await?;
+        }
+        Ok(())
+    }
+
+    /// Prepend the page of history before the oldest loaded message.
+    pub async fn load_older_messages(&mut self) -> anyhow::Result<()> {
+        let (Some(conversation), Some(oldest)) = (self.selected_conversation(), self.dm_messages.first()) else {
+            return Ok(());
+        };
+        if !self.dm_has_more {
+            return Ok(());
+        }
+        let mut older = api::list_messages(&conversation.id, Some(&oldest.id)).await?;
+        self.dm_has_more = older.len() >= DM_PAGE_SIZE;
+        older.append(&mut self.dm_messages);
+        self.dm_messages = older;
+        Ok(())
+    }
+
+    /// Pick up new conversations and messages while the messages screen is open.
+    async fn refresh_messages(&mut self) -> anyhow::Result<()> {
+        self.load_conversations().await?;
+        let newest_loaded = self.dm_messages.last().map(|m| m.created_at);
+        let changed = self
+            .selected_conversation()
+            .is_some_and(|c| newest_loaded.is_none_or(|newest| c.last_message_at > newest));
+        if changed {
+            self.load_selected_conversation().await?;
+        }
+        Ok(())
+    }
+
+    pub fn start_message(&mut self) {
+        if self.selected_conversation().is_some() {
+            self.dm_recipients.clear();
+            self.dm_input.clear();
+            self.field_errors.clear();
+            self.focus = CurrentFocus::MessageInput;
+        }
+    }
+
+    pub fn start_new_conversation(&mut self) {
+        self.dm_recipients.clear();
+        self.dm_input.clear();
+        self.field_errors.clear();
+        self.focus = CurrentFocus::MessageRecipients;
+    }
+
+    /// Send the message being written, starting a new conversation if recipients were given.
+    pub async fn submit_message(&mut self) -> anyhow::Result<()> {
+        let conversation_id = if self.dm_recipients.trim().is_empty() {
+            let Some(conversation) = self.selected_conversation() else {
+                return Ok(());
+            };
+            let id = conversation.id.clone();
+            api::send_message(&id, &self.dm_input).await?;
+            id
+        } else {
+            let recipients: Vec<String> = self
+                .dm_recipients
+                .split(',')
+                .map(|name| name.trim().trim_start_matches('@').to_string())
+                .filter(|name| !name.is_empty())
+                .collect();
+            api::create_conversation(&recipients, &self.dm_input).await?
+        };
+        self.dm_recipients.clear();
+        self.dm_input.clear();
+        self.field_errors.clear();
+        self.focus = CurrentFocus::ThreadList;
+        self.load_conversations().await?;
+        self.selected_conversation = self.conversations.iter().position(|c| c.id == conversation_id).unwrap_or(0);
+        self.load_selected_conversation().await
+    }
+
+    /// Block the other user of a one-to-one conversation, or lift the block.
+    pub async fn toggle_block_selected(&mut self) -> anyhow::Result<()> {
+        let Some([username]) = self.selected_conversation().map(|c| c.participants.as_slice()) else {
+            self.status_message = Some("Blocking works on one-to-one conversations".to_string());
+            return Ok(());
+        };
+        let username = username.clone();
+        let blocked = !self.blocked_users.contains(&username);
+        api::set_blocked(&username, blocked).await?;
+        self.blocked_users = api::list_blocks().await?;
+        self.status_message = Some(if blocked {
+            format!("Blocked {}: neither of you can message the other", username)
+        } else {
+            format!("Unblocked {}", username)
+        });
+        Ok(())
+    }
+
+    pub async fn open_profile(&mut self, username: &str) -> anyhow::Result<()> {
+        self.profile = Some(api::get_profile(username).await?);
+        self.selected_activity = 0;
+        self.profile_field = None;
+        self.state = AppState::Profile;
+        self.load_avatars().await;
+        Ok(())
+    }
+
+    /// Open the profile of the selected post's author, or of the selected thread's.
+    pub async fn open_author_profile(&mut self) -> anyhow::Result<()> {
+        let author = match self.focus {
+            CurrentFocus::Conversation => self.selected_post().filter(|p| !p.is_deleted()).map(|p| p.author().to_string()),
+            _ => self.threads.get(self.selected_thread).map(|t| t.author.clone()),
+        };
+        match author {
+            Some(author) => self.open_profile(&author).await,
+            None => Ok(()),
+        }
+    }
+
+    pub fn is_own_profile(&self) -> bool {
+        match (&self.profile, &self.current_user) {
+            (Some(profile), Some(user)) => profile.username == user.username,
+            _ => false,
+        }
+    }
+
+    /// Start editing one of our own profile fields, prefilled with its value.
+    pub fn start_profile_edit(&mut self, field: &'static str) {
+        let Some(profile) = self.profile.as_ref().filter(|_| self.is_own_profile()) else {
+            return;
+        };
+        let current = match field {
+            "display_name" => &profile.display_name,
+            "bio" => &profile.bio,
+            "signature" => &profile.signature,
+            // The path of an image to upload
+            "avatar" => &None,
+            _ => &profile.location,
+        };
+        self.profile_input = current.clone().unwrap_or_default();
+        self.profile_field = Some(field);
+        self.field_errors.clear();
+    }
+
+    pub async fn submit_profile_edit(&mut self) -> anyhow::Result<()> {
+        let Some(field) = self.profile_field else {
+            return Ok(());
+        };
+        if field == "avatar" {
+            let data_url = self.image_data_url(self.profile_input.trim())?;
+            api::upload_avatar(&data_url).await?;
+        } else {
+            self.profile = Some(api::update_profile(field, &self.profile_input).await?);
+        }
+        self.profile_field = None;
+        self.reload_own_posts().await
+    }
+
+    pub async fn remove_avatar(&mut self) -> anyhow::Result<()> {
+        if !self.is_own_profile() {
+            return Ok(());
+        }
+        api::delete_avatar().await?;
+        self.reload_own_posts().await?;
+        self.status_message = Some("Avatar removed".to_string());
+        Ok(())
+    }
+
+    /// Our signature and avatar show with our posts, so reload them and our
+    /// profile after changing either.
+    async fn reload_own_posts(&mut self) -> anyhow::Result<()> {
+        if let Some(user) = self.current_user.as_ref().map(|u| u.username.clone()) {
+            self.profile = Some(api::get_profile(&user).await?);
+        }
+        self.load_threads().await?;
+        if let Some(thread_id) = self.current_thread_id.clone() {
+            self.comments = api::list_comments(&thread_id).await?;
+        }
+        self.load_avatars().await;
+        Ok(())
+    }
+
+    /// Jump to the thread or comment selected in the profile's recent activity.
+    pub async fn open_selected_activity(&mut self) -> anyhow::Result<()> {
+        let Some(activity) = self.profile.as_ref().and_then(|p| p.recent_activity.get(self.selected_activity)) else {
+            return Ok(());
+        };
+        let (thread_id, comment_id) = (activity.thread_id.clone(), activity.comment_id.clone());
+        self.show_thread(&thread_id, comment_id.as_deref()).await
+    }
+
+    pub async fn open_settings(&mut self) -> anyhow::Result<()> {
+        self.two_factor = Some(api::two_factor_status().await?);
+        self.selected_setting = 0;
+        self.settings_action = None;
+        self.recovery_codes.clear();
+        self.state = AppState::Settings;
+        Ok(())
+    }
+
+    /// What the settings screen lists, in order.
+    pub fn settings_actions(&self) -> [SettingsAction; 5] {
+        let two_factor = match &self.two_factor {
+            Some(status) if status.enabled => SettingsAction::DisableTwoFactor,
+            _ => SettingsAction::EnableTwoFactor,
+        };
+        [SettingsAction::ChangePassword, two_factor, SettingsAction::SshKeys, SettingsAction::ExportData, SettingsAction::DeleteAccount]
+    }
+
+    /// Open the form for the selected settings action.
+    pub async fn start_settings_action(&mut self) -> anyhow::Result<()> {
+        let actions = self.settings_actions();
+        let action = actions[self.selected_setting.min(actions.len() - 1)];
+        self.settings_inputs = vec![String::new(); action.fields().len()];
+        match action {
+            SettingsAction::ExportData if self.remote => {
+                anyhow::bail!("Exporting saves a file on your computer, so it needs the ternimal client");
+            }
+            SettingsAction::ExportData => {
+                let username = self.current_user.as_ref().map(|u| u.username.as_str()).unwrap_or("ternimal");
+                self.settings_inputs[0] = format!("~/{}-export.json", username);
+            }
+            // A fresh secret each time; it only takes effect once a code from it is confirmed
+            SettingsAction::EnableTwoFactor => self.two_factor_setup = Some(api::setup_two_factor().await?),
+            SettingsAction::SshKeys => return self.open_ssh_keys().await,
+            _ => {}
+        }
+        self.settings_field = 0;
+        self.delete_posts = false;
+        self.field_errors.clear();
+        self.settings_action = Some(action);
+        Ok(())
+    }
+
+    pub async fn submit_settings_action(&mut self) -> anyhow::Result<()> {
+        let Some(action) = self.settings_action else {
+            return Ok(());
+        };
+        match action {
+            SettingsAction::ChangePassword => {
+                let [current, new, repeated] = &self.settings_inputs[..] else {
+                    return Ok(());
+                };
+                if new != repeated {
+                    let message = "does not match the new password".to_string();
+                    return Err(api::InvalidInput {
+                        message: format!("confirm_password: {}", message),
+                        fields: HashMap::from([("confirm_password".to_string(), message)]),
+                    }
+                    .into());
+                }
+                let revoked = api::change_password(current, new).await?;
+                // Logging in again this session uses the new password
+                self.password_input = new.clone();
+                self.status_message = Some(format!("Password changed; signed out of {} other session(s)", revoked));
+            }
+            SettingsAction::EnableTwoFactor => {
+                self.recovery_codes = api::enable_two_factor(&self.settings_inputs[0]).await?;
+                self.two_factor_setup = None;
+                self.two_factor = Some(api::two_factor_status().await?);
+                self.status_message = Some("Two-factor login enabled".to_string());
+            }
+            SettingsAction::DisableTwoFactor => {
+                api::disable_two_factor(&self.settings_inputs[0], &self.settings_inputs[1]).await?;
+                self.two_factor = Some(api::two_factor_status().await?);
+                self.status_message = Some("Two-factor login disabled".to_string());
+            }
+            SettingsAction::SshKeys => {}
+            SettingsAction::ExportData => {
+                let path = self.settings_inputs[0].trim().to_string();
+                let path = match (path.strip_prefix("~/"), std::env::var_os("HOME")) {
+                    (Some(rest), Some(home)) => std::path::Path::new(&home).join(rest),
+                    _ => std::path::PathBuf::from(&path),
+                };
+                let archive = api::export_account().await?;
+                std::fs::write(&path, archive)?;
+                self.status_message = Some(format!("Exported your data to {}", path.display()));
+            }
+            SettingsAction::DeleteAccount => {
+                api::delete_account(&self.settings_inputs[0], self.delete_posts).await?;
+                api::set_session_token(None);
+                self.current_user = None;
+                self.username_input.clear();
+                self.password_input.clear();
+                self.focus = CurrentFocus::Username;
+                self.state = AppState::Login;
+                self.status_message = Some("Your account has been deleted".to_string());
+            }
+        }
+        self.settings_action = None;
+        self.settings_inputs.clear();
+        Ok(())
+    }
+
+    pub async fn open_ssh_keys(&mut self) -> anyhow::Result<()> {
+        self.ssh_keys = api::list_ssh_keys().await?;
+        self.selected_ssh_key = 0;
+        self.ssh_key_input = None;
+        self.state = AppState::SshKeys;
+        Ok(())
+    }
+
+    /// Start adding a key, offering ~/.ssh/id_ed25519.pub. Over SSH the key
+    /// has to be pasted, as files would be read on the server.
+    pub fn start_ssh_key_input(&mut self) {
+        self.field_errors.clear();
+        self.ssh_key_input = Some(if self.remote { String::new() } else { ssh_auth::default_public_key_path() });
+    }
+
+    pub async fn submit_ssh_key(&mut self) -> anyhow::Result<()> {
+        let Some(input) = &self.ssh_key_input else {
+            return Ok(());
+        };
+        let public_key = if self.remote { input.trim().to_string() } else { ssh_auth::read_public_key(input)? };
+        let key = api::add_ssh_key(&public_key, None).await?;
+        self.status_message = Some(format!("Added {} ({})", key.name, key.fingerprint));
+        self.ssh_key_input = None;
+        self.ssh_keys = api::list_ssh_keys().await?;
+        self.selected_ssh_key = self.ssh_keys.len().saturating_sub(1);
+        Ok(())
+    }
+
+    pub async fn delete_selected_ssh_key(&mut self) -> anyhow::Result<()> {
+        let Some(key) = self.ssh_keys.get(self.selected_ssh_key) else {
+            return Ok(());
+        };
+        api::delete_ssh_key(&key.id).await?;
+        self.status_message = Some(format!("Removed {}", key.name));
+        self.ssh_keys = api::list_ssh_keys().await?;
+        self.selected_ssh_key = self.selected_ssh_key.min(self.ssh_keys.len().saturating_sub(1));
+        Ok(())
+    }
+
+    /// Copy the selected post to the system clipboard, via an OSC 52 escape
+    /// sequence sent with the next frame.
+    pub fn copy_selected(&mut self) {
+        let Some(content) = self.selected_post().map(|p| p.content().to_string()) else {
+            return;
+        };
+        self.clipboard = Some(content);
+        self.status_message = Some("Copied to clipboard".to_string());
+    }
+
+    /// Send text waiting for the clipboard to the terminal.
+    pub fn flush_clipboard(&mut self, out: &mut impl std::io::Write) -> std::io::Result<()> {
+        if let Some(content) = self.clipboard.take() {
+            let encoded = base64::engine::general_purpose::STANDARD.encode(content);
+            write!(out, "\x1b]52;c;{}\x07", encoded)?;
+            out.flush()?;
+        }
+        Ok(())
+    }
+
+    /// The image at `path` for a post or avatar, as a data URL.
+    fn image_data_url(&self, path: &str) -> anyhow::Result<String> {
+        if self.remote {
+            anyhow::bail!("Images are attached from your computer, so they need the ternimal client");
+        }
+        api::create_data_url(path)
+    }
+
     pub async fn create_thread(&mut self, title: String, content: String) -> anyhow::Result<()> {
-        if let Some(user) = &self.current_user {
+        if self.current_user.is_some() {
             let image_url = if !self.new_thread_image_path.is_empty() {
-                Some(api::create_data_url(&self.new_thread_image_path)?)
+                Some(self.image_data_url(&self.new_thread_image_path)?)
             } else {
                 None
             };
@@ -202,37 +1804,42 @@ impl App {
             
             let new_thread = NewThread {
                 title,
-                author: user.username.clone(),
                 content,
                 image_url,
                 category_id,
+                tags: parse_tags(&self.new_thread_tags),
             };
-            api::create_thread(new_thread).await?;
+            if api::create_thread(new_thread).await? {
+                self.status_message = Some(HELD_MESSAGE.to_string());
+            }
         }
         Ok(())
     }
 
     pub async fn create_reply(&mut self, content: String) -> anyhow::Result<()> {
-        if let (Some(user), Some(thread_id)) = (&self.current_user, &self.current_thread_id) {
+        if let (Some(_), Some(thread_id)) = (&self.current_user, &self.current_thread_id) {
             let image_url = if !self.reply_image_path.is_empty() {
-                Some(api::create_data_url(&self.reply_image_path)?)
+                Some(self.image_data_url(&self.reply_image_path)?)
             } else {
                 None
             };
             
             let new_comment = NewComment {
                 thread_id: thread_id.clone(),
-                author: user.username.clone(),
                 content,
                 image_url,
             };
-            api::create_comment(new_comment).await?;
-            // Refresh comments
+            if api::create_comment(new_comment).await? {
+                self.status_message = Some(HELD_MESSAGE.to_string());
+            }
+            // Refresh comments and jump to the new one
             self.comments = api::list_comments(thread_id).await?;
+            self.select_last_post();
         }
         Ok(())
     }
 
+    #[allow(dead_code)]
     pub async fn clear_all_threads(&mut self) -> anyhow::Result<()> {
         delete_all_threads().await?;
         self.threads.clear();
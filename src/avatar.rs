@@ -0,0 +1,318 @@
+// src/avatar.rs
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::io::{self, Write};
+use std::path::PathBuf;
+
+use base64::Engine;
+use image::{imageops::FilterType, RgbaImage};
+use ratatui::{
+    style::{Color, Style},
+    text::{Line, Span},
+};
+
+// Size of the avatar next to each post, in terminal cells. Cells are about
+// twice as tall as they are wide, so this is roughly square
+pub const COLS: u16 = 6;
+pub const ROWS: u16 = 3;
+
+// Brightness ramp for the ASCII fallback, darkest first
+const ASCII_RAMP: &[u8] = b" .:-=+*#%@";
+
+/// How avatars are drawn, picked from the environment at startup.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Graphics {
+    // The real image, through the kitty graphics protocol (kitty, WezTerm, Ghostty)
+    Kitty,
+    // Two pixels per cell with the upper half block; 256 colours unless the terminal has truecolor
+    Blocks { truecolor: bool },
+    // Brightness as characters, for terminals without colour
+    Ascii,
+}
+
+impl Graphics {
+    /// `TERNIMAL_AVATARS=kitty|blocks|ascii` overrides the detection.
+    pub fn detect() -> Graphics {
+        let var = |name: &str| std::env::var(name).unwrap_or_default();
+        let truecolor = matches!(var("COLORTERM").as_str(), "truecolor" | "24bit");
+        match var("TERNIMAL_AVATARS").as_str() {
+            "kitty" => return Graphics::Kitty,
+            "blocks" => return Graphics::Blocks { truecolor },
+            "ascii" => return Graphics::Ascii,
+            _ => {}
+        }
+        if !var("KITTY_WINDOW_ID").is_empty()
+            || var("TERM").contains("kitty")
+            || matches!(var("TERM_PROGRAM").as_str(), "WezTerm" | "ghostty")
+        {
+            Graphics::Kitty
+        } else if std::env::var_os("NO_COLOR").is_some() || var("TERM") == "dumb" {
+            Graphics::Ascii
+        } else {
+            Graphics::Blocks { truecolor }
+        }
+    }
+
+    /// For a terminal known only by its TERM, as SSH clients send it. Without
+    /// COLORTERM there is no telling whether it has truecolor.
+    pub fn for_term(term: &str) -> Graphics {
+        if term.contains("kitty") {
+            Graphics::Kitty
+        } else if term == "dumb" {
+            Graphics::Ascii
+        } else {
+            Graphics::Blocks { truecolor: false }
+        }
+    }
+}
+
+pub struct Avatar {
+    image: RgbaImage,
+    png: Vec<u8>,
+    // Image id for the kitty protocol
+    id: u32,
+}
+
+impl Avatar {
+    fn from_png(hash: &str, png: Vec<u8>) -> anyhow::Result<Avatar> {
+        let image = image::load_from_memory(&png)?.to_rgba8();
+        // Ids must be non-zero; the hash makes them stable across runs
+        let id = u32::from_str_radix(hash.get(..8).unwrap_or(hash), 16).unwrap_or(0) | 1;
+        Ok(Avatar { image, png, id })
+    }
+
+    /// The avatar as `rows` lines of `cols` cells. With the kitty protocol the
+    /// cells are blank and the image is placed over them after drawing.
+    pub fn lines(&self, graphics: Graphics, cols: u16, rows: u16) -> Vec<Line<'static>> {
+        match graphics {
+            Graphics::Kitty => blank(cols, rows),
+            Graphics::Blocks { truecolor } => {
+                let pixels = image::imageops::resize(&self.image, cols as u32, rows as u32 * 2, FilterType::Triangle);
+                (0..rows as u32)
+                    .map(|row| {
+                        Line::from(
+                            (0..cols as u32)
+                                .map(|col| {
+                                    let top = color(pixels.get_pixel(col, row * 2), truecolor);
+                                    let bottom = color(pixels.get_pixel(col, row * 2 + 1), truecolor);
+                                    Span::styled("▀", Style::default().fg(top).bg(bottom))
+                                })
+                                .collect::<Vec<_>>(),
+                        )
+                    })
+                    .collect()
+            }
+            Graphics::Ascii => {
+                let pixels = image::imageops::resize(&self.image, cols as u32, rows as u32, FilterType::Triangle);
+                (0..rows as u32)
+                    .map(|row| {
+                        let text: String = (0..cols as u32)
+                            .map(|col| {
+                                let [r, g, b] = blend(pixels.get_pixel(col, row));
+                                let luma = (299 * r as usize + 587 * g as usize + 114 * b as usize) / 1000;
+                                ASCII_RAMP[luma * (ASCII_RAMP.len() - 1) / 255] as char
+                            })
+                            .collect();
+                        Line::from(text)
+                    })
+                    .collect()
+            }
+        }
+    }
+}
+
+/// RGB of a pixel over a black background.
+fn blend(pixel: &image::Rgba<u8>) -> [u8; 3] {
+    let [r, g, b, a] = pixel.0;
+    let over = |c: u8| (c as u16 * a as u16 / 255) as u8;
+    [over(r), over(g), over(b)]
+}
+
+fn color(pixel: &image::Rgba<u8>, truecolor: bool) -> Color {
+    let [r, g, b] = blend(pixel);
+    if truecolor {
+        Color::Rgb(r, g, b)
+    } else {
+        // Nearest colour of the 6x6x6 cube in the 256-colour palette
+        let level = |c: u8| (c as u16 * 5 / 255) as u8;
+        Color::Indexed(16 + 36 * level(r) + 6 * level(g) + level(b))
+    }
+}
+
+fn blank(cols: u16, rows: u16) -> Vec<Line<'static>> {
+    (0..rows).map(|_| Line::from(" ".repeat(cols as usize))).collect()
+}
+
+/// Stand-in for users without an avatar: their initials on a colour picked from the name.
+pub fn placeholder(username: &str, graphics: Graphics, cols: u16, rows: u16) -> Vec<Line<'static>> {
+    const COLORS: [Color; 6] = [Color::Red, Color::Green, Color::Yellow, Color::Blue, Color::Magenta, Color::Cyan];
+    let initials: String = username.chars().take(2).collect::<String>().to_uppercase();
+    let label = format!("{:^width$}", initials, width = cols as usize);
+    if graphics == Graphics::Ascii {
+        return (0..rows)
+            .map(|row| Line::from(if row == rows / 2 { label.clone() } else { " ".repeat(cols as usize) }))
+            .collect();
+    }
+    let hash = username.bytes().fold(0usize, |h, b| h.wrapping_mul(31).wrapping_add(b as usize));
+    let style = Style::default().fg(Color::Black).bg(COLORS[hash % COLORS.len()]);
+    (0..rows)
+        .map(|row| {
+            let text = if row == rows / 2 { label.clone() } else { " ".repeat(cols as usize) };
+            Line::from(Span::styled(text, style))
+        })
+        .collect()
+}
+
+/// Where a kitty image goes on screen, in cells.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Placement {
+    pub x: u16,
+    pub y: u16,
+    pub cols: u16,
+    pub rows: u16,
+    id: u32,
+}
+
+/// Avatars by hash, in memory and on disk under ~/.cache/ternimal/avatars.
+pub struct AvatarCache {
+    pub graphics: Graphics,
+    avatars: HashMap<String, Avatar>,
+    // Hashes that could not be fetched, so we don't ask again every refresh
+    failed: HashSet<String>,
+    dir: Option<PathBuf>,
+    // Kitty: images already sent to the terminal, what is on screen, and what
+    // the frame being drawn wants there
+    transmitted: HashSet<u32>,
+    placed: Vec<Placement>,
+    pending: RefCell<Vec<Placement>>,
+}
+
+impl AvatarCache {
+    pub fn new() -> AvatarCache {
+        let dir = std::env::var_os("XDG_CACHE_HOME")
+            .map(PathBuf::from)
+            .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".cache")))
+            .map(|cache| cache.join("ternimal/avatars"));
+        AvatarCache {
+            graphics: Graphics::detect(),
+            avatars: HashMap::new(),
+            failed: HashSet::new(),
+            dir,
+            transmitted: HashSet::new(),
+            placed: Vec::new(),
+            pending: RefCell::new(Vec::new()),
+        }
+    }
+
+    pub fn get(&self, hash: &str) -> Option<&Avatar> {
+        self.avatars.get(hash)
+    }
+
+    /// Whether `hash` still has to be fetched from the server. Loads it from
+    /// the disk cache if it is there.
+    pub fn needs(&mut self, hash: &str) -> bool {
+        if self.avatars.contains_key(hash) || self.failed.contains(hash) {
+            return false;
+        }
+        let cached = self.path(hash).and_then(|path| std::fs::read(path).ok());
+        match cached.map(|png| Avatar::from_png(hash, png)) {
+            Some(Ok(avatar)) => {
+                self.avatars.insert(hash.to_string(), avatar);
+                false
+            }
+            _ => true,
+        }
+    }
+
+    /// Keep a freshly downloaded avatar, in memory and on disk.
+    pub fn insert(&mut self, hash: &str, png: Vec<u8>) {
+        match Avatar::from_png(hash, png) {
+            Ok(avatar) => {
+                if let Some(path) = self.path(hash) {
+                    let _ = path.parent().map(std::fs::create_dir_all);
+                    let _ = std::fs::write(path, &avatar.png);
+                }
+                self.avatars.insert(hash.to_string(), avatar);
+            }
+            Err(_) => self.mark_failed(hash),
+        }
+    }
+
+    pub fn mark_failed(&mut self, hash: &str) {
+        self.failed.insert(hash.to_string());
+    }
+
+    fn path(&self, hash: &str) -> Option<PathBuf> {
+        // Hashes come from the server; never let one escape the cache directory
+        if hash.is_empty() || !hash.chars().all(|c| c.is_ascii_hexdigit()) {
+            return None;
+        }
+        self.dir.as_ref().map(|dir| dir.join(format!("{}.png", hash)))
+    }
+
+    /// The avatar for `hash`, or the placeholder for `username`, as lines of cells.
+    pub fn lines(&self, username: &str, hash: Option<&str>, cols: u16, rows: u16) -> Vec<Line<'static>> {
+        match hash.and_then(|h| self.get(h)) {
+            Some(avatar) => avatar.lines(self.graphics, cols, rows),
+            None => placeholder(username, self.graphics, cols, rows),
+        }
+    }
+
+    /// Called while drawing: put the image for `hash` over the given cells
+    /// once the frame is on screen. Only does anything with the kitty protocol.
+    pub fn place(&self, hash: Option<&str>, x: u16, y: u16, cols: u16, rows: u16) {
+        if self.graphics != Graphics::Kitty {
+            return;
+        }
+        if let Some(avatar) = hash.and_then(|h| self.get(h)) {
+            self.pending.borrow_mut().push(Placement { x, y, cols, rows, id: avatar.id });
+        }
+    }
+
+    /// Called after drawing: bring the images on screen in line with the
+    /// placements of the frame just drawn.
+    pub fn flush(&mut self, out: &mut impl Write) -> io::Result<()> {
+        let pending = self.pending.take();
+        if self.graphics != Graphics::Kitty || pending == self.placed {
+            return Ok(());
+        }
+        // Delete every placement (but keep the image data) and place them anew
+        write!(out, "\x1b_Ga=d,d=a,q=2\x1b\\")?;
+        for placement in &pending {
+            if self.transmitted.insert(placement.id) {
+                if let Some(avatar) = self.avatars.values().find(|a| a.id == placement.id) {
+                    transmit(out, avatar)?;
+                }
+            }
+            write!(
+                out,
+                "\x1b[{};{}H\x1b_Ga=p,i={},c={},r={},C=1,q=2\x1b\\",
+                placement.y + 1,
+                placement.x + 1,
+                placement.id,
+                placement.cols,
+                placement.rows
+            )?;
+        }
+        out.flush()?;
+        self.placed = pending;
+        Ok(())
+    }
+}
+
+/// Send a PNG to the terminal under the avatar's id, in protocol-sized chunks.
+fn transmit(out: &mut impl Write, avatar: &Avatar) -> io::Result<()> {
+    let data = base64::engine::general_purpose::STANDARD.encode(&avatar.png);
+    let chunks: Vec<&[u8]> = data.as_bytes().chunks(4096).collect();
+    for (i, chunk) in chunks.iter().enumerate() {
+        let more = u8::from(i + 1 < chunks.len());
+        if i == 0 {
+            write!(out, "\x1b_Gf=100,a=t,i={},q=2,m={};", avatar.id, more)?;
+        } else {
+            write!(out, "\x1b_Gm={};", more)?;
+        }
+        out.write_all(chunk)?;
+        write!(out, "\x1b\\")?;
+    }
+    Ok(())
+}
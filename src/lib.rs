@@ -0,0 +1,10 @@
+// src/lib.rs
+//! The forum client: its state, the API it talks to and the screens it draws.
+//! The `ternimal` binary runs it in the local terminal; the server runs one per
+//! SSH session.
+pub mod api;
+pub mod app;
+mod avatar;
+mod qr;
+mod ssh_auth;
+pub mod ui;
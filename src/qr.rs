@@ -0,0 +1,39 @@
+// src/qr.rs
+use qrcode::{EcLevel, QrCode};
+use ratatui::{
+    style::{Color, Style},
+    text::{Line, Span},
+};
+
+// Light modules around the code. The standard asks for 4, but phone cameras
+// read 2 fine and the code has to fit in a terminal
+const QUIET_ZONE: usize = 2;
+
+/// `data` as a QR code, two modules per cell with the upper half block, dark
+/// on light whatever the terminal's own colours. `None` if it is too long to encode.
+pub fn lines(data: &str) -> Option<Vec<Line<'static>>> {
+    // The lowest error correction keeps the code, and so the cells, smallest
+    let code = QrCode::with_error_correction_level(data, EcLevel::L).ok()?;
+    let width = code.width();
+    let colors = code.to_colors();
+    let size = width + 2 * QUIET_ZONE;
+    let dark = |x: usize, y: usize| {
+        let (x, y) = (x.wrapping_sub(QUIET_ZONE), y.wrapping_sub(QUIET_ZONE));
+        x < width && y < width && colors[y * width + x] == qrcode::Color::Dark
+    };
+    let color = |dark: bool| if dark { Color::Black } else { Color::White };
+    Some(
+        (0..size.div_ceil(2))
+            .map(|row| {
+                Line::from(
+                    (0..size)
+                        .map(|x| {
+                            let style = Style::default().fg(color(dark(x, row * 2))).bg(color(dark(x, row * 2 + 1)));
+                            Span::styled("▀", style)
+                        })
+                        .collect::<Vec<_>>(),
+                )
+            })
+            .collect(),
+    )
+}
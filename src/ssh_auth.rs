@@ -0,0 +1,183 @@
+// src/ssh_auth.rs
+use std::path::PathBuf;
+
+use anyhow::Context;
+use ssh_key::{Algorithm, HashAlg, LineEnding, PrivateKey, PublicKey, SshSig};
+
+// The key file tried when ssh-agent has no ed25519 key
+const KEY_FILE: &str = ".ssh/id_ed25519";
+
+/// An ed25519 key that can answer a key-login challenge.
+pub enum Signer {
+    // Held by ssh-agent, which signs without us seeing the private key
+    Agent(PublicKey),
+    File(PathBuf, PrivateKey),
+}
+
+impl Signer {
+    /// Where the key is, for messages: "ssh-agent (alice@laptop)" or the file path.
+    pub fn describe(&self) -> String {
+        match self {
+            Signer::Agent(key) => format!("ssh-agent ({})", key.comment()),
+            Signer::File(path, _) => path.display().to_string(),
+        }
+    }
+
+    /// `nonce` signed for `namespace`, armored as by `ssh-keygen -Y sign`.
+    pub fn sign(&self, namespace: &str, nonce: &str) -> anyhow::Result<String> {
+        let signature = match self {
+            Signer::Agent(key) => {
+                let data = SshSig::signed_data(namespace, HashAlg::Sha512, nonce.as_bytes())?;
+                let signature = agent::sign(key, &data)?;
+                SshSig::new(key.key_data().clone(), namespace, HashAlg::Sha512, signature)?
+            }
+            Signer::File(_, key) => key.sign(namespace, HashAlg::Sha512, nonce.as_bytes())?,
+        };
+        Ok(signature.to_pem(LineEnding::LF)?)
+    }
+}
+
+fn home() -> Option<PathBuf> {
+    std::env::var_os("HOME").map(PathBuf::from)
+}
+
+/// `~/.ssh/id_ed25519.pub`, the key offered for registration by default.
+pub fn default_public_key_path() -> String {
+    format!("~/{}.pub", KEY_FILE)
+}
+
+/// The keys to try logging in with: ssh-agent's ed25519 keys, then ~/.ssh/id_ed25519.
+pub fn signers() -> anyhow::Result<Vec<Signer>> {
+    let mut signers: Vec<Signer> = agent::identities()
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|key| key.algorithm() == Algorithm::Ed25519)
+        .map(Signer::Agent)
+        .collect();
+    let path = home().map(|home| home.join(KEY_FILE));
+    if let Some(path) = path.filter(|p| p.exists()) {
+        let key = PrivateKey::read_openssh_file(&path).with_context(|| format!("Could not read {}", path.display()))?;
+        let in_agent = signers.iter().any(|s| matches!(s, Signer::Agent(k) if k.key_data() == key.public_key().key_data()));
+        if !in_agent && !key.is_encrypted() {
+            signers.push(Signer::File(path, key));
+        } else if !in_agent && signers.is_empty() {
+            anyhow::bail!("{} has a passphrase; add it to ssh-agent with ssh-add", path.display());
+        }
+    }
+    if signers.is_empty() {
+        anyhow::bail!("No ed25519 key found in ssh-agent or ~/{}", KEY_FILE);
+    }
+    Ok(signers)
+}
+
+/// A public key to register: the contents of the file at `input` (`~/` expanded),
+/// or `input` itself when it already is an OpenSSH public key line.
+pub fn read_public_key(input: &str) -> anyhow::Result<String> {
+    let input = input.trim();
+    if input.starts_with("ssh-") {
+        return Ok(input.to_string());
+    }
+    let path = match (input.strip_prefix("~/"), home()) {
+        (Some(rest), Some(home)) => home.join(rest),
+        _ => PathBuf::from(input),
+    };
+    let text = std::fs::read_to_string(&path).with_context(|| format!("Could not read {}", path.display()))?;
+    Ok(text.trim().to_string())
+}
+
+/// Just enough of the ssh-agent protocol to list keys and sign with them
+/// (draft-miller-ssh-agent).
+#[cfg(unix)]
+mod agent {
+    use std::io::{Read, Write};
+    use std::os::unix::net::UnixStream;
+
+    use ssh_key::{PublicKey, Signature};
+
+    const REQUEST_IDENTITIES: u8 = 11;
+    const IDENTITIES_ANSWER: u8 = 12;
+    const SIGN_REQUEST: u8 = 13;
+    const SIGN_RESPONSE: u8 = 14;
+
+    fn put_string(out: &mut Vec<u8>, bytes: &[u8]) {
+        out.extend_from_slice(&(bytes.len() as u32).to_be_bytes());
+        out.extend_from_slice(bytes);
+    }
+
+    fn take<'a>(input: &mut &'a [u8], len: usize) -> anyhow::Result<&'a [u8]> {
+        if input.len() < len {
+            anyhow::bail!("Truncated reply from ssh-agent");
+        }
+        let (head, rest) = input.split_at(len);
+        *input = rest;
+        Ok(head)
+    }
+
+    fn take_u32(input: &mut &[u8]) -> anyhow::Result<u32> {
+        let bytes = take(input, 4)?;
+        Ok(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    fn take_string<'a>(input: &mut &'a [u8]) -> anyhow::Result<&'a [u8]> {
+        let len = take_u32(input)? as usize;
+        take(input, len)
+    }
+
+    /// Send one message and return the reply's payload, after checking its type.
+    fn request(message: &[u8], expected: u8) -> anyhow::Result<Vec<u8>> {
+        let socket = std::env::var_os("SSH_AUTH_SOCK").ok_or_else(|| anyhow::anyhow!("ssh-agent is not running"))?;
+        let mut stream = UnixStream::connect(socket)?;
+        let mut framed = Vec::new();
+        put_string(&mut framed, message);
+        stream.write_all(&framed)?;
+
+        let mut len = [0u8; 4];
+        stream.read_exact(&mut len)?;
+        let mut reply = vec![0u8; u32::from_be_bytes(len) as usize];
+        stream.read_exact(&mut reply)?;
+        match reply.split_first() {
+            Some((kind, payload)) if *kind == expected => Ok(payload.to_vec()),
+            _ => anyhow::bail!("ssh-agent refused the request"),
+        }
+    }
+
+    pub fn identities() -> anyhow::Result<Vec<PublicKey>> {
+        let reply = request(&[REQUEST_IDENTITIES], IDENTITIES_ANSWER)?;
+        let mut input = reply.as_slice();
+        let count = take_u32(&mut input)?;
+        let mut keys = Vec::new();
+        for _ in 0..count {
+            let blob = take_string(&mut input)?;
+            let comment = String::from_utf8_lossy(take_string(&mut input)?).to_string();
+            // Keys of types we can't parse (certificates, security keys) are skipped
+            if let Ok(mut key) = PublicKey::from_bytes(blob) {
+                key.set_comment(comment);
+                keys.push(key);
+            }
+        }
+        Ok(keys)
+    }
+
+    pub fn sign(key: &PublicKey, data: &[u8]) -> anyhow::Result<Signature> {
+        let mut message = vec![SIGN_REQUEST];
+        put_string(&mut message, &key.to_bytes()?);
+        put_string(&mut message, data);
+        message.extend_from_slice(&0u32.to_be_bytes());
+        let reply = request(&message, SIGN_RESPONSE)?;
+        let mut input = reply.as_slice();
+        Ok(Signature::try_from(take_string(&mut input)?)?)
+    }
+}
+
+#[cfg(not(unix))]
+mod agent {
+    use ssh_key::{PublicKey, Signature};
+
+    pub fn identities() -> anyhow::Result<Vec<PublicKey>> {
+        Ok(Vec::new())
+    }
+
+    pub fn sign(_key: &PublicKey, _data: &[u8]) -> anyhow::Result<Signature> {
+        anyhow::bail!("ssh-agent is not supported on this platform")
+    }
+}
@@ -0,0 +1,2232 @@
+// src/ui.rs
+use crossterm::event::{KeyCode, KeyEvent};
+use ratatui::{
+    layout::{Constraint, Direction, Layout, Rect},
+    style::{Color, Modifier, Style},
+    text::{Span, Line},
+    widgets::{block::{Position, Title}, Block, Borders, Cell, Paragraph, Clear, Row, Table, TableState},
+};
+use chrono::{DateTime, Local, Utc};
+
+use crate::api;
+use crate::app::{self, App, AppState, CategoryAction, CurrentFocus, Post, SettingsAction, ThreadFlag};
+use crate::{avatar, qr};
+
+// Number of posts PageUp/PageDown move the conversation selection by
+const CONVERSATION_PAGE: isize = 5;
+// Size of the avatar on the profile screen, in cells
+const PROFILE_AVATAR_COLS: u16 = 12;
+const PROFILE_AVATAR_ROWS: u16 = 6;
+
+/// Act on a key pressed on the app's current screen.
+pub async fn handle_key(key: KeyEvent, app: &mut App) -> anyhow::Result<()> {
+    match app.state {
+        AppState::Login => handle_login_keys(key, app).await,
+        AppState::Forum => handle_forum_keys(key, app).await,
+        AppState::Moderation => handle_moderation_keys(key, app).await,
+        AppState::Audit => handle_audit_keys(key, app).await,
+        AppState::Trash => handle_trash_keys(key, app).await,
+        AppState::Held => handle_held_keys(key, app).await,
+        AppState::Categories => handle_categories_keys(key, app).await,
+        AppState::Notifications => handle_notifications_keys(key, app).await,
+        AppState::Messages => handle_messages_keys(key, app).await,
+        AppState::Profile => handle_profile_keys(key, app).await,
+        AppState::Settings => handle_settings_keys(key, app).await,
+        AppState::SshKeys => handle_ssh_keys_keys(key, app).await,
+    }
+}
+
+/// Draw the screen for the app's current state.
+pub fn draw(f: &mut ratatui::Frame, app: &App) {
+    match app.state {
+        AppState::Login => draw_login_screen(f, app),
+        AppState::Forum => draw_forum_ui(f, app),
+        AppState::Moderation => draw_moderation_screen(f, app),
+        AppState::Audit => draw_audit_screen(f, app),
+        AppState::Trash => draw_trash_screen(f, app),
+        AppState::Held => draw_held_screen(f, app),
+        AppState::Categories => draw_categories_screen(f, app),
+        AppState::Notifications => draw_notifications_screen(f, app),
+        AppState::Messages => draw_messages_screen(f, app),
+        AppState::Profile => draw_profile_screen(f, app),
+        AppState::Settings => draw_settings_screen(f, app),
+        AppState::SshKeys => draw_ssh_keys_screen(f, app),
+    }
+}
+
+/// Bordered block for a text input, turned red with the message in its title
+/// when the server rejected the field.
+fn input_block<'a>(title: &'a str, error: Option<&'a str>) -> Block<'a> {
+    let block = Block::default().borders(Borders::ALL);
+    match error {
+        Some(error) => block
+            .title(format!("{} - {}", title, error))
+            .border_style(Style::default().fg(Color::Red)),
+        None => block.title(title),
+    }
+}
+
+fn draw_login_screen(f: &mut ratatui::Frame, app: &App) {
+    let size = f.size();
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .margin(2)
+        .constraints(
+            [
+                Constraint::Percentage(30),
+                Constraint::Length(3),
+                Constraint::Length(3),
+                Constraint::Length(if app.two_factor_prompt { 3 } else { 0 }),
+                Constraint::Percentage(30),
+            ]
+            .as_ref(),
+        )
+        .split(size);
+
+    let title_text = "TERNIMAL\n\nTerminal Forum Client";
+    
+    let title = Paragraph::new(title_text)
+        .style(Style::default().fg(Color::Cyan).add_modifier(Modifier::BOLD))
+        .alignment(ratatui::layout::Alignment::Center)
+        .block(Block::default().borders(Borders::ALL));
+    f.render_widget(title, chunks[0]);
+
+    let username_style = if app.focus == CurrentFocus::Username {
+        Style::default().fg(Color::Yellow)
+    } else {
+        Style::default().fg(Color::White)
+    };
+    let username = Paragraph::new(app.username_input.as_str())
+        .style(username_style)
+        .block(input_block("Username", app.field_error("username")));
+    f.render_widget(username, chunks[1]);
+
+    let password_style = if app.focus == CurrentFocus::Password {
+        Style::default().fg(Color::Yellow)
+    } else {
+        Style::default().fg(Color::White)
+    };
+    let password_display: String = app.password_input.chars().map(|_| '*').collect();
+    let password = Paragraph::new(password_display)
+        .style(password_style)
+        .block(input_block("Password", app.field_error("password")));
+    f.render_widget(password, chunks[2]);
+
+    if app.two_factor_prompt {
+        let code_style = if app.focus == CurrentFocus::TwoFactorCode {
+            Style::default().fg(Color::Yellow)
+        } else {
+            Style::default().fg(Color::White)
+        };
+        let code = Paragraph::new(app.two_factor_input.as_str())
+            .style(code_style)
+            .block(input_block("Two-factor code (or a recovery code)", None));
+        f.render_widget(code, chunks[3]);
+    }
+
+    if let Some(message) = &app.status_message {
+        let error = Paragraph::new(message.as_str())
+            .style(Style::default().fg(Color::Red))
+            .alignment(ratatui::layout::Alignment::Center)
+            .wrap(ratatui::widgets::Wrap { trim: true });
+        f.render_widget(error, chunks[4]);
+    }
+}
+
+fn draw_forum_ui(f: &mut ratatui::Frame, app: &App) {
+    let size = f.size();
+    
+    // Check if we are in a modal mode (NewThread or Reply)
+    if app.focus == CurrentFocus::NewThread {
+        // Draw background (thread list) dimmed? Or just draw the modal over it.
+        // Let's draw the modal.
+        let chunks = Layout::default()
+            .direction(Direction::Vertical)
+            .margin(5)
+            .constraints([Constraint::Length(3), Constraint::Length(3), Constraint::Min(5), Constraint::Length(3)].as_ref())
+            .split(size);
+            
+        let title_style = if app.new_thread_focus == CurrentFocus::Username { Style::default().fg(Color::Yellow) } else { Style::default() };
+        let content_style = if app.new_thread_focus == CurrentFocus::ThreadList { Style::default().fg(Color::Yellow) } else { Style::default() }; // Reusing enums loosely here for sub-focus
+        
+        let title_input = Paragraph::new(app.new_thread_title.as_str())
+            .block(input_block("Thread Title", app.field_error("title")))
+            .style(title_style);
+        f.render_widget(title_input, chunks[0]);
+        
+        let tags_focused = app.new_thread_focus == CurrentFocus::NewThreadTags;
+        draw_tags_input(f, app, "Tags (up to 5, space-separated)", &app.new_thread_tags, tags_focused, chunks[1]);
+        
+        let content_input = Paragraph::new(app.new_thread_content.as_str())
+            .block(input_block("Content", app.field_error("content")))
+            .style(content_style);
+        f.render_widget(content_input, chunks[2]);
+        
+        draw_help_line(f, app, "Tab: Complete tag / Switch Focus | Enter: Submit | Esc: Cancel", chunks[3]);
+        return;
+    }
+    
+    if app.focus == CurrentFocus::Reply {
+        let area = centered_rect(60, 40, size);
+        let title = match app.selected_post() {
+            Some(post) => format!("Reply to {}", post.author()),
+            None => "Reply Content".to_string(),
+        };
+        let input = Paragraph::new(app.reply_content.as_str())
+            .block(input_block(&title, app.field_error("content")))
+            .style(Style::default().fg(Color::Yellow))
+            .wrap(ratatui::widgets::Wrap { trim: false });
+        f.render_widget(Clear, area); // Clear background
+        f.render_widget(input, area);
+        // Errors other than field errors (locked thread, rate limit...) go under the editor
+        if let (Some(message), None) = (&app.status_message, app.field_error("content")) {
+            let below = Rect { y: area.y + area.height, height: 1, ..area };
+            if below.y < size.height {
+                f.render_widget(Paragraph::new(message.as_str()).style(Style::default().fg(Color::Red)), below);
+            }
+        }
+        return;
+    }
+
+    if app.focus == CurrentFocus::Report {
+        let area = centered_rect(60, 20, size);
+        let title = match app.selected_post() {
+            Some(post) => format!("Report post by {} - reason", post.author()),
+            None => "Report reason".to_string(),
+        };
+        let input = Paragraph::new(app.report_reason.as_str())
+            .block(Block::default().borders(Borders::ALL).title(title))
+            .style(Style::default().fg(Color::Red))
+            .wrap(ratatui::widgets::Wrap { trim: false });
+        f.render_widget(Clear, area);
+        f.render_widget(input, area);
+        return;
+    }
+
+    let rows = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Min(3), Constraint::Length(1)].as_ref())
+        .split(size);
+
+    let chunks = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([Constraint::Percentage(45), Constraint::Percentage(55)].as_ref())
+        .split(rows[0]);
+
+    draw_thread_list(f, app, chunks[0]);
+    draw_conversation(f, app, chunks[1]);
+    draw_status_bar(f, app, rows[1]);
+
+    if app.focus == CurrentFocus::TagFilter {
+        let area = centered_rect(60, 20, size);
+        f.render_widget(Clear, area);
+        draw_tags_input(f, app, "Show threads tagged (empty for all)", &app.tag_filter_input, true, area);
+    }
+
+    if app.focus == CurrentFocus::ReactionPicker {
+        draw_reaction_picker(f, app, size);
+    }
+}
+
+/// One-line picker over the conversation with the emoji the user can react with.
+fn draw_reaction_picker(f: &mut ratatui::Frame, app: &App, size: Rect) {
+    let mine: Vec<&str> = app
+        .selected_post()
+        .map(|p| p.reactions().iter().filter(|r| r.mine).map(|r| r.emoji.as_str()).collect())
+        .unwrap_or_default();
+    let mut spans = Vec::new();
+    for (i, emoji) in app::REACTION_EMOJI.iter().enumerate() {
+        let mut style = Style::default();
+        if mine.contains(emoji) {
+            style = style.fg(Color::Yellow);
+        }
+        if i == app.selected_reaction {
+            style = style.add_modifier(Modifier::REVERSED);
+        }
+        spans.push(Span::styled(format!(" {} ", emoji), style));
+    }
+    let width = 44.min(size.width);
+    let area = Rect {
+        x: size.x + (size.width - width) / 2,
+        y: size.y + size.height.saturating_sub(3) / 2,
+        width,
+        height: 3.min(size.height),
+    };
+    let picker = Paragraph::new(Line::from(spans))
+        .alignment(ratatui::layout::Alignment::Center)
+        .block(Block::default().borders(Borders::ALL).title("React (←→/1-8, Enter)"));
+    f.render_widget(Clear, area);
+    f.render_widget(picker, area);
+}
+
+/// Tags input with the existing tags matching the one being typed listed
+/// along the bottom border.
+fn draw_tags_input(f: &mut ratatui::Frame, app: &App, title: &str, input: &str, focused: bool, area: Rect) {
+    let mut block = input_block(title, app.field_error("tags"));
+    if focused && !app.tag_suggestions.is_empty() {
+        let suggestions: Vec<String> = app
+            .tag_suggestions
+            .iter()
+            .take(5)
+            .map(|t| format!("{} ({})", t.name, t.count))
+            .collect();
+        block = block.title(
+            Title::from(Span::styled(format!(" Tab: {} ", suggestions.join(" · ")), Style::default().fg(Color::Blue)))
+                .position(Position::Bottom),
+        );
+    }
+    let style = if focused { Style::default().fg(Color::Yellow) } else { Style::default() };
+    f.render_widget(Paragraph::new(input).style(style).block(block), area);
+}
+
+/// Tags rendered as colored chips, each preceded by a space.
+fn tag_chips(tags: &[String]) -> Vec<Span<'static>> {
+    tags.iter()
+        .flat_map(|tag| {
+            [
+                Span::raw(" "),
+                Span::styled(format!(" {} ", tag), Style::default().fg(Color::Black).bg(Color::Blue)),
+            ]
+        })
+        .collect()
+}
+
+fn draw_status_bar(f: &mut ratatui::Frame, app: &App, area: Rect) {
+    let mut help = match app.focus {
+        CurrentFocus::Conversation => "↑↓/PgUp/PgDn/Home/End: Select | r: Reply | >: Quote | e: React | u: Profile | w: Subscribe | y: Copy | !: Report | ←: Threads | q: Quit",
+        CurrentFocus::TagFilter => "Tab: Complete tag | Enter: Apply | Esc: Cancel",
+        _ => "↑↓: Select | Enter: Open | n: New Thread | s: Sort | a: Archive | t: Tags | u/p: Author/My profile | i: Inbox | m: Messages | S: Settings | →: Conversation | q: Quit",
+    }
+    .to_string();
+    if app.is_moderator() {
+        help.push_str(" | P/L/A: Pin/Lock/Archive | D: Delete | M: Reports");
+    }
+    if app.is_admin() {
+        help.push_str(" | H: Audit log | C: Categories");
+    }
+    // Unread notification and message badges in front of the key help
+    let badge_style = Style::default().fg(Color::Black).bg(Color::Yellow).add_modifier(Modifier::BOLD);
+    let mut badges = Vec::new();
+    if app.unread_notifications > 0 {
+        badges.push(Span::styled(format!(" 🔔 {} ", app.unread_notifications), badge_style));
+        badges.push(Span::raw(" "));
+    }
+    if app.unread_messages > 0 {
+        badges.push(Span::styled(format!(" ✉ {} ", app.unread_messages), badge_style));
+        badges.push(Span::raw(" "));
+    }
+    if badges.is_empty() {
+        draw_help_line(f, app, &help, area);
+        return;
+    }
+    let badges = Line::from(badges);
+    let chunks = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([Constraint::Length(badges.width() as u16), Constraint::Min(0)].as_ref())
+        .split(area);
+    f.render_widget(Paragraph::new(badges), chunks[0]);
+    draw_help_line(f, app, &help, chunks[1]);
+}
+
+/// Key help, preceded by the current status message if there is one.
+fn draw_help_line(f: &mut ratatui::Frame, app: &App, help: &str, area: Rect) {
+    let line = match &app.status_message {
+        Some(message) => Line::from(vec![
+            Span::styled(message.as_str(), Style::default().fg(Color::Green)),
+            Span::raw(" | "),
+            Span::styled(help, Style::default().fg(Color::Gray)),
+        ]),
+        None => Line::from(Span::styled(help, Style::default().fg(Color::Gray))),
+    };
+    f.render_widget(Paragraph::new(line), area);
+}
+
+fn draw_moderation_screen(f: &mut ratatui::Frame, app: &App) {
+    let rows = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Min(3), Constraint::Length(1)].as_ref())
+        .split(f.size());
+    let chunks = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([Constraint::Percentage(40), Constraint::Percentage(60)].as_ref())
+        .split(rows[0]);
+
+    let items: Vec<Row> = app
+        .reports
+        .iter()
+        .map(|r| {
+            Row::new(vec![
+                Cell::from(r.target_type.clone()).style(Style::default().fg(Color::Magenta)),
+                Cell::from(r.reason.clone()),
+                Cell::from(relative_time(r.created_at)).style(Style::default().fg(Color::Gray)),
+            ])
+        })
+        .collect();
+    let widths = [Constraint::Length(8), Constraint::Min(10), Constraint::Length(8)];
+    let table = Table::new(items, widths)
+        .block(Block::default().borders(Borders::ALL).title(format!("Open reports ({})", app.reports.len())))
+        .highlight_style(Style::default().add_modifier(Modifier::BOLD).fg(Color::Cyan))
+        .highlight_symbol("> ");
+    let mut state = TableState::default();
+    state.select(Some(app.selected_report));
+    f.render_stateful_widget(table, chunks[0], &mut state);
+
+    let detail = match app.reports.get(app.selected_report) {
+        Some(r) => {
+            let label = Style::default().fg(Color::Gray);
+            let mut text = vec![
+                Line::from(vec![Span::styled("Thread:   ", label), Span::raw(r.thread_title.clone().unwrap_or_else(|| "[gone]".to_string()))]),
+                Line::from(vec![Span::styled("Author:   ", label), Span::styled(r.target_author.clone().unwrap_or_default(), Style::default().fg(Color::Cyan))]),
+                Line::from(vec![Span::styled("Reporter: ", label), Span::raw(r.reporter.clone())]),
+                Line::from(vec![Span::styled("Reported: ", label), Span::raw(local_time(r.created_at))]),
+                Line::from(vec![Span::styled("Reason:   ", label), Span::styled(r.reason.clone(), Style::default().fg(Color::Red))]),
+                Line::from(""),
+            ];
+            let width = chunks[1].width.saturating_sub(2) as usize;
+            match &r.target_content {
+                Some(content) => text.extend(wrap_text(content, width).into_iter().map(Line::from)),
+                None => text.push(Line::from(Span::styled("[content no longer exists]", label))),
+            }
+            Paragraph::new(text)
+        }
+        None => Paragraph::new("No open reports").alignment(ratatui::layout::Alignment::Center),
+    };
+    f.render_widget(detail.block(Block::default().borders(Borders::ALL).title("Reported content")), chunks[1]);
+
+    let help = "↑↓: Select | k: Keep | h: Hide | d: Delete | x: Dismiss | b/s/m: Ban/Suspend/Mute author | o: Held | t: Trash | g: Refresh | Esc: Back";
+    draw_help_line(f, app, help, rows[1]);
+
+    if app.focus == CurrentFocus::Sanction {
+        let author = app
+            .reports
+            .get(app.selected_report)
+            .and_then(|r| r.target_author.clone())
+            .unwrap_or_default();
+        let title = match app.pending_sanction {
+            Some("suspend") => format!("Suspend {} for {} days - reason", author, app::SUSPENSION_DAYS),
+            Some(action) => format!("{} {} - reason", action, author),
+            None => "Reason".to_string(),
+        };
+        let area = centered_rect(60, 20, f.size());
+        let input = Paragraph::new(app.sanction_reason.as_str())
+            .block(Block::default().borders(Borders::ALL).title(title))
+            .style(Style::default().fg(Color::Red))
+            .wrap(ratatui::widgets::Wrap { trim: false });
+        f.render_widget(Clear, area);
+        f.render_widget(input, area);
+    }
+}
+
+fn draw_trash_screen(f: &mut ratatui::Frame, app: &App) {
+    let rows = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Min(3), Constraint::Length(1)].as_ref())
+        .split(f.size());
+    let chunks = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([Constraint::Percentage(45), Constraint::Percentage(55)].as_ref())
+        .split(rows[0]);
+
+    let items: Vec<Row> = app
+        .deleted_items
+        .iter()
+        .map(|d| {
+            let label = if d.target_type == "thread" { d.thread_title.clone() } else { d.content.clone() };
+            Row::new(vec![
+                Cell::from(d.target_type.clone()).style(Style::default().fg(Color::Magenta)),
+                Cell::from(label),
+                Cell::from(relative_time(d.deleted_at)).style(Style::default().fg(Color::Gray)),
+            ])
+        })
+        .collect();
+    let widths = [Constraint::Length(8), Constraint::Min(10), Constraint::Length(8)];
+    let table = Table::new(items, widths)
+        .block(Block::default().borders(Borders::ALL).title(format!("Deleted ({})", app.deleted_items.len())))
+        .highlight_style(Style::default().add_modifier(Modifier::BOLD).fg(Color::Cyan))
+        .highlight_symbol("> ");
+    let mut state = TableState::default();
+    state.select(Some(app.selected_deleted));
+    f.render_stateful_widget(table, chunks[0], &mut state);
+
+    let detail = match app.deleted_items.get(app.selected_deleted) {
+        Some(d) => {
+            let label = Style::default().fg(Color::Gray);
+            let mut text = vec![
+                Line::from(vec![Span::styled("Thread:     ", label), Span::raw(d.thread_title.clone())]),
+                Line::from(vec![Span::styled("Author:     ", label), Span::styled(d.author.clone(), Style::default().fg(Color::Cyan))]),
+                Line::from(vec![Span::styled("Deleted by: ", label), Span::raw(d.deleted_by.clone().unwrap_or_default())]),
+                Line::from(vec![Span::styled("Deleted:    ", label), Span::raw(local_time(d.deleted_at))]),
+                Line::from(""),
+            ];
+            let width = chunks[1].width.saturating_sub(2) as usize;
+            text.extend(wrap_text(&d.content, width).into_iter().map(Line::from));
+            Paragraph::new(text)
+        }
+        None => Paragraph::new("Nothing has been deleted").alignment(ratatui::layout::Alignment::Center),
+    };
+    f.render_widget(detail.block(Block::default().borders(Borders::ALL).title("Deleted content")), chunks[1]);
+
+    draw_help_line(f, app, "↑↓: Select | u: Restore | g: Refresh | Esc: Back", rows[1]);
+}
+
+fn draw_held_screen(f: &mut ratatui::Frame, app: &App) {
+    let rows = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Min(3), Constraint::Length(1)].as_ref())
+        .split(f.size());
+    let chunks = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([Constraint::Percentage(45), Constraint::Percentage(55)].as_ref())
+        .split(rows[0]);
+
+    let items: Vec<Row> = app
+        .held_posts
+        .iter()
+        .map(|h| {
+            Row::new(vec![
+                Cell::from(h.target_type.clone()).style(Style::default().fg(Color::Magenta)),
+                Cell::from(h.author.clone()).style(Style::default().fg(Color::Cyan)),
+                Cell::from(relative_time(h.created_at)).style(Style::default().fg(Color::Gray)),
+            ])
+        })
+        .collect();
+    let widths = [Constraint::Length(8), Constraint::Min(10), Constraint::Length(8)];
+    let table = Table::new(items, widths)
+        .block(Block::default().borders(Borders::ALL).title(format!("Awaiting approval ({})", app.held_posts.len())))
+        .highlight_style(Style::default().add_modifier(Modifier::BOLD).fg(Color::Cyan))
+        .highlight_symbol("> ");
+    let mut state = TableState::default();
+    state.select(Some(app.selected_held));
+    f.render_stateful_widget(table, chunks[0], &mut state);
+
+    let detail = match app.held_posts.get(app.selected_held) {
+        Some(h) => {
+            let label = Style::default().fg(Color::Gray);
+            let mut text = vec![
+                Line::from(vec![Span::styled("Thread: ", label), Span::raw(h.thread_title.clone())]),
+                Line::from(vec![Span::styled("Posted: ", label), Span::raw(local_time(h.created_at))]),
+                Line::from(""),
+            ];
+            let width = chunks[1].width.saturating_sub(2) as usize;
+            text.extend(wrap_text(&h.content, width).into_iter().map(Line::from));
+            Paragraph::new(text)
+        }
+        None => Paragraph::new("No posts awaiting approval").alignment(ratatui::layout::Alignment::Center),
+    };
+    f.render_widget(detail.block(Block::default().borders(Borders::ALL).title("Held content")), chunks[1]);
+
+    draw_help_line(f, app, "↑↓: Select | a: Approve | x: Reject | g: Refresh | Esc: Back", rows[1]);
+}
+
+fn draw_audit_screen(f: &mut ratatui::Frame, app: &App) {
+    let rows = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Length(3), Constraint::Min(3), Constraint::Length(1)].as_ref())
+        .split(f.size());
+    let chunks = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([Constraint::Percentage(55), Constraint::Percentage(45)].as_ref())
+        .split(rows[1]);
+
+    let filter_style = if app.focus == CurrentFocus::AuditFilter {
+        Style::default().fg(Color::Yellow)
+    } else {
+        Style::default()
+    };
+    let filter = Paragraph::new(app.audit_filter.as_str())
+        .style(filter_style)
+        .block(Block::default().borders(Borders::ALL).title(format!("Filter ({})", app::AUDIT_FILTER_KEYS.join(", "))));
+    f.render_widget(filter, rows[0]);
+
+    let items: Vec<Row> = app
+        .audit_entries
+        .iter()
+        .map(|e| {
+            Row::new(vec![
+                Cell::from(local_time(e.created_at)).style(Style::default().fg(Color::Gray)),
+                Cell::from(e.actor.clone()).style(Style::default().fg(Color::Cyan)),
+                Cell::from(e.action.clone()).style(Style::default().fg(Color::Magenta)),
+                Cell::from(e.target_type.clone()),
+            ])
+        })
+        .collect();
+    let widths = [Constraint::Length(16), Constraint::Length(12), Constraint::Min(10), Constraint::Length(8)];
+    let table = Table::new(items, widths)
+        .header(Row::new(vec!["Time", "Actor", "Action", "Target"]).style(Style::default().add_modifier(Modifier::BOLD)))
+        .block(Block::default().borders(Borders::ALL).title(format!("Audit log ({})", app.audit_entries.len())))
+        .highlight_style(Style::default().add_modifier(Modifier::BOLD).fg(Color::Cyan))
+        .highlight_symbol("> ");
+    let mut state = TableState::default();
+    state.select(Some(app.selected_audit));
+    f.render_stateful_widget(table, chunks[0], &mut state);
+
+    let detail = match app.audit_entries.get(app.selected_audit) {
+        Some(e) => {
+            let label = Style::default().fg(Color::Gray);
+            let pretty = |value: &Option<serde_json::Value>| match value {
+                Some(v) => serde_json::to_string_pretty(v).unwrap_or_default(),
+                None => "-".to_string(),
+            };
+            let mut text = vec![
+                Line::from(vec![Span::styled("Target: ", label), Span::raw(format!("{} {}", e.target_type, e.target_id.clone().unwrap_or_default()))]),
+                Line::from(""),
+                Line::from(Span::styled("Before:", label)),
+            ];
+            text.extend(pretty(&e.before).lines().map(|l| Line::from(l.to_string())));
+            text.push(Line::from(""));
+            text.push(Line::from(Span::styled("After:", label)));
+            text.extend(pretty(&e.after).lines().map(|l| Line::from(l.to_string())));
+            Paragraph::new(text)
+        }
+        None => Paragraph::new("No matching entries").alignment(ratatui::layout::Alignment::Center),
+    };
+    f.render_widget(detail.block(Block::default().borders(Borders::ALL).title("Details")), chunks[1]);
+
+    let help = match app.focus {
+        CurrentFocus::AuditFilter => "Enter: Apply filter | Esc: Cancel",
+        _ => "↑↓: Select | /: Filter | g: Refresh | Esc: Back",
+    };
+    draw_help_line(f, app, help, rows[2]);
+}
+
+fn draw_notifications_screen(f: &mut ratatui::Frame, app: &App) {
+    let rows = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Min(3), Constraint::Length(1)].as_ref())
+        .split(f.size());
+    let chunks = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([Constraint::Percentage(55), Constraint::Percentage(45)].as_ref())
+        .split(rows[0]);
+
+    let items: Vec<Row> = app
+        .notifications
+        .iter()
+        .map(|n| {
+            let style = if n.read { Style::default() } else { Style::default().add_modifier(Modifier::BOLD) };
+            Row::new(vec![
+                Cell::from(if n.read { " " } else { "●" }).style(Style::default().fg(Color::Yellow)),
+                Cell::from(n.actor.clone()).style(Style::default().fg(Color::Cyan)),
+                Cell::from(n.kind.clone()).style(Style::default().fg(Color::Magenta)),
+                Cell::from(n.thread_title.clone()),
+                Cell::from(relative_time(n.created_at)).style(Style::default().fg(Color::Gray)),
+            ])
+            .style(style)
+        })
+        .collect();
+    let widths = [Constraint::Length(1), Constraint::Length(12), Constraint::Length(8), Constraint::Min(10), Constraint::Length(8)];
+    let table = Table::new(items, widths)
+        .block(Block::default().borders(Borders::ALL).title(format!("Notifications ({} unread)", app.unread_notifications)))
+        .highlight_style(Style::default().add_modifier(Modifier::BOLD).fg(Color::Cyan))
+        .highlight_symbol("> ");
+    let mut state = TableState::default();
+    state.select(Some(app.selected_notification));
+    f.render_stateful_widget(table, chunks[0], &mut state);
+
+    let detail = match app.notifications.get(app.selected_notification) {
+        Some(n) => {
+            let label = Style::default().fg(Color::Gray);
+            let mut text = vec![
+                Line::from(vec![Span::styled("Thread: ", label), Span::raw(n.thread_title.clone())]),
+                Line::from(vec![Span::styled("From: ", label), Span::raw(n.actor.clone())]),
+                Line::from(vec![Span::styled("Posted: ", label), Span::raw(local_time(n.created_at))]),
+                Line::from(""),
+            ];
+            let width = chunks[1].width.saturating_sub(2) as usize;
+            text.extend(wrap_text(&n.excerpt, width).into_iter().map(Line::from));
+            Paragraph::new(text)
+        }
+        None => Paragraph::new("No notifications").alignment(ratatui::layout::Alignment::Center),
+    };
+    f.render_widget(detail.block(Block::default().borders(Borders::ALL).title("Mention")), chunks[1]);
+
+    draw_help_line(f, app, "↑↓: Select | Enter: Open | r: Mark read | m: Mark all read | g: Refresh | Esc: Back", rows[1]);
+}
+
+fn draw_messages_screen(f: &mut ratatui::Frame, app: &App) {
+    let rows = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Min(3), Constraint::Length(1)].as_ref())
+        .split(f.size());
+    let chunks = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([Constraint::Percentage(35), Constraint::Percentage(65)].as_ref())
+        .split(rows[0]);
+
+    let items: Vec<Row> = app
+        .conversations
+        .iter()
+        .map(|c| {
+            let (style, unread) = if c.unread_count > 0 {
+                (Style::default().add_modifier(Modifier::BOLD), format!("({})", c.unread_count))
+            } else {
+                (Style::default(), String::new())
+            };
+            let mut names = c.participants.join(", ");
+            if c.participants.iter().any(|p| app.blocked_users.contains(p)) {
+                names.push_str(" ⊘");
+            }
+            Row::new(vec![
+                Cell::from(names),
+                Cell::from(unread).style(Style::default().fg(Color::Yellow)),
+                Cell::from(relative_time(c.last_message_at)).style(Style::default().fg(Color::Gray)),
+            ])
+            .style(style)
+        })
+        .collect();
+    let widths = [Constraint::Min(10), Constraint::Length(5), Constraint::Length(8)];
+    let table = Table::new(items, widths)
+        .block(Block::default().borders(Borders::ALL).title(format!("Conversations ({})", app.conversations.len())))
+        .highlight_style(Style::default().add_modifier(Modifier::BOLD).fg(Color::Cyan))
+        .highlight_symbol("> ");
+    let mut state = TableState::default();
+    state.select(Some(app.selected_conversation));
+    f.render_stateful_widget(table, chunks[0], &mut state);
+
+    // History is drawn bottom-up so the newest message is always visible
+    let title = match app.selected_conversation() {
+        Some(c) => format!("Messages with {}", c.participants.join(", ")),
+        None => "Messages".to_string(),
+    };
+    let block = Block::default().borders(Borders::ALL).title(title);
+    let inner = block.inner(chunks[1]);
+    f.render_widget(block, chunks[1]);
+    let me = app.current_user.as_ref().map(|u| u.username.as_str());
+    let mut lines: Vec<Line> = Vec::new();
+    if app.dm_has_more {
+        lines.push(Line::from(Span::styled("PgUp: load older messages", Style::default().fg(Color::DarkGray))));
+    }
+    for message in &app.dm_messages {
+        let sender_color = if Some(message.sender.as_str()) == me { Color::Green } else { Color::Cyan };
+        lines.push(Line::from(vec![
+            Span::styled(message.sender.clone(), Style::default().fg(sender_color).add_modifier(Modifier::BOLD)),
+            Span::styled(format!("  {}", local_time(message.created_at)), Style::default().fg(Color::Gray)),
+        ]));
+        lines.extend(wrap_text(&message.content, inner.width as usize).into_iter().map(Line::from));
+        lines.push(Line::from(""));
+    }
+    if app.conversations.is_empty() {
+        lines.push(Line::from("No conversations yet; press n to write to someone"));
+    }
+    let skip = lines.len().saturating_sub(inner.height as usize);
+    f.render_widget(Paragraph::new(lines.split_off(skip)), inner);
+
+    let help = match app.focus {
+        CurrentFocus::MessageRecipients => "Enter/Tab: Next | Esc: Cancel",
+        CurrentFocus::MessageInput => "Enter: Send | Esc: Cancel",
+        _ => "↑↓: Select | Enter/r: Write | n: New conversation | PgUp: Older | b: Block/unblock | g: Refresh | Esc: Back",
+    };
+    draw_help_line(f, app, help, rows[1]);
+
+    if matches!(app.focus, CurrentFocus::MessageRecipients | CurrentFocus::MessageInput) {
+        let area = centered_rect(60, 40, f.size());
+        f.render_widget(Clear, area);
+        let input_area = if app.dm_recipients.is_empty() && app.focus == CurrentFocus::MessageInput {
+            area
+        } else {
+            let parts = Layout::default()
+                .direction(Direction::Vertical)
+                .constraints([Constraint::Length(3), Constraint::Min(3)].as_ref())
+                .split(area);
+            let recipients_style = if app.focus == CurrentFocus::MessageRecipients {
+                Style::default().fg(Color::Yellow)
+            } else {
+                Style::default()
+            };
+            let recipients = Paragraph::new(app.dm_recipients.as_str())
+                .block(input_block("To (usernames, comma-separated)", app.field_error("participants")))
+                .style(recipients_style);
+            f.render_widget(recipients, parts[0]);
+            parts[1]
+        };
+        let title = match app.selected_conversation() {
+            Some(c) if app.dm_recipients.is_empty() => format!("Message to {}", c.participants.join(", ")),
+            _ => "Message".to_string(),
+        };
+        let input_style = if app.focus == CurrentFocus::MessageInput {
+            Style::default().fg(Color::Yellow)
+        } else {
+            Style::default()
+        };
+        let input = Paragraph::new(app.dm_input.as_str())
+            .block(input_block(&title, app.field_error("content")))
+            .style(input_style)
+            .wrap(ratatui::widgets::Wrap { trim: false });
+        f.render_widget(input, input_area);
+    }
+}
+
+fn draw_profile_screen(f: &mut ratatui::Frame, app: &App) {
+    let rows = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Min(3), Constraint::Length(1)].as_ref())
+        .split(f.size());
+    let chunks = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([Constraint::Percentage(45), Constraint::Percentage(55)].as_ref())
+        .split(rows[0]);
+    let Some(profile) = &app.profile else {
+        return;
+    };
+
+    let label = Style::default().fg(Color::Gray);
+    let width = chunks[0].width.saturating_sub(PROFILE_AVATAR_COLS + 4) as usize;
+    let mut name = vec![Span::styled(
+        profile.display_name.clone().unwrap_or_else(|| profile.username.clone()),
+        Style::default().fg(Color::Cyan).add_modifier(Modifier::BOLD),
+    )];
+    if profile.display_name.is_some() {
+        name.push(Span::styled(format!("  @{}", profile.username), label));
+    }
+    if profile.role != "user" {
+        name.push(Span::styled(format!("  [{}]", profile.role), Style::default().fg(Color::Magenta)));
+    }
+    let mut text = vec![
+        Line::from(name),
+        Line::from(vec![Span::styled("Joined: ", label), Span::raw(local_time(profile.created_at))]),
+    ];
+    if let Some(location) = &profile.location {
+        text.push(Line::from(vec![Span::styled("Location: ", label), Span::raw(location.clone())]));
+    }
+    text.push(Line::from(vec![
+        Span::styled("Threads: ", label),
+        Span::raw(profile.thread_count.to_string()),
+        Span::styled("  Comments: ", label),
+        Span::raw(profile.comment_count.to_string()),
+    ]));
+    if let Some(bio) = &profile.bio {
+        text.push(Line::from(""));
+        text.extend(wrap_text(bio, width).into_iter().map(Line::from));
+    }
+    if let Some(signature) = &profile.signature {
+        text.push(Line::from(""));
+        text.push(Line::from(Span::styled("Signature:", label)));
+        text.extend(wrap_text(signature, width).into_iter().map(|l| Line::from(Span::styled(l, Style::default().fg(Color::DarkGray)))));
+    }
+    let block = Block::default().borders(Borders::ALL).title("Profile");
+    let inner = block.inner(chunks[0]);
+    f.render_widget(block, chunks[0]);
+    let columns = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([Constraint::Length(PROFILE_AVATAR_COLS + 2), Constraint::Min(10)].as_ref())
+        .split(inner);
+    let hash = profile.avatar.as_deref();
+    let avatar = app.avatars.lines(&profile.username, hash, PROFILE_AVATAR_COLS, PROFILE_AVATAR_ROWS);
+    f.render_widget(Paragraph::new(avatar), columns[0]);
+    if app.profile_field.is_none() && inner.height >= PROFILE_AVATAR_ROWS {
+        app.avatars.place(hash, columns[0].x, columns[0].y, PROFILE_AVATAR_COLS, PROFILE_AVATAR_ROWS);
+    }
+    f.render_widget(Paragraph::new(text), columns[1]);
+
+    let items: Vec<Row> = profile
+        .recent_activity
+        .iter()
+        .map(|a| {
+            Row::new(vec![
+                Cell::from(a.kind.clone()).style(Style::default().fg(Color::Magenta)),
+                Cell::from(a.thread_title.clone()),
+                Cell::from(relative_time(a.created_at)).style(Style::default().fg(Color::Gray)),
+            ])
+        })
+        .collect();
+    let widths = [Constraint::Length(8), Constraint::Min(10), Constraint::Length(8)];
+    let table = Table::new(items, widths)
+        .block(Block::default().borders(Borders::ALL).title("Recent activity"))
+        .highlight_style(Style::default().add_modifier(Modifier::BOLD).fg(Color::Cyan))
+        .highlight_symbol("> ");
+    let mut state = TableState::default();
+    state.select(Some(app.selected_activity));
+    f.render_stateful_widget(table, chunks[1], &mut state);
+
+    let help = if app.profile_field.is_some() {
+        "Enter: Save | Esc: Cancel"
+    } else if app.is_own_profile() {
+        "↑↓: Select | Enter: Open | d/b/s/l: Edit display name/bio/signature/location | a/x: Upload/remove avatar | g: Refresh | Esc: Back"
+    } else {
+        "↑↓: Select | Enter: Open | g: Refresh | Esc: Back"
+    };
+    draw_help_line(f, app, help, rows[1]);
+
+    if let Some(field) = app.profile_field {
+        let title = match field {
+            "display_name" => "Display name",
+            "bio" => "Bio",
+            "signature" => "Signature",
+            "avatar" => "Avatar image path (PNG, JPEG, GIF or WebP)",
+            _ => "Location",
+        };
+        let area = centered_rect(60, 30, f.size());
+        let input = Paragraph::new(app.profile_input.as_str())
+            .block(input_block(title, app.field_error(field)))
+            .style(Style::default().fg(Color::Yellow))
+            .wrap(ratatui::widgets::Wrap { trim: false });
+        f.render_widget(Clear, area);
+        f.render_widget(input, area);
+    }
+}
+
+fn draw_settings_screen(f: &mut ratatui::Frame, app: &App) {
+    let rows = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Min(3), Constraint::Length(1)].as_ref())
+        .split(f.size());
+
+    let items: Vec<Row> = app
+        .settings_actions()
+        .iter()
+        .map(|action| {
+            let label_style = if *action == SettingsAction::DeleteAccount {
+                Style::default().fg(Color::Red)
+            } else {
+                Style::default()
+            };
+            let description = match (action, &app.two_factor) {
+                (SettingsAction::DisableTwoFactor, Some(status)) => {
+                    format!("{} ({} recovery codes left)", action.description(), status.recovery_codes_left)
+                }
+                _ => action.description().to_string(),
+            };
+            Row::new(vec![
+                Cell::from(action.label()).style(label_style),
+                Cell::from(description).style(Style::default().fg(Color::Gray)),
+            ])
+        })
+        .collect();
+    let title = match &app.current_user {
+        Some(user) => format!("Settings - {}", user.username),
+        None => "Settings".to_string(),
+    };
+    let table = Table::new(items, [Constraint::Length(20), Constraint::Min(10)])
+        .block(Block::default().borders(Borders::ALL).title(title))
+        .highlight_style(Style::default().add_modifier(Modifier::BOLD).fg(Color::Cyan))
+        .highlight_symbol("> ");
+    let mut state = TableState::default();
+    state.select(Some(app.selected_setting));
+    f.render_stateful_widget(table, rows[0], &mut state);
+
+    let help = match app.settings_action {
+        _ if !app.recovery_codes.is_empty() => "Enter/Esc: Done, I have saved them",
+        Some(SettingsAction::DeleteAccount) => "←→: Anonymize/delete posts | Enter: Delete account | Esc: Cancel",
+        Some(_) => "Tab/↑↓: Next field | Enter: Submit | Esc: Cancel",
+        None => "↑↓: Select | Enter: Open | Esc: Back",
+    };
+    draw_help_line(f, app, help, rows[1]);
+
+    if !app.recovery_codes.is_empty() {
+        draw_recovery_codes(f, &app.recovery_codes);
+        return;
+    }
+    let Some(action) = app.settings_action else {
+        return;
+    };
+    let fields = action.fields();
+    // Enrollment shows the QR code and the secret above the code input
+    let enrollment: Vec<Line> = match (action, &app.two_factor_setup) {
+        (SettingsAction::EnableTwoFactor, Some(setup)) => {
+            let mut lines = qr::lines(&setup.otpauth_url).unwrap_or_default();
+            lines.push(Line::from(""));
+            lines.push(Line::from("Scan with your authenticator app, or enter this key:"));
+            lines.push(Line::from(Span::styled(group_secret(&setup.secret), Style::default().fg(Color::Cyan))));
+            lines
+        }
+        _ => Vec::new(),
+    };
+    let extra = match action {
+        SettingsAction::DeleteAccount => 3,
+        _ => enrollment.len() as u16,
+    };
+    let height = fields.len() as u16 * 3 + extra + 2;
+    let outer = centered_rect(60, 100, f.size());
+    let area = Rect { y: outer.y + outer.height.saturating_sub(height) / 2, height: height.min(outer.height), ..outer };
+    f.render_widget(Clear, area);
+    let block = Block::default().borders(Borders::ALL).title(action.label());
+    let inner = block.inner(area);
+    f.render_widget(block, area);
+
+    let mut constraints = vec![Constraint::Length(enrollment.len() as u16)];
+    constraints.extend(vec![Constraint::Length(3); fields.len()]);
+    constraints.push(Constraint::Min(0));
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints(constraints)
+        .split(inner);
+    f.render_widget(Paragraph::new(enrollment).alignment(ratatui::layout::Alignment::Center), chunks[0]);
+    for (i, (title, name)) in fields.iter().enumerate() {
+        let value = &app.settings_inputs[i];
+        let shown = if name.ends_with("password") { "*".repeat(value.chars().count()) } else { value.clone() };
+        let style = if i == app.settings_field { Style::default().fg(Color::Yellow) } else { Style::default().fg(Color::Gray) };
+        let input = Paragraph::new(shown).block(input_block(title, app.field_error(name))).style(style);
+        f.render_widget(input, chunks[i + 1]);
+    }
+    if action == SettingsAction::DeleteAccount {
+        let choice = |selected: bool, label: &'static str| {
+            let style = if selected { Style::default().fg(Color::Yellow).add_modifier(Modifier::BOLD) } else { Style::default().fg(Color::Gray) };
+            Span::styled(format!("({}) {}", if selected { "•" } else { " " }, label), style)
+        };
+        let text = vec![
+            Line::from(vec![
+                Span::raw("Posts: "),
+                choice(!app.delete_posts, "Keep, anonymized"),
+                Span::raw("  "),
+                choice(app.delete_posts, "Delete, with replies to my threads"),
+            ]),
+            Line::from(""),
+            Line::from(Span::styled("This cannot be undone.", Style::default().fg(Color::Red))),
+        ];
+        f.render_widget(Paragraph::new(text), chunks[fields.len() + 1]);
+    }
+}
+
+/// A TOTP secret in groups of four, easier to type into an authenticator app.
+fn group_secret(secret: &str) -> String {
+    let chars: Vec<char> = secret.chars().collect();
+    chars.chunks(4).map(|group| group.iter().collect::<String>()).collect::<Vec<_>>().join(" ")
+}
+
+fn draw_recovery_codes(f: &mut ratatui::Frame, codes: &[String]) {
+    let mut text = vec![
+        Line::from("Each of these logs you in once in place of a code, should you lose"),
+        Line::from("your authenticator. Keep them somewhere safe: they are not shown again."),
+        Line::from(""),
+    ];
+    text.extend(codes.iter().map(|code| Line::from(Span::styled(code.clone(), Style::default().fg(Color::Cyan)))));
+    let height = text.len() as u16 + 2;
+    let outer = centered_rect(60, 100, f.size());
+    let area = Rect { y: outer.y + outer.height.saturating_sub(height) / 2, height: height.min(outer.height), ..outer };
+    f.render_widget(Clear, area);
+    let paragraph = Paragraph::new(text)
+        .alignment(ratatui::layout::Alignment::Center)
+        .block(Block::default().borders(Borders::ALL).title("Recovery codes"));
+    f.render_widget(paragraph, area);
+}
+
+fn draw_ssh_keys_screen(f: &mut ratatui::Frame, app: &App) {
+    let rows = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Min(3), Constraint::Length(1)].as_ref())
+        .split(f.size());
+
+    let items: Vec<Row> = app
+        .ssh_keys
+        .iter()
+        .map(|key| {
+            Row::new(vec![
+                Cell::from(key.name.clone()),
+                Cell::from(key.algorithm.clone()),
+                Cell::from(key.fingerprint.clone()).style(Style::default().fg(Color::Gray)),
+                Cell::from(relative_time(key.created_at)),
+                Cell::from(key.last_used_at.map(relative_time).unwrap_or_else(|| "never".to_string())),
+            ])
+        })
+        .collect();
+    let widths = [Constraint::Length(24), Constraint::Length(12), Constraint::Min(20), Constraint::Length(10), Constraint::Length(10)];
+    let table = Table::new(items, widths)
+        .header(Row::new(vec!["Name", "Type", "Fingerprint", "Added", "Last used"]).style(Style::default().add_modifier(Modifier::BOLD)))
+        .block(Block::default().borders(Borders::ALL).title(format!("SSH keys ({})", app.ssh_keys.len())))
+        .highlight_style(Style::default().add_modifier(Modifier::BOLD).fg(Color::Cyan))
+        .highlight_symbol("> ");
+    let mut state = TableState::default();
+    state.select(Some(app.selected_ssh_key));
+    f.render_stateful_widget(table, rows[0], &mut state);
+    if app.ssh_keys.is_empty() {
+        let inner = Block::default().borders(Borders::ALL).inner(rows[0]);
+        let hint = Paragraph::new("No keys yet. Press a to add your ~/.ssh/id_ed25519.pub, then log in with an empty password.")
+            .style(Style::default().fg(Color::Gray))
+            .alignment(ratatui::layout::Alignment::Center);
+        f.render_widget(hint, Rect { y: inner.y + 2, height: 1, ..inner });
+    }
+
+    let help = match app.ssh_key_input {
+        Some(_) => "Enter: Add | Esc: Cancel",
+        None => "↑↓: Select | a: Add key | d: Remove key | Esc: Back",
+    };
+    draw_help_line(f, app, help, rows[1]);
+
+    if let Some(input) = &app.ssh_key_input {
+        let outer = centered_rect(60, 100, f.size());
+        let area = Rect { y: outer.y + outer.height.saturating_sub(3) / 2, height: 3, ..outer };
+        f.render_widget(Clear, area);
+        let input = Paragraph::new(input.as_str())
+            .block(input_block(if app.remote { "Public key" } else { "Public key file, or the key itself" }, app.field_error("public_key")))
+            .style(Style::default().fg(Color::Yellow));
+        f.render_widget(input, area);
+    }
+}
+
+fn draw_categories_screen(f: &mut ratatui::Frame, app: &App) {
+    let rows = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Min(3), Constraint::Length(1)].as_ref())
+        .split(f.size());
+
+    let items: Vec<Row> = app
+        .categories
+        .iter()
+        .map(|c| {
+            let name = match c.parent_id {
+                Some(_) => Line::from(vec![Span::styled("  └ ", Style::default().fg(Color::DarkGray)), Span::raw(c.name.clone())]),
+                None => Line::from(Span::styled(c.name.clone(), Style::default().fg(Color::Magenta))),
+            };
+            Row::new(vec![
+                Cell::from(name),
+                Cell::from(c.thread_count.to_string()),
+                Cell::from(c.description.clone().unwrap_or_default()).style(Style::default().fg(Color::Gray)),
+            ])
+        })
+        .collect();
+    let widths = [Constraint::Length(28), Constraint::Length(8), Constraint::Min(10)];
+    let table = Table::new(items, widths)
+        .header(Row::new(vec!["Name", "Threads", "Description"]).style(Style::default().add_modifier(Modifier::BOLD)))
+        .block(Block::default().borders(Borders::ALL).title(format!("Categories ({})", app.categories.len())))
+        .highlight_style(Style::default().add_modifier(Modifier::BOLD).fg(Color::Cyan))
+        .highlight_symbol("> ");
+    let mut state = TableState::default();
+    state.select(Some(app.selected_category_row));
+    f.render_stateful_widget(table, rows[0], &mut state);
+
+    let help = match app.focus {
+        CurrentFocus::CategoryInput => "Enter: Save | Esc: Cancel",
+        CurrentFocus::CategoryChoice => "↑↓: Select | Enter: Confirm | Esc: Cancel",
+        _ => "↑↓: Select | n/N: New category/subcategory | r: Rename | e: Describe | p: Parent | K/J: Move up/down | d: Delete | g: Refresh | Esc: Back",
+    };
+    draw_help_line(f, app, help, rows[1]);
+
+    let selected_name = app.categories.get(app.selected_category_row).map(|c| c.name.clone()).unwrap_or_default();
+    if app.focus == CurrentFocus::CategoryInput {
+        let (title, field) = match &app.category_action {
+            Some(CategoryAction::Create(Some(parent_id))) => {
+                let parent = app.categories.iter().find(|c| c.id == *parent_id).map(|c| c.name.as_str()).unwrap_or("?");
+                (format!("New subcategory of {}", parent), "name")
+            }
+            Some(CategoryAction::Describe) => (format!("Description of {}", selected_name), "description"),
+            Some(CategoryAction::Rename) => (format!("Rename {}", selected_name), "name"),
+            _ => ("New category".to_string(), "name"),
+        };
+        let area = centered_rect(60, 20, f.size());
+        let input = Paragraph::new(app.category_input.as_str())
+            .block(input_block(&title, app.field_error(field)))
+            .style(Style::default().fg(Color::Yellow))
+            .wrap(ratatui::widgets::Wrap { trim: false });
+        f.render_widget(Clear, area);
+        f.render_widget(input, area);
+    }
+
+    if app.focus == CurrentFocus::CategoryChoice {
+        let title = match app.category_action {
+            Some(CategoryAction::Delete) => format!("Delete {}", selected_name),
+            _ => format!("Parent of {}", selected_name),
+        };
+        let items: Vec<Row> = app
+            .category_choices
+            .iter()
+            .map(|(_, label)| Row::new(vec![Cell::from(label.clone())]))
+            .collect();
+        let table = Table::new(items, [Constraint::Min(10)])
+            .block(Block::default().borders(Borders::ALL).title(title))
+            .highlight_style(Style::default().add_modifier(Modifier::BOLD).fg(Color::Cyan))
+            .highlight_symbol("> ");
+        let mut state = TableState::default();
+        state.select(Some(app.selected_choice));
+        let area = centered_rect(50, 50, f.size());
+        f.render_widget(Clear, area);
+        f.render_stateful_widget(table, area, &mut state);
+    }
+}
+
+fn centered_rect(percent_x: u16, percent_y: u16, r: Rect) -> Rect {
+    let popup_layout = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([
+            Constraint::Percentage((100 - percent_y) / 2),
+            Constraint::Percentage(percent_y),
+            Constraint::Percentage((100 - percent_y) / 2),
+        ].as_ref())
+        .split(r);
+
+    Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([
+            Constraint::Percentage((100 - percent_x) / 2),
+            Constraint::Percentage(percent_x),
+            Constraint::Percentage((100 - percent_x) / 2),
+        ].as_ref())
+        .split(popup_layout[1])[1]
+}
+
+fn draw_thread_list(f: &mut ratatui::Frame, app: &App, area: Rect) {
+    let rows: Vec<Row> = app
+        .threads
+        .iter()
+        .map(|t| {
+            let mut title = thread_markers(t);
+            let mut title_span = Span::raw(t.title.clone());
+            if t.has_unread() {
+                title_span.style = Style::default().add_modifier(Modifier::BOLD);
+            }
+            if t.pinned {
+                title_span.style = title_span.style.fg(Color::Yellow);
+            }
+            title.push(title_span);
+            if t.has_unread() && t.unread_count > 0 {
+                title.push(Span::styled(format!(" ({})", t.unread_count), Style::default().fg(Color::Green)));
+            }
+            title.extend(tag_chips(&t.tags));
+            Row::new(vec![
+                Cell::from(Line::from(title)),
+                Cell::from(t.category_name.clone().unwrap_or_default()).style(Style::default().fg(Color::Magenta)),
+                Cell::from(t.author.clone()).style(Style::default().fg(Color::Cyan)),
+                Cell::from(t.comment_count.to_string()),
+                Cell::from(relative_time(t.last_activity_at)).style(Style::default().fg(Color::Gray)),
+            ])
+        })
+        .collect();
+
+    let border_style = if app.focus == CurrentFocus::ThreadList {
+        Style::default().fg(Color::Yellow)
+    } else {
+        Style::default().fg(Color::White)
+    };
+
+    let widths = [
+        Constraint::Min(12),
+        Constraint::Length(10),
+        Constraint::Length(12),
+        Constraint::Length(4),
+        Constraint::Length(8),
+    ];
+    let header = Row::new(vec!["Title", "Category", "Author", "#", "Active"])
+        .style(Style::default().fg(Color::Gray).add_modifier(Modifier::UNDERLINED));
+    let mut title = if app.show_archived {
+        format!("Archived threads (sort: {})", app.sort_mode.label())
+    } else {
+        format!("Threads (sort: {})", app.sort_mode.label())
+    };
+    if !app.tag_filter.is_empty() {
+        title.push_str(&format!(" tagged {}", app.tag_filter.join(" + ")));
+    }
+    let table = Table::new(rows, widths)
+        .header(header)
+        .block(Block::default().borders(Borders::ALL).title(title).border_style(border_style))
+        .highlight_style(Style::default().add_modifier(Modifier::BOLD).fg(Color::Cyan))
+        .highlight_symbol("> ");
+
+    let mut state = TableState::default();
+    state.select(Some(app.selected_thread));
+    f.render_stateful_widget(table, area, &mut state);
+}
+
+/// Pinned/locked/archived markers shown before a thread title.
+fn thread_markers(thread: &api::Thread) -> Vec<Span<'static>> {
+    let mut markers = Vec::new();
+    if thread.pinned {
+        markers.push(Span::styled("📌", Style::default().fg(Color::Yellow)));
+    }
+    if thread.locked {
+        markers.push(Span::styled("🔒", Style::default().fg(Color::Red)));
+    }
+    if thread.archived {
+        markers.push(Span::styled("📦", Style::default().fg(Color::Gray)));
+    }
+    if thread.held {
+        markers.push(Span::styled("⏳", Style::default().fg(Color::Yellow)));
+    }
+    if !markers.is_empty() {
+        markers.push(Span::raw(" "));
+    }
+    markers
+}
+
+fn draw_conversation(f: &mut ratatui::Frame, app: &App, area: Rect) {
+    let border_style = if app.focus == CurrentFocus::Conversation {
+        Style::default().fg(Color::Yellow)
+    } else {
+        Style::default().fg(Color::White)
+    };
+    let block = Block::default().borders(Borders::ALL).title("Conversation").border_style(border_style);
+    f.render_widget(block, area);
+
+    let inner_area = area.inner(&ratatui::layout::Margin { vertical: 1, horizontal: 1 });
+    
+    let Some(thread) = app.get_current_thread() else {
+        let p = Paragraph::new("Select a thread to view").alignment(ratatui::layout::Alignment::Center);
+        f.render_widget(p, inner_area);
+        return;
+    };
+
+    let mut title_line = thread_markers(thread);
+    title_line.push(Span::styled(format!("Title: {}", thread.title), Style::default().add_modifier(Modifier::BOLD)));
+    if thread.subscribed {
+        title_line.push(Span::styled("  🔔 subscribed", Style::default().fg(Color::Gray)));
+    }
+    let mut text = vec![Line::from(title_line)];
+    if !thread.tags.is_empty() {
+        let mut chips = tag_chips(&thread.tags);
+        chips.remove(0);
+        text.push(Line::from(chips));
+    }
+    if thread.is_read_only() {
+        let state = if thread.archived { "archived" } else { "locked" };
+        text.push(Line::from(Span::styled(format!("This thread is {}; replies are closed.", state), Style::default().fg(Color::Red))));
+    }
+    text.push(Line::from(Span::raw("")));
+
+    // Posts are wrapped by hand so we know which lines belong to the selected one.
+    // The author's avatar sits in a column to the left of each post
+    let avatar_indent = Span::raw(" ".repeat(avatar::COLS as usize + 1));
+    let body_width = inner_area.width.saturating_sub(avatar::COLS + 3) as usize;
+    let mut selected_span = (0, 0);
+    // First line of each post with its avatar, to place images once we know the scroll
+    let mut avatar_lines = Vec::new();
+    for (i, post) in app.posts().into_iter().enumerate() {
+        let selected = i == app.selected_comment && app.focus == CurrentFocus::Conversation;
+        let gutter = if selected {
+            Span::styled("▌ ", Style::default().fg(Color::Yellow))
+        } else {
+            Span::raw("  ")
+        };
+        let header = match post {
+            Post::Thread(_) => format!("{} (original post)", post.author()),
+            Post::Comment(c) if c.held => format!("{} (awaiting approval):", post.author()),
+            Post::Comment(_) => format!("{}:", post.author()),
+        };
+        let mut header_style = Style::default().fg(Color::Cyan);
+        if selected {
+            header_style = header_style.add_modifier(Modifier::BOLD | Modifier::REVERSED);
+        }
+
+        let mut lines = vec![vec![
+            Span::styled(header, header_style),
+            Span::styled(format!("  {}", local_time(post.created_at())), Style::default().fg(Color::DarkGray)),
+        ]];
+        let body_style = if post.is_deleted() {
+            Style::default().fg(Color::DarkGray).add_modifier(Modifier::ITALIC)
+        } else {
+            Style::default()
+        };
+        for line in wrap_text(post.content(), body_width) {
+            lines.push(vec![Span::styled(line, body_style)]);
+        }
+        if let Some(signature) = post.signature() {
+            let signature_style = Style::default().fg(Color::DarkGray);
+            lines.push(vec![Span::styled("--", signature_style)]);
+            for line in wrap_text(signature, body_width) {
+                lines.push(vec![Span::styled(line, signature_style)]);
+            }
+        }
+        if !post.reactions().is_empty() {
+            let mut counts = Vec::new();
+            for reaction in post.reactions() {
+                // The user's own reactions stand out so they know what a toggle will undo
+                let style = if reaction.mine {
+                    Style::default().fg(Color::Yellow).add_modifier(Modifier::BOLD)
+                } else {
+                    Style::default().fg(Color::Gray)
+                };
+                counts.push(Span::styled(format!("{} {}", reaction.emoji, reaction.count), style));
+                counts.push(Span::raw("  "));
+            }
+            lines.push(counts);
+        }
+
+        let start = text.len();
+        let avatar = if post.is_deleted() {
+            Vec::new()
+        } else {
+            avatar_lines.push((start, post.avatar()));
+            // Short posts are padded so the whole avatar shows
+            lines.resize_with(lines.len().max(avatar::ROWS as usize), Vec::new);
+            app.avatars.lines(post.author(), post.avatar(), avatar::COLS, avatar::ROWS)
+        };
+        for (row, spans) in lines.into_iter().enumerate() {
+            let mut line = vec![gutter.clone()];
+            match avatar.get(row) {
+                Some(cells) => {
+                    line.extend(cells.spans.iter().cloned());
+                    line.push(Span::raw(" "));
+                }
+                None => line.push(avatar_indent.clone()),
+            }
+            line.extend(spans);
+            text.push(Line::from(line));
+        }
+        if i == app.selected_comment {
+            selected_span = (start, text.len());
+        }
+        text.push(Line::from(Span::raw("")));
+
+        if let Post::Thread(_) = post {
+            text.push(Line::from(Span::styled("--- Comments ---", Style::default().fg(Color::Gray))));
+        }
+    }
+
+    let scroll = scroll_to_show(selected_span, inner_area.height as usize);
+    let paragraph = Paragraph::new(text).scroll((scroll as u16, 0));
+    f.render_widget(paragraph, inner_area);
+
+    // Images would cover any popup drawn over the conversation, so they are
+    // only placed while no popup is open
+    if matches!(app.focus, CurrentFocus::ThreadList | CurrentFocus::Conversation) {
+        let height = inner_area.height as usize;
+        for (line, hash) in avatar_lines {
+            if line >= scroll && line + avatar::ROWS as usize <= scroll + height {
+                let y = inner_area.y + (line - scroll) as u16;
+                app.avatars.place(hash, inner_area.x + 2, y, avatar::COLS, avatar::ROWS);
+            }
+        }
+    }
+}
+
+/// Smallest scroll offset that keeps the `(start, end)` line range on screen,
+/// preferring the top of the range when it is taller than the viewport.
+fn scroll_to_show((start, end): (usize, usize), height: usize) -> usize {
+    if end <= height {
+        0
+    } else {
+        (end - height).min(start)
+    }
+}
+
+/// Compact "time ago" label, e.g. "5m ago".
+fn relative_time(then: DateTime<Utc>) -> String {
+    let secs = (Utc::now() - then).num_seconds().max(0);
+    match secs {
+        0..=59 => "now".to_string(),
+        60..=3599 => format!("{}m ago", secs / 60),
+        3600..=86_399 => format!("{}h ago", secs / 3600),
+        86_400..=2_591_999 => format!("{}d ago", secs / 86_400),
+        2_592_000..=31_535_999 => format!("{}mo ago", secs / 2_592_000),
+        _ => format!("{}y ago", secs / 31_536_000),
+    }
+}
+
+/// Timestamp in the user's local timezone, e.g. "2024-05-01 14:03".
+fn local_time(timestamp: DateTime<Utc>) -> String {
+    timestamp.with_timezone(&Local).format("%Y-%m-%d %H:%M").to_string()
+}
+
+/// Word-wrap `text` to `width` columns, keeping explicit line breaks.
+fn wrap_text(text: &str, width: usize) -> Vec<String> {
+    let width = width.max(1);
+    let mut lines = Vec::new();
+    for raw_line in text.lines() {
+        let mut current = String::new();
+        for word in raw_line.split_whitespace() {
+            let mut word = word.to_string();
+            // Hard-split words longer than the whole line
+            while word.chars().count() > width {
+                if !current.is_empty() {
+                    lines.push(std::mem::take(&mut current));
+                }
+                let split_at = word.char_indices().nth(width).map(|(i, _)| i).unwrap_or(word.len());
+                lines.push(word[..split_at].to_string());
+                word = word[split_at..].to_string();
+            }
+            let needed = if current.is_empty() { word.chars().count() } else { current.chars().count() + 1 + word.chars().count() };
+            if needed > width && !current.is_empty() {
+                lines.push(std::mem::take(&mut current));
+            }
+            if !current.is_empty() {
+                current.push(' ');
+            }
+            current.push_str(&word);
+        }
+        lines.push(current);
+    }
+    lines
+}
+
+async fn handle_login_keys(key: KeyEvent, app: &mut App) -> anyhow::Result<()> {
+    match key.code {
+        KeyCode::Enter if !app.username_input.is_empty() && !app.password_input.is_empty() => {
+            if let Err(e) = app.login().await {
+                app.set_field_errors(&e);
+                app.status_message = Some(e.to_string());
+            }
+        }
+        // No password: log in with an SSH key instead
+        KeyCode::Enter if !app.username_input.is_empty() => {
+            app.status_message = None;
+            if let Err(e) = app.key_login().await {
+                app.set_field_errors(&e);
+                app.status_message = Some(format!("Key login failed: {}", e));
+            }
+        }
+        KeyCode::Tab => {
+            app.focus = match app.focus {
+                CurrentFocus::Username => CurrentFocus::Password,
+                CurrentFocus::Password if app.two_factor_prompt => CurrentFocus::TwoFactorCode,
+                CurrentFocus::Password => CurrentFocus::Username,
+                _ => CurrentFocus::Username,
+            };
+        }
+        KeyCode::Char(c) => {
+            match app.focus {
+                CurrentFocus::Username => app.username_input.push(c),
+                CurrentFocus::Password => app.password_input.push(c),
+                CurrentFocus::TwoFactorCode => app.two_factor_input.push(c),
+                _ => {}
+            }
+        }
+        KeyCode::Backspace => {
+            match app.focus {
+                CurrentFocus::Username => {
+                    app.username_input.pop();
+                }
+                CurrentFocus::Password => {
+                    app.password_input.pop();
+                }
+                CurrentFocus::TwoFactorCode => {
+                    app.two_factor_input.pop();
+                }
+                _ => {}
+            }
+        }
+        KeyCode::Esc => app.should_quit = true,
+        _ => {}
+    }
+    Ok(())
+}
+
+async fn handle_forum_keys(key: KeyEvent, app: &mut App) -> anyhow::Result<()> {
+    app.status_message = None;
+    if app.focus == CurrentFocus::ReactionPicker {
+        let react = match key.code {
+            KeyCode::Left => {
+                app.selected_reaction = app.selected_reaction.saturating_sub(1);
+                false
+            }
+            KeyCode::Right => {
+                app.selected_reaction = (app.selected_reaction + 1).min(app::REACTION_EMOJI.len() - 1);
+                false
+            }
+            KeyCode::Char(c) if ('1'..='8').contains(&c) => {
+                app.selected_reaction = c as usize - '1' as usize;
+                true
+            }
+            KeyCode::Enter => true,
+            KeyCode::Esc => {
+                app.focus = CurrentFocus::Conversation;
+                false
+            }
+            _ => false,
+        };
+        if react {
+            if let Err(e) = app.react_to_selected().await {
+                app.status_message = Some(format!("Reaction failed: {}", e));
+            }
+        }
+        return Ok(());
+    }
+    match key.code {
+        KeyCode::Up => match app.focus {
+            CurrentFocus::ThreadList => {
+                app.selected_thread = app.selected_thread.saturating_sub(1);
+            }
+            CurrentFocus::Conversation => app.move_post_selection(-1),
+            _ => {}
+        },
+        KeyCode::Down => match app.focus {
+            CurrentFocus::ThreadList if app.selected_thread < app.threads.len().saturating_sub(1) => {
+                app.selected_thread += 1;
+            }
+            CurrentFocus::Conversation => app.move_post_selection(1),
+            _ => {}
+        },
+        KeyCode::PageUp if app.focus == CurrentFocus::Conversation => app.move_post_selection(-CONVERSATION_PAGE),
+        KeyCode::PageDown if app.focus == CurrentFocus::Conversation => app.move_post_selection(CONVERSATION_PAGE),
+        KeyCode::Home if app.focus == CurrentFocus::Conversation => app.selected_comment = 0,
+        KeyCode::End if app.focus == CurrentFocus::Conversation => app.select_last_post(),
+        KeyCode::Left => app.focus = CurrentFocus::ThreadList,
+        KeyCode::Right if app.get_current_thread().is_some() => {
+            app.focus = CurrentFocus::Conversation;
+        }
+        KeyCode::Enter => {
+            match app.focus {
+                CurrentFocus::ThreadList => {
+                    app.open_thread(app.selected_thread).await?;
+                }
+                CurrentFocus::NewThread => {
+                    match app.create_thread(app.new_thread_title.clone(), app.new_thread_content.clone()).await {
+                        Ok(()) => {
+                            app.load_threads().await?;
+                            app.focus = CurrentFocus::ThreadList;
+                        }
+                        // Keep the draft so it can be fixed or posted once the cooldown is over
+                        Err(e) => {
+                            app.set_field_errors(&e);
+                            app.status_message = Some(format!("Could not create thread: {}", e));
+                        }
+                    }
+                }
+                CurrentFocus::Reply => match app.create_reply(app.reply_content.clone()).await {
+                    Ok(()) => app.focus = CurrentFocus::Conversation,
+                    // Stay in the editor so the reply can be fixed
+                    Err(e) => {
+                        app.set_field_errors(&e);
+                        app.status_message = Some(format!("Reply failed: {}", e));
+                    }
+                },
+                CurrentFocus::TagFilter => {
+                    if let Err(e) = app.apply_tag_filter().await {
+                        app.status_message = Some(format!("Could not load threads: {}", e));
+                    }
+                }
+                CurrentFocus::Report => {
+                    if let Err(e) = app.submit_report().await {
+                        app.status_message = Some(format!("Report failed: {}", e));
+                    }
+                    app.focus = CurrentFocus::Conversation;
+                }
+                _ => {}
+            }
+        }
+        // In a tags input, Tab completes the tag being typed before it switches fields
+        KeyCode::Tab if app.complete_tag() => {}
+        KeyCode::Tab if app.focus == CurrentFocus::NewThread => {
+            app.new_thread_focus = match app.new_thread_focus {
+                CurrentFocus::Username => CurrentFocus::NewThreadTags, // Title -> Tags
+                CurrentFocus::NewThreadTags => CurrentFocus::ThreadList, // Tags -> Content
+                CurrentFocus::ThreadList => CurrentFocus::Username, // Content -> Title
+                _ => CurrentFocus::Username,
+            };
+            app.tag_suggestions.clear();
+        }
+        KeyCode::Esc => {
+            if app.focus == CurrentFocus::NewThread || app.focus == CurrentFocus::TagFilter {
+                app.focus = CurrentFocus::ThreadList;
+            } else if app.focus == CurrentFocus::Reply || app.focus == CurrentFocus::Report {
+                app.focus = CurrentFocus::Conversation;
+            }
+        }
+        KeyCode::Char(c) => {
+            if app.focus == CurrentFocus::NewThread {
+                match app.new_thread_focus {
+                    CurrentFocus::Username => app.new_thread_title.push(c),
+                    CurrentFocus::NewThreadTags => {
+                        app.new_thread_tags.push(c);
+                        app.update_tag_suggestions().await;
+                    }
+                    CurrentFocus::ThreadList => app.new_thread_content.push(c),
+                    _ => {}
+                }
+            } else if app.focus == CurrentFocus::TagFilter {
+                app.tag_filter_input.push(c);
+                app.update_tag_suggestions().await;
+            } else if app.focus == CurrentFocus::Reply {
+                app.reply_content.push(c);
+            } else if app.focus == CurrentFocus::Report {
+                app.report_reason.push(c);
+            } else if app.focus == CurrentFocus::Username || app.focus == CurrentFocus::Password {
+                // Login input handled in handle_login_keys
+            } else {
+                // Only handle hotkeys when not in input mode
+                match c {
+                    'n' => {
+                        app.focus = CurrentFocus::NewThread;
+                        app.new_thread_title.clear();
+                        app.new_thread_content.clear();
+                        app.new_thread_tags.clear();
+                        app.tag_suggestions.clear();
+                        app.field_errors.clear();
+                        app.new_thread_focus = CurrentFocus::Username;
+                    }
+                    's' if app.focus == CurrentFocus::ThreadList => app.cycle_sort_mode().await?,
+                    'a' if app.focus == CurrentFocus::ThreadList => app.toggle_archived_view().await?,
+                    't' if app.focus == CurrentFocus::ThreadList => app.start_tag_filter(),
+                    'P' | 'L' | 'A' if app.is_moderator() => {
+                        let flag = match c {
+                            'P' => ThreadFlag::Pinned,
+                            'L' => ThreadFlag::Locked,
+                            _ => ThreadFlag::Archived,
+                        };
+                        if let Err(e) = app.toggle_thread_flag(flag).await {
+                            app.status_message = Some(format!("Moderation failed: {}", e));
+                        }
+                    }
+                    'r' if app.get_current_thread().is_some() => app.start_reply(),
+                    '>' if app.focus == CurrentFocus::Conversation => app.start_quote(),
+                    'y' if app.focus == CurrentFocus::Conversation => app.copy_selected(),
+                    'e' if app.focus == CurrentFocus::Conversation => app.start_reaction_picker(),
+                    'w' if app.focus == CurrentFocus::Conversation => {
+                        if let Err(e) = app.toggle_subscription().await {
+                            app.status_message = Some(format!("Could not change subscription: {}", e));
+                        }
+                    }
+                    'D' if app.focus == CurrentFocus::Conversation && app.is_moderator() => {
+                        if let Err(e) = app.delete_selected_post().await {
+                            app.status_message = Some(format!("Delete failed: {}", e));
+                        }
+                    }
+                    '!' if app.focus == CurrentFocus::Conversation => app.start_report(),
+                    'u' => {
+                        if let Err(e) = app.open_author_profile().await {
+                            app.status_message = Some(format!("Could not load profile: {}", e));
+                        }
+                    }
+                    'p' => {
+                        let username = app.current_user.as_ref().map(|u| u.username.clone()).unwrap_or_default();
+                        if let Err(e) = app.open_profile(&username).await {
+                            app.status_message = Some(format!("Could not load profile: {}", e));
+                        }
+                    }
+                    'm' => {
+                        if let Err(e) = app.open_messages().await {
+                            app.status_message = Some(format!("Could not load messages: {}", e));
+                        }
+                    }
+                    'S' => {
+                        if let Err(e) = app.open_settings().await {
+                            app.status_message = Some(format!("Could not open settings: {}", e));
+                        }
+                    }
+                    'i' => {
+                        if let Err(e) = app.open_notifications().await {
+                            app.status_message = Some(format!("Could not load notifications: {}", e));
+                        }
+                    }
+                    'C' if app.is_admin() => {
+                        if let Err(e) = app.open_categories().await {
+                            app.status_message = Some(format!("Could not load categories: {}", e));
+                        }
+                    }
+                    'H' if app.is_admin() => {
+                        if let Err(e) = app.open_audit_log().await {
+                            app.status_message = Some(format!("Could not load audit log: {}", e));
+                        }
+                    }
+                    'M' if app.is_moderator() => {
+                        if let Err(e) = app.open_moderation().await {
+                            app.status_message = Some(format!("Could not load reports: {}", e));
+                        }
+                    }
+                    'q' => app.should_quit = true,
+                    _ => {}
+                }
+            }
+        }
+        KeyCode::Backspace => {
+            if app.focus == CurrentFocus::NewThread {
+                match app.new_thread_focus {
+                    CurrentFocus::Username => { app.new_thread_title.pop(); },
+                    CurrentFocus::NewThreadTags => {
+                        app.new_thread_tags.pop();
+                        app.update_tag_suggestions().await;
+                    }
+                    CurrentFocus::ThreadList => { app.new_thread_content.pop(); },
+                    _ => {}
+                }
+            } else if app.focus == CurrentFocus::TagFilter {
+                app.tag_filter_input.pop();
+                app.update_tag_suggestions().await;
+            } else if app.focus == CurrentFocus::Reply {
+                app.reply_content.pop();
+            } else if app.focus == CurrentFocus::Report {
+                app.report_reason.pop();
+            }
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+async fn handle_moderation_keys(key: KeyEvent, app: &mut App) -> anyhow::Result<()> {
+    app.status_message = None;
+    if app.focus == CurrentFocus::Sanction {
+        match key.code {
+            KeyCode::Enter => {
+                if let Err(e) = app.submit_sanction().await {
+                    app.status_message = Some(format!("Moderation failed: {}", e));
+                }
+            }
+            KeyCode::Esc => {
+                app.pending_sanction = None;
+                app.focus = CurrentFocus::ThreadList;
+            }
+            KeyCode::Char(c) => app.sanction_reason.push(c),
+            KeyCode::Backspace => {
+                app.sanction_reason.pop();
+            }
+            _ => {}
+        }
+        return Ok(());
+    }
+    let result = match key.code {
+        KeyCode::Up => {
+            app.selected_report = app.selected_report.saturating_sub(1);
+            Ok(())
+        }
+        KeyCode::Down => {
+            if app.selected_report < app.reports.len().saturating_sub(1) {
+                app.selected_report += 1;
+            }
+            Ok(())
+        }
+        KeyCode::Char('k') => app.settle_selected_report(Some("keep")).await,
+        KeyCode::Char('h') => app.settle_selected_report(Some("hide")).await,
+        KeyCode::Char('d') => app.settle_selected_report(Some("delete")).await,
+        KeyCode::Char('x') => app.settle_selected_report(None).await,
+        KeyCode::Char('g') => app.open_moderation().await,
+        KeyCode::Char('t') => app.open_trash().await,
+        KeyCode::Char('o') => app.open_held().await,
+        KeyCode::Char('b') => {
+            app.start_sanction("ban");
+            Ok(())
+        }
+        KeyCode::Char('s') => {
+            app.start_sanction("suspend");
+            Ok(())
+        }
+        KeyCode::Char('m') => {
+            app.start_sanction("mute");
+            Ok(())
+        }
+        KeyCode::Esc => {
+            // Hidden or deleted content should disappear from the forum view
+            app.state = AppState::Forum;
+            app.load_threads().await?;
+            if let Some(thread_id) = app.current_thread_id.clone() {
+                app.refresh_comments(&thread_id).await?;
+            }
+            Ok(())
+        }
+        _ => Ok(()),
+    };
+    if let Err(e) = result {
+        app.status_message = Some(format!("Moderation failed: {}", e));
+    }
+    Ok(())
+}
+
+async fn handle_audit_keys(key: KeyEvent, app: &mut App) -> anyhow::Result<()> {
+    app.status_message = None;
+    if app.focus == CurrentFocus::AuditFilter {
+        match key.code {
+            KeyCode::Enter => {
+                app.focus = CurrentFocus::ThreadList;
+                if let Err(e) = app.open_audit_log().await {
+                    app.status_message = Some(format!("Could not load audit log: {}", e));
+                }
+            }
+            KeyCode::Esc => app.focus = CurrentFocus::ThreadList,
+            KeyCode::Char(c) => app.audit_filter.push(c),
+            KeyCode::Backspace => {
+                app.audit_filter.pop();
+            }
+            _ => {}
+        }
+        return Ok(());
+    }
+    match key.code {
+        KeyCode::Up => app.selected_audit = app.selected_audit.saturating_sub(1),
+        KeyCode::Down if app.selected_audit < app.audit_entries.len().saturating_sub(1) => {
+            app.selected_audit += 1;
+        }
+        KeyCode::Char('/') => app.focus = CurrentFocus::AuditFilter,
+        KeyCode::Char('g') => {
+            if let Err(e) = app.open_audit_log().await {
+                app.status_message = Some(format!("Could not load audit log: {}", e));
+            }
+        }
+        KeyCode::Esc => app.state = AppState::Forum,
+        _ => {}
+    }
+    Ok(())
+}
+
+async fn handle_trash_keys(key: KeyEvent, app: &mut App) -> anyhow::Result<()> {
+    app.status_message = None;
+    let result = match key.code {
+        KeyCode::Up => {
+            app.selected_deleted = app.selected_deleted.saturating_sub(1);
+            Ok(())
+        }
+        KeyCode::Down => {
+            if app.selected_deleted < app.deleted_items.len().saturating_sub(1) {
+                app.selected_deleted += 1;
+            }
+            Ok(())
+        }
+        KeyCode::Char('u') => app.restore_selected().await,
+        KeyCode::Char('g') => app.open_trash().await,
+        KeyCode::Esc => app.open_moderation().await,
+        _ => Ok(()),
+    };
+    if let Err(e) = result {
+        app.status_message = Some(format!("Moderation failed: {}", e));
+    }
+    Ok(())
+}
+
+async fn handle_held_keys(key: KeyEvent, app: &mut App) -> anyhow::Result<()> {
+    app.status_message = None;
+    let result = match key.code {
+        KeyCode::Up => {
+            app.selected_held = app.selected_held.saturating_sub(1);
+            Ok(())
+        }
+        KeyCode::Down => {
+            if app.selected_held < app.held_posts.len().saturating_sub(1) {
+                app.selected_held += 1;
+            }
+            Ok(())
+        }
+        KeyCode::Char('a') => app.decide_selected_held("approve").await,
+        KeyCode::Char('x') => app.decide_selected_held("reject").await,
+        KeyCode::Char('g') => app.open_held().await,
+        KeyCode::Esc => app.open_moderation().await,
+        _ => Ok(()),
+    };
+    if let Err(e) = result {
+        app.status_message = Some(format!("Moderation failed: {}", e));
+    }
+    Ok(())
+}
+
+async fn handle_notifications_keys(key: KeyEvent, app: &mut App) -> anyhow::Result<()> {
+    app.status_message = None;
+    let result = match key.code {
+        KeyCode::Up => {
+            app.selected_notification = app.selected_notification.saturating_sub(1);
+            Ok(())
+        }
+        KeyCode::Down => {
+            if app.selected_notification < app.notifications.len().saturating_sub(1) {
+                app.selected_notification += 1;
+            }
+            Ok(())
+        }
+        KeyCode::Enter => app.open_selected_notification().await,
+        KeyCode::Char('r') => app.mark_selected_notification_read().await,
+        KeyCode::Char('m') => app.mark_all_notifications_read().await,
+        KeyCode::Char('g') => app.open_notifications().await,
+        KeyCode::Esc => {
+            app.state = AppState::Forum;
+            Ok(())
+        }
+        _ => Ok(()),
+    };
+    if let Err(e) = result {
+        app.status_message = Some(format!("Notifications: {}", e));
+    }
+    Ok(())
+}
+
+async fn handle_messages_keys(key: KeyEvent, app: &mut App) -> anyhow::Result<()> {
+    app.status_message = None;
+    if matches!(app.focus, CurrentFocus::MessageRecipients | CurrentFocus::MessageInput) {
+        let recipients = app.focus == CurrentFocus::MessageRecipients;
+        match key.code {
+            // Recipients stay non-empty once past them; without any, the message is a reply
+            KeyCode::Enter | KeyCode::Tab if recipients && !app.dm_recipients.trim().is_empty() => {
+                app.focus = CurrentFocus::MessageInput;
+            }
+            KeyCode::Enter | KeyCode::Tab if recipients => {}
+            // Stay in the editor so the message can be fixed
+            KeyCode::Enter => {
+                if let Err(e) = app.submit_message().await {
+                    app.set_field_errors(&e);
+                    if app.field_error("participants").is_some() {
+                        app.focus = CurrentFocus::MessageRecipients;
+                    }
+                    app.status_message = Some(format!("Could not send message: {}", e));
+                }
+            }
+            KeyCode::Tab if !app.dm_recipients.is_empty() => app.focus = CurrentFocus::MessageRecipients,
+            KeyCode::Esc => app.focus = CurrentFocus::ThreadList,
+            KeyCode::Char(c) if recipients => app.dm_recipients.push(c),
+            KeyCode::Char(c) => app.dm_input.push(c),
+            KeyCode::Backspace if recipients => {
+                app.dm_recipients.pop();
+            }
+            KeyCode::Backspace => {
+                app.dm_input.pop();
+            }
+            _ => {}
+        }
+        return Ok(());
+    }
+    let result = match key.code {
+        KeyCode::Up => app.select_conversation(app.selected_conversation.saturating_sub(1)).await,
+        KeyCode::Down => app.select_conversation(app.selected_conversation + 1).await,
+        KeyCode::Enter | KeyCode::Char('r') => {
+            app.start_message();
+            Ok(())
+        }
+        KeyCode::Char('n') => {
+            app.start_new_conversation();
+            Ok(())
+        }
+        KeyCode::PageUp => app.load_older_messages().await,
+        KeyCode::Char('b') => app.toggle_block_selected().await,
+        KeyCode::Char('g') => app.open_messages().await,
+        KeyCode::Esc => {
+            app.state = AppState::Forum;
+            Ok(())
+        }
+        _ => Ok(()),
+    };
+    if let Err(e) = result {
+        app.status_message = Some(format!("Messages: {}", e));
+    }
+    Ok(())
+}
+
+async fn handle_settings_keys(key: KeyEvent, app: &mut App) -> anyhow::Result<()> {
+    app.status_message = None;
+    // The recovery codes stay up until dismissed; they are not shown again
+    if !app.recovery_codes.is_empty() {
+        if matches!(key.code, KeyCode::Enter | KeyCode::Esc) {
+            app.recovery_codes.clear();
+        }
+        return Ok(());
+    }
+    if let Some(action) = app.settings_action {
+        let count = app.settings_inputs.len().max(1);
+        match key.code {
+            // Stay in the form so a rejected value can be fixed
+            KeyCode::Enter => {
+                if let Err(e) = app.submit_settings_action().await {
+                    app.set_field_errors(&e);
+                    app.status_message = Some(format!("{} failed: {}", action.label(), e));
+                }
+            }
+            KeyCode::Tab | KeyCode::Down => app.settings_field = (app.settings_field + 1) % count,
+            KeyCode::BackTab | KeyCode::Up => app.settings_field = (app.settings_field + count - 1) % count,
+            KeyCode::Left | KeyCode::Right if action == SettingsAction::DeleteAccount => app.delete_posts = !app.delete_posts,
+            KeyCode::Esc => app.settings_action = None,
+            KeyCode::Char(c) => {
+                if let Some(input) = app.settings_inputs.get_mut(app.settings_field) {
+                    input.push(c);
+                }
+            }
+            KeyCode::Backspace => {
+                if let Some(input) = app.settings_inputs.get_mut(app.settings_field) {
+                    input.pop();
+                }
+            }
+            _ => {}
+        }
+        return Ok(());
+    }
+    match key.code {
+        KeyCode::Up => app.selected_setting = app.selected_setting.saturating_sub(1),
+        KeyCode::Down => app.selected_setting = (app.selected_setting + 1).min(app.settings_actions().len() - 1),
+        KeyCode::Enter => {
+            if let Err(e) = app.start_settings_action().await {
+                app.status_message = Some(format!("Could not start: {}", e));
+            }
+        }
+        KeyCode::Esc => app.state = AppState::Forum,
+        _ => {}
+    }
+    Ok(())
+}
+
+async fn handle_ssh_keys_keys(key: KeyEvent, app: &mut App) -> anyhow::Result<()> {
+    app.status_message = None;
+    if let Some(input) = app.ssh_key_input.as_mut() {
+        match key.code {
+            // Stay in the input so a rejected key can be fixed
+            KeyCode::Enter => {
+                if let Err(e) = app.submit_ssh_key().await {
+                    app.set_field_errors(&e);
+                    app.status_message = Some(format!("Could not add key: {}", e));
+                }
+            }
+            KeyCode::Esc => app.ssh_key_input = None,
+            KeyCode::Char(c) => input.push(c),
+            KeyCode::Backspace => {
+                input.pop();
+            }
+            _ => {}
+        }
+        return Ok(());
+    }
+    let result = match key.code {
+        KeyCode::Up => {
+            app.selected_ssh_key = app.selected_ssh_key.saturating_sub(1);
+            Ok(())
+        }
+        KeyCode::Down => {
+            app.selected_ssh_key = (app.selected_ssh_key + 1).min(app.ssh_keys.len().saturating_sub(1));
+            Ok(())
+        }
+        KeyCode::Char('a') => {
+            app.start_ssh_key_input();
+            Ok(())
+        }
+        KeyCode::Char('d') => app.delete_selected_ssh_key().await,
+        KeyCode::Esc => {
+            app.state = AppState::Settings;
+            Ok(())
+        }
+        _ => Ok(()),
+    };
+    if let Err(e) = result {
+        app.status_message = Some(format!("SSH keys: {}", e));
+    }
+    Ok(())
+}
+
+async fn handle_profile_keys(key: KeyEvent, app: &mut App) -> anyhow::Result<()> {
+    app.status_message = None;
+    if app.profile_field.is_some() {
+        match key.code {
+            // Stay in the input so a rejected value can be fixed
+            KeyCode::Enter => {
+                if let Err(e) = app.submit_profile_edit().await {
+                    app.set_field_errors(&e);
+                    app.status_message = Some(format!("Could not update profile: {}", e));
+                }
+            }
+            KeyCode::Esc => app.profile_field = None,
+            KeyCode::Char(c) => app.profile_input.push(c),
+            KeyCode::Backspace => {
+                app.profile_input.pop();
+            }
+            _ => {}
+        }
+        return Ok(());
+    }
+    let activity_count = app.profile.as_ref().map_or(0, |p| p.recent_activity.len());
+    let result = match key.code {
+        KeyCode::Up => {
+            app.selected_activity = app.selected_activity.saturating_sub(1);
+            Ok(())
+        }
+        KeyCode::Down => {
+            if app.selected_activity < activity_count.saturating_sub(1) {
+                app.selected_activity += 1;
+            }
+            Ok(())
+        }
+        KeyCode::Enter => app.open_selected_activity().await,
+        KeyCode::Char('d') => {
+            app.start_profile_edit("display_name");
+            Ok(())
+        }
+        KeyCode::Char('b') => {
+            app.start_profile_edit("bio");
+            Ok(())
+        }
+        KeyCode::Char('s') => {
+            app.start_profile_edit("signature");
+            Ok(())
+        }
+        KeyCode::Char('l') => {
+            app.start_profile_edit("location");
+            Ok(())
+        }
+        KeyCode::Char('a') => {
+            app.start_profile_edit("avatar");
+            Ok(())
+        }
+        KeyCode::Char('x') => app.remove_avatar().await,
+        KeyCode::Char('g') => {
+            let username = app.profile.as_ref().map(|p| p.username.clone()).unwrap_or_default();
+            app.open_profile(&username).await
+        }
+        KeyCode::Esc => {
+            app.state = AppState::Forum;
+            Ok(())
+        }
+        _ => Ok(()),
+    };
+    if let Err(e) = result {
+        app.status_message = Some(format!("Profile: {}", e));
+    }
+    Ok(())
+}
+
+async fn handle_categories_keys(key: KeyEvent, app: &mut App) -> anyhow::Result<()> {
+    app.status_message = None;
+    if app.focus == CurrentFocus::CategoryInput {
+        match key.code {
+            // Stay in the input so a rejected name can be fixed
+            KeyCode::Enter => {
+                if let Err(e) = app.submit_category_input().await {
+                    app.set_field_errors(&e);
+                    app.status_message = Some(format!("Could not save category: {}", e));
+                }
+            }
+            KeyCode::Esc => {
+                app.category_action = None;
+                app.focus = CurrentFocus::ThreadList;
+            }
+            KeyCode::Char(c) => app.category_input.push(c),
+            KeyCode::Backspace => {
+                app.category_input.pop();
+            }
+            _ => {}
+        }
+        return Ok(());
+    }
+    if app.focus == CurrentFocus::CategoryChoice {
+        match key.code {
+            KeyCode::Up => app.selected_choice = app.selected_choice.saturating_sub(1),
+            KeyCode::Down if app.selected_choice < app.category_choices.len().saturating_sub(1) => {
+                app.selected_choice += 1;
+            }
+            KeyCode::Enter => {
+                if let Err(e) = app.submit_category_choice().await {
+                    app.status_message = Some(format!("Could not update category: {}", e));
+                }
+            }
+            KeyCode::Esc => {
+                app.category_action = None;
+                app.focus = CurrentFocus::ThreadList;
+            }
+            _ => {}
+        }
+        return Ok(());
+    }
+    let result = match key.code {
+        KeyCode::Up => {
+            app.selected_category_row = app.selected_category_row.saturating_sub(1);
+            Ok(())
+        }
+        KeyCode::Down => {
+            if app.selected_category_row < app.categories.len().saturating_sub(1) {
+                app.selected_category_row += 1;
+            }
+            Ok(())
+        }
+        KeyCode::Char('n') => {
+            app.start_new_category(false);
+            Ok(())
+        }
+        KeyCode::Char('N') => {
+            app.start_new_category(true);
+            Ok(())
+        }
+        KeyCode::Char('r') => {
+            app.start_category_input(CategoryAction::Rename);
+            Ok(())
+        }
+        KeyCode::Char('e') => {
+            app.start_category_input(CategoryAction::Describe);
+            Ok(())
+        }
+        KeyCode::Char('p') => {
+            app.start_category_choice(CategoryAction::SetParent);
+            Ok(())
+        }
+        KeyCode::Char('d') => {
+            app.start_category_choice(CategoryAction::Delete);
+            Ok(())
+        }
+        KeyCode::Char('K') => app.move_selected_category(-1).await,
+        KeyCode::Char('J') => app.move_selected_category(1).await,
+        KeyCode::Char('g') => app.open_categories().await,
+        KeyCode::Esc => {
+            // Renamed or deleted categories show up in the thread list
+            app.state = AppState::Forum;
+            app.load_threads().await
+        }
+        _ => Ok(()),
+    };
+    if let Err(e) = result {
+        app.status_message = Some(format!("Could not update category: {}", e));
+    }
+    Ok(())
+}